@@ -0,0 +1,204 @@
+use std::collections::BTreeMap;
+
+use chumsky::error::Rich;
+use chumsky::span::SimpleSpan;
+
+use crate::belt::ast::{Instruction, Program, Symbol};
+use crate::belt::encoding::{encode_program, encoded_len};
+use crate::belt::parser::parse_belt;
+
+#[derive(Debug)]
+pub struct Assembly {
+    pub instructions: Vec<Instruction>,
+    /// Source span of every instruction.
+    pub spans: Vec<SimpleSpan>,
+    /// Address of every label in words.
+    pub labels: BTreeMap<String, u16>,
+}
+
+impl Assembly {
+    pub fn image(&self) -> Vec<u16> {
+        encode_program(&self.instructions)
+    }
+
+    /// Span of the instruction occupying `address`, including the constant word of
+    /// two word instructions.
+    pub fn span_at(&self, address: u16) -> Option<SimpleSpan> {
+        let mut start = 0;
+        for (instruction, span) in self.instructions.iter().zip(&self.spans) {
+            start += encoded_len(instruction);
+            if (address as usize) < start {
+                return Some(*span);
+            }
+        }
+        None
+    }
+}
+
+fn set_constant(instruction: Instruction, value: u16) -> Instruction {
+    match instruction {
+        Instruction::Constant { op, pos, .. } => Instruction::Constant {
+            op,
+            pos,
+            constant: value,
+        },
+        Instruction::LoadConstant { .. } => Instruction::LoadConstant { constant: value },
+        _ => unreachable!("only instructions with a constant refer to labels"),
+    }
+}
+
+// First pass, assigns an address to every label.
+fn collect_labels<'src>(program: &Program) -> (BTreeMap<String, u16>, Vec<Rich<'src, char>>) {
+    let mut labels = BTreeMap::new();
+    let mut errors = vec![];
+    let mut address = 0usize;
+    for symbol in &program.symbols {
+        match &symbol.node {
+            Symbol::Instruction(instruction) => address += encoded_len(instruction),
+            Symbol::Reference(reference) => address += encoded_len(&reference.instruction),
+            Symbol::Label(label) => {
+                if labels.contains_key(&label.name) {
+                    errors.push(Rich::custom(
+                        label.span,
+                        format!("label `{}` is already defined", label.name),
+                    ));
+                } else if address > u16::MAX as usize {
+                    errors.push(Rich::custom(
+                        label.span,
+                        format!("label `{}` lies outside of memory", label.name),
+                    ));
+                } else {
+                    labels.insert(label.name.clone(), address as u16);
+                }
+            }
+            Symbol::Comment(_) | Symbol::Directive(_) => {}
+        }
+    }
+    (labels, errors)
+}
+
+/// Resolves the label references of a parsed program.
+pub fn assemble<'src>(program: &Program) -> Result<Assembly, Vec<Rich<'src, char>>> {
+    let (labels, mut errors) = collect_labels(program);
+
+    let mut instructions = vec![];
+    let mut spans = vec![];
+    for symbol in &program.symbols {
+        let instruction = match &symbol.node {
+            Symbol::Instruction(instruction) => *instruction,
+            Symbol::Reference(reference) => match labels.get(&reference.label.name) {
+                Some(&address) => set_constant(reference.instruction, address),
+                None => {
+                    errors.push(Rich::custom(
+                        reference.label.span,
+                        format!("label `{}` is not defined", reference.label.name),
+                    ));
+                    continue;
+                }
+            },
+            Symbol::Label(_) | Symbol::Comment(_) | Symbol::Directive(_) => continue,
+        };
+        instructions.push(instruction);
+        spans.push(symbol.span);
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(Assembly {
+        instructions,
+        spans,
+        labels,
+    })
+}
+
+pub fn assemble_belt(assembly: &str) -> Result<Assembly, Vec<Rich<'_, char>>> {
+    assemble(&parse_belt(assembly)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::belt::ast::{BeltPos, ConstantOp, ZeroOp};
+
+    #[test]
+    fn test_backward_and_forward_references() {
+        let source = "
+            start: lc end
+            loop:
+                jump b1 loop
+                nop
+            end:
+        ";
+        let assembly = assemble_belt(source).unwrap();
+        assert_eq!(assembly.labels["start"], 0);
+        assert_eq!(assembly.labels["loop"], 2);
+        assert_eq!(assembly.labels["end"], 5);
+        assert_eq!(
+            assembly.instructions,
+            [
+                Instruction::LoadConstant { constant: 5 },
+                Instruction::Constant {
+                    op: ConstantOp::Jump,
+                    pos: BeltPos(1),
+                    constant: 2,
+                },
+                Instruction::Zero { op: ZeroOp::Nop },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_span_at() {
+        let source = "lc 1\nloop: add b0 b0\njump b0 loop";
+        let assembly = assemble_belt(source).unwrap();
+        let text = |address| {
+            assembly
+                .span_at(address)
+                .map(|span| &source[span.into_range()])
+        };
+        assert_eq!(text(0), Some("lc 1"));
+        assert_eq!(text(1), Some("lc 1"));
+        assert_eq!(text(2), Some("add b0 b0"));
+        assert_eq!(text(4), Some("jump b0 loop"));
+        assert_eq!(text(5), None);
+    }
+
+    #[test]
+    fn test_label_as_constant() {
+        let assembly = assemble_belt("nop\ndata:\nand b0 data").unwrap();
+        assert_eq!(
+            assembly.instructions[1],
+            Instruction::Constant {
+                op: ConstantOp::And,
+                pos: BeltPos(0),
+                constant: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_undefined_label() {
+        let source = "lc 1\njump b0 nowhere";
+        let errors = assemble_belt(source).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "label `nowhere` is not defined");
+        assert_eq!(&source[errors[0].span().into_range()], "nowhere");
+    }
+
+    #[test]
+    fn test_duplicate_label() {
+        let source = "loop: nop\nloop: nop";
+        let errors = assemble_belt(source).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "label `loop` is already defined");
+        assert_eq!(errors[0].span().into_range(), 10..14);
+    }
+
+    #[test]
+    fn test_belt_position_is_not_a_label() {
+        assert!(parse_belt("b3: nop").is_err());
+        assert!(parse_belt("lc b3").is_err());
+        assert!(parse_belt("b16x: nop").is_ok());
+    }
+}
@@ -1,17 +1,36 @@
+use chumsky::span::SimpleSpan;
 use derive_more::From;
 
+use crate::span::Spanned;
+
 #[derive(Debug)]
 pub struct Program {
-    pub symbols: Vec<Symbol>,
+    pub symbols: Vec<Spanned<Symbol>>,
 }
 
 #[derive(Clone, Debug, From)]
 pub enum Symbol {
     Instruction(Instruction),
-    Comment,
+    Reference(Reference),
+    Label(Label),
+    /// Text of a comment after its `#`.
+    Comment(String),
     Directive(Directive),
 }
 
+#[derive(PartialEq, Clone, Debug)]
+pub struct Label {
+    pub name: String,
+    pub span: SimpleSpan,
+}
+
+/// Instruction whose constant is the address of `label`, filled in by the assembler.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Reference {
+    pub instruction: Instruction,
+    pub label: Label,
+}
+
 #[derive(PartialEq, Copy, Clone, Debug)]
 pub struct Alignment {
     pub alignment: usize,
@@ -76,10 +95,92 @@ pub struct BeltPos(pub u8);
 
 #[derive(PartialEq, Copy, Clone, Debug)]
 pub enum Instruction {
-    Constant { op: ConstantOp, pos: BeltPos, constant: u16 },
-    LoadConstant { constant: u16 },
-    Immediate { op: ImmediateOp, pos: BeltPos, imm: u8 },
-    Register { op: RegOp, pos1: BeltPos, pos2: BeltPos },
-    Unary { op: UnaryOp, pos: BeltPos },
-    Zero { op: ZeroOp },
+    Constant {
+        op: ConstantOp,
+        pos: BeltPos,
+        constant: u16,
+    },
+    LoadConstant {
+        constant: u16,
+    },
+    Immediate {
+        op: ImmediateOp,
+        pos: BeltPos,
+        /// 0 to 15.
+        imm: u8,
+    },
+    Register {
+        op: RegOp,
+        pos1: BeltPos,
+        pos2: BeltPos,
+    },
+    Unary {
+        op: UnaryOp,
+        pos: BeltPos,
+    },
+    Zero {
+        op: ZeroOp,
+    },
+}
+
+impl ConstantOp {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            ConstantOp::And => "and",
+            ConstantOp::Or => "or",
+            ConstantOp::Xor => "xor",
+            ConstantOp::Jump => "jump",
+        }
+    }
+}
+
+impl ImmediateOp {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            ImmediateOp::Left => "sl",
+            ImmediateOp::Right => "sr",
+            ImmediateOp::Call => "call",
+            ImmediateOp::Ret => "ret",
+        }
+    }
+}
+
+impl RegOp {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            RegOp::Add => "add",
+            RegOp::Sub => "sub",
+            RegOp::And => "and",
+            RegOp::Or => "or",
+            RegOp::Xor => "xor",
+            RegOp::Mul => "mul",
+            RegOp::Div => "div",
+            RegOp::Save => "save",
+            RegOp::ShiftLeft => "sl",
+            RegOp::ShiftRight => "sr",
+            RegOp::BranchLower => "blt",
+            RegOp::BranchLowerEq => "ble",
+            RegOp::BranchEq => "beq",
+        }
+    }
+}
+
+impl UnaryOp {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            UnaryOp::Load => "load",
+            UnaryOp::Jump => "jump",
+            UnaryOp::Push => "push",
+        }
+    }
+}
+
+impl ZeroOp {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            ZeroOp::Nop => "nop",
+            ZeroOp::Pop => "pop",
+            ZeroOp::Break => "break",
+        }
+    }
 }
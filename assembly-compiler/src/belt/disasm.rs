@@ -0,0 +1,216 @@
+use std::fmt::{Display, Formatter, Write};
+
+use crate::belt::ast::{BeltPos, Instruction};
+use crate::belt::encoding::{decode, DecodeError};
+
+impl Display for BeltPos {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "b{}", self.0)
+    }
+}
+
+/// Prints the instruction in the syntax accepted by `parse_belt`.
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Instruction::Constant { op, pos, constant } => {
+                write!(f, "{} {} 0x{:04X}", op.mnemonic(), pos, constant)
+            }
+            Instruction::LoadConstant { constant } => write!(f, "lc 0x{:04X}", constant),
+            Instruction::Immediate { op, pos, imm } => {
+                write!(f, "{} {} {}", op.mnemonic(), pos, imm)
+            }
+            Instruction::Register { op, pos1, pos2 } => {
+                write!(f, "{} {} {}", op.mnemonic(), pos1, pos2)
+            }
+            Instruction::Unary { op, pos } => write!(f, "{} {}", op.mnemonic(), pos),
+            Instruction::Zero { op } => write!(f, "{}", op.mnemonic()),
+        }
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Debug, Default)]
+pub struct DisasmOptions {
+    /// Address of the first word of the image.
+    pub base_address: u16,
+    pub show_addresses: bool,
+    pub show_raw_words: bool,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct DisasmLine {
+    pub address: u16,
+    pub words: Vec<u16>,
+    pub instruction: Result<Instruction, DecodeError>,
+}
+
+/// Decodes a memory image instruction by instruction, a word which does not decode
+/// becomes a line of its own.
+pub fn disassemble_lines(image: &[u16], base_address: u16) -> Vec<DisasmLine> {
+    let mut lines = vec![];
+    let mut offset = 0;
+    while offset < image.len() {
+        let (instruction, len) = match decode(&image[offset..]) {
+            Ok((instruction, len)) => (Ok(instruction), len),
+            Err(error) => (Err(error), 1),
+        };
+        lines.push(DisasmLine {
+            address: base_address.wrapping_add(offset as u16),
+            words: image[offset..offset + len].to_vec(),
+            instruction,
+        });
+        offset += len;
+    }
+    lines
+}
+
+///
+/// Turns a memory image back into assembly, one instruction per line.
+///
+/// Without the address and raw word columns the output parses with `parse_belt`,
+/// undecodable words are kept as comments.
+///
+pub fn disassemble(image: &[u16], options: &DisasmOptions) -> String {
+    let mut output = String::new();
+    for line in disassemble_lines(image, options.base_address) {
+        if options.show_addresses {
+            write!(output, "{:04X}:  ", line.address).unwrap();
+        }
+        if options.show_raw_words {
+            let raw = line
+                .words
+                .iter()
+                .map(|word| format!("{:04X}", word))
+                .collect::<Vec<_>>()
+                .join(" ");
+            write!(output, "{:<11}", raw).unwrap();
+        }
+        match line.instruction {
+            Ok(instruction) => writeln!(output, "{}", instruction).unwrap(),
+            Err(error) => writeln!(output, "# {}", error).unwrap(),
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::belt::ast::{ConstantOp, ImmediateOp, RegOp, Symbol, UnaryOp, ZeroOp};
+    use crate::belt::encoding::{encode, encode_program};
+    use crate::belt::parser::parse_belt;
+    use rstest::rstest;
+
+    fn instructions(source: &str) -> Vec<Instruction> {
+        parse_belt(source)
+            .unwrap()
+            .symbols
+            .into_iter()
+            .filter_map(|symbol| match symbol.node {
+                Symbol::Instruction(instruction) => Some(instruction),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[rstest]
+    #[case(Instruction::Constant { op: ConstantOp::And, pos: BeltPos(0), constant: 0x1234 }, "and b0 0x1234")]
+    #[case(Instruction::Constant { op: ConstantOp::Jump, pos: BeltPos(2), constant: 0x10 }, "jump b2 0x0010")]
+    #[case(Instruction::LoadConstant { constant: 0xDEF0 }, "lc 0xDEF0")]
+    #[case(Instruction::Immediate { op: ImmediateOp::Left, pos: BeltPos(3), imm: 4 }, "sl b3 4")]
+    #[case(Instruction::Immediate { op: ImmediateOp::Call, pos: BeltPos(0), imm: 2 }, "call b0 2")]
+    #[case(Instruction::Register { op: RegOp::ShiftRight, pos1: BeltPos(14), pos2: BeltPos(0) }, "sr b14 b0")]
+    #[case(Instruction::Register { op: RegOp::BranchLowerEq, pos1: BeltPos(1), pos2: BeltPos(2) }, "ble b1 b2")]
+    #[case(Instruction::Unary { op: UnaryOp::Load, pos: BeltPos(3) }, "load b3")]
+    #[case(Instruction::Zero { op: ZeroOp::Break }, "break")]
+    fn test_instruction_text(#[case] instruction: Instruction, #[case] expected: &str) {
+        assert_eq!(instruction.to_string(), expected);
+        assert_eq!(
+            disassemble(&encode(&instruction), &DisasmOptions::default()),
+            format!("{}\n", expected)
+        );
+    }
+
+    #[test]
+    fn test_columns() {
+        let image = encode_program(&instructions("lc 0xDEF0\nadd b0 b1\n"));
+        let options = DisasmOptions {
+            base_address: 0x0100,
+            show_addresses: true,
+            show_raw_words: true,
+        };
+        assert_eq!(
+            disassemble(&image, &options),
+            "0100:  C000 DEF0  lc 0xDEF0\n0102:  2001       add b0 b1\n"
+        );
+    }
+
+    #[test]
+    fn test_invalid_word() {
+        let lines = disassemble_lines(&[0x0000, 0xFFFF, 0xC000], 0);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1].address, 1);
+        assert_eq!(
+            lines[1].instruction,
+            Err(DecodeError::InvalidOpcode { word: 0xFFFF })
+        );
+        assert_eq!(
+            lines[2].instruction,
+            Err(DecodeError::MissingConstant { word: 0xC000 })
+        );
+        assert_eq!(
+            disassemble(&[0xFFFF], &DisasmOptions::default()),
+            "# invalid opcode in 0xFFFF\n"
+        );
+    }
+
+    // Every word which decodes disassembles to text the assembler takes back.
+    #[test]
+    fn test_round_trip_every_word() {
+        let mut decoded = vec![];
+        let mut text = String::new();
+        for word in 0..=u16::MAX {
+            if let Ok((instruction, _)) = decode(&[word, 0xA5A5]) {
+                decoded.push(instruction);
+                writeln!(text, "{}", instruction).unwrap();
+            }
+        }
+        assert_eq!(instructions(&text), decoded);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let source = "
+            # Adds a few numbers
+            lc 0x0005
+            and b0 0xFF
+            or b1 666
+            xor b2 0b1100
+            sl b3 4
+            sr b4 5
+            add b5 b6
+            sub b7 b8
+            mul b9 b10
+            div b11 b12
+            save b13 b0
+            sl b15 b0
+            load b3
+            jump b2 0x0010
+            jump b4
+            push b5
+            call b0 2
+            ret b1 15
+            blt b1 b2
+            ble b3 b4
+            beq b5 b15
+            nop
+            pop
+            break
+        ";
+        let program = instructions(source);
+        let image = encode_program(&program);
+        let text = disassemble(&image, &DisasmOptions::default());
+        assert_eq!(instructions(&text), program);
+        assert_eq!(encode_program(&instructions(&text)), image);
+    }
+}
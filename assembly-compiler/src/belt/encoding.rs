@@ -0,0 +1,334 @@
+//!
+//! Binary encoding of belt instructions.
+//!
+//! Every instruction is a single 16-bit word, instructions carrying a 16-bit constant
+//! are followed by one extension word holding it. The top bits select the format:
+//!
+//! ```text
+//! 0000 0000 0000 oooo             zero        nop, pop, break
+//! 0001 0000 oooo pppp             unary       load, jump, push
+//! 0010 oooo pppp qqqq             register    add .. branch_eq
+//! 01oo pppp iiii iiii             immediate   sl, sr, call, ret
+//! 10oo 0000 0000 pppp cccc...     constant    and, or, xor, jump
+//! 1100 0000 0000 0000 cccc...     load constant
+//! ```
+//!
+//! `o` is the operation, `p`/`q` belt positions, `i` the immediate and `c` the constant.
+//! Unused bits must be zero, anything else does not decode.
+//!
+
+use std::fmt::{Display, Formatter};
+
+use crate::belt::ast::{BeltPos, ConstantOp, ImmediateOp, Instruction, RegOp, UnaryOp, ZeroOp};
+
+const ZERO_OPS: [ZeroOp; 3] = [ZeroOp::Nop, ZeroOp::Pop, ZeroOp::Break];
+
+const UNARY_OPS: [UnaryOp; 3] = [UnaryOp::Load, UnaryOp::Jump, UnaryOp::Push];
+
+const REG_OPS: [RegOp; 13] = [
+    RegOp::Add,
+    RegOp::Sub,
+    RegOp::And,
+    RegOp::Or,
+    RegOp::Xor,
+    RegOp::Mul,
+    RegOp::Div,
+    RegOp::Save,
+    RegOp::ShiftLeft,
+    RegOp::ShiftRight,
+    RegOp::BranchLower,
+    RegOp::BranchLowerEq,
+    RegOp::BranchEq,
+];
+
+const IMMEDIATE_OPS: [ImmediateOp; 4] = [
+    ImmediateOp::Left,
+    ImmediateOp::Right,
+    ImmediateOp::Call,
+    ImmediateOp::Ret,
+];
+
+const CONSTANT_OPS: [ConstantOp; 4] = [
+    ConstantOp::And,
+    ConstantOp::Or,
+    ConstantOp::Xor,
+    ConstantOp::Jump,
+];
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum DecodeError {
+    Empty,
+    InvalidOpcode { word: u16 },
+    ReservedBits { word: u16 },
+    MissingConstant { word: u16 },
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::Empty => write!(f, "no instruction word to decode"),
+            DecodeError::InvalidOpcode { word } => write!(f, "invalid opcode in 0x{:04X}", word),
+            DecodeError::ReservedBits { word } => {
+                write!(f, "reserved bits are set in 0x{:04X}", word)
+            }
+            DecodeError::MissingConstant { word } => {
+                write!(f, "constant of 0x{:04X} is missing", word)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+fn op_index<T: PartialEq>(ops: &[T], op: &T) -> u16 {
+    ops.iter().position(|o| o == op).unwrap() as u16
+}
+
+// Belt positions only have four bits, the parser never produces anything larger.
+fn pos_bits(pos: BeltPos) -> u16 {
+    debug_assert!(pos.0 < 16, "belt position b{} cannot be encoded", pos.0);
+    (pos.0 & 0xF) as u16
+}
+
+// So do immediates, a larger one would spill into the position.
+fn imm_bits(imm: u8) -> u16 {
+    debug_assert!(imm < 16, "immediate {} cannot be encoded", imm);
+    (imm & 0xF) as u16
+}
+
+/// Number of words `instruction` takes up in memory.
+pub fn encoded_len(instruction: &Instruction) -> usize {
+    match instruction {
+        Instruction::Constant { .. } | Instruction::LoadConstant { .. } => 2,
+        _ => 1,
+    }
+}
+
+pub fn encode(instruction: &Instruction) -> Vec<u16> {
+    match *instruction {
+        Instruction::Zero { op } => vec![op_index(&ZERO_OPS, &op)],
+        Instruction::Unary { op, pos } => {
+            vec![0x1000 | op_index(&UNARY_OPS, &op) << 4 | pos_bits(pos)]
+        }
+        Instruction::Register { op, pos1, pos2 } => {
+            vec![0x2000 | op_index(&REG_OPS, &op) << 8 | pos_bits(pos1) << 4 | pos_bits(pos2)]
+        }
+        Instruction::Immediate { op, pos, imm } => {
+            vec![0x4000 | op_index(&IMMEDIATE_OPS, &op) << 12 | pos_bits(pos) << 8 | imm_bits(imm)]
+        }
+        Instruction::Constant { op, pos, constant } => vec![
+            0x8000 | op_index(&CONSTANT_OPS, &op) << 12 | pos_bits(pos),
+            constant,
+        ],
+        Instruction::LoadConstant { constant } => vec![0xC000, constant],
+    }
+}
+
+pub fn encode_program(instructions: &[Instruction]) -> Vec<u16> {
+    instructions.iter().flat_map(encode).collect()
+}
+
+/// Decodes the instruction at the start of `words`, returning it with the number of words used.
+pub fn decode(words: &[u16]) -> Result<(Instruction, usize), DecodeError> {
+    let &word = words.first().ok_or(DecodeError::Empty)?;
+    let pos = |shift: u16| BeltPos(((word >> shift) & 0xF) as u8);
+    let reserved = |mask: u16| {
+        if word & mask != 0 {
+            Err(DecodeError::ReservedBits { word })
+        } else {
+            Ok(())
+        }
+    };
+    let lookup = |ops_len: usize, index: u16| {
+        if (index as usize) < ops_len {
+            Ok(index as usize)
+        } else {
+            Err(DecodeError::InvalidOpcode { word })
+        }
+    };
+    let constant = || {
+        words
+            .get(1)
+            .copied()
+            .ok_or(DecodeError::MissingConstant { word })
+    };
+
+    match word >> 12 {
+        0x0 => {
+            reserved(0x0FF0)?;
+            let op = ZERO_OPS[lookup(ZERO_OPS.len(), word & 0xF)?];
+            Ok((Instruction::Zero { op }, 1))
+        }
+        0x1 => {
+            reserved(0x0F00)?;
+            let op = UNARY_OPS[lookup(UNARY_OPS.len(), (word >> 4) & 0xF)?];
+            Ok((Instruction::Unary { op, pos: pos(0) }, 1))
+        }
+        0x2 => {
+            let op = REG_OPS[lookup(REG_OPS.len(), (word >> 8) & 0xF)?];
+            Ok((
+                Instruction::Register {
+                    op,
+                    pos1: pos(4),
+                    pos2: pos(0),
+                },
+                1,
+            ))
+        }
+        0x4..=0x7 => {
+            // Immediates have 4 bits, as many as the assembler accepts.
+            reserved(0x00F0)?;
+            let op = IMMEDIATE_OPS[((word >> 12) & 0x3) as usize];
+            Ok((
+                Instruction::Immediate {
+                    op,
+                    pos: pos(8),
+                    imm: (word & 0xF) as u8,
+                },
+                1,
+            ))
+        }
+        0x8..=0xB => {
+            reserved(0x0FF0)?;
+            let op = CONSTANT_OPS[((word >> 12) & 0x3) as usize];
+            Ok((
+                Instruction::Constant {
+                    op,
+                    pos: pos(0),
+                    constant: constant()?,
+                },
+                2,
+            ))
+        }
+        0xC => {
+            reserved(0x0FFF)?;
+            Ok((
+                Instruction::LoadConstant {
+                    constant: constant()?,
+                },
+                2,
+            ))
+        }
+        _ => Err(DecodeError::InvalidOpcode { word }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn all_instructions() -> Vec<Instruction> {
+        let positions = (0..16).map(BeltPos);
+        let constants = [0, 1, 0x00FF, 0x1234, 0x8000, 0xFFFF];
+        let mut instructions = vec![];
+        instructions.extend(ZERO_OPS.map(|op| Instruction::Zero { op }));
+        for pos in positions.clone() {
+            instructions.extend(UNARY_OPS.map(|op| Instruction::Unary { op, pos }));
+            for pos2 in positions.clone() {
+                instructions.extend(REG_OPS.map(|op| Instruction::Register {
+                    op,
+                    pos1: pos,
+                    pos2,
+                }));
+            }
+            for imm in 0..16 {
+                instructions.extend(IMMEDIATE_OPS.map(|op| Instruction::Immediate {
+                    op,
+                    pos,
+                    imm,
+                }));
+            }
+            for constant in constants {
+                instructions.extend(CONSTANT_OPS.map(|op| Instruction::Constant {
+                    op,
+                    pos,
+                    constant,
+                }));
+            }
+        }
+        instructions.extend(constants.map(|constant| Instruction::LoadConstant { constant }));
+        instructions
+    }
+
+    #[rstest]
+    #[case(Instruction::Zero { op: ZeroOp::Nop }, &[0x0000])]
+    #[case(Instruction::Zero { op: ZeroOp::Break }, &[0x0002])]
+    #[case(Instruction::Unary { op: UnaryOp::Push, pos: BeltPos(3) }, &[0x1023])]
+    #[case(Instruction::Register { op: RegOp::Sub, pos1: BeltPos(7), pos2: BeltPos(8) }, &[0x2178])]
+    #[case(Instruction::Register { op: RegOp::BranchEq, pos1: BeltPos(15), pos2: BeltPos(0) }, &[0x2CF0])]
+    #[case(Instruction::Immediate { op: ImmediateOp::Left, pos: BeltPos(3), imm: 4 }, &[0x4304])]
+    #[case(Instruction::Immediate { op: ImmediateOp::Ret, pos: BeltPos(1), imm: 2 }, &[0x7102])]
+    #[case(Instruction::Immediate { op: ImmediateOp::Left, pos: BeltPos(15), imm: 15 }, &[0x4F0F])]
+    #[case(Instruction::Constant { op: ConstantOp::And, pos: BeltPos(0), constant: 0x1234 }, &[0x8000, 0x1234])]
+    #[case(Instruction::Constant { op: ConstantOp::Jump, pos: BeltPos(5), constant: 0x0010 }, &[0xB005, 0x0010])]
+    #[case(Instruction::LoadConstant { constant: 0xDEF0 }, &[0xC000, 0xDEF0])]
+    fn test_encode_known(#[case] instruction: Instruction, #[case] words: &[u16]) {
+        assert_eq!(encode(&instruction), words);
+        assert_eq!(decode(words), Ok((instruction, words.len())));
+    }
+
+    #[test]
+    fn test_round_trip_every_instruction() {
+        for instruction in all_instructions() {
+            let words = encode(&instruction);
+            assert_eq!(words.len(), encoded_len(&instruction));
+            assert_eq!(
+                decode(&words),
+                Ok((instruction, words.len())),
+                "{:?}",
+                instruction
+            );
+        }
+    }
+
+    #[test]
+    fn test_round_trip_every_word() {
+        for word in 0..=u16::MAX {
+            if let Ok((instruction, len)) = decode(&[word, 0xA5A5]) {
+                assert_eq!(
+                    encode(&instruction),
+                    [word, 0xA5A5][..len],
+                    "0x{:04X}",
+                    word
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_program() {
+        let program = [
+            Instruction::LoadConstant { constant: 5 },
+            Instruction::Immediate {
+                op: ImmediateOp::Left,
+                pos: BeltPos(0),
+                imm: 2,
+            },
+            Instruction::Zero { op: ZeroOp::Break },
+        ];
+        let mut words = &encode_program(&program)[..];
+        for instruction in program {
+            let (decoded, len) = decode(words).unwrap();
+            assert_eq!(decoded, instruction);
+            words = &words[len..];
+        }
+        assert!(words.is_empty());
+    }
+
+    #[rstest]
+    #[case(&[], DecodeError::Empty)]
+    #[case(&[0x0003], DecodeError::InvalidOpcode { word: 0x0003 })]
+    #[case(&[0x0010], DecodeError::ReservedBits { word: 0x0010 })]
+    #[case(&[0x1030], DecodeError::InvalidOpcode { word: 0x1030 })]
+    #[case(&[0x2D00], DecodeError::InvalidOpcode { word: 0x2D00 })]
+    #[case(&[0x3000], DecodeError::InvalidOpcode { word: 0x3000 })]
+    #[case(&[0x4010], DecodeError::ReservedBits { word: 0x4010 })]
+    #[case(&[0x8100, 0x0000], DecodeError::ReservedBits { word: 0x8100 })]
+    #[case(&[0x8000], DecodeError::MissingConstant { word: 0x8000 })]
+    #[case(&[0xC000], DecodeError::MissingConstant { word: 0xC000 })]
+    #[case(&[0xFFFF], DecodeError::InvalidOpcode { word: 0xFFFF })]
+    fn test_decode_fail(#[case] words: &[u16], #[case] error: DecodeError) {
+        assert_eq!(decode(words), Err(error));
+    }
+}
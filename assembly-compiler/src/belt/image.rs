@@ -0,0 +1,243 @@
+//!
+//! Image files of belt programs, the sections of memory to load with their addresses and the
+//! labels of the program for a debugger to show.
+//!
+//! The file is little-endian:
+//!
+//! ```text
+//! "BELT", version: u16, entry: u16, section count: u16, symbol count: u16,
+//! sections: { name, address: u16, word count: u32, words: [u16] }...,
+//! symbols: { name, address: u16 }...
+//! ```
+//!
+//! where a name is its length in bytes as a `u16` followed by its UTF-8.
+//!
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use crate::belt::assembler::Assembly;
+
+const MAGIC: &[u8; 4] = b"BELT";
+const VERSION: u16 = 1;
+const MEMORY_WORDS: usize = 1 << 16;
+
+/// Words of memory starting at `address`.
+#[derive(PartialEq, Clone, Debug)]
+pub struct ImageSection {
+    pub name: String,
+    pub address: u16,
+    pub words: Vec<u16>,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Image {
+    /// Address execution starts at.
+    pub entry: u16,
+    pub sections: Vec<ImageSection>,
+    /// Address of every label in words.
+    pub symbols: BTreeMap<String, u16>,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum ImageError {
+    /// The file does not start with `BELT`.
+    NotAnImage,
+    UnsupportedVersion(u16),
+    Truncated,
+    /// A section which runs past the end of memory.
+    SectionOutsideMemory {
+        name: String,
+    },
+}
+
+impl Display for ImageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImageError::NotAnImage => write!(f, "not a belt image"),
+            ImageError::UnsupportedVersion(version) => {
+                write!(f, "belt image version {} is not supported", version)
+            }
+            ImageError::Truncated => write!(f, "belt image ends early"),
+            ImageError::SectionOutsideMemory { name } => {
+                write!(f, "section `{}` runs past the end of memory", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+// Little-endian fields of a file being read, any of which may lie past its end.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], ImageError> {
+        if len > self.bytes.len() {
+            return Err(ImageError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u16(&mut self) -> Result<u16, ImageError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, ImageError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn name(&mut self) -> Result<String, ImageError> {
+        let len = self.u16()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+}
+
+fn write_name(bytes: &mut Vec<u8>, name: &str) {
+    bytes.extend((name.len() as u16).to_le_bytes());
+    bytes.extend(name.as_bytes());
+}
+
+impl Image {
+    /// The program as a single `.text` section at address 0, where it starts.
+    pub fn from_assembly(assembly: &Assembly) -> Image {
+        Image {
+            entry: 0,
+            sections: vec![ImageSection {
+                name: ".text".to_string(),
+                address: 0,
+                words: assembly.image(),
+            }],
+            symbols: assembly.labels.clone(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        for field in [
+            VERSION,
+            self.entry,
+            self.sections.len() as u16,
+            self.symbols.len() as u16,
+        ] {
+            bytes.extend(field.to_le_bytes());
+        }
+        for section in &self.sections {
+            write_name(&mut bytes, &section.name);
+            bytes.extend(section.address.to_le_bytes());
+            bytes.extend((section.words.len() as u32).to_le_bytes());
+            for word in &section.words {
+                bytes.extend(word.to_le_bytes());
+            }
+        }
+        for (name, address) in &self.symbols {
+            write_name(&mut bytes, name);
+            bytes.extend(address.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Image, ImageError> {
+        let mut file = Reader { bytes };
+        if file.take(MAGIC.len()).ok() != Some(MAGIC) {
+            return Err(ImageError::NotAnImage);
+        }
+        let version = file.u16()?;
+        if version != VERSION {
+            return Err(ImageError::UnsupportedVersion(version));
+        }
+        let entry = file.u16()?;
+        let (sections, symbols) = (file.u16()?, file.u16()?);
+
+        let sections = (0..sections)
+            .map(|_| {
+                let name = file.name()?;
+                let address = file.u16()?;
+                let len = file.u32()? as usize;
+                if address as usize + len > MEMORY_WORDS {
+                    return Err(ImageError::SectionOutsideMemory { name });
+                }
+                let words = file
+                    .take(2 * len)?
+                    .chunks_exact(2)
+                    .map(|word| u16::from_le_bytes([word[0], word[1]]))
+                    .collect();
+                Ok(ImageSection {
+                    name,
+                    address,
+                    words,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        let symbols = (0..symbols)
+            .map(|_| Ok((file.name()?, file.u16()?)))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Image {
+            entry,
+            sections,
+            symbols,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::belt::assembler::assemble_belt;
+
+    #[test]
+    fn test_round_trip() {
+        let assembly = assemble_belt("start: lc end\nnop\nend: jump b0 start").unwrap();
+        let mut image = Image::from_assembly(&assembly);
+        image.sections.push(ImageSection {
+            name: ".data".to_string(),
+            address: 0x8000,
+            words: vec![1, 0xFFFF],
+        });
+        image.entry = 2;
+        let bytes = image.to_bytes();
+        assert_eq!(&bytes[..8], b"BELT\x01\x00\x02\x00");
+        assert_eq!(Image::from_bytes(&bytes), Ok(image.clone()));
+        assert_eq!(image.sections[0].words, assembly.image());
+        assert_eq!(image.symbols["end"], assembly.labels["end"]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let image = Image {
+            entry: 0,
+            sections: vec![ImageSection {
+                name: ".data".to_string(),
+                address: 0xFFFF,
+                words: vec![1, 2],
+            }],
+            symbols: BTreeMap::new(),
+        };
+        let bytes = image.to_bytes();
+        assert_eq!(
+            Image::from_bytes(&bytes),
+            Err(ImageError::SectionOutsideMemory {
+                name: ".data".to_string()
+            })
+        );
+        let bytes = Image {
+            sections: vec![],
+            ..image
+        }
+        .to_bytes();
+        assert_eq!(
+            Image::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(ImageError::Truncated)
+        );
+        assert_eq!(Image::from_bytes(b"ELF"), Err(ImageError::NotAnImage));
+        assert_eq!(
+            Image::from_bytes(b"BELT\x02\x00"),
+            Err(ImageError::UnsupportedVersion(2))
+        );
+    }
+}
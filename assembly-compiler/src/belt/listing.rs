@@ -0,0 +1,80 @@
+//!
+//! Listings of belt programs, see [`crate::listing`].
+//!
+
+use crate::belt::assembler::Assembly;
+use crate::belt::ast::{Program, Symbol};
+use crate::belt::encoding::encode;
+use crate::listing::{Listing, ListingRow};
+
+/// The listing of a program assembled from `source`, which `program` was parsed from.
+pub fn listing(source: &str, program: &Program, assembly: &Assembly) -> Listing {
+    let mut statements = vec![];
+    let mut address = 0;
+    for (instruction, span) in assembly.instructions.iter().zip(&assembly.spans) {
+        let words = encode(instruction);
+        let raw = words
+            .iter()
+            .map(|word| format!("{:04X}", word))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let row = ListingRow {
+            address: Some(address),
+            raw,
+            ..Default::default()
+        };
+        statements.push((*span, vec![row]));
+        address += words.len() as u32;
+    }
+
+    let mut definitions = vec![];
+    let mut references = vec![];
+    for symbol in &program.symbols {
+        match &symbol.node {
+            Symbol::Label(label) => definitions.push((
+                label.name.clone(),
+                assembly
+                    .labels
+                    .get(&label.name)
+                    .map(|&address| address as u32),
+                label.span,
+            )),
+            Symbol::Reference(reference) => {
+                references.push((reference.label.name.clone(), reference.label.span))
+            }
+            _ => {}
+        }
+    }
+
+    Listing::new(source, 4, statements, definitions, references)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::belt::assembler::assemble;
+    use crate::belt::parser::parse_belt;
+
+    #[test]
+    fn test_listing() {
+        let source = "start: lc end\n# wait\nloop: jump b1 loop\n    nop\nend:\n";
+        let program = parse_belt(source).unwrap();
+        let assembly = assemble(&program).unwrap();
+        let expected = "\
+Address  Words       Line  Source
+0000     C000 0005      1  start: lc end
+                        2  # wait
+0002     B001 0002      3  loop: jump b1 loop
+0004     0000           4      nop
+                        5  end:
+
+Symbols
+
+Name              Value   Line  References
+end               0005       5  1
+loop              0002       3  3
+start             0000       1
+";
+        assert_eq!(listing(source, &program, &assembly).to_string(), expected);
+    }
+}
@@ -1,2 +1,7 @@
+pub mod assembler;
 pub mod ast;
+pub mod disasm;
+pub mod encoding;
+pub mod image;
+pub mod listing;
 pub mod parser;
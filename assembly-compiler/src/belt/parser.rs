@@ -1,10 +1,48 @@
 use crate::belt::ast::{
-    BeltPos, ConstantOp, ImmediateOp, Instruction, Program, RegOp, Symbol, UnaryOp, ZeroOp,
+    BeltPos, ConstantOp, ImmediateOp, Instruction, Label, Program, Reference, RegOp, Symbol,
+    UnaryOp, ZeroOp,
 };
-use crate::chumsky_utils::integer;
+use crate::chumsky_utils::{
+    end_of_line, inline_whitespace, integer, required_whitespace, skip_line, spanned,
+};
+use crate::diagnostics::Vocabulary;
+use crate::span::Spanned;
 
 use chumsky::prelude::*;
-use chumsky::text::{inline_whitespace, newline};
+use chumsky::text::newline;
+
+pub const VOCABULARY: Vocabulary = Vocabulary {
+    mnemonics: &[
+        "nop", "pop", "break", "and", "or", "xor", "jump", "lc", "sl", "sr", "call", "ret", "add",
+        "sub", "mul", "div", "save", "blt", "ble", "beq", "load", "push",
+    ],
+    registers: &[],
+};
+
+#[derive(Clone)]
+enum Constant {
+    Value(u16),
+    Label(Label),
+}
+
+impl Constant {
+    fn into_symbol(self, instruction: impl FnOnce(u16) -> Instruction) -> Symbol {
+        match self {
+            Constant::Value(value) => Symbol::Instruction(instruction(value)),
+            Constant::Label(label) => Symbol::Reference(Reference {
+                instruction: instruction(0),
+                label,
+            }),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Constant::Value(value) => format!("0x{:04X}", value),
+            Constant::Label(label) => format!("`{}`", label.name),
+        }
+    }
+}
 
 fn belt_pos<'src>() -> impl Parser<'src, &'src str, BeltPos, extra::Err<Rich<'src, char>>> {
     just("b")
@@ -12,64 +50,120 @@ fn belt_pos<'src>() -> impl Parser<'src, &'src str, BeltPos, extra::Err<Rich<'sr
         .labelled("belt position")
 }
 
-fn nop_instr<'src>() -> impl Parser<'src, &'src str, Instruction, extra::Err<Rich<'src, char>>> {
+// Names like `b3` would make `and b0 b3` ambiguous, so they are reserved for belt positions.
+fn label<'src>() -> impl Parser<'src, &'src str, Label, extra::Err<Rich<'src, char>>> {
+    any()
+        .filter(|c: &char| c.is_ascii_alphabetic() || *c == '_')
+        .then(
+            any()
+                .filter(|c: &char| c.is_ascii_alphanumeric() || *c == '_')
+                .repeated(),
+        )
+        .to_slice()
+        .try_map(|name: &str, span| {
+            let is_belt_pos = name.len() > 1
+                && name.starts_with('b')
+                && name[1..].chars().all(|c| c.is_ascii_digit());
+            if is_belt_pos {
+                return Err(Rich::custom(
+                    span,
+                    format!(
+                        "`{}` is a belt position and cannot be used as a label",
+                        name
+                    ),
+                ));
+            }
+            Ok(Label {
+                name: name.to_string(),
+                span,
+            })
+        })
+        .labelled("label")
+}
+
+fn constant<'src>() -> impl Parser<'src, &'src str, Constant, extra::Err<Rich<'src, char>>> {
+    choice((
+        integer::<u16>(16.try_into().unwrap(), false).map(Constant::Value),
+        label().map(Constant::Label),
+    ))
+    .labelled("constant")
+}
+
+fn nop_instr<'src>(
+) -> impl Parser<'src, &'src str, (Instruction, Vec<SimpleSpan>), extra::Err<Rich<'src, char>>> {
     choice([
         just("nop").to(ZeroOp::Nop),
         just("pop").to(ZeroOp::Pop),
         just("break").to(ZeroOp::Break),
     ])
     .labelled("instruction")
-    .map(|op| Instruction::Zero { op })
+    .map(|op| (Instruction::Zero { op }, vec![]))
 }
 
-fn constant_instr<'src>() -> impl Parser<'src, &'src str, Instruction, extra::Err<Rich<'src, char>>>
-{
+fn constant_instr<'src>(
+) -> impl Parser<'src, &'src str, (Symbol, Vec<SimpleSpan>), extra::Err<Rich<'src, char>>> {
     choice([
         just("and").to(ConstantOp::And),
         just("or").to(ConstantOp::Or),
         just("xor").to(ConstantOp::Xor),
+        just("jump").to(ConstantOp::Jump),
     ])
     .labelled("instruction")
-    .then_ignore(inline_whitespace().at_least(1))
-    .then(belt_pos().labelled("src"))
-    .padded()
-    .then(integer::<u16>(16.try_into().unwrap(), false).labelled("constant"))
-    .map(|((operation, belt_pos), constant)| Instruction::Constant {
-        op: operation,
-        pos: belt_pos,
-        constant,
-    })
+    .then_ignore(required_whitespace())
+    .then(spanned(belt_pos().labelled("src")))
+    .then_ignore(required_whitespace())
+    .then(spanned(constant()))
+    .map(
+        |((operation, (belt_pos, pos_span)), (constant, constant_span))| {
+            let symbol = constant.into_symbol(|constant| Instruction::Constant {
+                op: operation,
+                pos: belt_pos,
+                constant,
+            });
+            (symbol, vec![pos_span, constant_span])
+        },
+    )
 }
 
 fn load_constant_instr<'src>(
-) -> impl Parser<'src, &'src str, Instruction, extra::Err<Rich<'src, char>>> {
+) -> impl Parser<'src, &'src str, (Symbol, Vec<SimpleSpan>), extra::Err<Rich<'src, char>>> {
     just("lc")
         .labelled("instruction")
-        .ignore_then(inline_whitespace().at_least(1))
-        .ignore_then(integer::<u16>(16.try_into().unwrap(), false).labelled("constant"))
-        .padded()
-        .map(|constant| Instruction::LoadConstant { constant })
+        .ignore_then(required_whitespace())
+        .ignore_then(spanned(constant()))
+        .map(|(constant, span)| {
+            let symbol = constant.into_symbol(|constant| Instruction::LoadConstant { constant });
+            (symbol, vec![span])
+        })
 }
 
-fn imm_instr<'src>() -> impl Parser<'src, &'src str, Instruction, extra::Err<Rich<'src, char>>> {
+fn imm_instr<'src>(
+) -> impl Parser<'src, &'src str, (Instruction, Vec<SimpleSpan>), extra::Err<Rich<'src, char>>> {
     choice((
         just("sl").to(ImmediateOp::Left),
         just("sr").to(ImmediateOp::Right),
+        just("call").to(ImmediateOp::Call),
+        just("ret").to(ImmediateOp::Ret),
     ))
     .labelled("instruction")
-    .then_ignore(inline_whitespace().at_least(1))
-    .then(belt_pos().labelled("src"))
-    .then_ignore(inline_whitespace().at_least(1))
-    .then(integer::<u8>(4.try_into().unwrap(), false).labelled("constant"))
-    .padded()
-    .map(|((op, belt_pos), imm)| Instruction::Immediate {
-        op,
-        pos: belt_pos,
-        imm: imm,
+    .then_ignore(required_whitespace())
+    .then(spanned(belt_pos().labelled("src")))
+    .then_ignore(required_whitespace())
+    .then(spanned(
+        integer::<u8>(4.try_into().unwrap(), false).labelled("constant"),
+    ))
+    .map(|((op, (belt_pos, pos_span)), (imm, imm_span))| {
+        let instruction = Instruction::Immediate {
+            op,
+            pos: belt_pos,
+            imm: imm,
+        };
+        (instruction, vec![pos_span, imm_span])
     })
 }
 
-fn reg_instr<'src>() -> impl Parser<'src, &'src str, Instruction, extra::Err<Rich<'src, char>>> {
+fn reg_instr<'src>(
+) -> impl Parser<'src, &'src str, (Instruction, Vec<SimpleSpan>), extra::Err<Rich<'src, char>>> {
     choice((
         just("add").to(RegOp::Add),
         just("sub").to(RegOp::Sub),
@@ -81,77 +175,174 @@ fn reg_instr<'src>() -> impl Parser<'src, &'src str, Instruction, extra::Err<Ric
         just("save").to(RegOp::Save),
         just("sr").to(RegOp::ShiftRight),
         just("sl").to(RegOp::ShiftLeft),
+        just("blt").to(RegOp::BranchLower),
+        just("ble").to(RegOp::BranchLowerEq),
+        just("beq").to(RegOp::BranchEq),
     ))
     .labelled("instruction")
-    .then_ignore(inline_whitespace().at_least(1))
-    .then(belt_pos())
-    .then_ignore(inline_whitespace().at_least(1))
-    .then(belt_pos())
-    .padded()
-    .map(|((op, pos1), pos2)| Instruction::Register { op, pos1, pos2 })
+    .then_ignore(required_whitespace())
+    .then(spanned(belt_pos()))
+    .then_ignore(required_whitespace())
+    .then(spanned(belt_pos()))
+    .map(|((op, (pos1, span1)), (pos2, span2))| {
+        (Instruction::Register { op, pos1, pos2 }, vec![span1, span2])
+    })
 }
 
-fn unary_instr<'src>() -> impl Parser<'src, &'src str, Instruction, extra::Err<Rich<'src, char>>> {
-    just("load")
-        .labelled("instruction")
-        .ignore_then(inline_whitespace().at_least(1))
-        .ignore_then(belt_pos().labelled("address"))
-        .padded()
-        .map(|src| Instruction::Unary {
-            op: UnaryOp::Load,
-            pos: src,
-        })
+fn unary_instr<'src>(
+) -> impl Parser<'src, &'src str, (Instruction, Vec<SimpleSpan>), extra::Err<Rich<'src, char>>> {
+    choice((
+        just("load").to(UnaryOp::Load),
+        just("jump").to(UnaryOp::Jump),
+        just("push").to(UnaryOp::Push),
+    ))
+    .labelled("instruction")
+    .then_ignore(required_whitespace())
+    .then(spanned(belt_pos().labelled("src")))
+    .map(|(op, (pos, span))| (Instruction::Unary { op, pos }, vec![span]))
+}
+
+// Spellings mixing up the operand shapes of two instructions, they are reported with a hint
+// instead of a generic error about the unexpected operand.
+fn ambiguous_instr<'src>(
+) -> impl Parser<'src, &'src str, (Instruction, Vec<SimpleSpan>), extra::Err<Rich<'src, char>>> {
+    let branch = choice((
+        just("blt").to(RegOp::BranchLower),
+        just("ble").to(RegOp::BranchLowerEq),
+        just("beq").to(RegOp::BranchEq),
+    ))
+    .then_ignore(required_whitespace())
+    .then(spanned(belt_pos()))
+    .then_ignore(required_whitespace())
+    .then(spanned(constant()))
+    .validate(|((op, (pos1, span1)), (target, span2)), e, emitter| {
+        emitter.emit(Rich::custom(
+            e.span(),
+            format!(
+                "`{}` takes its target from the belt, load {} with `lc` first",
+                op.mnemonic(),
+                target.describe()
+            ),
+        ));
+        let instruction = Instruction::Register {
+            op,
+            pos1,
+            pos2: BeltPos(0),
+        };
+        (instruction, vec![span1, span2])
+    });
+
+    let jump = just("jump")
+        .ignore_then(required_whitespace())
+        .ignore_then(spanned(belt_pos()))
+        .then_ignore(required_whitespace())
+        .then(spanned(belt_pos()))
+        .validate(|((pos, span1), (target, span2)), e, emitter| {
+            emitter.emit(Rich::custom(
+                e.span(),
+                format!(
+                    "`jump {pos} {target}` is ambiguous, use `jump {target}` to jump to the address \
+                     in {target} or `jump {pos} <constant>` to jump when {pos} is not zero",
+                ),
+            ));
+            let instruction = Instruction::Unary {
+                op: UnaryOp::Jump,
+                pos: target,
+            };
+            (instruction, vec![span1, span2])
+        });
+
+    choice((branch, jump))
 }
 
-fn instruction_parser<'src>() -> impl Parser<'src, &'src str, Symbol, extra::Err<Rich<'src, char>>>
-{
+fn instruction_parser<'src>(
+) -> impl Parser<'src, &'src str, Spanned<Symbol>, extra::Err<Rich<'src, char>>> {
+    let instruction = |(instruction, operands)| (Symbol::Instruction(instruction), operands);
     choice((
+        ambiguous_instr().map(instruction),
         constant_instr(),
         load_constant_instr(),
-        imm_instr(),
-        unary_instr(),
-        reg_instr(),
-        nop_instr(),
+        imm_instr().map(instruction),
+        unary_instr().map(instruction),
+        reg_instr().map(instruction),
+        nop_instr().map(instruction),
     ))
-    .map(Symbol::from)
+    .map_with(|(node, operands), e| Spanned {
+        node,
+        span: e.span(),
+        operands,
+    })
+}
+
+fn label_definition<'src>(
+) -> impl Parser<'src, &'src str, Spanned<Symbol>, extra::Err<Rich<'src, char>>> {
+    // A missing colon fails at the start of the word, so that a misspelled mnemonic is not
+    // reported as a label without its colon.
+    label()
+        .then(just(':').or_not())
+        .try_map(|(label, colon), span| match colon {
+            Some(_) => Ok(Spanned::new(Symbol::Label(label), span)),
+            None => Err(Rich::custom(span, "expected `:` after label")),
+        })
 }
 
-fn comment<'src>() -> impl Parser<'src, &'src str, Symbol, extra::Err<Rich<'src, char>>> {
+fn comment<'src>() -> impl Parser<'src, &'src str, Spanned<Symbol>, extra::Err<Rich<'src, char>>> {
     just("#")
-        .then(any().and_is(newline()).not())
-        .repeated()
-        .padded_by(inline_whitespace())
-        .to(Symbol::Comment)
+        .ignore_then(any().and_is(newline().not()).repeated().to_slice())
+        .map_with(|text: &str, e| Spanned::new(Symbol::Comment(text.to_string()), e.span()))
 }
 
-pub fn parse_belt<'src>(assembly: &'src str) -> Result<Program, Vec<Rich<'src, char>>> {
-    let parser = choice((instruction_parser(), comment()))
+/// Parses as much of `assembly` as possible, a line which does not parse is left out of the
+/// program and its error is reported alongside the errors of all other lines.
+pub fn parse_belt_partial<'src>(assembly: &'src str) -> (Program, Vec<Rich<'src, char>>) {
+    // An empty statement is one of the alternatives instead of using `.or_not()`, which would
+    // swallow the error of a statement failing halfway through.
+    let statement = choice((
+        instruction_parser().map(Some),
+        comment().map(Some),
+        end_of_line().to(None),
+    ));
+    let line = label_definition()
+        .then_ignore(inline_whitespace())
         .or_not()
+        .then(statement)
         .padded_by(inline_whitespace())
+        .then_ignore(end_of_line())
+        .map(|(label, symbol)| {
+            label
+                .into_iter()
+                .chain(symbol)
+                .collect::<Vec<Spanned<Symbol>>>()
+        })
+        .recover_with(via_parser(skip_line().to(vec![])));
+
+    let parser = line
         .separated_by(newline())
         .allow_leading()
         .allow_trailing()
         // TODO: Once .flatten() is implemented for ItemParser, use that.
-        .collect::<Vec<Option<Symbol>>>();
+        .collect::<Vec<Vec<Spanned<Symbol>>>>();
 
-    let symbols = parser
-        .parse(assembly)
-        .into_result()?
-        .into_iter()
-        .flatten()
-        .collect();
+    let (lines, errors) = parser.parse(assembly).into_output_errors();
+    let symbols = lines.into_iter().flatten().flatten().collect();
+    (Program { symbols }, errors)
+}
 
-    Ok(Program { symbols })
+pub fn parse_belt<'src>(assembly: &'src str) -> Result<Program, Vec<Rich<'src, char>>> {
+    match parse_belt_partial(assembly) {
+        (program, errors) if errors.is_empty() => Ok(program),
+        (_, errors) => Err(errors),
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use chumsky::Parser;
+    use chumsky::{span::SimpleSpan, Parser};
     use rstest::rstest;
 
     use crate::belt::{
         ast::{BeltPos, ConstantOp, ImmediateOp, Instruction, RegOp, Symbol, UnaryOp, ZeroOp},
-        parser::instruction_parser,
+        parser::{instruction_parser, parse_belt, parse_belt_partial},
     };
 
     #[rstest]
@@ -172,6 +363,15 @@ mod test {
     #[case("sr b14 b0", Instruction::Register { op: RegOp::ShiftRight, pos1: BeltPos(14), pos2: BeltPos(0) })]
     #[case("sl b15 b0", Instruction::Register { op: RegOp::ShiftLeft, pos1: BeltPos(15), pos2: BeltPos(0) })]
     #[case("load b3", Instruction::Unary { op: UnaryOp::Load, pos: BeltPos(3) })]
+    #[case("jump b2 0x0010", Instruction::Constant { op: ConstantOp::Jump, pos: BeltPos(2), constant: 0x10 })]
+    #[case("jump b2 0", Instruction::Constant { op: ConstantOp::Jump, pos: BeltPos(2), constant: 0 })]
+    #[case("jump b4", Instruction::Unary { op: UnaryOp::Jump, pos: BeltPos(4) })]
+    #[case("push b5", Instruction::Unary { op: UnaryOp::Push, pos: BeltPos(5) })]
+    #[case("call b0 2", Instruction::Immediate { op: ImmediateOp::Call, pos: BeltPos(0), imm: 2 })]
+    #[case("ret b1 15", Instruction::Immediate { op: ImmediateOp::Ret, pos: BeltPos(1), imm: 15 })]
+    #[case("blt b1 b2", Instruction::Register { op: RegOp::BranchLower, pos1: BeltPos(1), pos2: BeltPos(2) })]
+    #[case("ble b3 b4", Instruction::Register { op: RegOp::BranchLowerEq, pos1: BeltPos(3), pos2: BeltPos(4) })]
+    #[case("beq b5 b15", Instruction::Register { op: RegOp::BranchEq, pos1: BeltPos(5), pos2: BeltPos(15) })]
     #[case("nop", Instruction::Zero { op: ZeroOp::Nop })]
     #[case("pop", Instruction::Zero { op: ZeroOp::Pop })]
     #[case("break", Instruction::Zero { op: ZeroOp::Break })]
@@ -183,7 +383,7 @@ mod test {
             }
             panic!("Errors found.");
         }
-        let result_symbol = result.unwrap();
+        let result_symbol = result.unwrap().node;
         match result_symbol {
             Symbol::Instruction(instruction) => assert_eq!(instruction, expected),
             _ => panic!("{:?} is not an instruction!", result_symbol),
@@ -200,18 +400,88 @@ mod test {
     #[case("add b5 b6a")]
     #[case("sub b7 b8a")]
     #[case("mul b9 b10a")]
-    #[case("and b5 b6a")]
-    #[case("or b7 b8a")]
-    #[case("xor b9 b10a")]
+    #[case("and b5 b16")]
+    #[case("or b7 b8 b9")]
+    #[case("xor b9 0x1g")]
     #[case("div b11 b12a")]
     #[case("save b13 b0a")]
     #[case("sr b14 b0a")]
     #[case("sl b15 b0a")]
     #[case("load b3a")]
+    #[case("jump b16")]
+    #[case("jump b2 0x10000")]
+    #[case("push b1 b2")]
+    #[case("push 5")]
+    #[case("call b0")]
+    #[case("call b0 16")]
+    #[case("ret b0 b1")]
+    #[case("blt b1")]
+    #[case("beq b1 b2 b3")]
     pub fn test_instruction_fail(#[case] input: &str) {
         let result = instruction_parser().parse(input);
         if !result.has_errors() {
             panic!("No errors found for malformed instruction.");
         }
     }
+
+    #[rstest]
+    #[case(
+        "blt b1 0x10",
+        "`blt` takes its target from the belt, load 0x0010 with `lc` first"
+    )]
+    #[case(
+        "beq b0 4",
+        "`beq` takes its target from the belt, load 0x0004 with `lc` first"
+    )]
+    #[case(
+        "jump b1 b2",
+        "`jump b1 b2` is ambiguous, use `jump b2` to jump to the address in b2 \
+                          or `jump b1 <constant>` to jump when b1 is not zero"
+    )]
+    pub fn test_ambiguous_instruction(#[case] input: &str, #[case] message: &str) {
+        let errors = parse_belt(input).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), message);
+    }
+
+    #[test]
+    pub fn test_parse_belt_multiple_lines() {
+        let program = parse_belt("lc 1\n  # loads two\nlc 2\n\nadd b0 b1\n").unwrap();
+        assert_eq!(program.symbols.len(), 4);
+        assert!(matches!(&program.symbols[1].node, Symbol::Comment(text) if text == " loads two"));
+    }
+
+    #[test]
+    pub fn test_recovers_after_bad_lines() {
+        let source = "lc 1\nadd b0 q\nloop: nop\nlcc 5\n  # fine\nsl b1 b2 b3\npop";
+        let (program, errors) = parse_belt_partial(source);
+        let lines: Vec<_> = errors
+            .iter()
+            .map(|error| source[..error.span().start].lines().count())
+            .collect();
+        assert_eq!(lines, [2, 4, 6]);
+        assert_eq!(&source[errors[0].span().into_range()], "q");
+        assert_eq!(program.symbols.len(), 5);
+        assert!(matches!(
+            program.symbols[4].node,
+            Symbol::Instruction(Instruction::Zero { op: ZeroOp::Pop })
+        ));
+        assert_eq!(parse_belt(source).unwrap_err(), errors);
+    }
+
+    #[test]
+    pub fn test_spans() {
+        let source = "  start: jump b1 start\n  # loop\nlc 0x10";
+        let program = parse_belt(source).unwrap();
+        let text = |span: SimpleSpan| &source[span.into_range()];
+        let spans: Vec<_> = program.symbols.iter().map(|s| text(s.span)).collect();
+        assert_eq!(spans, ["start:", "jump b1 start", "# loop", "lc 0x10"]);
+        let operands: Vec<_> = program.symbols[1]
+            .operands
+            .iter()
+            .map(|&s| text(s))
+            .collect();
+        assert_eq!(operands, ["b1", "start"]);
+        assert_eq!(text(program.symbols[3].operands[0]), "0x10");
+    }
 }
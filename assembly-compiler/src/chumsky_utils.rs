@@ -1,5 +1,5 @@
 use chumsky::prelude::*;
-use chumsky::text::digits;
+use chumsky::text::{digits, newline};
 use num_traits::{Num, PrimInt, WrappingAdd};
 use std::fmt::{Debug, Display};
 use std::num::NonZeroU32;
@@ -127,11 +127,14 @@ where
 
 // .count_ones() on signed integer returns 1 less than the number
 // of bits and we need one extra bit for the sign as well, hence two. Yes, it sounds weird.
+// Unsigned numbers take all the bits of the type up to the leading zeros, signed type or not.
 fn needed_bits_for_number<T: PrimInt>(num: T, signed: bool) -> u32 {
     if num < T::zero() {
         T::max_value().count_ones() + if signed { 2 } else { 0 } - num.leading_ones()
+    } else if signed {
+        T::max_value().count_ones() + 2 - num.leading_zeros()
     } else {
-        T::max_value().count_ones() + if signed { 2 } else { 0 } - num.leading_zeros()
+        T::zero().count_zeros() - num.leading_zeros()
     }
 }
 
@@ -175,6 +178,42 @@ where
     })
 }
 
+/// Pairs the output of `parser` with the span it was parsed from.
+pub fn spanned<'src, O>(
+    parser: impl Parser<'src, &'src str, O, extra::Err<Rich<'src, char>>>,
+) -> impl Parser<'src, &'src str, (O, SimpleSpan), extra::Err<Rich<'src, char>>> {
+    parser.map_with(|output, e| (output, e.span()))
+}
+
+// A space or a tab.
+fn blank<'src>() -> impl Parser<'src, &'src str, (), extra::Err<Rich<'src, char>>> + Clone {
+    one_of(" \t").labelled("inline whitespace").ignored()
+}
+
+/// Spaces and tabs. Unlike `chumsky::text::inline_whitespace` it leaves the errors of earlier
+/// alternatives alone, a line which fails halfway through is reported where it went wrong
+/// instead of at its start.
+pub fn inline_whitespace<'src>(
+) -> impl Parser<'src, &'src str, (), extra::Err<Rich<'src, char>>> + Clone {
+    blank().repeated()
+}
+
+/// At least one space or tab, such as between a mnemonic and its operands.
+pub fn required_whitespace<'src>(
+) -> impl Parser<'src, &'src str, (), extra::Err<Rich<'src, char>>> + Clone {
+    blank().repeated().at_least(1)
+}
+
+/// Succeeds without consuming anything at the end of a line or of the input.
+pub fn end_of_line<'src>() -> impl Parser<'src, &'src str, (), extra::Err<Rich<'src, char>>> {
+    choice((newline(), end())).rewind()
+}
+
+/// Consumes the rest of the line, to carry on with the next one after an error.
+pub fn skip_line<'src>() -> impl Parser<'src, &'src str, (), extra::Err<Rich<'src, char>>> {
+    any().and_is(newline().not()).repeated()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
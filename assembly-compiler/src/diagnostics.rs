@@ -0,0 +1,456 @@
+//!
+//! Rendering of parser and assembler errors as annotated source snippets.
+//!
+//! ```text
+//! error: unknown instruction `adii`
+//!  --> 2:1
+//!   |
+//! 2 | adii x1, x2, 5
+//!   | ^^^^
+//!   |
+//!   = help: did you mean `addi`?
+//! ```
+//!
+
+use std::fmt::Write;
+
+use chumsky::error::{Rich, RichPattern, RichReason};
+use chumsky::span::SimpleSpan;
+
+use crate::span::{LineCol, LineIndex};
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Style {
+    Plain,
+    Ansi,
+}
+
+/// Words an ISA's parser accepts, misspellings of them get a "did you mean" hint.
+#[derive(Copy, Clone, Debug)]
+pub struct Vocabulary {
+    pub mnemonics: &'static [&'static str],
+    pub registers: &'static [&'static str],
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: SimpleSpan,
+    /// Shown next to the underlined span.
+    pub label: Option<String>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+/// A diagnostic with its location resolved to lines and columns, for the web UI.
+#[derive(PartialEq, Clone, Debug)]
+pub struct StructuredDiagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub start: LineCol,
+    pub end: LineCol,
+    pub label: Option<String>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+// Expectations every line of assembly has, listing them only adds noise.
+const IMPLICIT_EXPECTATIONS: [&str; 4] =
+    ["inline whitespace", "whitespace", "newline", "end of input"];
+
+fn describe_char(c: char) -> String {
+    match c {
+        '\n' | '\r' => "end of line".to_string(),
+        _ => format!("`{}`", c),
+    }
+}
+
+fn join_alternatives(items: &[String]) -> String {
+    match items {
+        [] => String::new(),
+        [item] => item.clone(),
+        [init @ .., last] => format!("{} or {}", init.join(", "), last),
+    }
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + (ca != cb) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Closest candidate to `word`, as long as it is close enough to be a typo. Words of two
+/// letters are too short to tell a typo apart from a different word.
+pub fn suggest<'a>(word: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    if word.is_empty() || candidates.contains(&word) {
+        return None;
+    }
+    let word = word.to_ascii_lowercase();
+    candidates
+        .iter()
+        .map(|candidate| (edit_distance(&word, candidate), *candidate))
+        .filter(|&(distance, _)| distance <= 2 && distance * 3 <= word.len())
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+// The word around `offset`, where it starts and whether it is the first word of its statement.
+fn word_at(source: &str, offset: usize) -> Option<(&str, usize, bool)> {
+    let offset = offset.min(source.len());
+    let start = source[..offset]
+        .rfind(|c: char| !is_word_char(c))
+        .map_or(0, |i| i + 1);
+    let end = source[offset..]
+        .find(|c: char| !is_word_char(c))
+        .map_or(source.len(), |i| offset + i);
+    if start == end {
+        return None;
+    }
+    let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+    let before = &source[line_start..start];
+    let before = before.rsplit_once(':').map_or(before, |(_, rest)| rest);
+    Some((&source[start..end], start, before.trim().is_empty()))
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>, span: SimpleSpan) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            message: message.into(),
+            span,
+            label: None,
+            notes: vec![],
+            help: None,
+        }
+    }
+
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    /// Converts a parser error, looking up misspelled words of `source` in `vocabulary`.
+    pub fn from_rich(error: &Rich<'_, char>, source: &str, vocabulary: &Vocabulary) -> Self {
+        let span = *error.span();
+        let word = word_at(source, span.start);
+        let mut diagnostic = match (error.reason(), word) {
+            (RichReason::Custom(message), _) => Diagnostic::error(message.clone(), span),
+            // However far the parser got into an unknown mnemonic, the whole word is at fault.
+            (_, Some((word, start, true)))
+                if !vocabulary.mnemonics.is_empty()
+                    && !vocabulary
+                        .mnemonics
+                        .contains(&word.to_ascii_lowercase().as_str()) =>
+            {
+                let span = SimpleSpan::from(start..start + word.len());
+                Diagnostic::error(format!("unknown instruction `{}`", word), span)
+            }
+            _ => {
+                let found = match (word, error.found()) {
+                    (Some((word, start, _)), _) if start == span.start => format!("`{}`", word),
+                    (_, Some(&c)) => describe_char(c),
+                    (_, None) => "end of input".to_string(),
+                };
+                let mut expected: Vec<String> = vec![];
+                for pattern in error.expected() {
+                    let pattern = match pattern {
+                        RichPattern::Token(token) => describe_char(**token),
+                        other => other.to_string(),
+                    };
+                    if !IMPLICIT_EXPECTATIONS.contains(&pattern.as_str())
+                        && !expected.contains(&pattern)
+                    {
+                        expected.push(pattern);
+                    }
+                }
+                let diagnostic = Diagnostic::error(format!("unexpected {}", found), span);
+                if expected.is_empty() {
+                    diagnostic
+                } else {
+                    diagnostic.with_label(format!("expected {}", join_alternatives(&expected)))
+                }
+            }
+        };
+        for (context, _) in error.contexts() {
+            diagnostic = diagnostic.with_note(format!("while parsing {}", context));
+        }
+        if let Some((word, _, is_mnemonic)) = word {
+            let candidates = if is_mnemonic {
+                vocabulary.mnemonics
+            } else {
+                vocabulary.registers
+            };
+            if let Some(suggestion) = suggest(word, candidates) {
+                diagnostic = diagnostic.with_help(format!("did you mean `{}`?", suggestion));
+            }
+        }
+        diagnostic
+    }
+
+    pub fn structured(&self, index: &LineIndex) -> StructuredDiagnostic {
+        let (start, end) = index.range(self.span);
+        StructuredDiagnostic {
+            severity: self.severity,
+            message: self.message.clone(),
+            start,
+            end,
+            label: self.label.clone(),
+            notes: self.notes.clone(),
+            help: self.help.clone(),
+        }
+    }
+
+    pub fn render(&self, source: &str, style: Style) -> String {
+        let paint = |code: &str, text: &str| match style {
+            Style::Plain => text.to_string(),
+            Style::Ansi => format!("\x1b[{}m{}\x1b[0m", code, text),
+        };
+        let severity_colour = match self.severity {
+            Severity::Error => "1;31",
+            Severity::Warning => "1;33",
+        };
+
+        let index = LineIndex::new(source);
+        let (start, end) = index.range(self.span);
+        let text = index.line(start.line).unwrap_or("");
+        let line_number = start.line.to_string();
+        let gutter = " ".repeat(line_number.len());
+        let bar = paint("1;34", "|");
+
+        // Spans reaching over several lines are underlined up to the end of the first one.
+        let width = if end.line == start.line {
+            end.column.saturating_sub(start.column)
+        } else {
+            (text.chars().count() + 1).saturating_sub(start.column)
+        };
+        let mut underline = "^".repeat(width.max(1));
+        if let Some(label) = &self.label {
+            underline = format!("{} {}", underline, label);
+        }
+
+        let mut output = String::new();
+        writeln!(
+            output,
+            "{}{}",
+            paint(severity_colour, self.severity.name()),
+            paint("1", &format!(": {}", self.message))
+        )
+        .unwrap();
+        writeln!(
+            output,
+            "{}{} {}:{}",
+            gutter,
+            paint("1;34", "-->"),
+            start.line,
+            start.column
+        )
+        .unwrap();
+        writeln!(output, "{} {}", gutter, bar).unwrap();
+        writeln!(output, "{} {} {}", paint("1;34", &line_number), bar, text).unwrap();
+        writeln!(
+            output,
+            "{} {} {}{}",
+            gutter,
+            bar,
+            " ".repeat(start.column - 1),
+            paint(severity_colour, &underline)
+        )
+        .unwrap();
+        if !self.notes.is_empty() || self.help.is_some() {
+            writeln!(output, "{} {}", gutter, bar).unwrap();
+        }
+        for note in &self.notes {
+            writeln!(output, "{} {} note: {}", gutter, paint("1;34", "="), note).unwrap();
+        }
+        if let Some(help) = &self.help {
+            writeln!(output, "{} {} help: {}", gutter, paint("1;34", "="), help).unwrap();
+        }
+        output
+    }
+}
+
+pub fn from_rich_errors(
+    errors: &[Rich<'_, char>],
+    source: &str,
+    vocabulary: &Vocabulary,
+) -> Vec<Diagnostic> {
+    errors
+        .iter()
+        .map(|error| Diagnostic::from_rich(error, source, vocabulary))
+        .collect()
+}
+
+/// Renders every diagnostic, separated by an empty line.
+pub fn render_all(diagnostics: &[Diagnostic], source: &str, style: Style) -> String {
+    diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.render(source, style))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::belt::assembler::assemble_belt;
+    use crate::belt::parser::{parse_belt, VOCABULARY as BELT};
+    use crate::riscv::parser::{parse_riscv, VOCABULARY as RISCV};
+    use rstest::rstest;
+
+    fn diagnose(source: &str) -> Diagnostic {
+        let errors = parse_riscv(source).unwrap_err();
+        Diagnostic::from_rich(&errors[0], source, &RISCV)
+    }
+
+    #[rstest]
+    #[case("addi", &["addi", "add"], None)]
+    #[case("adi", &["addi", "add"], Some("addi"))]
+    #[case("ADDI", &["addi"], Some("addi"))]
+    #[case("xyz", &["addi", "add"], None)]
+    #[case("a", &["ra"], None)]
+    #[case("q2", &["t2"], None)]
+    fn test_suggest(
+        #[case] word: &str,
+        #[case] candidates: &[&str],
+        #[case] expected: Option<&str>,
+    ) {
+        assert_eq!(suggest(word, candidates), expected);
+    }
+
+    #[test]
+    fn test_render_expected_operand() {
+        let diagnostic = diagnose("addi x1, q2, 5");
+        assert_eq!(diagnostic.message, "unexpected `q2`");
+        assert_eq!(
+            diagnostic.render("addi x1, q2, 5", Style::Plain),
+            "error: unexpected `q2`\n \
+             --> 1:10\n  \
+             |\n\
+             1 | addi x1, q2, 5\n  \
+             |          ^ expected rs1\n"
+        );
+    }
+
+    #[test]
+    fn test_misspelled_mnemonic() {
+        let source = "add x1, x2, x3\nadii x1, x2, 5";
+        let diagnostic = diagnose(source);
+        assert_eq!(diagnostic.message, "unknown instruction `adii`");
+        assert_eq!(diagnostic.help.as_deref(), Some("did you mean `addi`?"));
+        let rendered = diagnostic.render(source, Style::Plain);
+        assert!(rendered.contains("2 | adii x1, x2, 5\n"), "{}", rendered);
+        assert!(
+            rendered.ends_with("  = help: did you mean `addi`?\n"),
+            "{}",
+            rendered
+        );
+    }
+
+    #[test]
+    fn test_misspelled_register() {
+        let diagnostic = diagnose("add x1, sp, zer0");
+        assert_eq!(diagnostic.help.as_deref(), Some("did you mean `zero`?"));
+    }
+
+    #[test]
+    fn test_custom_message() {
+        let source = "jump b1 b2";
+        let errors = parse_belt(source).unwrap_err();
+        let diagnostic = Diagnostic::from_rich(&errors[0], source, &BELT);
+        assert!(diagnostic.message.starts_with("`jump b1 b2` is ambiguous"));
+        assert_eq!(diagnostic.label, None);
+        assert_eq!(
+            diagnostic.render(source, Style::Plain).lines().nth(4),
+            Some("  | ^^^^^^^^^^")
+        );
+    }
+
+    #[test]
+    fn test_structured() {
+        let source = "nop\nlc nowhere";
+        let errors = assemble_belt(source).unwrap_err();
+        let diagnostic = Diagnostic::from_rich(&errors[0], source, &BELT);
+        let structured = diagnostic.structured(&LineIndex::new(source));
+        assert_eq!(structured.start, LineCol { line: 2, column: 4 });
+        assert_eq!(
+            structured.end,
+            LineCol {
+                line: 2,
+                column: 11
+            }
+        );
+        assert_eq!(structured.message, diagnostic.message);
+    }
+
+    #[test]
+    fn test_render_ansi() {
+        let diagnostic = Diagnostic::error("bad", SimpleSpan::from(0..3));
+        let rendered = diagnostic.render("nop", Style::Ansi);
+        assert!(rendered.starts_with("\x1b[1;31merror\x1b[0m\x1b[1m: bad\x1b[0m\n"));
+        assert!(rendered.contains("\x1b[1;31m^^^\x1b[0m"));
+    }
+
+    #[test]
+    fn test_render_all() {
+        let diagnostics = [
+            Diagnostic::error("first", SimpleSpan::from(0..1)),
+            Diagnostic::error("second", SimpleSpan::from(4..5)).with_note("some note"),
+        ];
+        let rendered = render_all(&diagnostics, "nop\npop", Style::Plain);
+        assert_eq!(
+            rendered,
+            "error: first\n \
+             --> 1:1\n  \
+             |\n\
+             1 | nop\n  \
+             | ^\n\
+             \n\
+             error: second\n \
+             --> 2:1\n  \
+             |\n\
+             2 | pop\n  \
+             | ^\n  \
+             |\n  \
+             = note: some note\n"
+        );
+    }
+}
@@ -1,3 +1,6 @@
 pub mod riscv;
 pub mod belt;
 mod chumsky_utils;
+pub mod diagnostics;
+pub mod listing;
+pub mod span;
@@ -0,0 +1,191 @@
+//!
+//! Assembler listings, every line of the source next to the addresses and words it assembled
+//! to, followed by a cross reference of the symbols of the program.
+//!
+//! ```text
+//! Address   Words      Line  Source
+//!                         1  # Counts down from ten.
+//! 00000000  00A00513      2  _start: addi a0, zero, 10
+//! 00000004  FFF50513      3  loop: addi a0, a0, -1
+//! 00000008                4      bnez a0, loop
+//! 00000008  FE051EE3             bne a0, zero, -4
+//!
+//! Symbols
+//!
+//! Name              Value      Line  References
+//! _start            00000000      2
+//! loop              00000004      3  4
+//! ```
+//!
+//! The ISAs build their listings with `riscv::listing` and `belt::listing`.
+//!
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use chumsky::span::SimpleSpan;
+
+use crate::span::LineIndex;
+
+/// A row of the listing, the first one of a line shows the line.
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct ListingRow {
+    pub address: Option<u32>,
+    /// Words or bytes in hexadecimal.
+    pub raw: String,
+    /// Number of the source line, only on the first row of every line.
+    pub line: Option<usize>,
+    /// The source line, or what the row stands for on the rows after it.
+    pub text: String,
+}
+
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct CrossReference {
+    /// Value of the symbol, none for one the program refers to without defining it.
+    pub value: Option<u32>,
+    /// Line defining the symbol.
+    pub definition: Option<usize>,
+    /// Lines referring to the symbol, in ascending order.
+    pub references: Vec<usize>,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Listing {
+    /// Hexadecimal digits of an address.
+    pub address_digits: usize,
+    pub rows: Vec<ListingRow>,
+    pub symbols: BTreeMap<String, CrossReference>,
+}
+
+impl Listing {
+    ///
+    /// Lists every line of `source` with the rows of the statements starting on it.
+    ///
+    /// `statements` are the rows every statement assembled to by its span, the first row of a
+    /// line shows the line in place of its text. `definitions` and `references` are the names
+    /// of symbols with where they appear in the source.
+    ///
+    pub fn new(
+        source: &str,
+        address_digits: usize,
+        mut statements: Vec<(SimpleSpan, Vec<ListingRow>)>,
+        definitions: Vec<(String, Option<u32>, SimpleSpan)>,
+        references: Vec<(String, SimpleSpan)>,
+    ) -> Listing {
+        let index = LineIndex::new(source);
+        statements.sort_by_key(|(span, _)| span.start);
+        let mut statements = statements.into_iter().peekable();
+        let mut rows = vec![];
+        let mut line = 1;
+        while let Some(text) = index.line(line) {
+            // The line break at the end of the source does not start another line.
+            if text.is_empty() && index.line(line + 1).is_none() && line > 1 {
+                break;
+            }
+            let start = rows.len();
+            while let Some((span, _)) = statements.peek() {
+                if index.line_col(span.start).line != line {
+                    break;
+                }
+                rows.extend(statements.next().unwrap().1);
+            }
+            if rows.len() == start {
+                rows.push(ListingRow::default());
+            }
+            rows[start].line = Some(line);
+            rows[start].text = text.to_string();
+            line += 1;
+        }
+
+        let mut symbols: BTreeMap<String, CrossReference> = BTreeMap::new();
+        for (name, value, span) in definitions {
+            let symbol = symbols.entry(name).or_default();
+            symbol.value = value;
+            symbol.definition = Some(index.line_col(span.start).line);
+        }
+        for (name, span) in references {
+            let references = &mut symbols.entry(name).or_default().references;
+            let line = index.line_col(span.start).line;
+            if !references.contains(&line) {
+                references.push(line);
+            }
+        }
+        for symbol in symbols.values_mut() {
+            symbol.references.sort();
+        }
+
+        Listing {
+            address_digits,
+            rows,
+            symbols,
+        }
+    }
+}
+
+impl Display for Listing {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let digits = self.address_digits;
+        let address_width = digits.max("Address".len());
+        let raw_width = self
+            .rows
+            .iter()
+            .map(|row| row.raw.len())
+            .max()
+            .unwrap_or(0)
+            .max("Words".len());
+        let header = format!(
+            "{:<address_width$}  {:<raw_width$}  {:>5}  Source",
+            "Address", "Words", "Line"
+        );
+        writeln!(f, "{}", header)?;
+        for row in &self.rows {
+            let address = row
+                .address
+                .map_or(String::new(), |address| format!("{:0digits$X}", address));
+            let line = row.line.map_or(String::new(), |line| line.to_string());
+            let row = format!(
+                "{:<address_width$}  {:<raw_width$}  {:>5}  {}",
+                address, row.raw, line, row.text
+            );
+            writeln!(f, "{}", row.trim_end())?;
+        }
+
+        if self.symbols.is_empty() {
+            return Ok(());
+        }
+        let name_width = self
+            .symbols
+            .keys()
+            .map(String::len)
+            .max()
+            .unwrap_or(0)
+            .max(16);
+        let value_width = digits.max("Value".len());
+        writeln!(f, "\nSymbols\n")?;
+        writeln!(
+            f,
+            "{:<name_width$}  {:<value_width$}  {:>5}  References",
+            "Name", "Value", "Line"
+        )?;
+        for (name, symbol) in &self.symbols {
+            let value = symbol
+                .value
+                .map_or("*UND*".to_string(), |value| format!("{:0digits$X}", value));
+            let definition = symbol
+                .definition
+                .map_or(String::new(), |line| line.to_string());
+            let references = symbol
+                .references
+                .iter()
+                .map(usize::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+            let row = format!(
+                "{:<name_width$}  {:<value_width$}  {:>5}  {}",
+                name, value, definition, references
+            );
+            writeln!(f, "{}", row.trim_end())?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+//!
+//! What the register-register operations compute, shared by everything that simulates them.
+//! RV32 registers are `u32` and RV64 registers `u64`.
+//!
+
+use crate::riscv::ast::ROpcode;
+
+impl ROpcode {
+    ///
+    /// Result of the operation on the values of `rs1` and `rs2`.
+    ///
+    /// Like the hardware the M extension never traps: division by zero gives all ones for the
+    /// quotient and the dividend for the remainder, and the one overflowing division,
+    /// `i32::MIN / -1`, gives the dividend for the quotient and zero for the remainder.
+    ///
+    pub fn evaluate(&self, rs1: u32, rs2: u32) -> u32 {
+        let (signed1, signed2) = (rs1 as i32, rs2 as i32);
+        let shift = rs2 & 0x1F;
+        match self {
+            ROpcode::Add => rs1.wrapping_add(rs2),
+            ROpcode::Sub => rs1.wrapping_sub(rs2),
+            ROpcode::Sll => rs1 << shift,
+            ROpcode::Slt => (signed1 < signed2) as u32,
+            ROpcode::Sltu => (rs1 < rs2) as u32,
+            ROpcode::Xor => rs1 ^ rs2,
+            ROpcode::Srl => rs1 >> shift,
+            ROpcode::Sra => (signed1 >> shift) as u32,
+            ROpcode::Or => rs1 | rs2,
+            ROpcode::And => rs1 & rs2,
+            ROpcode::Mul => rs1.wrapping_mul(rs2),
+            ROpcode::Mulh => ((signed1 as i64 * signed2 as i64) >> 32) as u32,
+            ROpcode::Mulhsu => ((signed1 as i64 * rs2 as i64) >> 32) as u32,
+            ROpcode::Mulhu => ((rs1 as u64 * rs2 as u64) >> 32) as u32,
+            ROpcode::Div if rs2 == 0 => u32::MAX,
+            ROpcode::Div => signed1.wrapping_div(signed2) as u32,
+            ROpcode::Divu => rs1.checked_div(rs2).unwrap_or(u32::MAX),
+            ROpcode::Rem if rs2 == 0 => rs1,
+            ROpcode::Rem => signed1.wrapping_rem(signed2) as u32,
+            ROpcode::Remu => rs1.checked_rem(rs2).unwrap_or(rs1),
+            _ => unreachable!("`{}` only exists in RV64", self.mnemonic()),
+        }
+    }
+
+    ///
+    /// Result of the operation on the 64-bit registers of RV64.
+    ///
+    /// The `w` operations compute what their RV32 counterpart computes on the lower words and
+    /// sign extend the result, so `divuw` by zero gives all ones as well.
+    ///
+    pub fn evaluate_64(&self, rs1: u64, rs2: u64) -> u64 {
+        if let Some(operation) = self.word_operation() {
+            return operation.evaluate(rs1 as u32, rs2 as u32) as i32 as u64;
+        }
+        let (signed1, signed2) = (rs1 as i64, rs2 as i64);
+        let shift = rs2 & 0x3F;
+        match self {
+            ROpcode::Add => rs1.wrapping_add(rs2),
+            ROpcode::Sub => rs1.wrapping_sub(rs2),
+            ROpcode::Sll => rs1 << shift,
+            ROpcode::Slt => (signed1 < signed2) as u64,
+            ROpcode::Sltu => (rs1 < rs2) as u64,
+            ROpcode::Xor => rs1 ^ rs2,
+            ROpcode::Srl => rs1 >> shift,
+            ROpcode::Sra => (signed1 >> shift) as u64,
+            ROpcode::Or => rs1 | rs2,
+            ROpcode::And => rs1 & rs2,
+            ROpcode::Mul => rs1.wrapping_mul(rs2),
+            ROpcode::Mulh => ((signed1 as i128 * signed2 as i128) >> 64) as u64,
+            ROpcode::Mulhsu => ((signed1 as i128 * rs2 as i128) >> 64) as u64,
+            ROpcode::Mulhu => ((rs1 as u128 * rs2 as u128) >> 64) as u64,
+            ROpcode::Div if rs2 == 0 => u64::MAX,
+            ROpcode::Div => signed1.wrapping_div(signed2) as u64,
+            ROpcode::Divu => rs1.checked_div(rs2).unwrap_or(u64::MAX),
+            ROpcode::Rem if rs2 == 0 => rs1,
+            ROpcode::Rem => signed1.wrapping_rem(signed2) as u64,
+            ROpcode::Remu => rs1.checked_rem(rs2).unwrap_or(rs1),
+            _ => unreachable!("{:?} is a word operation", self),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    const MIN: u32 = i32::MIN as u32;
+    const MINUS_ONE: u32 = u32::MAX;
+
+    #[rstest]
+    #[case(ROpcode::Add, MINUS_ONE, 2, 1)]
+    #[case(ROpcode::Sub, 0, 1, MINUS_ONE)]
+    #[case(ROpcode::Sll, 1, 33, 2)]
+    #[case(ROpcode::Slt, MINUS_ONE, 0, 1)]
+    #[case(ROpcode::Sltu, MINUS_ONE, 0, 0)]
+    #[case(ROpcode::Sra, MIN, 31, MINUS_ONE)]
+    #[case(ROpcode::Srl, MIN, 31, 1)]
+    #[case(ROpcode::Mul, 0x1_0001, 0x1_0001, 0x2_0001)]
+    #[case(ROpcode::Mul, MINUS_ONE, 3, -3i32 as u32)]
+    #[case(ROpcode::Mulh, MINUS_ONE, 3, MINUS_ONE)]
+    #[case(ROpcode::Mulh, MIN, MIN, 0x4000_0000)]
+    #[case(ROpcode::Mulhsu, MINUS_ONE, MINUS_ONE, MINUS_ONE)]
+    #[case(ROpcode::Mulhsu, 2, MINUS_ONE, 1)]
+    #[case(ROpcode::Mulhu, MINUS_ONE, MINUS_ONE, 0xFFFF_FFFE)]
+    #[case(ROpcode::Div, -7i32 as u32, 2, -3i32 as u32)]
+    #[case(ROpcode::Divu, -7i32 as u32, 2, 0x7FFF_FFFC)]
+    #[case(ROpcode::Rem, -7i32 as u32, 2, MINUS_ONE)]
+    #[case(ROpcode::Remu, 7, 2, 1)]
+    fn test_evaluate(#[case] opcode: ROpcode, #[case] a: u32, #[case] b: u32, #[case] r: u32) {
+        assert_eq!(opcode.evaluate(a, b), r);
+    }
+
+    #[rstest]
+    #[case(ROpcode::Div, 7, MINUS_ONE)]
+    #[case(ROpcode::Divu, 7, MINUS_ONE)]
+    #[case(ROpcode::Rem, 7, 7)]
+    #[case(ROpcode::Remu, 7, 7)]
+    #[case(ROpcode::Rem, MIN, MIN)]
+    fn test_divide_by_zero(#[case] opcode: ROpcode, #[case] a: u32, #[case] r: u32) {
+        assert_eq!(opcode.evaluate(a, 0), r);
+    }
+
+    #[test]
+    fn test_signed_overflow() {
+        assert_eq!(ROpcode::Div.evaluate(MIN, MINUS_ONE), MIN);
+        assert_eq!(ROpcode::Rem.evaluate(MIN, MINUS_ONE), 0);
+    }
+
+    #[rstest]
+    #[case(ROpcode::Add, u32::MAX as u64, 1, 0x1_0000_0000)]
+    #[case(ROpcode::Addw, u32::MAX as u64, 1, 0)]
+    #[case(ROpcode::Addw, 0x7FFF_FFFF, 1, 0xFFFF_FFFF_8000_0000)]
+    #[case(ROpcode::Sll, 1, 63, 1 << 63)]
+    #[case(ROpcode::Sllw, 1, 63, 0xFFFF_FFFF_8000_0000)]
+    #[case(ROpcode::Srl, u64::MAX, 32, 0xFFFF_FFFF)]
+    #[case(ROpcode::Srlw, u64::MAX, 31, 1)]
+    #[case(ROpcode::Sraw, 0x8000_0000, 4, 0xFFFF_FFFF_F800_0000)]
+    #[case(ROpcode::Subw, 0, 1, u64::MAX)]
+    #[case(ROpcode::Mulh, u64::MAX, 3, u64::MAX)]
+    #[case(ROpcode::Mulhu, u64::MAX, u64::MAX, u64::MAX - 1)]
+    #[case(ROpcode::Mulw, 0x1_0000_0002, 3, 6)]
+    #[case(ROpcode::Div, 1 << 63, u64::MAX, 1 << 63)]
+    #[case(ROpcode::Divuw, 7, 0, u64::MAX)]
+    #[case(ROpcode::Remw, 0x1_FFFF_FFF9, 2, u64::MAX)]
+    fn test_evaluate_64(#[case] opcode: ROpcode, #[case] a: u64, #[case] b: u64, #[case] r: u64) {
+        assert_eq!(opcode.evaluate_64(a, b), r);
+    }
+}
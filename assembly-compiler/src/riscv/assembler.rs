@@ -0,0 +1,1278 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use chumsky::error::Rich;
+use chumsky::span::SimpleSpan;
+
+use crate::riscv::ast::{
+    BImmediate, CImmediate, DataValue, Directive, IImmediate, Instruction, JImmediate, LSImmediate,
+    Label, Program, Relocation, RelocationKind, Symbol, UImmediate,
+};
+use crate::riscv::compressed::{compress, compress_program};
+use crate::riscv::encoding::{encode_bytes, encoded_len};
+use crate::riscv::isa::Isa;
+use crate::riscv::parser::parse_riscv_with;
+use crate::riscv::pseudo::{nop, split_immediate};
+
+/// Where symbols go before the first `.section`.
+const DEFAULT_SECTION: &str = ".text";
+
+#[derive(PartialEq, Copy, Clone, Debug, Default)]
+pub struct AssembleOptions {
+    /// Instructions may be aligned to two bytes when it includes the C extension.
+    pub isa: Isa,
+    /// Replace instructions by their compressed forms where possible, only with the C
+    /// extension.
+    pub compress: bool,
+    /// Address of the first section, where the program is loaded.
+    pub origin: u32,
+    /// Leave references which depend on where the sections end up, including those to labels
+    /// the program does not define, to the linker as relocations, for an object file.
+    pub relocatable: bool,
+}
+
+/// A contiguous part of the program, laid out after the sections before it.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Section {
+    pub name: String,
+    /// Address of the first byte.
+    pub address: u32,
+    /// The largest `.align` inside the section in bytes, at least 4.
+    pub alignment: u32,
+    pub bytes: Vec<u8>,
+    /// Source span of every instruction and piece of data by the address of its first byte, in
+    /// ascending order.
+    pub spans: Vec<(u32, SimpleSpan)>,
+    /// References left to the linker by the address of their instruction or data, in ascending
+    /// order. Only a relocatable program has them.
+    pub relocations: Vec<(u32, Relocation)>,
+}
+
+impl Section {
+    /// Whether the section holds code, which is aligned with `nop`s instead of zeros.
+    pub fn is_code(&self) -> bool {
+        is_code(&self.name)
+    }
+
+    pub fn end(&self) -> u32 {
+        self.address + self.bytes.len() as u32
+    }
+}
+
+fn is_code(name: &str) -> bool {
+    name == ".text" || name.starts_with(".text.")
+}
+
+#[derive(Debug)]
+pub struct Assembly {
+    pub isa: Isa,
+    /// Sections in the order they first appear in the source.
+    pub sections: Vec<Section>,
+    /// Address of every label in bytes.
+    pub labels: BTreeMap<String, u32>,
+    /// Index into `sections` of the section every label is in.
+    pub label_sections: BTreeMap<String, usize>,
+    /// Values of `.equ` and `.set`.
+    pub constants: BTreeMap<String, u32>,
+    /// Labels exported with `.globl`.
+    pub globals: BTreeSet<String>,
+}
+
+impl Assembly {
+    /// Memory from the first section up to the end of the last one, the gaps between sections
+    /// are zero.
+    pub fn image(&self) -> Vec<u8> {
+        let origin = self.origin();
+        let mut image = vec![];
+        for section in &self.sections {
+            image.resize((section.address - origin) as usize, 0);
+            image.extend(&section.bytes);
+        }
+        image
+    }
+
+    /// Address of the first section, the one of [`Assembly::image`].
+    pub fn origin(&self) -> u32 {
+        self.sections.first().map_or(0, |section| section.address)
+    }
+
+    /// Labels referred to by relocations which the program does not define.
+    pub fn undefined(&self) -> BTreeSet<&str> {
+        self.sections
+            .iter()
+            .flat_map(|section| &section.relocations)
+            .map(|(_, relocation)| relocation.label.name.as_str())
+            .filter(|&name| !self.labels.contains_key(name) && !self.constants.contains_key(name))
+            .collect()
+    }
+
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+
+    /// Span of the instruction or data occupying `address`, any of its bytes.
+    pub fn span_at(&self, address: u32) -> Option<SimpleSpan> {
+        let section = self
+            .sections
+            .iter()
+            .find(|section| (section.address..section.end()).contains(&address))?;
+        let index = section
+            .spans
+            .partition_point(|&(start, _)| start <= address);
+        Some(section.spans[index.checked_sub(1)?].1)
+    }
+}
+
+/// Bytes from `address` to the next multiple of `alignment`.
+fn padding(address: u32, alignment: u32) -> u32 {
+    address.wrapping_neg() % alignment
+}
+
+///
+/// Padding of `length` bytes at `address`, `nop`s from the first aligned word on in code.
+///
+/// With the C extension a `c.nop` fills the halfword before that word, so that execution can
+/// run through the padding.
+///
+fn fill(address: u32, length: u32, code: bool, isa: Isa) -> Vec<u8> {
+    let zeros = if code {
+        padding(address, isa.instruction_alignment()).min(length)
+    } else {
+        length
+    };
+    let mut instructions = vec![];
+    let mut rest = length - zeros;
+    if isa.c && rest >= 2 && padding(address + zeros, 4) == 2 {
+        instructions.extend(nop().iter().filter_map(compress));
+        rest -= 2;
+    }
+    instructions.extend(nop().repeat(rest as usize / 4));
+    let mut bytes = vec![0; zeros as usize];
+    bytes.extend(encode_bytes(&instructions));
+    bytes
+}
+
+/// Bytes the symbol takes up at `address`.
+fn size(symbol: &Symbol, address: u32) -> u32 {
+    match symbol {
+        Symbol::Directive(Directive::Alignment(alignment)) => {
+            padding(address, alignment.alignment as u32)
+        }
+        Symbol::Directive(Directive::Data(data)) => (data.width.bytes() * data.values.len()) as u32,
+        Symbol::Directive(Directive::Bytes(bytes)) => bytes.len() as u32,
+        _ => symbol.instructions().iter().map(encoded_len).sum::<usize>() as u32,
+    }
+}
+
+fn set_immediate(instruction: Instruction, value: i32) -> Instruction {
+    match instruction {
+        Instruction::IType {
+            opcode, rd, rs1, ..
+        } => Instruction::IType {
+            opcode,
+            rd,
+            rs1,
+            imm: IImmediate(value as i16),
+        },
+        Instruction::LType {
+            opcode, rd, rs1, ..
+        } => Instruction::LType {
+            opcode,
+            rd,
+            rs1,
+            imm: LSImmediate(value as i16),
+        },
+        Instruction::SType {
+            opcode, rs1, rs2, ..
+        } => Instruction::SType {
+            opcode,
+            rs1,
+            rs2,
+            imm: LSImmediate(value as i16),
+        },
+        Instruction::FloatLoad {
+            format, rd, rs1, ..
+        } => Instruction::FloatLoad {
+            format,
+            rd,
+            rs1,
+            imm: LSImmediate(value as i16),
+        },
+        Instruction::FloatStore {
+            format, rs1, rs2, ..
+        } => Instruction::FloatStore {
+            format,
+            rs1,
+            rs2,
+            imm: LSImmediate(value as i16),
+        },
+        Instruction::BType {
+            opcode, rs1, rs2, ..
+        } => Instruction::BType {
+            opcode,
+            rs1,
+            rs2,
+            imm: BImmediate(value as i16),
+        },
+        Instruction::UType { opcode, rd, .. } => Instruction::UType {
+            opcode,
+            rd,
+            imm: UImmediate(value),
+        },
+        Instruction::JType { opcode, rd, .. } => Instruction::JType {
+            opcode,
+            rd,
+            imm: JImmediate(value),
+        },
+        Instruction::Compressed {
+            opcode,
+            rd,
+            rs1,
+            rs2,
+            ..
+        } => Instruction::Compressed {
+            opcode,
+            rd,
+            rs1,
+            rs2,
+            imm: CImmediate(value),
+        },
+        _ => unreachable!("only instructions with an immediate refer to labels"),
+    }
+}
+
+// Labels, constants and the `%pcrel_hi` targets, all by address.
+struct Symbols<'a> {
+    labels: BTreeMap<String, u32>,
+    sections: BTreeMap<String, usize>,
+    constants: BTreeMap<String, u32>,
+    pcrel_hi: BTreeMap<u32, &'a Label>,
+}
+
+impl Symbols<'_> {
+    fn address<'src>(&self, label: &Label) -> Result<u32, Rich<'src, char>> {
+        let address = self
+            .labels
+            .get(&label.name)
+            .or_else(|| self.constants.get(&label.name));
+        address.copied().ok_or_else(|| {
+            Rich::custom(label.span, format!("label `{}` is not defined", label.name))
+        })
+    }
+
+    /// Offset from `pc` to the label, `bits` wide including the sign.
+    fn offset<'src>(&self, label: &Label, pc: u32, bits: u32) -> Result<i32, Rich<'src, char>> {
+        let offset = self.address(label)?.wrapping_sub(pc) as i32;
+        if bits < 32 && !(-(1 << (bits - 1))..1 << (bits - 1)).contains(&offset) {
+            return Err(Rich::custom(
+                label.span,
+                format!(
+                    "label `{}` is {} bytes away, out of range of a {} bit offset",
+                    label.name, offset, bits
+                ),
+            ));
+        }
+        Ok(offset)
+    }
+
+    // Whether the address of the label is only known once the program is linked.
+    fn address_unknown(&self, label: &Label) -> bool {
+        !self.constants.contains_key(&label.name)
+    }
+
+    // Whether the offset from `section` to the label is only known once the program is linked.
+    fn offset_unknown(&self, label: &Label, section: usize) -> bool {
+        self.sections.get(&label.name) != Some(&section)
+    }
+
+    /// Whether a reference from `section` is left to the linker in a relocatable program.
+    fn relocates(&self, relocation: &Relocation, section: usize) -> bool {
+        let label = &relocation.label;
+        match relocation.kind {
+            RelocationKind::Branch
+            | RelocationKind::Jump
+            | RelocationKind::PcrelHi
+            | RelocationKind::Pcrel => self.offset_unknown(label, section),
+            // Follows the `%pcrel_hi` it completes.
+            RelocationKind::PcrelLo => {
+                let Some(&auipc_section) = self.sections.get(&label.name) else {
+                    return true;
+                };
+                match self.pcrel_hi.get(&self.labels[&label.name]) {
+                    Some(target) => self.offset_unknown(target, auipc_section),
+                    None => false,
+                }
+            }
+            RelocationKind::Hi
+            | RelocationKind::Lo
+            | RelocationKind::Absolute
+            | RelocationKind::Word => self.address_unknown(label),
+        }
+    }
+
+    /// The offset of the `%pcrel_hi` at the label, which `%pcrel_lo` completes.
+    fn pcrel_offset<'src>(&self, label: &Label) -> Result<i32, Rich<'src, char>> {
+        let auipc = self.address(label)?;
+        match self.pcrel_hi.get(&auipc) {
+            Some(target) => self.offset(target, auipc, 32),
+            None => Err(Rich::custom(
+                label.span,
+                format!("label `{}` does not mark a `%pcrel_hi`", label.name),
+            )),
+        }
+    }
+}
+
+// The sections without their contents and the address of every symbol of the program.
+struct Layout<'a> {
+    sections: Vec<Section>,
+    placements: Vec<(usize, u32)>,
+    symbols: Symbols<'a>,
+}
+
+// First pass, assigns an address to every symbol and label.
+fn lay_out<'a, 'src>(
+    program: &'a Program,
+    options: &AssembleOptions,
+) -> (Layout<'a>, Vec<Rich<'src, char>>) {
+    let instruction_alignment = options.isa.instruction_alignment();
+    let mut errors = vec![];
+    let mut names = vec![DEFAULT_SECTION.to_string()];
+    let mut sizes = vec![0u32];
+    let mut alignments = vec![4u32];
+    let mut current = 0;
+    let mut offsets = vec![];
+    for symbol in &program.symbols {
+        match &symbol.node {
+            Symbol::Directive(Directive::Section(name)) => {
+                current = match names.iter().position(|other| other == name) {
+                    Some(index) => index,
+                    None => {
+                        names.push(name.clone());
+                        sizes.push(0);
+                        alignments.push(4);
+                        names.len() - 1
+                    }
+                };
+            }
+            Symbol::Directive(Directive::Alignment(alignment)) => {
+                alignments[current] = alignments[current].max(alignment.alignment as u32);
+            }
+            node if !node.instructions().is_empty()
+                && sizes[current] % instruction_alignment != 0 =>
+            {
+                errors.push(Rich::custom(
+                    symbol.span,
+                    format!(
+                        "instruction is not aligned to {} bytes, add `.align {}` before it",
+                        instruction_alignment,
+                        instruction_alignment.trailing_zeros()
+                    ),
+                ));
+            }
+            _ => {}
+        }
+        offsets.push((current, sizes[current]));
+        sizes[current] += size(&symbol.node, sizes[current]);
+    }
+
+    // Every section starts where the one before it ends, aligned for everything inside it.
+    let mut sections = vec![];
+    let mut address = options.origin;
+    for ((name, size), alignment) in names.into_iter().zip(sizes).zip(alignments) {
+        address += padding(address, alignment);
+        sections.push(Section {
+            name,
+            address,
+            alignment,
+            bytes: Vec::with_capacity(size as usize),
+            spans: vec![],
+            relocations: vec![],
+        });
+        address += size;
+    }
+    let placements: Vec<_> = offsets
+        .into_iter()
+        .map(|(section, offset)| (section, sections[section].address + offset))
+        .collect();
+
+    let mut symbols = Symbols {
+        labels: BTreeMap::new(),
+        sections: BTreeMap::new(),
+        constants: BTreeMap::new(),
+        pcrel_hi: BTreeMap::new(),
+    };
+    for (symbol, &(section, address)) in program.symbols.iter().zip(&placements) {
+        let (label, value) = match &symbol.node {
+            Symbol::Reference(reference)
+                if reference.relocation.kind == RelocationKind::PcrelHi =>
+            {
+                symbols
+                    .pcrel_hi
+                    .insert(address, &reference.relocation.label);
+                continue;
+            }
+            Symbol::Label(label) => (label, address),
+            Symbol::Directive(Directive::Constant(constant)) => (&constant.label, constant.value),
+            _ => continue,
+        };
+        if symbols.labels.contains_key(&label.name) || symbols.constants.contains_key(&label.name) {
+            errors.push(Rich::custom(
+                label.span,
+                format!("label `{}` is already defined", label.name),
+            ));
+        } else if let Symbol::Label(_) = symbol.node {
+            symbols.labels.insert(label.name.clone(), value);
+            symbols.sections.insert(label.name.clone(), section);
+        } else {
+            symbols.constants.insert(label.name.clone(), value);
+        }
+    }
+
+    let layout = Layout {
+        sections,
+        placements,
+        symbols,
+    };
+    (layout, errors)
+}
+
+// The instructions a symbol at `pc` stands for with the label filled in.
+fn resolve<'src>(
+    symbols: &Symbols,
+    symbol: &Symbol,
+    pc: u32,
+) -> Result<Vec<Instruction>, Rich<'src, char>> {
+    let (instructions, relocation): (&[Instruction], &Relocation) = match symbol {
+        Symbol::Reference(reference) => (
+            std::slice::from_ref(&reference.instruction),
+            &reference.relocation,
+        ),
+        Symbol::Pseudo(pseudo) => match &pseudo.relocation {
+            Some(relocation) => (&pseudo.expansion, relocation),
+            None => return Ok(pseudo.expansion.clone()),
+        },
+        _ => return Ok(symbol.instructions().to_vec()),
+    };
+    let label = &relocation.label;
+    let value = match relocation.kind {
+        RelocationKind::Branch | RelocationKind::Jump => {
+            let bits = offset_bits(relocation.kind, instructions[0]);
+            symbols.offset(label, pc, bits)?
+        }
+        RelocationKind::PcrelHi | RelocationKind::Pcrel => symbols.offset(label, pc, 32)?,
+        RelocationKind::Hi | RelocationKind::Lo | RelocationKind::Absolute => {
+            symbols.address(label)? as i32
+        }
+        RelocationKind::PcrelLo => symbols.pcrel_offset(label)?,
+        RelocationKind::Word => unreachable!("only data refers to a label as a word"),
+    };
+    Ok(relocate(instructions, relocation.kind, value))
+}
+
+/// Bits of the offset a reference of `kind` starting with `first` reaches, including the sign.
+pub fn offset_bits(kind: RelocationKind, first: Instruction) -> u32 {
+    // `c.beqz`, `c.bnez`, `c.j` and `c.jal` reach less far than their base instructions.
+    match (kind, first) {
+        (RelocationKind::Branch, Instruction::Compressed { .. }) => 9,
+        (RelocationKind::Branch, _) => 13,
+        (RelocationKind::Jump, Instruction::Compressed { .. }) => 12,
+        (RelocationKind::Jump, _) => 21,
+        _ => 32,
+    }
+}
+
+///
+/// The instructions of a reference with their immediates filled in from `value`.
+///
+/// `value` is the address of the label for `%hi`, `%lo` and absolute references, its offset
+/// from the first instruction for pc-relative ones, and the offset of the `%pcrel_hi` it
+/// completes for `%pcrel_lo`.
+///
+pub fn relocate(
+    instructions: &[Instruction],
+    kind: RelocationKind,
+    value: i32,
+) -> Vec<Instruction> {
+    let first = instructions[0];
+    let (hi, lo) = split_immediate(value);
+    match kind {
+        RelocationKind::Branch | RelocationKind::Jump => vec![set_immediate(first, value)],
+        RelocationKind::Hi | RelocationKind::PcrelHi => vec![set_immediate(first, hi)],
+        RelocationKind::Lo | RelocationKind::PcrelLo => vec![set_immediate(first, lo as i32)],
+        RelocationKind::Pcrel | RelocationKind::Absolute => vec![
+            set_immediate(first, hi),
+            set_immediate(instructions[1], lo as i32),
+        ],
+        RelocationKind::Word => unreachable!("only data refers to a label as a word"),
+    }
+}
+
+// The reference of a symbol to a label, if it has one.
+fn relocation(symbol: &Symbol) -> Option<&Relocation> {
+    match symbol {
+        Symbol::Reference(reference) => Some(&reference.relocation),
+        Symbol::Pseudo(pseudo) => pseudo.relocation.as_ref(),
+        _ => None,
+    }
+}
+
+///
+/// Second pass, the bytes a symbol at `address` in `section` puts into it.
+///
+/// In a relocatable program the references left to the linker go to `relocations` and their
+/// immediates and data stay zero.
+///
+fn emit<'src>(
+    symbols: &Symbols,
+    symbol: &Symbol,
+    (section, address): (usize, u32),
+    code: bool,
+    options: &AssembleOptions,
+    relocations: &mut Vec<(u32, Relocation)>,
+) -> Result<Vec<u8>, Rich<'src, char>> {
+    let bytes = match symbol {
+        Symbol::Directive(Directive::Alignment(_)) => {
+            fill(address, size(symbol, address), code, options.isa)
+        }
+        Symbol::Directive(Directive::Data(data)) => {
+            let mut bytes = vec![];
+            for value in &data.values {
+                let value = match value {
+                    DataValue::Number(number) => *number,
+                    DataValue::Label(label) => {
+                        let relocation = Relocation {
+                            kind: RelocationKind::Word,
+                            label: label.clone(),
+                        };
+                        if options.relocatable && symbols.relocates(&relocation, section) {
+                            relocations.push((address + bytes.len() as u32, relocation));
+                            0
+                        } else {
+                            symbols.address(label)?
+                        }
+                    }
+                };
+                bytes.extend(&value.to_le_bytes()[..data.width.bytes()]);
+            }
+            bytes
+        }
+        Symbol::Directive(Directive::Bytes(bytes)) => bytes.clone(),
+        _ => match relocation(symbol) {
+            Some(relocation) if options.relocatable && symbols.relocates(relocation, section) => {
+                relocations.push((address, relocation.clone()));
+                encode_bytes(symbol.instructions())
+            }
+            _ => encode_bytes(&resolve(symbols, symbol, address)?),
+        },
+    };
+    Ok(bytes)
+}
+
+///
+/// Compresses the branches and jumps to labels whose offsets fit into a compressed instruction.
+///
+/// Every compressed instruction moves the ones after it closer, so the program is laid out
+/// again until no more fit. Instructions never grow back, which ends the loop.
+///
+fn compress_references(program: &mut Program, options: &AssembleOptions) {
+    loop {
+        let (layout, _) = lay_out(program, options);
+        let mut compressed = vec![];
+        for (index, (symbol, &(section, pc))) in
+            program.symbols.iter().zip(&layout.placements).enumerate()
+        {
+            let (instruction, relocation) = match &symbol.node {
+                Symbol::Reference(reference) => (reference.instruction, &reference.relocation),
+                Symbol::Pseudo(pseudo) => match &pseudo.relocation {
+                    Some(relocation) if pseudo.expansion.len() == 1 => {
+                        (pseudo.expansion[0], relocation)
+                    }
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            if !matches!(
+                relocation.kind,
+                RelocationKind::Branch | RelocationKind::Jump
+            ) || matches!(instruction, Instruction::Compressed { .. })
+                || options.relocatable && layout.symbols.relocates(relocation, section)
+            {
+                continue;
+            }
+            let Ok(offset) = layout.symbols.offset(&relocation.label, pc, 32) else {
+                continue;
+            };
+            match compress(&set_immediate(instruction, offset)) {
+                Some(instruction) if options.isa.missing_base(&instruction).is_none() => {
+                    compressed.push((index, set_immediate(instruction, 0)));
+                }
+                _ => {}
+            }
+        }
+        if compressed.is_empty() {
+            return;
+        }
+        for (index, instruction) in compressed {
+            match &mut program.symbols[index].node {
+                Symbol::Reference(reference) => reference.instruction = instruction,
+                Symbol::Pseudo(pseudo) => pseudo.expansion[0] = instruction,
+                _ => unreachable!("only references and pseudo-instructions were compressed"),
+            }
+        }
+    }
+}
+
+/// Lays out the sections of a parsed program from address 0 and resolves its labels.
+pub fn assemble<'src>(program: &Program) -> Result<Assembly, Vec<Rich<'src, char>>> {
+    assemble_with(program, &AssembleOptions::default())
+}
+
+pub fn assemble_with<'src>(
+    program: &Program,
+    options: &AssembleOptions,
+) -> Result<Assembly, Vec<Rich<'src, char>>> {
+    let compressed;
+    let program = if options.compress && options.isa.c {
+        let mut copy = program.clone();
+        compress_program(&mut copy, options.isa);
+        compress_references(&mut copy, options);
+        compressed = copy;
+        &compressed
+    } else {
+        program
+    };
+    let (mut layout, mut errors) = lay_out(program, options);
+
+    let mut globals = BTreeSet::new();
+    for (symbol, &(index, address)) in program.symbols.iter().zip(&layout.placements) {
+        if let Symbol::Directive(Directive::Global(label)) = &symbol.node {
+            globals.insert(label.name.clone());
+        }
+        let section = &mut layout.sections[index];
+        let code = section.is_code();
+        let placement = (index, address);
+        let relocations = &mut section.relocations;
+        match emit(
+            &layout.symbols,
+            &symbol.node,
+            placement,
+            code,
+            options,
+            relocations,
+        ) {
+            Ok(bytes) if bytes.is_empty() => {}
+            Ok(bytes) => {
+                section.spans.push((address, symbol.span));
+                section.bytes.extend(bytes);
+            }
+            Err(error) => errors.push(error),
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(Assembly {
+        isa: options.isa,
+        sections: layout.sections,
+        labels: layout.symbols.labels,
+        label_sections: layout.symbols.sections,
+        constants: layout.symbols.constants,
+        globals,
+    })
+}
+
+pub fn assemble_riscv(assembly: &str) -> Result<Assembly, Vec<Rich<'_, char>>> {
+    assemble_riscv_with(assembly, &AssembleOptions::default())
+}
+
+pub fn assemble_riscv_with<'src>(
+    assembly: &'src str,
+    options: &AssembleOptions,
+) -> Result<Assembly, Vec<Rich<'src, char>>> {
+    assemble_with(&parse_riscv_with(assembly, options.isa)?, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::ast::{BOpcode, IOpcode, JOpcode, Register, UOpcode};
+    use crate::riscv::encoding::{decode_bytes, from_bytes};
+    use rstest::rstest;
+
+    fn text(assembly: &Assembly) -> Vec<Instruction> {
+        let mut bytes = &assembly.section(".text").unwrap().bytes[..];
+        let mut instructions = vec![];
+        while !bytes.is_empty() {
+            let (instruction, len) = decode_bytes(bytes).unwrap();
+            instructions.push(instruction);
+            bytes = &bytes[len..];
+        }
+        instructions
+    }
+
+    #[test]
+    fn test_backward_and_forward_references() {
+        let source = "
+            start: beq a0, zero, end
+            loop:
+                addi a0, a0, -1
+                bnez a0, loop
+            end: jal ra, start
+        ";
+        let assembly = assemble_riscv(source).unwrap();
+        assert_eq!(assembly.labels["start"], 0);
+        assert_eq!(assembly.labels["loop"], 4);
+        assert_eq!(assembly.labels["end"], 12);
+        assert_eq!(
+            text(&assembly),
+            [
+                Instruction::BType {
+                    opcode: BOpcode::Beq,
+                    rs1: Register(10),
+                    rs2: Register(0),
+                    imm: BImmediate(12),
+                },
+                Instruction::IType {
+                    opcode: IOpcode::Addi,
+                    rd: Register(10),
+                    rs1: Register(10),
+                    imm: IImmediate(-1),
+                },
+                Instruction::BType {
+                    opcode: BOpcode::Bne,
+                    rs1: Register(10),
+                    rs2: Register(0),
+                    imm: BImmediate(-4),
+                },
+                Instruction::JType {
+                    opcode: JOpcode::Jal,
+                    rd: Register(1),
+                    imm: JImmediate(-12),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_span_at() {
+        let source = "li a0, 0x12345678\nloop: j loop";
+        let assembly = assemble_riscv(source).unwrap();
+        let text = |address| {
+            assembly
+                .span_at(address)
+                .map(|span| &source[span.into_range()])
+        };
+        assert_eq!(text(0), Some("li a0, 0x12345678"));
+        assert_eq!(text(7), Some("li a0, 0x12345678"));
+        assert_eq!(text(8), Some("j loop"));
+        assert_eq!(text(12), None);
+        assert_eq!(assembly.labels["loop"], 8);
+    }
+
+    // `%lo` is sign extended, so `%hi` rounds up whenever bit 11 of the address is set.
+    #[rstest]
+    #[case(0x7FC, 0, 0x7FC)]
+    #[case(0x800, 1, -0x800)]
+    #[case(0x1234, 1, 0x234)]
+    fn test_hi_lo(#[case] address: usize, #[case] hi: i32, #[case] lo: i16) {
+        let source = format!(
+            "lui a0, %hi(data)\nlw a1, %lo(data)(a0)\n{}data:",
+            "nop\n".repeat(address / 4 - 2)
+        );
+        let assembly = assemble_riscv(&source).unwrap();
+        assert_eq!(assembly.labels["data"] as usize, address);
+        let instructions = text(&assembly);
+        assert_eq!(
+            instructions[0],
+            Instruction::UType {
+                opcode: UOpcode::Lui,
+                rd: Register(10),
+                imm: UImmediate(hi),
+            }
+        );
+        let Instruction::LType { imm, .. } = instructions[1] else {
+            panic!("expected a load, got {:?}", instructions[1]);
+        };
+        assert_eq!(imm, LSImmediate(lo));
+    }
+
+    #[test]
+    fn test_pcrel_pair() {
+        let source = format!(
+            "nop\nhere: auipc a0, %pcrel_hi(data)\naddi a0, a0, %pcrel_lo(here)\n{}data:",
+            "nop\n".repeat(0x200)
+        );
+        let assembly = assemble_riscv(&source).unwrap();
+        // `data` lies 0x808 bytes after the `auipc`.
+        assert_eq!(
+            &text(&assembly)[1..3],
+            [
+                Instruction::UType {
+                    opcode: UOpcode::Auipc,
+                    rd: Register(10),
+                    imm: UImmediate(1),
+                },
+                Instruction::IType {
+                    opcode: IOpcode::Addi,
+                    rd: Register(10),
+                    rs1: Register(10),
+                    imm: IImmediate(-0x7F8),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pcrel_lo_needs_pcrel_hi() {
+        let source = "here: lui a0, %hi(here)\naddi a0, a0, %pcrel_lo(here)";
+        let errors = assemble_riscv(source).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].to_string(),
+            "label `here` does not mark a `%pcrel_hi`"
+        );
+        assert_eq!(errors[0].span().into_range(), 47..51);
+    }
+
+    #[test]
+    fn test_far_pseudo_instructions() {
+        let source = "
+            la a0, data
+            call function
+            tail function
+            data:
+            function: ret
+        ";
+        let words = from_bytes(&assemble_riscv(source).unwrap().image());
+        assert_eq!(
+            words,
+            [
+                // auipc a0, 0; addi a0, a0, 24
+                0x0000_0517,
+                0x0185_0513,
+                // auipc ra, 0; jalr ra, 16(ra)
+                0x0000_0097,
+                0x0100_80E7,
+                // auipc t1, 0; jalr zero, 8(t1)
+                0x0000_0317,
+                0x0083_0067,
+                // ret
+                0x0000_8067,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_branch_out_of_range() {
+        let source = format!("beqz a0, far\n{}far: nop\nj far", "nop\n".repeat(1024));
+        let errors = assemble_riscv(&source).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].to_string(),
+            "label `far` is 4100 bytes away, out of range of a 13 bit offset"
+        );
+        assert_eq!(errors[0].span().into_range(), 9..12);
+
+        // The largest forward branch still fits.
+        let source = format!("beqz a0, far\n{}far: nop", "nop\n".repeat(1022));
+        assert!(assemble_riscv(&source).is_ok());
+    }
+
+    #[test]
+    fn test_undefined_label() {
+        let source = "nop\nj nowhere";
+        let errors = assemble_riscv(source).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "label `nowhere` is not defined");
+        assert_eq!(&source[errors[0].span().into_range()], "nowhere");
+    }
+
+    #[test]
+    fn test_duplicate_label() {
+        let source = "loop: nop\nloop: nop";
+        let errors = assemble_riscv(source).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "label `loop` is already defined");
+        assert_eq!(errors[0].span().into_range(), 10..14);
+    }
+
+    #[test]
+    fn test_data_directives() {
+        let source = r#"
+            .data
+            .byte 1, -1, 0xFF
+            .half 0x1234, -2
+            .word 0xDEADBEEF, -1
+            .ascii "ab", "c"
+            .asciz "\x41\n"
+            .string ""
+            .space 2
+            .zero 3, 0x7F
+        "#;
+        let assembly = assemble_riscv(source).unwrap();
+        assert_eq!(
+            assembly.section(".data").unwrap().bytes,
+            [
+                0x01, 0xFF, 0xFF, 0x34, 0x12, 0xFE, 0xFF, 0xEF, 0xBE, 0xAD, 0xDE, 0xFF, 0xFF, 0xFF,
+                0xFF, b'a', b'b', b'c', b'A', b'\n', 0, 0, 0, 0, 0x7F, 0x7F, 0x7F,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sections() {
+        let source = "
+            .data
+            message: .asciz \"hi\"
+            .text
+            .globl _start
+            _start: la a0, message
+            j _start
+            .section .rodata, \"a\", @progbits
+            table: .word _start, message, table
+        ";
+        let assembly = assemble_riscv(source).unwrap();
+        let names: Vec<_> = assembly
+            .sections
+            .iter()
+            .map(|section| (section.name.as_str(), section.address))
+            .collect();
+        // The default section comes first even though `.data` is the first directive.
+        assert_eq!(names, [(".text", 0), (".data", 12), (".rodata", 16)]);
+        assert_eq!(assembly.labels["message"], 12);
+        assert_eq!(assembly.labels["table"], 16);
+        assert_eq!(
+            from_bytes(&assembly.section(".rodata").unwrap().bytes),
+            [0, 12, 16]
+        );
+        assert!(assembly.globals.contains("_start"));
+        // auipc a0, 0; addi a0, a0, 12
+        assert_eq!(
+            from_bytes(&assembly.image()[..8]),
+            [0x0000_0517, 0x00C5_0513]
+        );
+        assert_eq!(&assembly.image()[12..15], b"hi\0");
+    }
+
+    #[test]
+    fn test_alignment() {
+        let source = "
+            .data
+            .byte 1
+            .align 2
+            aligned: .byte 2
+            .p2align 3
+            .text
+            nop
+            .align 4
+            entry: ret
+        ";
+        let assembly = assemble_riscv(source).unwrap();
+        assert_eq!(assembly.labels["aligned"], 24 + 4);
+        assert_eq!(assembly.labels["entry"], 16);
+        let data = assembly.section(".data").unwrap();
+        // The section is aligned for its strictest `.align`.
+        assert_eq!(data.address, 24);
+        assert_eq!(data.bytes, [1, 0, 0, 0, 2, 0, 0, 0]);
+        // Code is padded with `nop`s.
+        assert_eq!(
+            from_bytes(&assembly.section(".text").unwrap().bytes),
+            [0x13, 0x13, 0x13, 0x13, 0x8067]
+        );
+    }
+
+    #[test]
+    fn test_origin() {
+        let source = "
+            _start: la a0, message
+            lui a1, %hi(message)
+            .data
+            message: .word message
+        ";
+        let options = AssembleOptions {
+            origin: 0x8000_0000,
+            ..Default::default()
+        };
+        let assembly = assemble_riscv_with(source, &options).unwrap();
+        assert_eq!(assembly.origin(), 0x8000_0000);
+        assert_eq!(assembly.labels["message"], 0x8000_000C);
+        // auipc a0, 0; addi a0, a0, 12; lui a1, 0x80000
+        assert_eq!(
+            from_bytes(&assembly.image()),
+            [0x0000_0517, 0x00C5_0513, 0x8000_05B7, 0x8000_000C]
+        );
+    }
+
+    #[test]
+    fn test_relocatable() {
+        let source = "
+            _start: call puts
+            loop: bnez a0, loop
+            la a1, message
+            lui a2, %hi(LENGTH)
+            .equ LENGTH, 0x2000
+            .data
+            message: .word message, puts
+        ";
+        let options = AssembleOptions {
+            relocatable: true,
+            ..Default::default()
+        };
+        let assembly = assemble_riscv_with(source, &options).unwrap();
+        let relocations = |name| -> Vec<_> {
+            assembly
+                .section(name)
+                .unwrap()
+                .relocations
+                .iter()
+                .map(|(address, relocation)| {
+                    (*address, relocation.kind, relocation.label.name.as_str())
+                })
+                .collect()
+        };
+        // The branch within `.text` and the constant are resolved by the assembler.
+        assert_eq!(
+            relocations(".text"),
+            [
+                (0, RelocationKind::Pcrel, "puts"),
+                (12, RelocationKind::Pcrel, "message"),
+            ]
+        );
+        assert_eq!(
+            relocations(".data"),
+            [
+                (24, RelocationKind::Word, "message"),
+                (28, RelocationKind::Word, "puts"),
+            ]
+        );
+        assert_eq!(assembly.undefined(), BTreeSet::from(["puts"]));
+        // Immediates left to the linker are zero.
+        assert_eq!(
+            from_bytes(&assembly.image()),
+            [
+                0x0000_0097,
+                0x0000_80E7,
+                0x0005_1063,
+                0x0000_0597,
+                0x0005_8593,
+                0x0000_2637,
+                0,
+                0
+            ]
+        );
+    }
+
+    #[test]
+    fn test_misaligned_instruction() {
+        let source = ".byte 1
+nop";
+        let errors = assemble_riscv(source).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(&source[errors[0].span().into_range()], "nop");
+        assert!(assemble_riscv(
+            ".byte 1
+.align 2
+nop"
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_constants() {
+        let source = "
+            .equ UART, 0x10000000
+            .set COUNT, 3
+            li a0, UART
+            li a1, COUNT
+            .data
+            .word COUNT
+        ";
+        let assembly = assemble_riscv(source).unwrap();
+        assert_eq!(assembly.constants["UART"], 0x1000_0000);
+        assert!(assembly.labels.is_empty());
+        // lui a0, 0x10000; addi a0, a0, 0; lui a1, 0; addi a1, a1, 3
+        assert_eq!(
+            from_bytes(&assembly.section(".text").unwrap().bytes),
+            [0x1000_0537, 0x0005_0513, 0x0000_05B7, 0x0035_8593]
+        );
+        assert_eq!(assembly.section(".data").unwrap().bytes, [3, 0, 0, 0]);
+
+        let errors = assemble_riscv(".equ X, 1\nX: nop").unwrap_err();
+        assert_eq!(errors[0].to_string(), "label `X` is already defined");
+    }
+
+    #[test]
+    fn test_span_of_data() {
+        let source = ".data\n.word 1\n.align 3\n.byte 3";
+        let assembly = assemble_riscv(source).unwrap();
+        let text = |address| {
+            assembly
+                .span_at(address)
+                .map(|span| &source[span.into_range()])
+        };
+        assert_eq!(text(2), Some(".word 1"));
+        assert_eq!(text(4), Some(".align 3"));
+        assert_eq!(text(8), Some(".byte 3"));
+        assert_eq!(text(9), None);
+    }
+
+    const COMPRESSED: AssembleOptions = AssembleOptions {
+        isa: Isa::RV32IC,
+        compress: false,
+        origin: 0,
+        relocatable: false,
+    };
+
+    #[test]
+    fn test_compress_option() {
+        let source = "
+            addi sp, sp, -16
+            sw ra, 12(sp)
+            li a0, 10
+            loop:
+                addi a0, a0, -1
+                bnez a0, loop
+            lw ra, 12(sp)
+            addi sp, sp, 16
+            ret
+        ";
+        let size = |options| assemble_riscv_with(source, &options).unwrap().image().len();
+        assert_eq!(size(COMPRESSED), 32);
+        let options = AssembleOptions {
+            compress: true,
+            ..COMPRESSED
+        };
+        assert_eq!(size(options), 16);
+        // Without the C extension there is nothing to compress to.
+        let options = AssembleOptions {
+            compress: true,
+            ..Default::default()
+        };
+        assert_eq!(size(options), 32);
+
+        // The branch to the label is compressed once the program is laid out.
+        let assembly = assemble_riscv_with(
+            source,
+            &AssembleOptions {
+                compress: true,
+                ..COMPRESSED
+            },
+        )
+        .unwrap();
+        assert_eq!(assembly.labels["loop"], 6);
+        let program = parse_riscv_with("c.bnez a0, -2", Isa::RV32IC).unwrap();
+        assert_eq!(
+            text(&assembly)[4],
+            program.symbols[0].node.instructions()[0]
+        );
+    }
+
+    #[test]
+    fn test_compress_mv() {
+        let options = AssembleOptions {
+            compress: true,
+            ..COMPRESSED
+        };
+        let assembly = assemble_riscv_with("mv a0, a1", &options).unwrap();
+        assert_eq!(assembly.image(), [0x2E, 0x85]);
+    }
+
+    #[test]
+    fn test_compress_references() {
+        let options = AssembleOptions {
+            compress: true,
+            ..COMPRESSED
+        };
+        let size = |source: &str| assemble_riscv_with(source, &options).unwrap().image().len();
+        assert_eq!(size("loop: j loop"), 2);
+        assert_eq!(size("beqz a0, end\nbnez s0, end\nend: ret"), 6);
+
+        // The first two branches only reach `end` once the ones after them are compressed.
+        let source = format!("beqz s0, end\n{}end: ret", "bnez a0, end\n".repeat(64));
+        assert_eq!(size(&source), 132);
+
+        // Out of range of `c.j`, or not the registers of `c.beqz`.
+        let source = format!("j far\n{}far: ret", "nop\n".repeat(1024));
+        assert_eq!(size(&source), 4 + 2048 + 2);
+        assert_eq!(size("beqz t0, end\nend: ret"), 6);
+
+        // A label the linker resolves keeps the full range.
+        let options = AssembleOptions {
+            relocatable: true,
+            ..options
+        };
+        let assembly = assemble_riscv_with("j puts", &options).unwrap();
+        assert_eq!(assembly.image().len(), 4);
+    }
+
+    #[test]
+    fn test_compress_rv64() {
+        // `c.jal` is RV32 only, RV64 keeps the 32-bit `jal`.
+        let source = "jal ra, 8\naddi a0, a0, 1";
+        let size = |isa| {
+            let options = AssembleOptions {
+                isa,
+                compress: true,
+                ..Default::default()
+            };
+            assemble_riscv_with(source, &options).unwrap().image().len()
+        };
+        assert_eq!(size(Isa::RV32IC), 4);
+        assert_eq!(size(Isa::RV64IMAFDC_ZICSR), 6);
+    }
+
+    #[test]
+    fn test_compressed_labels() {
+        let source = "
+            start: c.beqz a0, end
+            c.addi a0, -1
+            lw a1, 0(a0)
+            c.j start
+            end: c.jr ra
+        ";
+        let assembly = assemble_riscv_with(source, &COMPRESSED).unwrap();
+        assert_eq!(assembly.labels["end"], 10);
+        let resolved = "c.beqz a0, 10\nc.addi a0, -1\nlw a1, 0(a0)\nc.j -8\nc.jr ra";
+        let program = parse_riscv_with(resolved, Isa::RV32IC).unwrap();
+        let instructions: Vec<_> = program
+            .symbols
+            .iter()
+            .flat_map(|symbol| symbol.node.instructions().to_vec())
+            .collect();
+        assert_eq!(text(&assembly), instructions);
+    }
+
+    #[test]
+    fn test_compressed_branch_out_of_range() {
+        let source = format!("c.beqz a0, far\n{}far: c.nop", "c.nop\n".repeat(128));
+        let errors = assemble_riscv_with(&source, &COMPRESSED).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].to_string(),
+            "label `far` is 258 bytes away, out of range of a 9 bit offset"
+        );
+
+        let source = format!("c.beqz a0, far\n{}far: c.nop", "c.nop\n".repeat(126));
+        assert!(assemble_riscv_with(&source, &COMPRESSED).is_ok());
+    }
+
+    #[test]
+    fn test_compressed_alignment() {
+        // Instructions only need to be aligned to halfwords.
+        let source = ".byte 1\nc.nop";
+        let errors = assemble_riscv_with(source, &COMPRESSED).unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "instruction is not aligned to 2 bytes, add `.align 1` before it"
+        );
+        assert!(assemble_riscv_with(".half 1\nnop", &COMPRESSED).is_ok());
+
+        // A `c.nop` pads up to the next word.
+        let assembly = assemble_riscv_with("c.nop\n.align 3\nret", &COMPRESSED).unwrap();
+        assert_eq!(
+            assembly.section(".text").unwrap().bytes,
+            [0x01, 0x00, 0x01, 0x00, 0x13, 0x00, 0x00, 0x00, 0x67, 0x80, 0x00, 0x00]
+        );
+    }
+}
@@ -1,30 +1,151 @@
 use std::num::NonZeroU32;
+use chumsky::span::SimpleSpan;
 use derive_more::From;
 
-#[derive(Debug)]
+use crate::riscv::isa::Xlen;
+use crate::span::Spanned;
+
+#[derive(Clone, Debug)]
 pub struct Program {
-    pub symbols: Vec<Symbol>,
+    pub symbols: Vec<Spanned<Symbol>>,
 }
 
-#[derive(Debug, From)]
+#[derive(Clone, Debug, From)]
 pub enum Symbol {
     Instruction(Instruction),
-    Comment,
+    Reference(Reference),
+    Pseudo(Pseudo),
+    Label(Label),
+    /// Text of a comment after its `#` or `//`.
+    Comment(String),
     Directive(Directive),
 }
 
-#[derive(Debug)]
+impl Symbol {
+    /// Base instructions the symbol stands for, pseudo-instructions are expanded.
+    pub fn instructions(&self) -> &[Instruction] {
+        match self {
+            Symbol::Instruction(instruction) => std::slice::from_ref(instruction),
+            Symbol::Reference(reference) => std::slice::from_ref(&reference.instruction),
+            Symbol::Pseudo(pseudo) => &pseudo.expansion,
+            Symbol::Label(_) | Symbol::Comment(_) | Symbol::Directive(_) => &[],
+        }
+    }
+}
+
+/// A pseudo-instruction as written in the source together with the base instructions it
+/// expands to, the assembler fills in the offsets of a `relocation`.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Pseudo {
+    pub opcode: PseudoOpcode,
+    pub expansion: Vec<Instruction>,
+    pub relocation: Option<Relocation>,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Label {
+    pub name: String,
+    pub span: SimpleSpan,
+}
+
+/// How the address of a label turns into an immediate.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum RelocationKind {
+    /// Offset from the branch to the label.
+    Branch,
+    /// Offset from the `jal` to the label.
+    Jump,
+    /// `%hi(label)`, upper 20 bits of the address.
+    Hi,
+    /// `%lo(label)`, lower 12 bits of the address.
+    Lo,
+    /// `%pcrel_hi(label)`, upper 20 bits of the offset from the instruction to the label.
+    PcrelHi,
+    /// `%pcrel_lo(label)`, lower 12 bits of the offset computed by the `%pcrel_hi` of the
+    /// instruction at `label`.
+    PcrelLo,
+    /// Offset from an `auipc` to the label, split between the `auipc` and the instruction
+    /// after it. Used by `la`, `call` and `tail`.
+    Pcrel,
+    /// Value of the symbol, split between a `lui` and the `addi` after it. Used by `li`.
+    Absolute,
+    /// `.word label`, the address of the label as data. Only the assembler produces it, for the
+    /// data of an object file.
+    Word,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Relocation {
+    pub kind: RelocationKind,
+    pub label: Label,
+}
+
+/// Instruction whose immediate refers to a label, filled in by the assembler.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Reference {
+    pub instruction: Instruction,
+    pub relocation: Relocation,
+}
+
+#[derive(Clone, Debug)]
 pub struct Alignment {
     pub alignment: usize,
 }
 
-#[derive(Debug)]
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum DataWidth {
+    Byte,
+    Half,
+    Word,
+}
+
+impl DataWidth {
+    pub fn bytes(self) -> usize {
+        match self {
+            DataWidth::Byte => 1,
+            DataWidth::Half => 2,
+            DataWidth::Word => 4,
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum DataValue {
+    /// Two's complement of the value, truncated to the width of the data.
+    Number(u32),
+    /// Address of the label, `.word` only.
+    Label(Label),
+}
+
+/// `.word`, `.half` and `.byte`.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Data {
+    pub width: DataWidth,
+    pub values: Vec<DataValue>,
+}
+
+/// `.equ name, value` and `.set name, value`, a symbol which stands for a number.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Constant {
+    pub label: Label,
+    pub value: u32,
+}
+
+#[derive(Clone, Debug)]
 pub enum Directive {
+    /// `.align n` and `.p2align n`, both align to 2^n bytes.
     Alignment(Alignment),
-    Other(String),
+    /// `.text`, `.data` and `.section name`, where the symbols after it go.
+    Section(String),
+    /// `.globl name`, exports the label.
+    Global(Label),
+    Data(Data),
+    /// `.ascii`, `.asciz`, `.string`, `.space` and `.zero`, copied into the section as is.
+    Bytes(Vec<u8>),
+    Constant(Constant),
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(PartialEq, Copy, Clone, Debug)]
 pub enum IOpcode {
     Addi,
     Slti,
@@ -35,9 +156,15 @@ pub enum IOpcode {
     Slli,
     Srli,
     Srai,
+    Jalr,
+    /// RV64I from here on, the result is the lower word sign extended.
+    Addiw,
+    Slliw,
+    Srliw,
+    Sraiw,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(PartialEq, Copy, Clone, Debug)]
 pub enum ROpcode {
     Add,
     Sub,
@@ -49,23 +176,51 @@ pub enum ROpcode {
     Sra,
     Or,
     And,
+    /// RV64I, the result is the lower word sign extended.
+    Addw,
+    Subw,
+    Sllw,
+    Srlw,
+    Sraw,
+    /// The M extension from here on.
+    Mul,
+    Mulh,
+    Mulhsu,
+    Mulhu,
+    Div,
+    Divu,
+    Rem,
+    Remu,
+    /// RV64M.
+    Mulw,
+    Divw,
+    Divuw,
+    Remw,
+    Remuw,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(PartialEq, Copy, Clone, Debug)]
 pub enum SOpcode {
     Sb,
     Sh,
     Sw,
+    /// RV64I.
+    Sd,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(PartialEq, Copy, Clone, Debug)]
 pub enum LOpcode {
     Lb,
     Lh,
     Lw,
+    Lbu,
+    Lhu,
+    /// RV64I.
+    Ld,
+    Lwu,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(PartialEq, Copy, Clone, Debug)]
 pub enum BOpcode {
     Beq,
     Bne,
@@ -75,17 +230,315 @@ pub enum BOpcode {
     Bgeu,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(PartialEq, Copy, Clone, Debug)]
 pub enum JOpcode {
     Jal,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(PartialEq, Copy, Clone, Debug)]
 pub enum UOpcode {
     Lui,
     Auipc,
 }
 
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum PseudoOpcode {
+    Nop,
+    Li,
+    La,
+    Mv,
+    Not,
+    Neg,
+    Seqz,
+    Snez,
+    Sltz,
+    Sgtz,
+    Beqz,
+    Bnez,
+    Blez,
+    Bgez,
+    Bltz,
+    Bgtz,
+    Bgt,
+    Ble,
+    Bgtu,
+    Bleu,
+    J,
+    Jal,
+    Jr,
+    Jalr,
+    Ret,
+    Call,
+    Tail,
+    Csrr,
+    Csrw,
+    Csrs,
+    Csrc,
+    Csrwi,
+    Csrsi,
+    Csrci,
+    FmvS,
+    FmvD,
+    FnegS,
+    FnegD,
+    FabsS,
+    FabsD,
+    Frcsr,
+    Fscsr,
+    Frrm,
+    Fsrm,
+    Frflags,
+    Fsflags,
+    SextW,
+    Negw,
+}
+
+/// The 16-bit instructions of the C extension, each stands for one base instruction.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum COpcode {
+    Addi4spn,
+    Lw,
+    Sw,
+    Nop,
+    Addi,
+    Jal,
+    Li,
+    Addi16sp,
+    Lui,
+    Srli,
+    Srai,
+    Andi,
+    Sub,
+    Xor,
+    Or,
+    And,
+    J,
+    Beqz,
+    Bnez,
+    Slli,
+    Lwsp,
+    Jr,
+    Mv,
+    Ebreak,
+    Jalr,
+    Add,
+    Swsp,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum SystemOpcode {
+    Ecall,
+    Ebreak,
+}
+
+/// The A extension, `lr.w` and `sc.w` reserve a word and store to it only if nothing else did
+/// in between, the others read, modify and write a word in one step. The operations ending in
+/// `.d` do the same with a doubleword on RV64.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum AOpcode {
+    LrW,
+    ScW,
+    AmoswapW,
+    AmoaddW,
+    AmoxorW,
+    AmoandW,
+    AmoorW,
+    AmominW,
+    AmomaxW,
+    AmominuW,
+    AmomaxuW,
+    LrD,
+    ScD,
+    AmoswapD,
+    AmoaddD,
+    AmoxorD,
+    AmoandD,
+    AmoorD,
+    AmominD,
+    AmomaxD,
+    AmominuD,
+    AmomaxuD,
+}
+
+/// The `.aq` and `.rl` suffixes of an atomic instruction, `.aqrl` sets both.
+#[derive(PartialEq, Copy, Clone, Debug, Default)]
+pub struct AtomicOrdering {
+    /// No later memory access happens before this one.
+    pub acquire: bool,
+    /// No earlier memory access happens after this one.
+    pub release: bool,
+}
+
+/// The Zicsr extension, atomically reads a CSR into `rd` and then writes, sets or clears bits
+/// of it.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum CsrOpcode {
+    Csrrw,
+    Csrrs,
+    Csrrc,
+}
+
+/// Number of a control and status register, 12 bits.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Csr(pub u16);
+
+impl Csr {
+    /// Names the assembler knows, the user counters and the machine-mode registers.
+    pub const NAMES: [(&'static str, u16); 26] = [
+        ("fflags", 0x001),
+        ("frm", 0x002),
+        ("fcsr", 0x003),
+        ("cycle", 0xC00),
+        ("time", 0xC01),
+        ("instret", 0xC02),
+        ("cycleh", 0xC80),
+        ("timeh", 0xC81),
+        ("instreth", 0xC82),
+        ("mvendorid", 0xF11),
+        ("marchid", 0xF12),
+        ("mimpid", 0xF13),
+        ("mhartid", 0xF14),
+        ("mstatus", 0x300),
+        ("misa", 0x301),
+        ("mie", 0x304),
+        ("mtvec", 0x305),
+        ("mscratch", 0x340),
+        ("mepc", 0x341),
+        ("mcause", 0x342),
+        ("mtval", 0x343),
+        ("mip", 0x344),
+        ("mcycle", 0xB00),
+        ("minstret", 0xB02),
+        ("mcycleh", 0xB80),
+        ("minstreth", 0xB82),
+    ];
+
+    pub fn from_name(name: &str) -> Option<Csr> {
+        Self::NAMES
+            .iter()
+            .find(|(known, _)| *known == name)
+            .map(|&(_, number)| Csr(number))
+    }
+
+    pub fn name(&self) -> Option<&'static str> {
+        Self::NAMES
+            .iter()
+            .find(|(_, number)| *number == self.0)
+            .map(|&(name, _)| name)
+    }
+}
+
+/// Width of the floating-point numbers an F or D instruction works on, the `.s` or `.d` suffix.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum FloatFormat {
+    Single,
+    Double,
+}
+
+/// How a floating-point result which is not exact is rounded, `Dynamic` takes the mode in
+/// `frm`.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum RoundingMode {
+    /// To nearest, ties to even.
+    Rne,
+    /// Towards zero.
+    Rtz,
+    /// Down, towards negative infinity.
+    Rdn,
+    /// Up, towards positive infinity.
+    Rup,
+    /// To nearest, ties away from zero.
+    Rmm,
+    Dynamic,
+}
+
+/// Floating-point operations on two floating-point registers with the result in a third.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum FOpcode {
+    Fadd,
+    Fsub,
+    Fmul,
+    Fdiv,
+    /// Sign injection, the magnitude of `rs1` with the sign of `rs2`, its negation or the
+    /// exclusive or of both signs.
+    Fsgnj,
+    Fsgnjn,
+    Fsgnjx,
+    Fmin,
+    Fmax,
+}
+
+/// Floating-point operations on one floating-point register.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum FUnaryOpcode {
+    Sqrt,
+    /// `fcvt.s.d` and `fcvt.d.s`, converts from the other format to the format of the
+    /// instruction.
+    Convert,
+}
+
+/// Floating-point operations with the result in an integer register.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum FToIntOpcode {
+    /// `fcvt.w.s`, to a signed word.
+    Convert,
+    /// `fcvt.wu.s`, to an unsigned word.
+    ConvertUnsigned,
+    /// `fcvt.l.s`, to a signed doubleword on RV64.
+    ConvertLong,
+    /// `fcvt.lu.s`, to an unsigned doubleword on RV64.
+    ConvertLongUnsigned,
+    /// `fmv.x.w`, copies the bits.
+    Move,
+    /// `fclass.s`, sets the bit of the class of the number, such as negative zero or NaN.
+    Classify,
+}
+
+/// Floating-point operations on an integer register.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum IntToFOpcode {
+    /// `fcvt.s.w`, from a signed word.
+    Convert,
+    /// `fcvt.s.wu`, from an unsigned word.
+    ConvertUnsigned,
+    /// `fcvt.s.l`, from a signed doubleword on RV64.
+    ConvertLong,
+    /// `fcvt.s.lu`, from an unsigned doubleword on RV64.
+    ConvertLongUnsigned,
+    /// `fmv.w.x`, copies the bits.
+    Move,
+}
+
+/// Comparisons of two floating-point registers, the result is 1 or 0 in an integer register.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum FCompareOpcode {
+    Feq,
+    Flt,
+    Fle,
+}
+
+/// Fused multiply-add, `rs1 * rs2 + rs3` rounded only once, with either product or sum negated.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum FusedOpcode {
+    Fmadd,
+    Fmsub,
+    Fnmsub,
+    Fnmadd,
+}
+
+/// Devices ordered by a `fence`, a combination of [`FenceSet::INPUT`], [`FenceSet::OUTPUT`],
+/// [`FenceSet::READ`] and [`FenceSet::WRITE`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FenceSet(pub u8);
+
+impl FenceSet {
+    pub const INPUT: u8 = 0b1000;
+    pub const OUTPUT: u8 = 0b0100;
+    pub const READ: u8 = 0b0010;
+    pub const WRITE: u8 = 0b0001;
+    pub const ALL: FenceSet = FenceSet(0b1111);
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct IImmediate(pub i16);
 
@@ -101,8 +554,16 @@ pub struct BImmediate(pub i16);
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct JImmediate(pub i32);
 
+/// Unsigned 5-bit immediate of `csrrwi`, `csrrsi` and `csrrci`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CsrImmediate(pub u8);
+
+/// Immediate of a compressed instruction as written in the source, the encoding scales it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CImmediate(pub i32);
+
 #[derive(Copy, Clone, Debug, PartialEq)]
-pub struct Register(u8);
+pub struct Register(pub u8);
 
 impl From<u8> for Register {
     fn from(value: u8) -> Self {
@@ -110,7 +571,17 @@ impl From<u8> for Register {
     }
 }
 
-#[derive(Debug)]
+/// A register of the F and D extensions, `f0` to `f31`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FRegister(pub u8);
+
+impl From<u8> for FRegister {
+    fn from(value: u8) -> Self {
+        FRegister(value)
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
 pub enum Instruction {
     IType {
         opcode: IOpcode,
@@ -152,20 +623,604 @@ pub enum Instruction {
         rs1: Register,
         imm: LSImmediate,
     },
+    Fence {
+        pred: FenceSet,
+        succ: FenceSet,
+    },
+    System {
+        opcode: SystemOpcode,
+    },
+    /// `rs1` holds the address, `rs2` is `x0` for `lr.w`.
+    Atomic {
+        opcode: AOpcode,
+        rd: Register,
+        rs1: Register,
+        rs2: Register,
+        ordering: AtomicOrdering,
+    },
+    Csr {
+        opcode: CsrOpcode,
+        rd: Register,
+        rs1: Register,
+        csr: Csr,
+    },
+    /// `csrrwi`, `csrrsi` and `csrrci`, which take an immediate instead of `rs1`.
+    CsrImmediate {
+        opcode: CsrOpcode,
+        rd: Register,
+        imm: CsrImmediate,
+        csr: Csr,
+    },
+    /// `flw` and `fld`.
+    FloatLoad {
+        format: FloatFormat,
+        rd: FRegister,
+        rs1: Register,
+        imm: LSImmediate,
+    },
+    /// `fsw` and `fsd`.
+    FloatStore {
+        format: FloatFormat,
+        rs1: Register,
+        rs2: FRegister,
+        imm: LSImmediate,
+    },
+    /// `rm` is [`RoundingMode::Dynamic`] for the opcodes which do not round.
+    FloatRType {
+        opcode: FOpcode,
+        format: FloatFormat,
+        rd: FRegister,
+        rs1: FRegister,
+        rs2: FRegister,
+        rm: RoundingMode,
+    },
+    FloatUnary {
+        opcode: FUnaryOpcode,
+        format: FloatFormat,
+        rd: FRegister,
+        rs1: FRegister,
+        rm: RoundingMode,
+    },
+    /// `rm` is [`RoundingMode::Dynamic`] for moves and `fclass`.
+    FloatToInt {
+        opcode: FToIntOpcode,
+        format: FloatFormat,
+        rd: Register,
+        rs1: FRegister,
+        rm: RoundingMode,
+    },
+    /// `rm` is [`RoundingMode::Dynamic`] for moves.
+    IntToFloat {
+        opcode: IntToFOpcode,
+        format: FloatFormat,
+        rd: FRegister,
+        rs1: Register,
+        rm: RoundingMode,
+    },
+    FloatCompare {
+        opcode: FCompareOpcode,
+        format: FloatFormat,
+        rd: Register,
+        rs1: FRegister,
+        rs2: FRegister,
+    },
+    FloatFused {
+        opcode: FusedOpcode,
+        format: FloatFormat,
+        rd: FRegister,
+        rs1: FRegister,
+        rs2: FRegister,
+        rs3: FRegister,
+        rm: RoundingMode,
+    },
+    /// The operands as written in the source, registers and immediate the opcode does not
+    /// take are `x0` and 0.
+    Compressed {
+        opcode: COpcode,
+        rd: Register,
+        rs1: Register,
+        rs2: Register,
+        imm: CImmediate,
+    },
 }
 
 impl IOpcode {
-    pub fn immediate_properties(&self) -> (NonZeroU32, bool) {
+    ///
+    /// Bits of the immediate and whether it is signed, shifts can shift by up to `xlen - 1`.
+    ///
+    /// `sltiu` sign-extends its immediate like the others and only compares unsigned.
+    ///
+    pub fn immediate_properties(&self, xlen: Xlen) -> (NonZeroU32, bool) {
         match self {
             IOpcode::Addi => (12.try_into().unwrap(), true),
             IOpcode::Slti => (12.try_into().unwrap(), true),
-            IOpcode::Sltiu => (12.try_into().unwrap(), false),
+            IOpcode::Sltiu => (12.try_into().unwrap(), true),
             IOpcode::Xori => (12.try_into().unwrap(), true),
             IOpcode::Ori => (12.try_into().unwrap(), true),
             IOpcode::Andi => (12.try_into().unwrap(), true),
-            IOpcode::Slli => (5.try_into().unwrap(), false),
-            IOpcode::Srli => (5.try_into().unwrap(), false),
-            IOpcode::Srai => (5.try_into().unwrap(), false),
+            IOpcode::Slli => (xlen.shift_bits().try_into().unwrap(), false),
+            IOpcode::Srli => (xlen.shift_bits().try_into().unwrap(), false),
+            IOpcode::Srai => (xlen.shift_bits().try_into().unwrap(), false),
+            IOpcode::Jalr => (12.try_into().unwrap(), true),
+            IOpcode::Addiw => (12.try_into().unwrap(), true),
+            IOpcode::Slliw => (5.try_into().unwrap(), false),
+            IOpcode::Srliw => (5.try_into().unwrap(), false),
+            IOpcode::Sraiw => (5.try_into().unwrap(), false),
+        }
+    }
+}
+
+impl Register {
+    /// Names of `x0` to `x31` in the standard calling convention, `s0` is also known as `fp`.
+    pub const ABI_NAMES: [&'static str; 32] = [
+        "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+        "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+        "t5", "t6",
+    ];
+
+    pub fn abi_name(&self) -> &'static str {
+        Self::ABI_NAMES[(self.0 & 0x1F) as usize]
+    }
+}
+
+impl FRegister {
+    /// Names of `f0` to `f31` in the standard calling convention.
+    pub const ABI_NAMES: [&'static str; 32] = [
+        "ft0", "ft1", "ft2", "ft3", "ft4", "ft5", "ft6", "ft7", "fs0", "fs1", "fa0", "fa1", "fa2",
+        "fa3", "fa4", "fa5", "fa6", "fa7", "fs2", "fs3", "fs4", "fs5", "fs6", "fs7", "fs8", "fs9",
+        "fs10", "fs11", "ft8", "ft9", "ft10", "ft11",
+    ];
+
+    pub fn abi_name(&self) -> &'static str {
+        Self::ABI_NAMES[(self.0 & 0x1F) as usize]
+    }
+}
+
+impl RoundingMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RoundingMode::Rne => "rne",
+            RoundingMode::Rtz => "rtz",
+            RoundingMode::Rdn => "rdn",
+            RoundingMode::Rup => "rup",
+            RoundingMode::Rmm => "rmm",
+            RoundingMode::Dynamic => "dyn",
+        }
+    }
+}
+
+impl FOpcode {
+    /// Whether the result is rounded, the others are exact and take no rounding mode.
+    pub fn rounds(&self) -> bool {
+        matches!(
+            self,
+            FOpcode::Fadd | FOpcode::Fsub | FOpcode::Fmul | FOpcode::Fdiv
+        )
+    }
+
+    pub fn mnemonic(&self, format: FloatFormat) -> &'static str {
+        match (self, format) {
+            (FOpcode::Fadd, FloatFormat::Single) => "fadd.s",
+            (FOpcode::Fadd, FloatFormat::Double) => "fadd.d",
+            (FOpcode::Fsub, FloatFormat::Single) => "fsub.s",
+            (FOpcode::Fsub, FloatFormat::Double) => "fsub.d",
+            (FOpcode::Fmul, FloatFormat::Single) => "fmul.s",
+            (FOpcode::Fmul, FloatFormat::Double) => "fmul.d",
+            (FOpcode::Fdiv, FloatFormat::Single) => "fdiv.s",
+            (FOpcode::Fdiv, FloatFormat::Double) => "fdiv.d",
+            (FOpcode::Fsgnj, FloatFormat::Single) => "fsgnj.s",
+            (FOpcode::Fsgnj, FloatFormat::Double) => "fsgnj.d",
+            (FOpcode::Fsgnjn, FloatFormat::Single) => "fsgnjn.s",
+            (FOpcode::Fsgnjn, FloatFormat::Double) => "fsgnjn.d",
+            (FOpcode::Fsgnjx, FloatFormat::Single) => "fsgnjx.s",
+            (FOpcode::Fsgnjx, FloatFormat::Double) => "fsgnjx.d",
+            (FOpcode::Fmin, FloatFormat::Single) => "fmin.s",
+            (FOpcode::Fmin, FloatFormat::Double) => "fmin.d",
+            (FOpcode::Fmax, FloatFormat::Single) => "fmax.s",
+            (FOpcode::Fmax, FloatFormat::Double) => "fmax.d",
+        }
+    }
+}
+
+impl FUnaryOpcode {
+    pub fn mnemonic(&self, format: FloatFormat) -> &'static str {
+        match (self, format) {
+            (FUnaryOpcode::Sqrt, FloatFormat::Single) => "fsqrt.s",
+            (FUnaryOpcode::Sqrt, FloatFormat::Double) => "fsqrt.d",
+            (FUnaryOpcode::Convert, FloatFormat::Single) => "fcvt.s.d",
+            (FUnaryOpcode::Convert, FloatFormat::Double) => "fcvt.d.s",
+        }
+    }
+
+    /// Rounding mode when the source leaves it out, `rne` for `fcvt.d.s`, which is exact, as
+    /// with GNU as.
+    pub fn default_rounding(&self, format: FloatFormat) -> RoundingMode {
+        match (self, format) {
+            (FUnaryOpcode::Convert, FloatFormat::Double) => RoundingMode::Rne,
+            _ => RoundingMode::Dynamic,
+        }
+    }
+}
+
+impl FToIntOpcode {
+    pub fn rounds(&self) -> bool {
+        !matches!(self, FToIntOpcode::Move | FToIntOpcode::Classify)
+    }
+
+    pub fn mnemonic(&self, format: FloatFormat) -> &'static str {
+        match (self, format) {
+            (FToIntOpcode::Convert, FloatFormat::Single) => "fcvt.w.s",
+            (FToIntOpcode::Convert, FloatFormat::Double) => "fcvt.w.d",
+            (FToIntOpcode::ConvertUnsigned, FloatFormat::Single) => "fcvt.wu.s",
+            (FToIntOpcode::ConvertUnsigned, FloatFormat::Double) => "fcvt.wu.d",
+            (FToIntOpcode::ConvertLong, FloatFormat::Single) => "fcvt.l.s",
+            (FToIntOpcode::ConvertLong, FloatFormat::Double) => "fcvt.l.d",
+            (FToIntOpcode::ConvertLongUnsigned, FloatFormat::Single) => "fcvt.lu.s",
+            (FToIntOpcode::ConvertLongUnsigned, FloatFormat::Double) => "fcvt.lu.d",
+            (FToIntOpcode::Move, FloatFormat::Single) => "fmv.x.w",
+            (FToIntOpcode::Move, FloatFormat::Double) => "fmv.x.d",
+            (FToIntOpcode::Classify, FloatFormat::Single) => "fclass.s",
+            (FToIntOpcode::Classify, FloatFormat::Double) => "fclass.d",
+        }
+    }
+}
+
+impl IntToFOpcode {
+    pub fn rounds(&self) -> bool {
+        !matches!(self, IntToFOpcode::Move)
+    }
+
+    pub fn mnemonic(&self, format: FloatFormat) -> &'static str {
+        match (self, format) {
+            (IntToFOpcode::Convert, FloatFormat::Single) => "fcvt.s.w",
+            (IntToFOpcode::Convert, FloatFormat::Double) => "fcvt.d.w",
+            (IntToFOpcode::ConvertUnsigned, FloatFormat::Single) => "fcvt.s.wu",
+            (IntToFOpcode::ConvertUnsigned, FloatFormat::Double) => "fcvt.d.wu",
+            (IntToFOpcode::ConvertLong, FloatFormat::Single) => "fcvt.s.l",
+            (IntToFOpcode::ConvertLong, FloatFormat::Double) => "fcvt.d.l",
+            (IntToFOpcode::ConvertLongUnsigned, FloatFormat::Single) => "fcvt.s.lu",
+            (IntToFOpcode::ConvertLongUnsigned, FloatFormat::Double) => "fcvt.d.lu",
+            (IntToFOpcode::Move, FloatFormat::Single) => "fmv.w.x",
+            (IntToFOpcode::Move, FloatFormat::Double) => "fmv.d.x",
+        }
+    }
+
+    /// Rounding mode when the source leaves it out, `rne` for `fcvt.d.w` and `fcvt.d.wu`,
+    /// which are exact, as with GNU as.
+    pub fn default_rounding(&self, format: FloatFormat) -> RoundingMode {
+        match (self, format) {
+            (IntToFOpcode::Convert | IntToFOpcode::ConvertUnsigned, FloatFormat::Double) => {
+                RoundingMode::Rne
+            }
+            _ => RoundingMode::Dynamic,
+        }
+    }
+}
+
+impl FCompareOpcode {
+    pub fn mnemonic(&self, format: FloatFormat) -> &'static str {
+        match (self, format) {
+            (FCompareOpcode::Feq, FloatFormat::Single) => "feq.s",
+            (FCompareOpcode::Feq, FloatFormat::Double) => "feq.d",
+            (FCompareOpcode::Flt, FloatFormat::Single) => "flt.s",
+            (FCompareOpcode::Flt, FloatFormat::Double) => "flt.d",
+            (FCompareOpcode::Fle, FloatFormat::Single) => "fle.s",
+            (FCompareOpcode::Fle, FloatFormat::Double) => "fle.d",
+        }
+    }
+}
+
+impl FusedOpcode {
+    pub fn mnemonic(&self, format: FloatFormat) -> &'static str {
+        match (self, format) {
+            (FusedOpcode::Fmadd, FloatFormat::Single) => "fmadd.s",
+            (FusedOpcode::Fmadd, FloatFormat::Double) => "fmadd.d",
+            (FusedOpcode::Fmsub, FloatFormat::Single) => "fmsub.s",
+            (FusedOpcode::Fmsub, FloatFormat::Double) => "fmsub.d",
+            (FusedOpcode::Fnmsub, FloatFormat::Single) => "fnmsub.s",
+            (FusedOpcode::Fnmsub, FloatFormat::Double) => "fnmsub.d",
+            (FusedOpcode::Fnmadd, FloatFormat::Single) => "fnmadd.s",
+            (FusedOpcode::Fnmadd, FloatFormat::Double) => "fnmadd.d",
+        }
+    }
+}
+
+impl FloatFormat {
+    pub fn load_mnemonic(&self) -> &'static str {
+        match self {
+            FloatFormat::Single => "flw",
+            FloatFormat::Double => "fld",
+        }
+    }
+
+    pub fn store_mnemonic(&self) -> &'static str {
+        match self {
+            FloatFormat::Single => "fsw",
+            FloatFormat::Double => "fsd",
+        }
+    }
+}
+
+impl IOpcode {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            IOpcode::Addi => "addi",
+            IOpcode::Slti => "slti",
+            IOpcode::Sltiu => "sltiu",
+            IOpcode::Xori => "xori",
+            IOpcode::Ori => "ori",
+            IOpcode::Andi => "andi",
+            IOpcode::Slli => "slli",
+            IOpcode::Srli => "srli",
+            IOpcode::Srai => "srai",
+            IOpcode::Jalr => "jalr",
+            IOpcode::Addiw => "addiw",
+            IOpcode::Slliw => "slliw",
+            IOpcode::Srliw => "srliw",
+            IOpcode::Sraiw => "sraiw",
+        }
+    }
+}
+
+impl ROpcode {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            ROpcode::Add => "add",
+            ROpcode::Sub => "sub",
+            ROpcode::Sll => "sll",
+            ROpcode::Slt => "slt",
+            ROpcode::Sltu => "sltu",
+            ROpcode::Xor => "xor",
+            ROpcode::Srl => "srl",
+            ROpcode::Sra => "sra",
+            ROpcode::Or => "or",
+            ROpcode::And => "and",
+            ROpcode::Addw => "addw",
+            ROpcode::Subw => "subw",
+            ROpcode::Sllw => "sllw",
+            ROpcode::Srlw => "srlw",
+            ROpcode::Sraw => "sraw",
+            ROpcode::Mul => "mul",
+            ROpcode::Mulh => "mulh",
+            ROpcode::Mulhsu => "mulhsu",
+            ROpcode::Mulhu => "mulhu",
+            ROpcode::Div => "div",
+            ROpcode::Divu => "divu",
+            ROpcode::Rem => "rem",
+            ROpcode::Remu => "remu",
+            ROpcode::Mulw => "mulw",
+            ROpcode::Divw => "divw",
+            ROpcode::Divuw => "divuw",
+            ROpcode::Remw => "remw",
+            ROpcode::Remuw => "remuw",
+        }
+    }
+}
+
+impl SOpcode {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            SOpcode::Sb => "sb",
+            SOpcode::Sh => "sh",
+            SOpcode::Sw => "sw",
+            SOpcode::Sd => "sd",
+        }
+    }
+}
+
+impl LOpcode {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            LOpcode::Lb => "lb",
+            LOpcode::Lh => "lh",
+            LOpcode::Lw => "lw",
+            LOpcode::Lbu => "lbu",
+            LOpcode::Lhu => "lhu",
+            LOpcode::Ld => "ld",
+            LOpcode::Lwu => "lwu",
+        }
+    }
+}
+
+impl BOpcode {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            BOpcode::Beq => "beq",
+            BOpcode::Bne => "bne",
+            BOpcode::Blt => "blt",
+            BOpcode::Bge => "bge",
+            BOpcode::Bltu => "bltu",
+            BOpcode::Bgeu => "bgeu",
+        }
+    }
+}
+
+impl JOpcode {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            JOpcode::Jal => "jal",
+        }
+    }
+}
+
+impl UOpcode {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            UOpcode::Lui => "lui",
+            UOpcode::Auipc => "auipc",
+        }
+    }
+}
+
+impl SystemOpcode {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            SystemOpcode::Ecall => "ecall",
+            SystemOpcode::Ebreak => "ebreak",
+        }
+    }
+}
+
+impl AOpcode {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            AOpcode::LrW => "lr.w",
+            AOpcode::ScW => "sc.w",
+            AOpcode::AmoswapW => "amoswap.w",
+            AOpcode::AmoaddW => "amoadd.w",
+            AOpcode::AmoxorW => "amoxor.w",
+            AOpcode::AmoandW => "amoand.w",
+            AOpcode::AmoorW => "amoor.w",
+            AOpcode::AmominW => "amomin.w",
+            AOpcode::AmomaxW => "amomax.w",
+            AOpcode::AmominuW => "amominu.w",
+            AOpcode::AmomaxuW => "amomaxu.w",
+            AOpcode::LrD => "lr.d",
+            AOpcode::ScD => "sc.d",
+            AOpcode::AmoswapD => "amoswap.d",
+            AOpcode::AmoaddD => "amoadd.d",
+            AOpcode::AmoxorD => "amoxor.d",
+            AOpcode::AmoandD => "amoand.d",
+            AOpcode::AmoorD => "amoor.d",
+            AOpcode::AmominD => "amomin.d",
+            AOpcode::AmomaxD => "amomax.d",
+            AOpcode::AmominuD => "amominu.d",
+            AOpcode::AmomaxuD => "amomaxu.d",
+        }
+    }
+}
+
+impl CsrOpcode {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            CsrOpcode::Csrrw => "csrrw",
+            CsrOpcode::Csrrs => "csrrs",
+            CsrOpcode::Csrrc => "csrrc",
+        }
+    }
+
+    pub fn immediate_mnemonic(&self) -> &'static str {
+        match self {
+            CsrOpcode::Csrrw => "csrrwi",
+            CsrOpcode::Csrrs => "csrrsi",
+            CsrOpcode::Csrrc => "csrrci",
+        }
+    }
+}
+
+impl COpcode {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            COpcode::Addi4spn => "c.addi4spn",
+            COpcode::Lw => "c.lw",
+            COpcode::Sw => "c.sw",
+            COpcode::Nop => "c.nop",
+            COpcode::Addi => "c.addi",
+            COpcode::Jal => "c.jal",
+            COpcode::Li => "c.li",
+            COpcode::Addi16sp => "c.addi16sp",
+            COpcode::Lui => "c.lui",
+            COpcode::Srli => "c.srli",
+            COpcode::Srai => "c.srai",
+            COpcode::Andi => "c.andi",
+            COpcode::Sub => "c.sub",
+            COpcode::Xor => "c.xor",
+            COpcode::Or => "c.or",
+            COpcode::And => "c.and",
+            COpcode::J => "c.j",
+            COpcode::Beqz => "c.beqz",
+            COpcode::Bnez => "c.bnez",
+            COpcode::Slli => "c.slli",
+            COpcode::Lwsp => "c.lwsp",
+            COpcode::Jr => "c.jr",
+            COpcode::Mv => "c.mv",
+            COpcode::Ebreak => "c.ebreak",
+            COpcode::Jalr => "c.jalr",
+            COpcode::Add => "c.add",
+            COpcode::Swsp => "c.swsp",
+        }
+    }
+}
+
+impl Instruction {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Instruction::IType { opcode, .. } => opcode.mnemonic(),
+            Instruction::UType { opcode, .. } => opcode.mnemonic(),
+            Instruction::RType { opcode, .. } => opcode.mnemonic(),
+            Instruction::JType { opcode, .. } => opcode.mnemonic(),
+            Instruction::BType { opcode, .. } => opcode.mnemonic(),
+            Instruction::SType { opcode, .. } => opcode.mnemonic(),
+            Instruction::LType { opcode, .. } => opcode.mnemonic(),
+            Instruction::Fence { .. } => "fence",
+            Instruction::System { opcode } => opcode.mnemonic(),
+            Instruction::Atomic { opcode, .. } => opcode.mnemonic(),
+            Instruction::Csr { opcode, .. } => opcode.mnemonic(),
+            Instruction::CsrImmediate { opcode, .. } => opcode.immediate_mnemonic(),
+            Instruction::FloatLoad { format, .. } => format.load_mnemonic(),
+            Instruction::FloatStore { format, .. } => format.store_mnemonic(),
+            Instruction::FloatRType { opcode, format, .. } => opcode.mnemonic(*format),
+            Instruction::FloatUnary { opcode, format, .. } => opcode.mnemonic(*format),
+            Instruction::FloatToInt { opcode, format, .. } => opcode.mnemonic(*format),
+            Instruction::IntToFloat { opcode, format, .. } => opcode.mnemonic(*format),
+            Instruction::FloatCompare { opcode, format, .. } => opcode.mnemonic(*format),
+            Instruction::FloatFused { opcode, format, .. } => opcode.mnemonic(*format),
+            Instruction::Compressed { opcode, .. } => opcode.mnemonic(),
+        }
+    }
+}
+
+impl PseudoOpcode {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            PseudoOpcode::Nop => "nop",
+            PseudoOpcode::Li => "li",
+            PseudoOpcode::La => "la",
+            PseudoOpcode::Mv => "mv",
+            PseudoOpcode::Not => "not",
+            PseudoOpcode::Neg => "neg",
+            PseudoOpcode::Seqz => "seqz",
+            PseudoOpcode::Snez => "snez",
+            PseudoOpcode::Sltz => "sltz",
+            PseudoOpcode::Sgtz => "sgtz",
+            PseudoOpcode::Beqz => "beqz",
+            PseudoOpcode::Bnez => "bnez",
+            PseudoOpcode::Blez => "blez",
+            PseudoOpcode::Bgez => "bgez",
+            PseudoOpcode::Bltz => "bltz",
+            PseudoOpcode::Bgtz => "bgtz",
+            PseudoOpcode::Bgt => "bgt",
+            PseudoOpcode::Ble => "ble",
+            PseudoOpcode::Bgtu => "bgtu",
+            PseudoOpcode::Bleu => "bleu",
+            PseudoOpcode::J => "j",
+            PseudoOpcode::Jal => "jal",
+            PseudoOpcode::Jr => "jr",
+            PseudoOpcode::Jalr => "jalr",
+            PseudoOpcode::Ret => "ret",
+            PseudoOpcode::Call => "call",
+            PseudoOpcode::Tail => "tail",
+            PseudoOpcode::Csrr => "csrr",
+            PseudoOpcode::Csrw => "csrw",
+            PseudoOpcode::Csrs => "csrs",
+            PseudoOpcode::Csrc => "csrc",
+            PseudoOpcode::Csrwi => "csrwi",
+            PseudoOpcode::Csrsi => "csrsi",
+            PseudoOpcode::Csrci => "csrci",
+            PseudoOpcode::FmvS => "fmv.s",
+            PseudoOpcode::FmvD => "fmv.d",
+            PseudoOpcode::FnegS => "fneg.s",
+            PseudoOpcode::FnegD => "fneg.d",
+            PseudoOpcode::FabsS => "fabs.s",
+            PseudoOpcode::FabsD => "fabs.d",
+            PseudoOpcode::Frcsr => "frcsr",
+            PseudoOpcode::Fscsr => "fscsr",
+            PseudoOpcode::Frrm => "frrm",
+            PseudoOpcode::Fsrm => "fsrm",
+            PseudoOpcode::Frflags => "frflags",
+            PseudoOpcode::Fsflags => "fsflags",
+            PseudoOpcode::SextW => "sext.w",
+            PseudoOpcode::Negw => "negw",
         }
     }
 }
@@ -0,0 +1,778 @@
+//!
+//! The C extension, 16-bit encodings of common instructions. They only reach some registers and
+//! small immediates, and each stands for one base instruction, which is what gets executed.
+//!
+//! The lowest two bits tell the lengths apart, `11` starts a 32-bit instruction and everything
+//! else is one of the three quadrants of 16-bit instructions. `rd'`, `rs1'` and `rs2'` are the
+//! registers `x8` to `x15` in three bits:
+//!
+//! ```text
+//!   15      13  12  11  10  9       7   6   5   4       2   1   0
+//! |     funct4    |       rd/rs1      |        rs2        |   op  |  CR
+//! |   funct3  |imm|       rd/rs1      |        imm        |   op  |  CI
+//! |   funct3  |          imm          |        rs2        |   op  |  CSS
+//! |   funct3  |              imm              |    rd'    |   op  |  CIW
+//! |   funct3  |    imm    |    rs1'   |  imm  |    rd'    |   op  |  CL
+//! |   funct3  |    imm    |    rs1'   |  imm  |    rs2'   |   op  |  CS
+//! |         funct6        |    rd'    | funct2|    rs2'   |   op  |  CA
+//! |   funct3  |   offset  |    rs1'   |       offset      |   op  |  CB
+//! |   funct3  |                jump target                |   op  |  CJ
+//! ```
+//!
+
+use crate::riscv::ast::{
+    BImmediate, BOpcode, CImmediate, COpcode, IImmediate, IOpcode, Instruction, JImmediate,
+    JOpcode, LOpcode, LSImmediate, Program, ROpcode, Register, SOpcode, Symbol, SystemOpcode,
+    UImmediate, UOpcode,
+};
+use crate::riscv::encoding::{encode, DecodeError};
+use crate::riscv::isa::Isa;
+
+const ZERO: Register = Register(0);
+const RA: Register = Register(1);
+const SP: Register = Register(2);
+
+/// Whether `rd`, `rs1`, `rs2` and the immediate are operands of the opcode.
+fn operands(opcode: COpcode) -> (bool, bool, bool, bool) {
+    match opcode {
+        COpcode::Nop | COpcode::Ebreak => (false, false, false, false),
+        COpcode::Addi4spn | COpcode::Lw | COpcode::Lwsp => (true, true, false, true),
+        COpcode::Sw | COpcode::Swsp => (false, true, true, true),
+        COpcode::Addi
+        | COpcode::Li
+        | COpcode::Addi16sp
+        | COpcode::Lui
+        | COpcode::Srli
+        | COpcode::Srai
+        | COpcode::Andi
+        | COpcode::Slli => (true, false, false, true),
+        COpcode::Sub | COpcode::Xor | COpcode::Or | COpcode::And | COpcode::Mv | COpcode::Add => {
+            (true, false, true, false)
+        }
+        COpcode::Jal | COpcode::J => (false, false, false, true),
+        COpcode::Beqz | COpcode::Bnez => (false, true, false, true),
+        COpcode::Jr | COpcode::Jalr => (false, true, false, false),
+    }
+}
+
+/// Why the operands of a compressed instruction do not fit into its encoding, other
+/// instructions always pass.
+pub fn check(instruction: &Instruction) -> Result<(), String> {
+    let Instruction::Compressed {
+        opcode,
+        rd,
+        rs1,
+        rs2,
+        imm: CImmediate(imm),
+    } = *instruction
+    else {
+        return Ok(());
+    };
+    let mnemonic = opcode.mnemonic();
+    let compact = |register: Register| {
+        if (8..16).contains(&register.0) {
+            Ok(())
+        } else {
+            Err(format!(
+                "`{}` only takes the registers s0, s1 and a0 to a5 (x8 to x15), not {}",
+                mnemonic, register
+            ))
+        }
+    };
+    let nonzero = |register: Register| {
+        if register == ZERO {
+            Err(format!("`{}` cannot take `zero` as a register", mnemonic))
+        } else {
+            Ok(())
+        }
+    };
+    let stack = |register: Register| {
+        if register == SP {
+            Ok(())
+        } else {
+            Err(format!("`{}` only works relative to `sp`", mnemonic))
+        }
+    };
+    let immediate = |min: i32, max: i32, step: i32, nonzero: bool| {
+        if (min..=max).contains(&imm) && imm % step == 0 && !(nonzero && imm == 0) {
+            return Ok(());
+        }
+        let what = match (nonzero, step) {
+            (false, 1) => "an immediate".to_string(),
+            (true, 1) => "a nonzero immediate".to_string(),
+            (false, step) => format!("a multiple of {}", step),
+            (true, step) => format!("a nonzero multiple of {}", step),
+        };
+        Err(format!(
+            "`{}` takes {} from {} to {}, not {}",
+            mnemonic, what, min, max, imm
+        ))
+    };
+
+    match opcode {
+        COpcode::Nop | COpcode::Ebreak => Ok(()),
+        COpcode::Addi4spn => {
+            compact(rd)?;
+            stack(rs1)?;
+            immediate(4, 1020, 4, true)
+        }
+        COpcode::Lw => {
+            compact(rd)?;
+            compact(rs1)?;
+            immediate(0, 124, 4, false)
+        }
+        COpcode::Sw => {
+            compact(rs1)?;
+            compact(rs2)?;
+            immediate(0, 124, 4, false)
+        }
+        COpcode::Addi => {
+            nonzero(rd)?;
+            immediate(-32, 31, 1, true)
+        }
+        COpcode::Li => {
+            nonzero(rd)?;
+            immediate(-32, 31, 1, false)
+        }
+        COpcode::Addi16sp => {
+            stack(rd)?;
+            immediate(-512, 496, 16, true)
+        }
+        COpcode::Lui if rd == SP => Err("`c.lui` cannot load `sp`, see `c.addi16sp`".to_string()),
+        COpcode::Lui => {
+            nonzero(rd)?;
+            immediate(-32, 31, 1, true)
+        }
+        COpcode::Srli | COpcode::Srai => {
+            compact(rd)?;
+            immediate(1, 31, 1, false)
+        }
+        COpcode::Andi => {
+            compact(rd)?;
+            immediate(-32, 31, 1, false)
+        }
+        COpcode::Sub | COpcode::Xor | COpcode::Or | COpcode::And => {
+            compact(rd)?;
+            compact(rs2)
+        }
+        COpcode::Jal | COpcode::J => immediate(-2048, 2046, 2, false),
+        COpcode::Beqz | COpcode::Bnez => {
+            compact(rs1)?;
+            immediate(-256, 254, 2, false)
+        }
+        COpcode::Slli => {
+            nonzero(rd)?;
+            immediate(1, 31, 1, false)
+        }
+        COpcode::Lwsp => {
+            nonzero(rd)?;
+            stack(rs1)?;
+            immediate(0, 252, 4, false)
+        }
+        COpcode::Swsp => {
+            stack(rs1)?;
+            immediate(0, 252, 4, false)
+        }
+        COpcode::Jr | COpcode::Jalr => nonzero(rs1),
+        COpcode::Mv | COpcode::Add => {
+            nonzero(rd)?;
+            nonzero(rs2)
+        }
+    }
+}
+
+impl Instruction {
+    /// The base instruction a compressed instruction stands for, others are returned as they are.
+    pub fn expand(&self) -> Instruction {
+        let Instruction::Compressed {
+            opcode,
+            rd,
+            rs1,
+            rs2,
+            imm: CImmediate(imm),
+        } = *self
+        else {
+            return *self;
+        };
+        let i_type = |opcode, rd, rs1, imm: i32| Instruction::IType {
+            opcode,
+            rd,
+            rs1,
+            imm: IImmediate(imm as i16),
+        };
+        let r_type = |opcode, rd, rs1| Instruction::RType {
+            opcode,
+            rd,
+            rs1,
+            rs2,
+        };
+        let jal = |rd| Instruction::JType {
+            opcode: JOpcode::Jal,
+            rd,
+            imm: JImmediate(imm),
+        };
+        let branch = |opcode| Instruction::BType {
+            opcode,
+            rs1,
+            rs2: ZERO,
+            imm: BImmediate(imm as i16),
+        };
+        match opcode {
+            COpcode::Addi4spn => i_type(IOpcode::Addi, rd, rs1, imm),
+            COpcode::Nop => i_type(IOpcode::Addi, ZERO, ZERO, 0),
+            COpcode::Addi | COpcode::Addi16sp => i_type(IOpcode::Addi, rd, rd, imm),
+            COpcode::Li => i_type(IOpcode::Addi, rd, ZERO, imm),
+            COpcode::Srli => i_type(IOpcode::Srli, rd, rd, imm),
+            COpcode::Srai => i_type(IOpcode::Srai, rd, rd, imm),
+            COpcode::Andi => i_type(IOpcode::Andi, rd, rd, imm),
+            COpcode::Slli => i_type(IOpcode::Slli, rd, rd, imm),
+            COpcode::Jr => i_type(IOpcode::Jalr, ZERO, rs1, 0),
+            COpcode::Jalr => i_type(IOpcode::Jalr, RA, rs1, 0),
+            COpcode::Lw | COpcode::Lwsp => Instruction::LType {
+                opcode: LOpcode::Lw,
+                rd,
+                rs1,
+                imm: LSImmediate(imm as i16),
+            },
+            COpcode::Sw | COpcode::Swsp => Instruction::SType {
+                opcode: SOpcode::Sw,
+                rs1,
+                rs2,
+                imm: LSImmediate(imm as i16),
+            },
+            COpcode::Lui => Instruction::UType {
+                opcode: UOpcode::Lui,
+                rd,
+                imm: UImmediate(imm),
+            },
+            COpcode::Sub => r_type(ROpcode::Sub, rd, rd),
+            COpcode::Xor => r_type(ROpcode::Xor, rd, rd),
+            COpcode::Or => r_type(ROpcode::Or, rd, rd),
+            COpcode::And => r_type(ROpcode::And, rd, rd),
+            COpcode::Add => r_type(ROpcode::Add, rd, rd),
+            COpcode::Mv => r_type(ROpcode::Add, rd, ZERO),
+            COpcode::J => jal(ZERO),
+            COpcode::Jal => jal(RA),
+            COpcode::Beqz => branch(BOpcode::Beq),
+            COpcode::Bnez => branch(BOpcode::Bne),
+            COpcode::Ebreak => Instruction::System {
+                opcode: SystemOpcode::Ebreak,
+            },
+        }
+    }
+}
+
+///
+/// The compressed instruction which stands for `instruction`, if there is one.
+///
+/// Where several fit, `c.addi` wins over `c.addi16sp` like with GNU as. A candidate counts as
+/// the same instruction if its expansion encodes to the same word, except that `addi` without
+/// an immediate moves like the `add` from `zero` which `c.mv` expands to, as with llvm-mc.
+///
+pub fn compress(instruction: &Instruction) -> Option<Instruction> {
+    if let Instruction::IType {
+        opcode: IOpcode::Addi,
+        rd,
+        rs1,
+        imm: IImmediate(0),
+    } = *instruction
+    {
+        if rd != ZERO && rs1 != ZERO {
+            return compress(&Instruction::RType {
+                opcode: ROpcode::Add,
+                rd,
+                rs1: ZERO,
+                rs2: rs1,
+            });
+        }
+    }
+    let (candidates, rd, rs1, rs2, imm): (&[COpcode], _, _, _, _) = match *instruction {
+        Instruction::IType {
+            opcode,
+            rd,
+            rs1,
+            imm,
+        } => {
+            let candidates: &[COpcode] = match opcode {
+                IOpcode::Addi => &[
+                    COpcode::Nop,
+                    COpcode::Li,
+                    COpcode::Addi,
+                    COpcode::Addi16sp,
+                    COpcode::Addi4spn,
+                ],
+                IOpcode::Andi => &[COpcode::Andi],
+                IOpcode::Slli => &[COpcode::Slli],
+                IOpcode::Srli => &[COpcode::Srli],
+                IOpcode::Srai => &[COpcode::Srai],
+                IOpcode::Jalr => &[COpcode::Jr, COpcode::Jalr],
+                _ => &[],
+            };
+            (candidates, rd, rs1, ZERO, imm.0 as i32)
+        }
+        Instruction::LType {
+            opcode: LOpcode::Lw,
+            rd,
+            rs1,
+            imm,
+        } => (&[COpcode::Lw, COpcode::Lwsp], rd, rs1, ZERO, imm.0 as i32),
+        Instruction::SType {
+            opcode: SOpcode::Sw,
+            rs1,
+            rs2,
+            imm,
+        } => (&[COpcode::Sw, COpcode::Swsp], ZERO, rs1, rs2, imm.0 as i32),
+        Instruction::UType {
+            opcode: UOpcode::Lui,
+            rd,
+            imm,
+        } => (&[COpcode::Lui], rd, ZERO, ZERO, imm.0),
+        Instruction::RType {
+            opcode,
+            rd,
+            rs1,
+            rs2,
+        } => {
+            let candidates: &[COpcode] = match opcode {
+                ROpcode::Add => &[COpcode::Mv, COpcode::Add],
+                ROpcode::Sub => &[COpcode::Sub],
+                ROpcode::Xor => &[COpcode::Xor],
+                ROpcode::Or => &[COpcode::Or],
+                ROpcode::And => &[COpcode::And],
+                _ => &[],
+            };
+            (candidates, rd, rs1, rs2, 0)
+        }
+        Instruction::JType { rd, imm, .. } => (&[COpcode::J, COpcode::Jal], rd, ZERO, ZERO, imm.0),
+        Instruction::BType {
+            opcode,
+            rs1,
+            rs2,
+            imm,
+        } => {
+            let candidates: &[COpcode] = match opcode {
+                BOpcode::Beq => &[COpcode::Beqz],
+                BOpcode::Bne => &[COpcode::Bnez],
+                _ => &[],
+            };
+            (candidates, ZERO, rs1, rs2, imm.0 as i32)
+        }
+        Instruction::System {
+            opcode: SystemOpcode::Ebreak,
+        } => (&[COpcode::Ebreak], ZERO, ZERO, ZERO, 0),
+        _ => return None,
+    };
+
+    candidates
+        .iter()
+        .map(|&opcode| {
+            let (has_rd, has_rs1, has_rs2, has_imm) = operands(opcode);
+            let keep = |has: bool, register: Register| if has { register } else { ZERO };
+            Instruction::Compressed {
+                opcode,
+                rd: keep(has_rd, rd),
+                rs1: keep(has_rs1, rs1),
+                rs2: keep(has_rs2, rs2),
+                imm: CImmediate(if has_imm { imm } else { 0 }),
+            }
+        })
+        .find(|compressed| {
+            check(compressed).is_ok() && encode(&compressed.expand()) == encode(instruction)
+        })
+}
+
+///
+/// Replaces every instruction of `program` which has a compressed form by it.
+///
+/// Instructions whose immediate refers to a label keep their 32-bit form, the value of the
+/// label is only known once the program is laid out, which in turn depends on the size of the
+/// instructions. The assembler compresses the branches and jumps among them after laying the
+/// program out. Compressed forms which `isa` lacks as RV64, like `c.jal`, are left out.
+///
+pub fn compress_program(program: &mut Program, isa: Isa) {
+    for symbol in &mut program.symbols {
+        let instructions = match &mut symbol.node {
+            Symbol::Instruction(instruction) => std::slice::from_mut(instruction),
+            Symbol::Pseudo(pseudo) if pseudo.relocation.is_none() => &mut pseudo.expansion[..],
+            _ => continue,
+        };
+        for instruction in instructions {
+            if let Some(compressed) = compress(instruction) {
+                if isa.missing_base(&compressed).is_some() {
+                    continue;
+                }
+                *instruction = compressed;
+            }
+        }
+    }
+}
+
+// Bits `high` to `low` of `value`, shifted down to bit 0.
+fn field(value: i32, high: u32, low: u32) -> u16 {
+    ((value as u32 >> low) & ((1 << (high - low + 1)) - 1)) as u16
+}
+
+fn reg(register: Register) -> u16 {
+    (register.0 & 0x1F) as u16
+}
+
+// The three bit field of `x8` to `x15`.
+fn compact(register: Register) -> u16 {
+    (register.0.wrapping_sub(8) & 0x7) as u16
+}
+
+// The offset of `c.j` and `c.jal` in bits 12 to 2.
+fn jump_target(imm: i32) -> u16 {
+    field(imm, 11, 11) << 12
+        | field(imm, 4, 4) << 11
+        | field(imm, 9, 8) << 9
+        | field(imm, 10, 10) << 8
+        | field(imm, 6, 6) << 7
+        | field(imm, 7, 7) << 6
+        | field(imm, 3, 1) << 3
+        | field(imm, 5, 5) << 2
+}
+
+/// The halfword of a compressed instruction, the operands are expected to pass [`check`].
+pub fn encode_compressed(instruction: &Instruction) -> u16 {
+    let Instruction::Compressed {
+        opcode,
+        rd,
+        rs1,
+        rs2,
+        imm: CImmediate(imm),
+    } = *instruction
+    else {
+        unreachable!("{:?} is not a compressed instruction", instruction);
+    };
+    // Immediates split like the ones of `c.addi`, `c.li`, `c.lui` and the shifts.
+    let small = field(imm, 5, 5) << 12 | field(imm, 4, 0) << 2;
+    let memory = field(imm, 5, 3) << 10 | field(imm, 2, 2) << 6 | field(imm, 6, 6) << 5;
+    let arithmetic = |funct2: u16| {
+        0b100 << 13 | 0b11 << 10 | compact(rd) << 7 | funct2 << 5 | compact(rs2) << 2 | 0b01
+    };
+    match opcode {
+        COpcode::Addi4spn => {
+            field(imm, 5, 4) << 11
+                | field(imm, 9, 6) << 7
+                | field(imm, 2, 2) << 6
+                | field(imm, 3, 3) << 5
+                | compact(rd) << 2
+        }
+        COpcode::Lw => 0b010 << 13 | memory | compact(rs1) << 7 | compact(rd) << 2,
+        COpcode::Sw => 0b110 << 13 | memory | compact(rs1) << 7 | compact(rs2) << 2,
+        COpcode::Nop => 0b01,
+        COpcode::Addi => small | reg(rd) << 7 | 0b01,
+        COpcode::Jal => 0b001 << 13 | jump_target(imm) | 0b01,
+        COpcode::Li => 0b010 << 13 | small | reg(rd) << 7 | 0b01,
+        COpcode::Addi16sp => {
+            0b011 << 13
+                | field(imm, 9, 9) << 12
+                | reg(SP) << 7
+                | field(imm, 4, 4) << 6
+                | field(imm, 6, 6) << 5
+                | field(imm, 8, 7) << 3
+                | field(imm, 5, 5) << 2
+                | 0b01
+        }
+        COpcode::Lui => 0b011 << 13 | small | reg(rd) << 7 | 0b01,
+        COpcode::Srli => 0b100 << 13 | small | compact(rd) << 7 | 0b01,
+        COpcode::Srai => 0b100 << 13 | 0b01 << 10 | small | compact(rd) << 7 | 0b01,
+        COpcode::Andi => 0b100 << 13 | 0b10 << 10 | small | compact(rd) << 7 | 0b01,
+        COpcode::Sub => arithmetic(0b00),
+        COpcode::Xor => arithmetic(0b01),
+        COpcode::Or => arithmetic(0b10),
+        COpcode::And => arithmetic(0b11),
+        COpcode::J => 0b101 << 13 | jump_target(imm) | 0b01,
+        COpcode::Beqz | COpcode::Bnez => {
+            let funct3 = if opcode == COpcode::Beqz {
+                0b110
+            } else {
+                0b111
+            };
+            funct3 << 13
+                | field(imm, 8, 8) << 12
+                | field(imm, 4, 3) << 10
+                | compact(rs1) << 7
+                | field(imm, 7, 6) << 5
+                | field(imm, 2, 1) << 3
+                | field(imm, 5, 5) << 2
+                | 0b01
+        }
+        COpcode::Slli => small | reg(rd) << 7 | 0b10,
+        COpcode::Lwsp => {
+            0b010 << 13
+                | field(imm, 5, 5) << 12
+                | reg(rd) << 7
+                | field(imm, 4, 2) << 4
+                | field(imm, 7, 6) << 2
+                | 0b10
+        }
+        COpcode::Jr => 0b100 << 13 | reg(rs1) << 7 | 0b10,
+        COpcode::Mv => 0b100 << 13 | reg(rd) << 7 | reg(rs2) << 2 | 0b10,
+        COpcode::Ebreak => 0b100 << 13 | 1 << 12 | 0b10,
+        COpcode::Jalr => 0b100 << 13 | 1 << 12 | reg(rs1) << 7 | 0b10,
+        COpcode::Add => 0b100 << 13 | 1 << 12 | reg(rd) << 7 | reg(rs2) << 2 | 0b10,
+        COpcode::Swsp => {
+            0b110 << 13 | field(imm, 5, 2) << 9 | field(imm, 7, 6) << 7 | reg(rs2) << 2 | 0b10
+        }
+    }
+}
+
+// Sign extends the lowest `bits` bits of `value`.
+fn sign_extend(value: u32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    ((value << shift) as i32) >> shift
+}
+
+/// Decodes a halfword whose lowest two bits are not `11`, hints and reserved encodings are
+/// rejected.
+pub fn decode_compressed(parcel: u16) -> Result<Instruction, DecodeError> {
+    let bits = |high: u32, low: u32| (parcel as u32 >> low) & ((1 << (high - low + 1)) - 1);
+    let invalid = DecodeError::InvalidCompressed { parcel };
+    let full = Register(bits(11, 7) as u8);
+    let low = Register(bits(6, 2) as u8);
+    let compact_high = Register(8 + bits(9, 7) as u8);
+    let compact_low = Register(8 + bits(4, 2) as u8);
+    let small = sign_extend(bits(12, 12) << 5 | bits(6, 2), 6);
+    let shift = (bits(12, 12) << 5 | bits(6, 2)) as i32;
+    let memory = (bits(12, 10) << 3 | bits(6, 6) << 2 | bits(5, 5) << 6) as i32;
+    let jump = sign_extend(
+        bits(12, 12) << 11
+            | bits(11, 11) << 4
+            | bits(10, 9) << 8
+            | bits(8, 8) << 10
+            | bits(7, 7) << 6
+            | bits(6, 6) << 7
+            | bits(5, 3) << 1
+            | bits(2, 2) << 5,
+        12,
+    );
+    let branch = sign_extend(
+        bits(12, 12) << 8 | bits(11, 10) << 3 | bits(6, 5) << 6 | bits(4, 3) << 1 | bits(2, 2) << 5,
+        9,
+    );
+
+    let (opcode, rd, rs1, rs2, imm) = match (parcel & 0b11, bits(15, 13)) {
+        (0b00, 0b000) => {
+            let imm = bits(12, 11) << 4 | bits(10, 7) << 6 | bits(6, 6) << 2 | bits(5, 5) << 3;
+            (COpcode::Addi4spn, compact_low, SP, ZERO, imm as i32)
+        }
+        (0b00, 0b010) => (COpcode::Lw, compact_low, compact_high, ZERO, memory),
+        (0b00, 0b110) => (COpcode::Sw, ZERO, compact_high, compact_low, memory),
+        (0b01, 0b000) if parcel == 0b01 => (COpcode::Nop, ZERO, ZERO, ZERO, 0),
+        (0b01, 0b000) => (COpcode::Addi, full, ZERO, ZERO, small),
+        (0b01, 0b001) => (COpcode::Jal, ZERO, ZERO, ZERO, jump),
+        (0b01, 0b010) => (COpcode::Li, full, ZERO, ZERO, small),
+        (0b01, 0b011) if full == SP => {
+            let imm = bits(12, 12) << 9
+                | bits(6, 6) << 4
+                | bits(5, 5) << 6
+                | bits(4, 3) << 7
+                | bits(2, 2) << 5;
+            (COpcode::Addi16sp, SP, ZERO, ZERO, sign_extend(imm, 10))
+        }
+        (0b01, 0b011) => (COpcode::Lui, full, ZERO, ZERO, small),
+        (0b01, 0b100) => match (bits(11, 10), bits(12, 12), bits(6, 5)) {
+            (0b00, _, _) => (COpcode::Srli, compact_high, ZERO, ZERO, shift),
+            (0b01, _, _) => (COpcode::Srai, compact_high, ZERO, ZERO, shift),
+            (0b10, _, _) => (COpcode::Andi, compact_high, ZERO, ZERO, small),
+            (_, 0, funct2) => {
+                let opcode = match funct2 {
+                    0b00 => COpcode::Sub,
+                    0b01 => COpcode::Xor,
+                    0b10 => COpcode::Or,
+                    _ => COpcode::And,
+                };
+                (opcode, compact_high, ZERO, compact_low, 0)
+            }
+            _ => return Err(invalid),
+        },
+        (0b01, 0b101) => (COpcode::J, ZERO, ZERO, ZERO, jump),
+        (0b01, 0b110) => (COpcode::Beqz, ZERO, compact_high, ZERO, branch),
+        (0b01, 0b111) => (COpcode::Bnez, ZERO, compact_high, ZERO, branch),
+        (0b10, 0b000) => (COpcode::Slli, full, ZERO, ZERO, shift),
+        (0b10, 0b010) => {
+            let imm = bits(12, 12) << 5 | bits(6, 4) << 2 | bits(3, 2) << 6;
+            (COpcode::Lwsp, full, SP, ZERO, imm as i32)
+        }
+        (0b10, 0b100) => match (bits(12, 12), full.0, low.0) {
+            (0, _, 0) => (COpcode::Jr, ZERO, full, ZERO, 0),
+            (0, _, _) => (COpcode::Mv, full, ZERO, low, 0),
+            (_, 0, 0) => (COpcode::Ebreak, ZERO, ZERO, ZERO, 0),
+            (_, _, 0) => (COpcode::Jalr, ZERO, full, ZERO, 0),
+            _ => (COpcode::Add, full, ZERO, low, 0),
+        },
+        (0b10, 0b110) => {
+            let imm = bits(12, 9) << 2 | bits(8, 7) << 6;
+            (COpcode::Swsp, ZERO, SP, low, imm as i32)
+        }
+        _ => return Err(invalid),
+    };
+    let instruction = Instruction::Compressed {
+        opcode,
+        rd,
+        rs1,
+        rs2,
+        imm: CImmediate(imm),
+    };
+    check(&instruction).map_err(|_| invalid)?;
+    Ok(instruction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::isa::Isa;
+    use crate::riscv::parser::parse_riscv_with;
+    use rstest::rstest;
+
+    fn instruction(source: &str) -> Instruction {
+        let program = parse_riscv_with(source, Isa::RV32IMC).unwrap();
+        program.symbols[0].node.instructions()[0]
+    }
+
+    // Parcels as produced by GNU as with `-march=rv32ic`.
+    #[rstest]
+    #[case("c.addi4spn s0, sp, 16", 0x0800)]
+    #[case("c.lw a0, 4(a0)", 0x4148)]
+    #[case("c.sw a0, 4(a1)", 0xC1C8)]
+    #[case("c.nop", 0x0001)]
+    #[case("c.addi a0, 1", 0x0505)]
+    #[case("c.addi sp, -16", 0x1141)]
+    #[case("c.jal 0", 0x2001)]
+    #[case("c.li a0, 0", 0x4501)]
+    #[case("c.addi16sp sp, -64", 0x7139)]
+    #[case("c.lui a0, 1", 0x6505)]
+    #[case("c.srli a0, 1", 0x8105)]
+    #[case("c.srai a0, 1", 0x8505)]
+    #[case("c.andi a0, 1", 0x8905)]
+    #[case("c.sub a0, a1", 0x8D0D)]
+    #[case("c.xor a0, a1", 0x8D2D)]
+    #[case("c.or a0, a1", 0x8D4D)]
+    #[case("c.and a0, a1", 0x8D6D)]
+    #[case("c.j -2", 0xBFFD)]
+    #[case("c.beqz a0, 8", 0xC501)]
+    #[case("c.slli a0, 1", 0x0506)]
+    #[case("c.lwsp ra, 12(sp)", 0x40B2)]
+    #[case("c.jr ra", 0x8082)]
+    #[case("c.mv a0, a1", 0x852E)]
+    #[case("c.ebreak", 0x9002)]
+    #[case("c.jalr a0", 0x9502)]
+    #[case("c.add a0, a1", 0x952E)]
+    #[case("c.swsp ra, 12(sp)", 0xC606)]
+    fn test_encode_known(#[case] source: &str, #[case] parcel: u16) {
+        let instruction = instruction(source);
+        assert_eq!(
+            encode_compressed(&instruction),
+            parcel,
+            "{}: 0x{:04X} != 0x{:04X}",
+            source,
+            encode_compressed(&instruction),
+            parcel
+        );
+        assert_eq!(decode_compressed(parcel), Ok(instruction));
+    }
+
+    #[rstest]
+    #[case("addi a0, a0, 1", Some("c.addi a0, 1"))]
+    #[case("addi zero, zero, 0", Some("c.nop"))]
+    #[case("addi a0, zero, -5", Some("c.li a0, -5"))]
+    #[case("addi sp, sp, -16", Some("c.addi sp, -16"))]
+    #[case("addi sp, sp, -64", Some("c.addi16sp sp, -64"))]
+    #[case("addi s0, sp, 16", Some("c.addi4spn s0, sp, 16"))]
+    #[case("addi a0, a1, 1", None)]
+    #[case("addi a0, a0, 32", None)]
+    #[case("addi a0, zero, 0", Some("c.li a0, 0"))]
+    #[case("addi zero, a0, 0", None)]
+    #[case("addi t0, sp, 16", None)]
+    #[case("lw a0, 4(a1)", Some("c.lw a0, 4(a1)"))]
+    #[case("lw ra, 12(sp)", Some("c.lwsp ra, 12(sp)"))]
+    #[case("lw a0, 2(a1)", None)]
+    #[case("lw zero, 12(sp)", None)]
+    #[case("sw a0, 4(a1)", Some("c.sw a0, 4(a1)"))]
+    #[case("sw ra, 12(sp)", Some("c.swsp ra, 12(sp)"))]
+    #[case("lui a0, 1", Some("c.lui a0, 1"))]
+    #[case("lui a0, -1", Some("c.lui a0, -1"))]
+    #[case("lui a0, 32", None)]
+    #[case("lui sp, 1", None)]
+    #[case("lui a0, 0", None)]
+    #[case("slli a0, a0, 1", Some("c.slli a0, 1"))]
+    #[case("srai a0, a0, 1", Some("c.srai a0, 1"))]
+    #[case("srai t0, t0, 1", None)]
+    #[case("andi a0, a0, -1", Some("c.andi a0, -1"))]
+    #[case("add a0, zero, a1", Some("c.mv a0, a1"))]
+    #[case("add a0, a0, a1", Some("c.add a0, a1"))]
+    #[case("add a0, a1, a2", None)]
+    #[case("sub a0, a0, a1", Some("c.sub a0, a1"))]
+    #[case("sub a0, a1, a0", None)]
+    #[case("and a0, a0, a1", Some("c.and a0, a1"))]
+    #[case("jalr zero, 0(ra)", Some("c.jr ra"))]
+    #[case("jalr ra, 0(a0)", Some("c.jalr a0"))]
+    #[case("jalr ra, 4(a0)", None)]
+    #[case("jal zero, -2", Some("c.j -2"))]
+    #[case("jal ra, 2046", Some("c.jal 2046"))]
+    #[case("jal ra, 2048", None)]
+    #[case("jal t0, 8", None)]
+    #[case("beq a0, zero, 8", Some("c.beqz a0, 8"))]
+    #[case("bne s1, zero, -256", Some("c.bnez s1, -256"))]
+    #[case("beq a0, a1, 8", None)]
+    #[case("beq t0, zero, 8", None)]
+    #[case("ebreak", Some("c.ebreak"))]
+    #[case("ecall", None)]
+    #[case("mul a0, a0, a1", None)]
+    fn test_compress(#[case] source: &str, #[case] compressed: Option<&str>) {
+        let original = instruction(source);
+        let compressed = compressed.map(instruction);
+        assert_eq!(compress(&original), compressed);
+        if let Some(compressed) = compressed {
+            assert_eq!(encode(&compressed.expand()), encode(&original));
+        }
+    }
+
+    // `mv` is an `addi`, `c.mv` expands to an `add`.
+    #[rstest]
+    #[case("addi a0, a1, 0", "c.mv a0, a1", "add a0, zero, a1")]
+    #[case("addi a0, a0, 0", "c.mv a0, a0", "add a0, zero, a0")]
+    #[case("addi a0, sp, 0", "c.mv a0, sp", "add a0, zero, sp")]
+    fn test_compress_mv(#[case] source: &str, #[case] compressed: &str, #[case] expanded: &str) {
+        let compressed = instruction(compressed);
+        assert_eq!(compress(&instruction(source)), Some(compressed));
+        assert_eq!(compressed.expand(), instruction(expanded));
+    }
+
+    // Reserved encodings and operands the compressed forms cannot take.
+    #[rstest]
+    #[case(0x0000)]
+    #[case(0x0005)]
+    #[case(0x6501)]
+    #[case(0x9101)]
+    #[case(0x9D0D)]
+    #[case(0x2000)]
+    #[case(0x4002)]
+    #[case(0x8002)]
+    fn test_decode_invalid(#[case] parcel: u16) {
+        assert_eq!(
+            decode_compressed(parcel),
+            Err(DecodeError::InvalidCompressed { parcel })
+        );
+    }
+
+    #[rstest]
+    #[case(
+        "c.lw t0, 4(a0)",
+        "`c.lw` only takes the registers s0, s1 and a0 to a5 (x8 to x15), not t0"
+    )]
+    #[case("c.li zero, 1", "`c.li` cannot take `zero` as a register")]
+    #[case("c.lwsp a0, 4(a0)", "`c.lwsp` only works relative to `sp`")]
+    #[case(
+        "c.addi a0, 0",
+        "`c.addi` takes a nonzero immediate from -32 to 31, not 0"
+    )]
+    #[case("c.lw a0, 2(a1)", "`c.lw` takes a multiple of 4 from 0 to 124, not 2")]
+    #[case("c.lui sp, 1", "`c.lui` cannot load `sp`, see `c.addi16sp`")]
+    fn test_check(#[case] source: &str, #[case] message: &str) {
+        let errors = parse_riscv_with(source, Isa::RV32IC).unwrap_err();
+        assert_eq!(errors[0].to_string(), message);
+    }
+}
@@ -0,0 +1,751 @@
+use std::fmt::{Display, Formatter, Write};
+
+use crate::riscv::ast::{
+    AOpcode, AtomicOrdering, COpcode, Csr, FRegister, FenceSet, IOpcode, Instruction, Register,
+    RoundingMode,
+};
+use crate::riscv::encoding::{decode_bytes, instruction_len, to_bytes, DecodeError};
+
+/// Prints the ABI name of the register, or `xN` with the alternate flag (`{:#}`).
+impl Display for Register {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if f.alternate() {
+            write!(f, "x{}", self.0)
+        } else {
+            write!(f, "{}", self.abi_name())
+        }
+    }
+}
+
+/// Prints the ABI name of the register, or `fN` with the alternate flag (`{:#}`).
+impl Display for FRegister {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if f.alternate() {
+            write!(f, "f{}", self.0)
+        } else {
+            write!(f, "{}", self.abi_name())
+        }
+    }
+}
+
+impl Display for RoundingMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Prints the devices as a subset of `iorw`.
+impl Display for FenceSet {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let devices = [
+            (FenceSet::INPUT, 'i'),
+            (FenceSet::OUTPUT, 'o'),
+            (FenceSet::READ, 'r'),
+            (FenceSet::WRITE, 'w'),
+        ];
+        for (bit, name) in devices {
+            if self.0 & bit != 0 {
+                f.write_char(name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Prints the suffix of the mnemonic, `.aq`, `.rl`, `.aqrl` or nothing.
+impl Display for AtomicOrdering {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.acquire || self.release {
+            f.write_char('.')?;
+        }
+        if self.acquire {
+            f.write_str("aq")?;
+        }
+        if self.release {
+            f.write_str("rl")?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints the name of the CSR, or its number in hex if it has none the assembler knows.
+impl Display for Csr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            None => write!(f, "0x{:X}", self.0),
+        }
+    }
+}
+
+/// Prints the instruction in the syntax accepted by `parse_riscv`, registers are printed with
+/// numeric names when the alternate flag (`{:#}`) is set.
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let reg = |register: Register| {
+            if f.alternate() {
+                format!("{:#}", register)
+            } else {
+                register.to_string()
+            }
+        };
+        let freg = |register: FRegister| {
+            if f.alternate() {
+                format!("{:#}", register)
+            } else {
+                register.to_string()
+            }
+        };
+        // The default rounding mode is left out, mostly the dynamic one.
+        let rounding_or = |rm: RoundingMode, default: RoundingMode| {
+            if rm == default {
+                String::new()
+            } else {
+                format!(", {}", rm)
+            }
+        };
+        let rounding = |rm: RoundingMode| rounding_or(rm, RoundingMode::Dynamic);
+        match *self {
+            Instruction::IType {
+                opcode: IOpcode::Jalr,
+                rd,
+                rs1,
+                imm,
+            } => write!(f, "jalr {}, {}({})", reg(rd), imm.0, reg(rs1)),
+            Instruction::IType {
+                opcode,
+                rd,
+                rs1,
+                imm,
+            } => write!(
+                f,
+                "{} {}, {}, {}",
+                opcode.mnemonic(),
+                reg(rd),
+                reg(rs1),
+                imm.0
+            ),
+            // Upper immediates read best in hex, the parser only takes negative ones in decimal.
+            Instruction::UType { opcode, rd, imm } if imm.0 >= 0 => {
+                write!(f, "{} {}, 0x{:X}", opcode.mnemonic(), reg(rd), imm.0)
+            }
+            Instruction::UType { opcode, rd, imm } => {
+                write!(f, "{} {}, {}", opcode.mnemonic(), reg(rd), imm.0)
+            }
+            Instruction::RType {
+                opcode,
+                rd,
+                rs1,
+                rs2,
+            } => write!(
+                f,
+                "{} {}, {}, {}",
+                opcode.mnemonic(),
+                reg(rd),
+                reg(rs1),
+                reg(rs2)
+            ),
+            Instruction::JType { opcode, rd, imm } => {
+                write!(f, "{} {}, {}", opcode.mnemonic(), reg(rd), imm.0)
+            }
+            Instruction::BType {
+                opcode,
+                rs1,
+                rs2,
+                imm,
+            } => write!(
+                f,
+                "{} {}, {}, {}",
+                opcode.mnemonic(),
+                reg(rs1),
+                reg(rs2),
+                imm.0
+            ),
+            Instruction::SType {
+                opcode,
+                rs1,
+                rs2,
+                imm,
+            } => write!(
+                f,
+                "{} {}, {}({})",
+                opcode.mnemonic(),
+                reg(rs2),
+                imm.0,
+                reg(rs1)
+            ),
+            Instruction::LType {
+                opcode,
+                rd,
+                rs1,
+                imm,
+            } => write!(
+                f,
+                "{} {}, {}({})",
+                opcode.mnemonic(),
+                reg(rd),
+                imm.0,
+                reg(rs1)
+            ),
+            Instruction::Fence { pred, succ } if pred == FenceSet::ALL && succ == FenceSet::ALL => {
+                write!(f, "fence")
+            }
+            Instruction::Fence { pred, succ } => write!(f, "fence {}, {}", pred, succ),
+            Instruction::System { opcode } => write!(f, "{}", opcode.mnemonic()),
+            Instruction::Atomic {
+                opcode: opcode @ (AOpcode::LrW | AOpcode::LrD),
+                rd,
+                rs1,
+                ordering,
+                ..
+            } => write!(
+                f,
+                "{}{} {}, ({})",
+                opcode.mnemonic(),
+                ordering,
+                reg(rd),
+                reg(rs1)
+            ),
+            Instruction::Atomic {
+                opcode,
+                rd,
+                rs1,
+                rs2,
+                ordering,
+            } => write!(
+                f,
+                "{}{} {}, {}, ({})",
+                opcode.mnemonic(),
+                ordering,
+                reg(rd),
+                reg(rs2),
+                reg(rs1)
+            ),
+            Instruction::Csr {
+                opcode,
+                rd,
+                rs1,
+                csr,
+            } => write!(
+                f,
+                "{} {}, {}, {}",
+                opcode.mnemonic(),
+                reg(rd),
+                csr,
+                reg(rs1)
+            ),
+            Instruction::CsrImmediate {
+                opcode,
+                rd,
+                imm,
+                csr,
+            } => write!(
+                f,
+                "{} {}, {}, {}",
+                opcode.immediate_mnemonic(),
+                reg(rd),
+                csr,
+                imm.0
+            ),
+            Instruction::FloatLoad {
+                format,
+                rd,
+                rs1,
+                imm,
+            } => write!(
+                f,
+                "{} {}, {}({})",
+                format.load_mnemonic(),
+                freg(rd),
+                imm.0,
+                reg(rs1)
+            ),
+            Instruction::FloatStore {
+                format,
+                rs1,
+                rs2,
+                imm,
+            } => write!(
+                f,
+                "{} {}, {}({})",
+                format.store_mnemonic(),
+                freg(rs2),
+                imm.0,
+                reg(rs1)
+            ),
+            Instruction::FloatRType {
+                opcode,
+                format,
+                rd,
+                rs1,
+                rs2,
+                rm,
+            } => write!(
+                f,
+                "{} {}, {}, {}{}",
+                opcode.mnemonic(format),
+                freg(rd),
+                freg(rs1),
+                freg(rs2),
+                rounding(rm)
+            ),
+            Instruction::FloatUnary {
+                opcode,
+                format,
+                rd,
+                rs1,
+                rm,
+            } => write!(
+                f,
+                "{} {}, {}{}",
+                opcode.mnemonic(format),
+                freg(rd),
+                freg(rs1),
+                rounding_or(rm, opcode.default_rounding(format))
+            ),
+            Instruction::FloatToInt {
+                opcode,
+                format,
+                rd,
+                rs1,
+                rm,
+            } => write!(
+                f,
+                "{} {}, {}{}",
+                opcode.mnemonic(format),
+                reg(rd),
+                freg(rs1),
+                rounding(rm)
+            ),
+            Instruction::IntToFloat {
+                opcode,
+                format,
+                rd,
+                rs1,
+                rm,
+            } => write!(
+                f,
+                "{} {}, {}{}",
+                opcode.mnemonic(format),
+                freg(rd),
+                reg(rs1),
+                rounding_or(rm, opcode.default_rounding(format))
+            ),
+            Instruction::FloatCompare {
+                opcode,
+                format,
+                rd,
+                rs1,
+                rs2,
+            } => write!(
+                f,
+                "{} {}, {}, {}",
+                opcode.mnemonic(format),
+                reg(rd),
+                freg(rs1),
+                freg(rs2)
+            ),
+            Instruction::FloatFused {
+                opcode,
+                format,
+                rd,
+                rs1,
+                rs2,
+                rs3,
+                rm,
+            } => write!(
+                f,
+                "{} {}, {}, {}, {}{}",
+                opcode.mnemonic(format),
+                freg(rd),
+                freg(rs1),
+                freg(rs2),
+                freg(rs3),
+                rounding(rm)
+            ),
+            Instruction::Compressed {
+                opcode,
+                rd,
+                rs1,
+                rs2,
+                imm,
+            } => {
+                let mnemonic = opcode.mnemonic();
+                match opcode {
+                    COpcode::Nop | COpcode::Ebreak => write!(f, "{}", mnemonic),
+                    COpcode::Jr | COpcode::Jalr => write!(f, "{} {}", mnemonic, reg(rs1)),
+                    COpcode::J | COpcode::Jal => write!(f, "{} {}", mnemonic, imm.0),
+                    COpcode::Beqz | COpcode::Bnez => {
+                        write!(f, "{} {}, {}", mnemonic, reg(rs1), imm.0)
+                    }
+                    COpcode::Lw | COpcode::Lwsp => {
+                        write!(f, "{} {}, {}({})", mnemonic, reg(rd), imm.0, reg(rs1))
+                    }
+                    COpcode::Sw | COpcode::Swsp => {
+                        write!(f, "{} {}, {}({})", mnemonic, reg(rs2), imm.0, reg(rs1))
+                    }
+                    COpcode::Addi4spn => {
+                        write!(f, "{} {}, {}, {}", mnemonic, reg(rd), reg(rs1), imm.0)
+                    }
+                    COpcode::Sub
+                    | COpcode::Xor
+                    | COpcode::Or
+                    | COpcode::And
+                    | COpcode::Mv
+                    | COpcode::Add => write!(f, "{} {}, {}", mnemonic, reg(rd), reg(rs2)),
+                    _ => write!(f, "{} {}, {}", mnemonic, reg(rd), imm.0),
+                }
+            }
+        }
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Debug, Default)]
+pub struct DisasmOptions {
+    /// Address of the first word of the image.
+    pub base_address: u32,
+    pub show_addresses: bool,
+    pub show_raw_words: bool,
+    /// Print `x10` instead of `a0`.
+    pub numeric_registers: bool,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct DisasmLine {
+    pub address: u32,
+    /// The bytes of the instruction as a little-endian number.
+    pub word: u32,
+    /// Number of bytes, 2 for compressed instructions.
+    pub len: usize,
+    pub instruction: Result<Instruction, DecodeError>,
+}
+
+/// Decodes a memory image given as little-endian words, see [`disassemble_byte_lines`].
+pub fn disassemble_lines(image: &[u32], base_address: u32) -> Vec<DisasmLine> {
+    disassemble_byte_lines(&to_bytes(image), base_address)
+}
+
+/// Decodes a memory image instruction by instruction, compressed instructions take up two
+/// bytes and all others four.
+pub fn disassemble_byte_lines(image: &[u8], base_address: u32) -> Vec<DisasmLine> {
+    let mut lines = vec![];
+    let mut offset = 0;
+    while offset < image.len() {
+        let rest = &image[offset..];
+        let len = instruction_len(rest[0]).min(rest.len());
+        let mut word = [0; 4];
+        word[..len].copy_from_slice(&rest[..len]);
+        lines.push(DisasmLine {
+            address: base_address.wrapping_add(offset as u32),
+            word: u32::from_le_bytes(word),
+            len,
+            instruction: decode_bytes(rest).map(|(instruction, _)| instruction),
+        });
+        offset += len;
+    }
+    lines
+}
+
+///
+/// Turns a memory image back into assembly, one instruction per line.
+///
+/// Without the address and raw word columns the output parses with `parse_riscv_with` for the
+/// ISA of the image, undecodable words are kept as comments.
+///
+pub fn disassemble(image: &[u32], options: &DisasmOptions) -> String {
+    disassemble_bytes(&to_bytes(image), options)
+}
+
+/// Like [`disassemble`], for an image with compressed instructions in between.
+pub fn disassemble_bytes(image: &[u8], options: &DisasmOptions) -> String {
+    let mut output = String::new();
+    for line in disassemble_byte_lines(image, options.base_address) {
+        if options.show_addresses {
+            write!(output, "{:08X}:  ", line.address).unwrap();
+        }
+        if options.show_raw_words {
+            let raw = format!("{:0width$X}", line.word, width = 2 * line.len);
+            write!(output, "{:<8}  ", raw).unwrap();
+        }
+        match line.instruction {
+            Ok(instruction) if options.numeric_registers => {
+                writeln!(output, "{:#}", instruction).unwrap()
+            }
+            Ok(instruction) => writeln!(output, "{}", instruction).unwrap(),
+            Err(error) => writeln!(output, "# {}", error).unwrap(),
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::ast::{JImmediate, JOpcode, Symbol};
+    use crate::riscv::encoding::{encode, encode_bytes, encode_program, from_bytes, to_bytes};
+    use crate::riscv::isa::Isa;
+    use crate::riscv::parser::parse_riscv_with;
+    use rstest::rstest;
+
+    fn instructions(source: &str) -> Vec<Instruction> {
+        parse_riscv_with(source, Isa::RV32IMAFDC_ZICSR)
+            .unwrap()
+            .symbols
+            .into_iter()
+            .filter_map(|symbol| match symbol.node {
+                Symbol::Instruction(instruction) => Some(instruction),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[rstest]
+    #[case("addi x1, x2, 5", "addi ra, sp, 5", "addi x1, x2, 5")]
+    #[case("sltiu t0, t1, -1", "sltiu t0, t1, -1", "sltiu x5, x6, -1")]
+    #[case("srai a0, a1, 31", "srai a0, a1, 31", "srai x10, x11, 31")]
+    #[case("sub x0, x31, fp", "sub zero, t6, s0", "sub x0, x31, x8")]
+    #[case("sw a0, -8(sp)", "sw a0, -8(sp)", "sw x10, -8(x2)")]
+    #[case("lh s11, 2047(gp)", "lh s11, 2047(gp)", "lh x27, 2047(x3)")]
+    #[case("bgeu a7, tp, -4096", "bgeu a7, tp, -4096", "bgeu x17, x4, -4096")]
+    #[case("lui t3, 0x12345", "lui t3, 0x12345", "lui x28, 0x12345")]
+    #[case("auipc s2, -1", "auipc s2, -1", "auipc x18, -1")]
+    #[case("jal x0, -2048", "jal zero, -2048", "jal x0, -2048")]
+    #[case("jalr ra, t0, -4", "jalr ra, -4(t0)", "jalr x1, -4(x5)")]
+    #[case("lbu a0, 1(a1)", "lbu a0, 1(a1)", "lbu x10, 1(x11)")]
+    #[case("lhu a2, -2(a3)", "lhu a2, -2(a3)", "lhu x12, -2(x13)")]
+    #[case("fence iorw, iorw", "fence", "fence")]
+    #[case("fence wr, io", "fence rw, io", "fence rw, io")]
+    #[case("ecall", "ecall", "ecall")]
+    #[case("ebreak", "ebreak", "ebreak")]
+    #[case("mulhsu a0, a1, a2", "mulhsu a0, a1, a2", "mulhsu x10, x11, x12")]
+    #[case("remu t0, t1, t2", "remu t0, t1, t2", "remu x5, x6, x7")]
+    #[case("lr.w.aq a0, 0(a1)", "lr.w.aq a0, (a1)", "lr.w.aq x10, (x11)")]
+    #[case(
+        "amoor.w a0, a1, (sp)",
+        "amoor.w a0, a1, (sp)",
+        "amoor.w x10, x11, (x2)"
+    )]
+    #[case("csrrw t0, mtvec, t1", "csrrw t0, mtvec, t1", "csrrw x5, mtvec, x6")]
+    #[case("csrrs a0, 0x7C0, x0", "csrrs a0, 0x7C0, zero", "csrrs x10, 0x7C0, x0")]
+    #[case("csrrci zero, 0x344, 31", "csrrci zero, mip, 31", "csrrci x0, mip, 31")]
+    #[case("fld f8, -16(sp)", "fld fs0, -16(sp)", "fld f8, -16(x2)")]
+    #[case("fsw ft11, 4(a0)", "fsw ft11, 4(a0)", "fsw f31, 4(x10)")]
+    #[case("fmul.d fa0, fa1, fa2", "fmul.d fa0, fa1, fa2", "fmul.d f10, f11, f12")]
+    #[case(
+        "fdiv.s fs10, ft8, fa7, rup",
+        "fdiv.s fs10, ft8, fa7, rup",
+        "fdiv.s f26, f28, f17, rup"
+    )]
+    #[case(
+        "fcvt.wu.s a0, ft0, rtz",
+        "fcvt.wu.s a0, ft0, rtz",
+        "fcvt.wu.s x10, f0, rtz"
+    )]
+    #[case("fmv.w.x ft1, zero", "fmv.w.x ft1, zero", "fmv.w.x f1, x0")]
+    #[case("fcvt.d.s fa0, fa1", "fcvt.d.s fa0, fa1", "fcvt.d.s f10, f11")]
+    #[case(
+        "fcvt.d.w fa0, a0, dyn",
+        "fcvt.d.w fa0, a0, dyn",
+        "fcvt.d.w f10, x10, dyn"
+    )]
+    #[case("fle.d t0, fs1, fs2", "fle.d t0, fs1, fs2", "fle.d x5, f9, f18")]
+    #[case(
+        "fmsub.s ft0, ft1, ft2, ft3, rmm",
+        "fmsub.s ft0, ft1, ft2, ft3, rmm",
+        "fmsub.s f0, f1, f2, f3, rmm"
+    )]
+    fn test_instruction_text(#[case] source: &str, #[case] abi: &str, #[case] numeric: &str) {
+        let instruction = instructions(source)[0];
+        assert_eq!(instruction.to_string(), abi);
+        assert_eq!(format!("{:#}", instruction), numeric);
+        let image = [encode(&instruction)];
+        assert_eq!(
+            disassemble(&image, &DisasmOptions::default()),
+            format!("{}\n", abi)
+        );
+        let options = DisasmOptions {
+            numeric_registers: true,
+            ..DisasmOptions::default()
+        };
+        assert_eq!(disassemble(&image, &options), format!("{}\n", numeric));
+    }
+
+    #[rstest]
+    #[case("lr.d.aqrl a0, 0(a1)", "lr.d.aqrl a0, (a1)")]
+    #[case("amoswap.d a0, a1, (sp)", "amoswap.d a0, a1, (sp)")]
+    #[case("fcvt.lu.d a0, fa0, rtz", "fcvt.lu.d a0, fa0, rtz")]
+    #[case("fcvt.s.l fa0, a0, dyn", "fcvt.s.l fa0, a0")]
+    #[case("fmv.x.d a0, fa0", "fmv.x.d a0, fa0")]
+    fn test_instruction_text_64(#[case] source: &str, #[case] abi: &str) {
+        let program = parse_riscv_with(source, Isa::RV64IMAFDC_ZICSR).unwrap();
+        let instruction = program.symbols[0].node.instructions()[0];
+        assert_eq!(instruction.to_string(), abi);
+        let reparsed = parse_riscv_with(abi, Isa::RV64IMAFDC_ZICSR).unwrap();
+        assert_eq!(reparsed.symbols[0].node.instructions(), [instruction]);
+    }
+
+    #[test]
+    fn test_jal_text() {
+        let instruction = Instruction::JType {
+            opcode: JOpcode::Jal,
+            rd: Register(1),
+            imm: JImmediate(-2048),
+        };
+        assert_eq!(instruction.to_string(), "jal ra, -2048");
+        assert_eq!(format!("{:#}", instruction), "jal x1, -2048");
+    }
+
+    #[test]
+    fn test_columns() {
+        let image = encode_program(&instructions("addi sp, sp, -16\nsw ra, 12(sp)\n"));
+        let options = DisasmOptions {
+            base_address: 0x8000_0000,
+            show_addresses: true,
+            show_raw_words: true,
+            numeric_registers: false,
+        };
+        assert_eq!(
+            disassemble(&image, &options),
+            "80000000:  FF010113  addi sp, sp, -16\n80000004:  00112623  sw ra, 12(sp)\n"
+        );
+    }
+
+    #[rstest]
+    #[case("c.nop", "c.nop", "c.nop")]
+    #[case("c.jr ra", "c.jr ra", "c.jr x1")]
+    #[case("c.jal -2048", "c.jal -2048", "c.jal -2048")]
+    #[case("c.bnez s1, -256", "c.bnez s1, -256", "c.bnez x9, -256")]
+    #[case("c.lwsp a0, 252(sp)", "c.lwsp a0, 252(sp)", "c.lwsp x10, 252(x2)")]
+    #[case("c.sw a5, 124(s0)", "c.sw a5, 124(s0)", "c.sw x15, 124(x8)")]
+    #[case(
+        "c.addi4spn a0, sp, 1020",
+        "c.addi4spn a0, sp, 1020",
+        "c.addi4spn x10, x2, 1020"
+    )]
+    #[case("c.addi16sp sp, 496", "c.addi16sp sp, 496", "c.addi16sp x2, 496")]
+    #[case("c.mv t0, t6", "c.mv t0, t6", "c.mv x5, x31")]
+    #[case("c.slli a0, 31", "c.slli a0, 31", "c.slli x10, 31")]
+    fn test_compressed_text(#[case] source: &str, #[case] abi: &str, #[case] numeric: &str) {
+        let program = parse_riscv_with(source, Isa::RV32IC).unwrap();
+        let instruction = program.symbols[0].node.instructions()[0];
+        assert_eq!(instruction.to_string(), abi);
+        assert_eq!(format!("{:#}", instruction), numeric);
+        let image = encode_bytes(&[instruction]);
+        assert_eq!(
+            disassemble_bytes(&image, &DisasmOptions::default()),
+            format!("{}\n", abi)
+        );
+    }
+
+    #[test]
+    fn test_compressed_columns() {
+        // c.addi sp, -16; sw ra, 12(sp); c.jr ra and the first half of a cut off word.
+        let bytes = [0x41, 0x11, 0x23, 0x26, 0x11, 0x00, 0x82, 0x80, 0x13];
+        let options = DisasmOptions {
+            show_addresses: true,
+            show_raw_words: true,
+            ..DisasmOptions::default()
+        };
+        assert_eq!(
+            disassemble_bytes(&bytes, &options),
+            "00000000:  1141      c.addi sp, -16\n\
+             00000002:  00112623  sw ra, 12(sp)\n\
+             00000006:  8082      c.jr ra\n\
+             00000008:  13        # the instruction is cut off\n"
+        );
+        let lines = disassemble_byte_lines(&bytes, 0);
+        assert_eq!(
+            lines.iter().map(|line| line.len).collect::<Vec<_>>(),
+            [2, 4, 2, 1]
+        );
+    }
+
+    #[test]
+    fn test_invalid_word() {
+        let lines = disassemble_lines(&[0x0000_0013, 0xFFFF_FFFF], 0x100);
+        assert_eq!(lines[1].address, 0x104);
+        assert_eq!(
+            lines[1].instruction,
+            Err(DecodeError::InvalidOpcode { word: 0xFFFF_FFFF })
+        );
+        assert_eq!(
+            disassemble(&[0x0800_80B3], &DisasmOptions::default()),
+            "# invalid function bits in 0x080080B3\n"
+        );
+    }
+
+    // The prologue of a function as emitted by GCC, in little-endian bytes.
+    #[test]
+    fn test_gcc_bytes() {
+        let bytes = [
+            0x13, 0x01, 0x01, 0xFF, 0x23, 0x26, 0x11, 0x00, 0x23, 0x24, 0x81, 0x00, 0x13, 0x04,
+            0x01, 0x01,
+        ];
+        assert_eq!(
+            disassemble(&from_bytes(&bytes), &DisasmOptions::default()),
+            "addi sp, sp, -16\nsw ra, 12(sp)\nsw s0, 8(sp)\naddi s0, sp, 16\n"
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let source = "
+            addi x1, x2, -2048
+            slti x3, x4, 2047
+            sltiu x5, x6, 1
+            xori x7, x8, -1
+            ori x9, x10, 0x7F
+            andi x11, x12, 0b1010
+            slli x13, x14, 31
+            srli x15, x16, 1
+            srai x17, x18, 7
+            add x19, x20, x21
+            sub x22, x23, x24
+            sll x25, x26, x27
+            slt x28, x29, x30
+            sltu x31, x0, x1
+            xor a0, a1, a2
+            srl a3, a4, a5
+            sra a6, a7, s2
+            or s3, s4, s5
+            and s6, s7, s8
+            sb s9, -1(s10)
+            sh s11, 2(t3)
+            sw t4, 2047(t5)
+            lb t6, -2048(zero)
+            lh ra, 0(sp)
+            lw gp, 4(tp)
+            beq t0, t1, 4094
+            bne t2, s0, -4096
+            blt s1, a0, 2
+            bge a1, a2, -2
+            bltu a3, a4, 0
+            bgeu a5, a6, 64
+            lui a7, 0x7FFFF
+            lui s2, -524288
+            auipc s3, 0
+            jal ra, -1048576
+            jal t0, 1048574
+            jalr zero, 0(ra)
+            lbu s4, 2047(s5)
+            lhu s6, -2048(s7)
+            fence
+            fence r, w
+            ecall
+            ebreak
+        ";
+        let program = instructions(source);
+        let image = encode_program(&program);
+        for options in [
+            DisasmOptions::default(),
+            DisasmOptions {
+                numeric_registers: true,
+                ..DisasmOptions::default()
+            },
+        ] {
+            let text = disassemble(&image, &options);
+            assert_eq!(instructions(&text), program);
+        }
+        assert_eq!(from_bytes(&to_bytes(&image)), image);
+    }
+}
@@ -0,0 +1,1176 @@
+//!
+//! ELF32 files of assembled RV32 programs, relocatable objects for a linker like GNU ld and
+//! static executables for QEMU, a board's flasher or `objdump`.
+//!
+//! An object has every section of the program at address 0, its labels in `.symtab` and the
+//! references left to the linker in a `.rela` section next to the section they patch, see
+//! [`AssembleOptions::relocatable`]. An executable stays where it was assembled, see
+//! [`AssembleOptions::origin`], and is loaded by a single segment holding all of its sections,
+//! as they are laid out back to back.
+//!
+//! Executables of other toolchains, like GCC's, are read back with [`read_executable`] for a
+//! simulator to load.
+//!
+//! [`AssembleOptions::relocatable`]: crate::riscv::assembler::AssembleOptions::relocatable
+//! [`AssembleOptions::origin`]: crate::riscv::assembler::AssembleOptions::origin
+//!
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
+
+use crate::riscv::assembler::{Assembly, Section};
+use crate::riscv::ast::{IOpcode, Instruction, RelocationKind};
+use crate::riscv::encoding::decode_bytes;
+use crate::riscv::isa::Xlen;
+
+const EM_RISCV: u16 = 243;
+const ET_REL: u16 = 1;
+const ET_EXEC: u16 = 2;
+const EF_RISCV_RVC: u32 = 0x1;
+
+const HEADER_SIZE: u32 = 52;
+const PROGRAM_HEADER_SIZE: u32 = 32;
+const SECTION_HEADER_SIZE: u32 = 40;
+const SYMBOL_SIZE: u32 = 16;
+const RELA_SIZE: u32 = 12;
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
+const SHT_NOBITS: u32 = 8;
+const SHF_WRITE: u32 = 0x1;
+const SHF_ALLOC: u32 = 0x2;
+const SHF_EXECINSTR: u32 = 0x4;
+const SHF_INFO_LINK: u32 = 0x40;
+const SHN_UNDEF: u16 = 0;
+const SHN_ABS: u16 = 0xFFF1;
+const SHN_LORESERVE: u16 = 0xFF00;
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 0x1;
+const PF_W: u32 = 0x2;
+const PF_R: u32 = 0x4;
+/// Segments start at the same offset into a page in the file as in memory.
+const PAGE_SIZE: u32 = 0x1000;
+
+// Relocation types of the RISC-V psABI.
+const R_RISCV_32: u32 = 1;
+const R_RISCV_BRANCH: u32 = 16;
+const R_RISCV_JAL: u32 = 17;
+const R_RISCV_CALL: u32 = 18;
+const R_RISCV_PCREL_HI20: u32 = 23;
+const R_RISCV_PCREL_LO12_I: u32 = 24;
+const R_RISCV_PCREL_LO12_S: u32 = 25;
+const R_RISCV_HI20: u32 = 26;
+const R_RISCV_LO12_I: u32 = 27;
+const R_RISCV_LO12_S: u32 = 28;
+const R_RISCV_RVC_BRANCH: u32 = 44;
+const R_RISCV_RVC_JUMP: u32 = 45;
+
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct ExecutableOptions {
+    /// Label execution starts at, `_start` if the program has one and the start of `.text`
+    /// otherwise.
+    pub entry: Option<String>,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum ElfError {
+    /// ELF32 only holds RV32 programs.
+    NotRv32,
+    /// Labels an executable refers to which were left to the linker.
+    Unresolved {
+        labels: Vec<String>,
+    },
+    UnknownEntry {
+        label: String,
+    },
+    /// A file read back which does not start with the ELF magic.
+    NotElf,
+    NotExecutable,
+    /// A header or section of a file read back lies past its end.
+    Truncated,
+    /// A loadable segment with more bytes in the file than in memory, or whose memory reaches
+    /// past the end of the address space.
+    InvalidSegment {
+        address: u32,
+    },
+}
+
+impl Display for ElfError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ElfError::NotRv32 => write!(f, "only RV32 programs can be written as ELF32"),
+            ElfError::Unresolved { labels } => {
+                let labels: Vec<_> = labels.iter().map(|label| format!("`{}`", label)).collect();
+                write!(
+                    f,
+                    "references to {} are left to the linker, an executable has to be linked",
+                    labels.join(", ")
+                )
+            }
+            ElfError::UnknownEntry { label } => {
+                write!(f, "entry point `{}` is not a label of the program", label)
+            }
+            ElfError::NotElf => write!(f, "not an ELF file"),
+            ElfError::NotExecutable => write!(f, "not an executable, link it first"),
+            ElfError::Truncated => write!(f, "ELF file ends early"),
+            ElfError::InvalidSegment { address } => write!(
+                f,
+                "segment at 0x{:08X} does not fit into its memory or the address space",
+                address
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ElfError {}
+
+// Names of a string table by their offset into it, which starts with the empty name.
+struct Strings {
+    bytes: Vec<u8>,
+}
+
+impl Strings {
+    fn new() -> Strings {
+        Strings { bytes: vec![0] }
+    }
+
+    fn add(&mut self, name: &str) -> u32 {
+        let offset = self.bytes.len() as u32;
+        self.bytes.extend(name.as_bytes());
+        self.bytes.push(0);
+        offset
+    }
+}
+
+struct Symbol {
+    name: String,
+    value: u32,
+    binding: u8,
+    section: u16,
+}
+
+// A relocation of the psABI at `offset` into its section.
+struct Rela {
+    offset: u32,
+    kind: u32,
+    symbol: String,
+}
+
+#[derive(Default)]
+struct SectionHeader {
+    name: String,
+    kind: u32,
+    flags: u32,
+    address: u32,
+    offset: u32,
+    size: u32,
+    link: u32,
+    info: u32,
+    alignment: u32,
+    entry_size: u32,
+}
+
+// Little-endian bytes of the file.
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn position(&self) -> u32 {
+        self.bytes.len() as u32
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+
+    // Pads the file up to `position`, returning it.
+    fn pad_to(&mut self, position: u32) -> u32 {
+        self.bytes.resize(position as usize, 0);
+        position
+    }
+
+    fn align(&mut self, alignment: u32) -> u32 {
+        self.pad_to(self.position().next_multiple_of(alignment))
+    }
+}
+
+fn section_flags(section: &Section) -> u32 {
+    if section.is_code() {
+        SHF_ALLOC | SHF_EXECINSTR
+    } else if section.name == ".rodata" || section.name.starts_with(".rodata.") {
+        SHF_ALLOC
+    } else {
+        SHF_ALLOC | SHF_WRITE
+    }
+}
+
+// Whether the section takes up no space in the file, only a `.bss` of nothing but zeros.
+fn is_nobits(section: &Section) -> bool {
+    (section.name == ".bss" || section.name.starts_with(".bss."))
+        && section.bytes.iter().all(|&byte| byte == 0)
+}
+
+fn is_store(instruction: Option<Instruction>) -> bool {
+    matches!(
+        instruction,
+        Some(Instruction::SType { .. } | Instruction::FloatStore { .. })
+    )
+}
+
+///
+/// The relocations of the psABI the references left to the linker in `section` turn into.
+///
+/// A pair of `auipc` and `jalr` becomes a single `R_RISCV_CALL`. Other pairs starting with an
+/// `auipc`, like the one of `la`, become a `%pcrel_hi` and a `%pcrel_lo` of a local label at
+/// the `auipc`, which goes to `locals`, like GNU as does.
+///
+fn relas(section: &Section, index: u16, locals: &mut Vec<Symbol>) -> Vec<Rela> {
+    let mut relas = vec![];
+    for (address, relocation) in &section.relocations {
+        let offset = address - section.address;
+        let instruction = |offset: u32| {
+            decode_bytes(&section.bytes[offset as usize..])
+                .ok()
+                .map(|(instruction, _)| instruction)
+        };
+        let compressed = matches!(instruction(offset), Some(Instruction::Compressed { .. }));
+        let lo12 = |offset| {
+            if is_store(instruction(offset)) {
+                R_RISCV_LO12_S
+            } else {
+                R_RISCV_LO12_I
+            }
+        };
+        let symbol = relocation.label.name.clone();
+        let mut push = |offset, kind, symbol| {
+            relas.push(Rela {
+                offset,
+                kind,
+                symbol,
+            })
+        };
+        match relocation.kind {
+            RelocationKind::Branch if compressed => push(offset, R_RISCV_RVC_BRANCH, symbol),
+            RelocationKind::Branch => push(offset, R_RISCV_BRANCH, symbol),
+            RelocationKind::Jump if compressed => push(offset, R_RISCV_RVC_JUMP, symbol),
+            RelocationKind::Jump => push(offset, R_RISCV_JAL, symbol),
+            RelocationKind::Hi => push(offset, R_RISCV_HI20, symbol),
+            RelocationKind::Lo => push(offset, lo12(offset), symbol),
+            RelocationKind::PcrelHi => push(offset, R_RISCV_PCREL_HI20, symbol),
+            RelocationKind::PcrelLo if is_store(instruction(offset)) => {
+                push(offset, R_RISCV_PCREL_LO12_S, symbol)
+            }
+            RelocationKind::PcrelLo => push(offset, R_RISCV_PCREL_LO12_I, symbol),
+            RelocationKind::Pcrel => match instruction(offset + 4) {
+                Some(Instruction::IType {
+                    opcode: IOpcode::Jalr,
+                    ..
+                }) => push(offset, R_RISCV_CALL, symbol),
+                second => {
+                    let local = format!(".Lpcrel_hi{}", locals.len());
+                    push(offset, R_RISCV_PCREL_HI20, symbol);
+                    let kind = if is_store(second) {
+                        R_RISCV_PCREL_LO12_S
+                    } else {
+                        R_RISCV_PCREL_LO12_I
+                    };
+                    push(offset + 4, kind, local.clone());
+                    locals.push(Symbol {
+                        name: local,
+                        value: offset,
+                        binding: STB_LOCAL,
+                        section: index,
+                    });
+                }
+            },
+            RelocationKind::Absolute => {
+                push(offset, R_RISCV_HI20, symbol.clone());
+                push(offset + 4, lo12(offset + 4), symbol);
+            }
+            RelocationKind::Word => push(offset, R_RISCV_32, symbol),
+        }
+    }
+    relas
+}
+
+// Labels and constants, with the value of a label relative to its section in an object.
+fn symbols(assembly: &Assembly, relative: bool) -> Vec<Symbol> {
+    let binding = |name: &String| {
+        if assembly.globals.contains(name) {
+            STB_GLOBAL
+        } else {
+            STB_LOCAL
+        }
+    };
+    let labels = assembly.labels.iter().map(|(name, &address)| {
+        let index = assembly.label_sections[name];
+        let base = if relative {
+            assembly.sections[index].address
+        } else {
+            0
+        };
+        Symbol {
+            name: name.clone(),
+            value: address - base,
+            binding: binding(name),
+            section: index as u16 + 1,
+        }
+    });
+    let constants = assembly.constants.iter().map(|(name, &value)| Symbol {
+        name: name.clone(),
+        value,
+        binding: binding(name),
+        section: SHN_ABS,
+    });
+    labels.chain(constants).collect()
+}
+
+// The parts of a file both kinds share.
+struct Contents {
+    kind: u16,
+    entry: u32,
+    flags: u32,
+    /// The first segment in an executable.
+    segment: Option<(u32, u32)>,
+    symbols: Vec<Symbol>,
+    relas: Vec<Vec<Rela>>,
+}
+
+fn write(assembly: &Assembly, contents: Contents) -> Vec<u8> {
+    let mut file = Writer::default();
+    file.pad_to(HEADER_SIZE);
+    let segments = contents.segment.iter().count() as u32;
+    file.pad_to(HEADER_SIZE + segments * PROGRAM_HEADER_SIZE);
+
+    let mut headers = vec![SectionHeader::default()];
+    // In an executable the sections follow each other as in memory, at the offset into a page
+    // they are loaded at.
+    let origin = assembly.origin();
+    let image_offset = file.position() + origin.wrapping_sub(file.position()) % PAGE_SIZE;
+    let mut file_size = 0;
+    for section in &assembly.sections {
+        let nobits = is_nobits(section);
+        let offset = if contents.segment.is_some() {
+            image_offset + section.address - origin
+        } else {
+            file.align(section.alignment)
+        };
+        if !nobits {
+            file.pad_to(offset);
+            file.bytes.extend(&section.bytes);
+            file_size = file_size.max(section.end() - origin);
+        }
+        headers.push(SectionHeader {
+            name: section.name.clone(),
+            kind: if nobits { SHT_NOBITS } else { SHT_PROGBITS },
+            flags: section_flags(section),
+            address: if contents.segment.is_some() {
+                section.address
+            } else {
+                0
+            },
+            offset,
+            size: section.bytes.len() as u32,
+            alignment: section.alignment,
+            ..Default::default()
+        });
+    }
+
+    // Locals come first in the symbol table, `sh_info` is the index of the first global.
+    let mut symbols = contents.symbols;
+    symbols.sort_by_key(|symbol| symbol.binding);
+    let first_global = 1 + symbols
+        .iter()
+        .filter(|symbol| symbol.binding == STB_LOCAL)
+        .count() as u32;
+    let indices: BTreeMap<&str, u32> = symbols
+        .iter()
+        .enumerate()
+        .map(|(index, symbol)| (symbol.name.as_str(), index as u32 + 1))
+        .collect();
+    let relas: Vec<_> = contents
+        .relas
+        .iter()
+        .enumerate()
+        .filter(|(_, relas)| !relas.is_empty())
+        .collect();
+    let symtab = (headers.len() + relas.len()) as u32;
+
+    for (index, entries) in &relas {
+        let offset = file.align(4);
+        for rela in entries.iter() {
+            file.u32(rela.offset);
+            file.u32(indices[rela.symbol.as_str()] << 8 | rela.kind);
+            file.u32(0);
+        }
+        headers.push(SectionHeader {
+            name: format!(".rela{}", assembly.sections[*index].name),
+            kind: SHT_RELA,
+            flags: SHF_INFO_LINK,
+            offset,
+            size: entries.len() as u32 * RELA_SIZE,
+            link: symtab,
+            info: *index as u32 + 1,
+            alignment: 4,
+            entry_size: RELA_SIZE,
+            ..Default::default()
+        });
+    }
+
+    let mut strings = Strings::new();
+    let offset = file.align(4);
+    file.bytes.extend([0; SYMBOL_SIZE as usize]);
+    for symbol in &symbols {
+        file.u32(strings.add(&symbol.name));
+        file.u32(symbol.value);
+        file.u32(0);
+        file.u8(symbol.binding << 4);
+        file.u8(0);
+        file.u16(symbol.section);
+    }
+    headers.push(SectionHeader {
+        name: ".symtab".to_string(),
+        kind: SHT_SYMTAB,
+        offset,
+        size: (symbols.len() as u32 + 1) * SYMBOL_SIZE,
+        link: symtab + 1,
+        info: first_global,
+        alignment: 4,
+        entry_size: SYMBOL_SIZE,
+        ..Default::default()
+    });
+    let offset = file.position();
+    file.bytes.extend(&strings.bytes);
+    headers.push(SectionHeader {
+        name: ".strtab".to_string(),
+        kind: SHT_STRTAB,
+        offset,
+        size: strings.bytes.len() as u32,
+        alignment: 1,
+        ..Default::default()
+    });
+
+    let mut names = Strings::new();
+    let name_offsets: Vec<u32> = headers
+        .iter()
+        .map(|header| match header.name.as_str() {
+            "" => 0,
+            name => names.add(name),
+        })
+        .collect();
+    let shstrtab_name = names.add(".shstrtab");
+    let offset = file.position();
+    file.bytes.extend(&names.bytes);
+
+    let section_headers = file.align(4);
+    for (header, name) in headers.iter().zip(name_offsets) {
+        write_section_header(&mut file, header, name);
+    }
+    let shstrtab = SectionHeader {
+        kind: SHT_STRTAB,
+        offset,
+        size: names.bytes.len() as u32,
+        alignment: 1,
+        ..Default::default()
+    };
+    write_section_header(&mut file, &shstrtab, shstrtab_name);
+
+    let mut header = Writer::default();
+    header.bytes.extend(b"\x7FELF");
+    // 32-bit, little-endian, version 1 and the System V ABI.
+    header.bytes.extend([1, 1, 1, 0]);
+    header.pad_to(16);
+    header.u16(contents.kind);
+    header.u16(EM_RISCV);
+    header.u32(1);
+    header.u32(contents.entry);
+    header.u32(if segments > 0 { HEADER_SIZE } else { 0 });
+    header.u32(section_headers);
+    header.u32(contents.flags);
+    header.u16(HEADER_SIZE as u16);
+    header.u16(if segments > 0 {
+        PROGRAM_HEADER_SIZE as u16
+    } else {
+        0
+    });
+    header.u16(segments as u16);
+    header.u16(SECTION_HEADER_SIZE as u16);
+    header.u16(headers.len() as u16 + 1);
+    header.u16(headers.len() as u16);
+    if let Some((memory_size, flags)) = contents.segment {
+        header.u32(PT_LOAD);
+        header.u32(image_offset);
+        header.u32(origin);
+        header.u32(origin);
+        header.u32(file_size);
+        header.u32(memory_size);
+        header.u32(flags);
+        header.u32(PAGE_SIZE);
+    }
+    file.bytes[..header.bytes.len()].copy_from_slice(&header.bytes);
+    file.bytes
+}
+
+fn write_section_header(file: &mut Writer, header: &SectionHeader, name: u32) {
+    file.u32(name);
+    file.u32(header.kind);
+    file.u32(header.flags);
+    file.u32(header.address);
+    file.u32(header.offset);
+    file.u32(header.size);
+    file.u32(header.link);
+    file.u32(header.info);
+    file.u32(header.alignment);
+    file.u32(header.entry_size);
+}
+
+fn flags(assembly: &Assembly) -> Result<u32, ElfError> {
+    if assembly.isa.xlen != Xlen::Rv32 {
+        return Err(ElfError::NotRv32);
+    }
+    Ok(if assembly.isa.c { EF_RISCV_RVC } else { 0 })
+}
+
+///
+/// A relocatable object of the program, for one assembled with
+/// [`AssembleOptions::relocatable`] to be linked with others.
+///
+/// Labels the program does not define become undefined globals, and so do `.globl`s of them.
+///
+/// [`AssembleOptions::relocatable`]: crate::riscv::assembler::AssembleOptions::relocatable
+///
+pub fn write_object(assembly: &Assembly) -> Result<Vec<u8>, ElfError> {
+    let flags = flags(assembly)?;
+    let mut symbols = symbols(assembly, true);
+    let mut locals = vec![];
+    let mut relocations = vec![];
+    for (index, section) in assembly.sections.iter().enumerate() {
+        relocations.push(relas(section, index as u16 + 1, &mut locals));
+    }
+    symbols.extend(locals);
+    let defined: BTreeSet<_> = symbols.iter().map(|symbol| symbol.name.clone()).collect();
+    let undefined: BTreeSet<&str> = assembly
+        .undefined()
+        .into_iter()
+        .chain(assembly.globals.iter().map(String::as_str))
+        .filter(|name| !defined.contains(*name))
+        .collect();
+    symbols.extend(undefined.into_iter().map(|name| Symbol {
+        name: name.to_string(),
+        value: 0,
+        binding: STB_GLOBAL,
+        section: SHN_UNDEF,
+    }));
+    let contents = Contents {
+        kind: ET_REL,
+        entry: 0,
+        flags,
+        segment: None,
+        symbols,
+        relas: relocations,
+    };
+    Ok(write(assembly, contents))
+}
+
+/// A static executable of the program, which has to be assembled where it is loaded and
+/// without references left to the linker.
+pub fn write_executable(
+    assembly: &Assembly,
+    options: &ExecutableOptions,
+) -> Result<Vec<u8>, ElfError> {
+    let flags = flags(assembly)?;
+    let unresolved: BTreeSet<_> = assembly
+        .sections
+        .iter()
+        .flat_map(|section| &section.relocations)
+        .map(|(_, relocation)| relocation.label.name.clone())
+        .collect();
+    if !unresolved.is_empty() {
+        return Err(ElfError::Unresolved {
+            labels: unresolved.into_iter().collect(),
+        });
+    }
+    let entry = match &options.entry {
+        Some(label) => *assembly
+            .labels
+            .get(label)
+            .ok_or_else(|| ElfError::UnknownEntry {
+                label: label.clone(),
+            })?,
+        None => match assembly.labels.get("_start") {
+            Some(&address) => address,
+            None => assembly
+                .section(".text")
+                .map_or(assembly.origin(), |section| section.address),
+        },
+    };
+    let end = assembly.sections.last().map_or(0, Section::end);
+    let contents = Contents {
+        kind: ET_EXEC,
+        entry,
+        flags,
+        segment: Some((end - assembly.origin(), PF_R | PF_W | PF_X)),
+        symbols: symbols(assembly, false),
+        relas: vec![],
+    };
+    Ok(write(assembly, contents))
+}
+
+// Little-endian fields of a file being read, any of which may lie past its end. Offsets are
+// `usize` for those taken from the file not to overflow.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn slice(&self, at: usize, len: usize) -> Result<&[u8], ElfError> {
+        self.bytes.get(at..at + len).ok_or(ElfError::Truncated)
+    }
+
+    fn u8(&self, at: usize) -> Result<u8, ElfError> {
+        Ok(self.slice(at, 1)?[0])
+    }
+
+    fn u16(&self, at: usize) -> Result<u16, ElfError> {
+        Ok(u16::from_le_bytes(self.slice(at, 2)?.try_into().unwrap()))
+    }
+
+    fn u32(&self, at: usize) -> Result<u32, ElfError> {
+        Ok(u32::from_le_bytes(self.slice(at, 4)?.try_into().unwrap()))
+    }
+
+    // An offset or size in the file.
+    fn offset(&self, at: usize) -> Result<usize, ElfError> {
+        Ok(self.u32(at)? as usize)
+    }
+
+    fn string(&self, at: usize) -> Result<String, ElfError> {
+        let bytes = self.bytes.get(at..).ok_or(ElfError::Truncated)?;
+        let end = bytes
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or(ElfError::Truncated)?;
+        Ok(String::from_utf8_lossy(&bytes[..end]).into_owned())
+    }
+}
+
+/// Bytes of an executable loaded to `address`, followed by zeros up to `memory_size`.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Segment {
+    pub address: u32,
+    pub bytes: Vec<u8>,
+    pub memory_size: u32,
+    pub executable: bool,
+}
+
+/// The parts of an executable a simulator needs to run it.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Executable {
+    pub entry: u32,
+    pub segments: Vec<Segment>,
+    /// Names of the functions and data by their address, a function wins over other symbols
+    /// and a global over a local at the same address.
+    pub symbols: BTreeMap<u32, String>,
+}
+
+///
+/// Reads the loadable segments and the symbol table of an RV32 executable, like one of GCC
+/// or [`write_executable`].
+///
+/// Symbols left out are undefined and absolute ones, sections, files, and the `.L` and `$`
+/// ones compilers name internal labels and mapping symbols with.
+///
+pub fn read_executable(bytes: &[u8]) -> Result<Executable, ElfError> {
+    let file = Reader { bytes };
+    if !bytes.starts_with(b"\x7FELF") {
+        return Err(ElfError::NotElf);
+    }
+    // 32-bit and little-endian.
+    if file.u8(4)? != 1 || file.u8(5)? != 1 || file.u16(18)? != EM_RISCV {
+        return Err(ElfError::NotRv32);
+    }
+    if file.u16(16)? != ET_EXEC {
+        return Err(ElfError::NotExecutable);
+    }
+    let entry = file.u32(24)?;
+
+    let (program_headers, program_header_size) = (file.offset(28)?, file.u16(42)? as usize);
+    let mut segments = vec![];
+    for index in 0..file.u16(44)? as usize {
+        let at = program_headers + index * program_header_size;
+        if file.u32(at)? != PT_LOAD {
+            continue;
+        }
+        let (address, memory_size) = (file.u32(at + 8)?, file.u32(at + 20)?);
+        let bytes = file.slice(file.offset(at + 4)?, file.offset(at + 16)?)?;
+        if bytes.len() > memory_size as usize || address as u64 + memory_size as u64 > 1 << 32 {
+            return Err(ElfError::InvalidSegment { address });
+        }
+        segments.push(Segment {
+            address,
+            bytes: bytes.to_vec(),
+            memory_size,
+            executable: file.u32(at + 24)? & PF_X != 0,
+        });
+    }
+
+    let (section_headers, section_header_size) = (file.offset(32)?, file.u16(46)? as usize);
+    let section = |index: usize| section_headers + index * section_header_size;
+    let mut symbols = BTreeMap::new();
+    let mut ranks = BTreeMap::new();
+    for index in 0..file.u16(48)? as usize {
+        let at = section(index);
+        if file.u32(at + 4)? != SHT_SYMTAB {
+            continue;
+        }
+        let (offset, size) = (file.offset(at + 16)?, file.offset(at + 20)?);
+        let names = file.offset(section(file.offset(at + 24)?) + 16)?;
+        for symbol in (offset..offset + size)
+            .step_by(SYMBOL_SIZE as usize)
+            .skip(1)
+        {
+            let info = file.u8(symbol + 12)?;
+            let (binding, kind) = (info >> 4, info & 0xF);
+            let section = file.u16(symbol + 14)?;
+            if section == SHN_UNDEF
+                || section >= SHN_LORESERVE
+                || !matches!(kind, 0 | STT_OBJECT | STT_FUNC)
+            {
+                continue;
+            }
+            let name = file.string(names + file.offset(symbol)?)?;
+            if name.is_empty() || name.starts_with(".L") || name.starts_with('$') {
+                continue;
+            }
+            let address = file.u32(symbol + 4)?;
+            let rank = (kind == STT_FUNC, binding != STB_LOCAL);
+            if ranks.get(&address).is_none_or(|&best| rank > best) {
+                ranks.insert(address, rank);
+                symbols.insert(address, name);
+            }
+        }
+    }
+
+    Ok(Executable {
+        entry,
+        segments,
+        symbols,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::assembler::{assemble_riscv_with, AssembleOptions};
+    use crate::riscv::isa::Isa;
+    use rstest::rstest;
+
+    const SOURCE: &str = "
+        .globl _start
+        .globl exit
+        _start:
+            la a0, message
+            call puts
+            lui a1, %hi(counter)
+            sw a0, %lo(counter)(a1)
+        loop:
+            bnez a0, loop
+            j _start
+        .data
+        message: .asciz \"hi\"
+        .align 2
+        table: .word loop, puts
+        .section .bss
+        counter: .zero 4
+    ";
+
+    fn assemble(source: &str, relocatable: bool, origin: u32) -> Assembly {
+        let options = AssembleOptions {
+            isa: Isa::RV32IMAC_ZICSR,
+            origin,
+            relocatable,
+            ..Default::default()
+        };
+        assemble_riscv_with(source, &options).unwrap()
+    }
+
+    // Reads back the parts of a file which the tests look at.
+    struct Elf {
+        bytes: Vec<u8>,
+    }
+
+    impl Elf {
+        fn u16(&self, at: u32) -> u16 {
+            let at = at as usize;
+            u16::from_le_bytes(self.bytes[at..at + 2].try_into().unwrap())
+        }
+
+        fn u32(&self, at: u32) -> u32 {
+            let at = at as usize;
+            u32::from_le_bytes(self.bytes[at..at + 4].try_into().unwrap())
+        }
+
+        fn string(&self, at: u32) -> String {
+            let bytes = &self.bytes[at as usize..];
+            let end = bytes.iter().position(|&byte| byte == 0).unwrap();
+            String::from_utf8(bytes[..end].to_vec()).unwrap()
+        }
+
+        // Name, type, flags, address, offset and size of every section header.
+        fn sections(&self) -> Vec<(String, u32, u32, u32, u32, u32)> {
+            let start = self.u32(32);
+            let count = self.u16(48) as u32;
+            let field =
+                |index: u32, field: u32| self.u32(start + index * SECTION_HEADER_SIZE + 4 * field);
+            let names = field(self.u16(50) as u32, 4);
+            (0..count)
+                .map(|index| {
+                    (
+                        self.string(names + field(index, 0)),
+                        field(index, 1),
+                        field(index, 2),
+                        field(index, 3),
+                        field(index, 4),
+                        field(index, 5),
+                    )
+                })
+                .collect()
+        }
+
+        fn section(&self, name: &str) -> (u32, u32) {
+            let (_, _, _, _, offset, size) = self
+                .sections()
+                .into_iter()
+                .find(|section| section.0 == name)
+                .unwrap();
+            (offset, size)
+        }
+
+        // Name, value, binding and section index of every symbol.
+        fn symbols(&self) -> Vec<(String, u32, u8, u16)> {
+            let (offset, size) = self.section(".symtab");
+            let (names, _) = self.section(".strtab");
+            (offset..offset + size)
+                .step_by(SYMBOL_SIZE as usize)
+                .map(|at| {
+                    (
+                        self.string(names + self.u32(at)),
+                        self.u32(at + 4),
+                        self.bytes[at as usize + 12] >> 4,
+                        self.u16(at + 14),
+                    )
+                })
+                .collect()
+        }
+
+        // Offset, type and symbol name of every relocation in the section.
+        fn relas(&self, name: &str) -> Vec<(u32, u32, String)> {
+            let symbols = self.symbols();
+            let (offset, size) = self.section(name);
+            (offset..offset + size)
+                .step_by(RELA_SIZE as usize)
+                .map(|at| {
+                    let info = self.u32(at + 4);
+                    (
+                        self.u32(at),
+                        info & 0xFF,
+                        symbols[(info >> 8) as usize].0.clone(),
+                    )
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_object_header() {
+        let elf = Elf {
+            bytes: write_object(&assemble(SOURCE, true, 0)).unwrap(),
+        };
+        assert_eq!(&elf.bytes[..7], b"\x7FELF\x01\x01\x01");
+        assert_eq!(elf.u16(16), ET_REL);
+        assert_eq!(elf.u16(18), EM_RISCV);
+        assert_eq!(elf.u32(24), 0);
+        assert_eq!(elf.u32(36), EF_RISCV_RVC);
+        assert_eq!(elf.u16(44), 0);
+    }
+
+    #[test]
+    fn test_object_sections() {
+        let elf = Elf {
+            bytes: write_object(&assemble(SOURCE, true, 0)).unwrap(),
+        };
+        let sections: Vec<_> = elf
+            .sections()
+            .into_iter()
+            .map(|(name, kind, flags, address, _, size)| (name, kind, flags, address, size))
+            .collect();
+        let section = |name: &str, kind, flags, size| (name.to_string(), kind, flags, 0, size);
+        assert_eq!(
+            sections,
+            [
+                section("", 0, 0, 0),
+                section(".text", SHT_PROGBITS, SHF_ALLOC | SHF_EXECINSTR, 32),
+                section(".data", SHT_PROGBITS, SHF_ALLOC | SHF_WRITE, 12),
+                section(".bss", SHT_NOBITS, SHF_ALLOC | SHF_WRITE, 4),
+                section(".rela.text", SHT_RELA, SHF_INFO_LINK, 5 * RELA_SIZE),
+                section(".rela.data", SHT_RELA, SHF_INFO_LINK, 2 * RELA_SIZE),
+                section(".symtab", SHT_SYMTAB, 0, 9 * SYMBOL_SIZE),
+                section(".strtab", SHT_STRTAB, 0, 57),
+                section(".shstrtab", SHT_STRTAB, 0, 66),
+            ]
+        );
+        // Each section starts at its alignment.
+        let (text, _) = elf.section(".text");
+        let (data, _) = elf.section(".data");
+        assert_eq!(&elf.bytes[data as usize..data as usize + 3], b"hi\0");
+        assert!(text.is_multiple_of(4) && data.is_multiple_of(4));
+    }
+
+    #[test]
+    fn test_object_symbols() {
+        let elf = Elf {
+            bytes: write_object(&assemble(SOURCE, true, 0)).unwrap(),
+        };
+        let symbol =
+            |name: &str, value, binding, section| (name.to_string(), value, binding, section);
+        // Locals come first, labels have their offset into the section.
+        assert_eq!(
+            elf.symbols(),
+            [
+                symbol("", 0, STB_LOCAL, SHN_UNDEF),
+                symbol("counter", 0, STB_LOCAL, 3),
+                symbol("loop", 24, STB_LOCAL, 1),
+                symbol("message", 0, STB_LOCAL, 2),
+                symbol("table", 4, STB_LOCAL, 2),
+                symbol(".Lpcrel_hi0", 0, STB_LOCAL, 1),
+                symbol("_start", 0, STB_GLOBAL, 1),
+                symbol("exit", 0, STB_GLOBAL, SHN_UNDEF),
+                symbol("puts", 0, STB_GLOBAL, SHN_UNDEF),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_object_relocations() {
+        let elf = Elf {
+            bytes: write_object(&assemble(SOURCE, true, 0)).unwrap(),
+        };
+        let rela = |offset, kind, symbol: &str| (offset, kind, symbol.to_string());
+        // `la` refers to the `auipc` of its `addi` by a local label, the branch within `.text`
+        // is resolved already.
+        assert_eq!(
+            elf.relas(".rela.text"),
+            [
+                rela(0, R_RISCV_PCREL_HI20, "message"),
+                rela(4, R_RISCV_PCREL_LO12_I, ".Lpcrel_hi0"),
+                rela(8, R_RISCV_CALL, "puts"),
+                rela(16, R_RISCV_HI20, "counter"),
+                rela(20, R_RISCV_LO12_S, "counter"),
+            ]
+        );
+        assert_eq!(
+            elf.relas(".rela.data"),
+            [rela(4, R_RISCV_32, "loop"), rela(8, R_RISCV_32, "puts")]
+        );
+    }
+
+    #[rstest]
+    #[case("c.beqz a0, far", R_RISCV_RVC_BRANCH)]
+    #[case("c.j far", R_RISCV_RVC_JUMP)]
+    #[case("beqz a0, far", R_RISCV_BRANCH)]
+    #[case("jal far", R_RISCV_JAL)]
+    #[case("tail far", R_RISCV_CALL)]
+    #[case("lw a0, %lo(far)(a0)", R_RISCV_LO12_I)]
+    fn test_relocation_kind(#[case] source: &str, #[case] kind: u32) {
+        let elf = Elf {
+            bytes: write_object(&assemble(source, true, 0)).unwrap(),
+        };
+        assert_eq!(elf.relas(".rela.text"), [(0, kind, "far".to_string())]);
+    }
+
+    #[test]
+    fn test_executable() {
+        let source = SOURCE.replace("call puts", "nop").replace(", puts", "");
+        let assembly = assemble(&source, false, 0x8000_0000);
+        let elf = Elf {
+            bytes: write_executable(&assembly, &ExecutableOptions::default()).unwrap(),
+        };
+        assert_eq!(elf.u16(16), ET_EXEC);
+        assert_eq!(elf.u32(24), 0x8000_0000);
+        assert_eq!(elf.u16(44), 1);
+        // The only segment loads every section, `.bss` as the zeros after the file's bytes.
+        let segment: Vec<_> = (0..8)
+            .map(|field| elf.u32(elf.u32(28) + 4 * field))
+            .collect();
+        assert_eq!(
+            segment,
+            [
+                PT_LOAD,
+                0x1000,
+                0x8000_0000,
+                0x8000_0000,
+                36,
+                40,
+                PF_R | PF_W | PF_X,
+                PAGE_SIZE
+            ]
+        );
+        let (offset, _) = elf.section(".text");
+        assert_eq!(
+            &elf.bytes[offset as usize..offset as usize + 36],
+            &assembly.image()[..36]
+        );
+        assert!(elf
+            .sections()
+            .iter()
+            .all(|section| !section.0.starts_with(".rela")));
+        assert!(elf
+            .symbols()
+            .contains(&("loop".to_string(), 0x8000_0014, STB_LOCAL, 1)));
+    }
+
+    #[rstest]
+    #[case(Some("loop"), 0x8000_0008)]
+    #[case(None, 0x8000_0000)]
+    fn test_entry(#[case] entry: Option<&str>, #[case] expected: u32) {
+        let assembly = assemble("nop\nnop\nloop: j loop", false, 0x8000_0000);
+        let options = ExecutableOptions {
+            entry: entry.map(str::to_string),
+        };
+        let elf = Elf {
+            bytes: write_executable(&assembly, &options).unwrap(),
+        };
+        assert_eq!(elf.u32(24), expected);
+    }
+
+    #[test]
+    fn test_entry_start() {
+        let assembly = assemble("nop\n_start: j _start", false, 0x100);
+        let elf = Elf {
+            bytes: write_executable(&assembly, &ExecutableOptions::default()).unwrap(),
+        };
+        assert_eq!(elf.u32(24), 0x104);
+        // The file offset of the image is the same into a page as its address.
+        assert_eq!(elf.section(".text").0 % PAGE_SIZE, 0x100);
+    }
+
+    #[test]
+    fn test_executable_errors() {
+        let assembly = assemble(SOURCE, true, 0);
+        let error = write_executable(&assembly, &ExecutableOptions::default()).unwrap_err();
+        assert_eq!(
+            error,
+            ElfError::Unresolved {
+                labels: vec![
+                    "counter".to_string(),
+                    "loop".to_string(),
+                    "message".to_string(),
+                    "puts".to_string(),
+                ]
+            }
+        );
+
+        let assembly = assemble("nop", false, 0);
+        let options = ExecutableOptions {
+            entry: Some("main".to_string()),
+        };
+        let error = write_executable(&assembly, &options).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "entry point `main` is not a label of the program"
+        );
+    }
+
+    #[test]
+    fn test_not_rv32() {
+        let options = AssembleOptions {
+            isa: Isa::RV64I,
+            ..Default::default()
+        };
+        let assembly = assemble_riscv_with("nop", &options).unwrap();
+        assert_eq!(write_object(&assembly).unwrap_err(), ElfError::NotRv32);
+    }
+
+    #[test]
+    fn test_read_executable() {
+        let source = "
+            .globl _start
+            main:
+            _start: la a0, counter
+            loop: j loop
+            .L1: nop
+            .equ SIZE, 4
+            .section .bss
+            counter: .zero 4
+        ";
+        let assembly = assemble(source, false, 0x8000_0000);
+        let bytes = write_executable(&assembly, &ExecutableOptions::default()).unwrap();
+        let executable = read_executable(&bytes).unwrap();
+        assert_eq!(executable.entry, 0x8000_0000);
+        assert_eq!(
+            executable.segments,
+            [Segment {
+                address: 0x8000_0000,
+                bytes: assembly.image()[..16].to_vec(),
+                memory_size: 20,
+                executable: true,
+            }]
+        );
+        // The global wins over the local at the same address, constants and `.L` labels are
+        // left out.
+        let symbols: Vec<_> = executable
+            .symbols
+            .iter()
+            .map(|(&address, name)| (address, name.as_str()))
+            .collect();
+        assert_eq!(
+            symbols,
+            [
+                (0x8000_0000, "_start"),
+                (0x8000_0008, "loop"),
+                (0x8000_0010, "counter"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_executable_errors() {
+        let bytes = write_executable(&assemble("nop", false, 0), &Default::default()).unwrap();
+        assert_eq!(read_executable(&bytes[..60]), Err(ElfError::Truncated));
+        assert_eq!(read_executable(b"MZ"), Err(ElfError::NotElf));
+        let mut elf64 = bytes.clone();
+        elf64[4] = 2;
+        assert_eq!(read_executable(&elf64), Err(ElfError::NotRv32));
+
+        // More bytes than memory in the first program header.
+        let mut overfull = bytes.clone();
+        overfull[52 + 20..52 + 24].copy_from_slice(&0u32.to_le_bytes());
+        let error = read_executable(&overfull).unwrap_err();
+        assert_eq!(error, ElfError::InvalidSegment { address: 0 });
+        assert_eq!(
+            error.to_string(),
+            "segment at 0x00000000 does not fit into its memory or the address space"
+        );
+
+        let object = write_object(&assemble("nop", true, 0)).unwrap();
+        let error = read_executable(&object).unwrap_err();
+        assert_eq!(error.to_string(), "not an executable, link it first");
+    }
+}
@@ -0,0 +1,1339 @@
+//!
+//! Binary encoding of RV32I and RV64I instructions and their extensions, every instruction is one
+//! little-endian 32-bit word apart from the compressed ones, see [`crate::riscv::compressed`]:
+//!
+//! ```text
+//!  31       25 24   20 19   15 14  12 11        7 6      0
+//! | funct7    | rs2   | rs1   |funct3| rd        | opcode |  R
+//! | imm[11:0]         | rs1   |funct3| rd        | opcode |  I
+//! | imm[11:5] | rs2   | rs1   |funct3| imm[4:0]  | opcode |  S
+//! |imm[12|10:5]| rs2  | rs1   |funct3|imm[4:1|11]| opcode |  B
+//! | imm[31:12]                       | rd        | opcode |  U
+//! | imm[20|10:1|11|19:12]            | rd        | opcode |  J
+//! | fm  | pred | succ | rs1   |funct3| rd        | opcode |  fence
+//! |funct5|aq|rl| rs2   | rs1   |funct3| rd        | opcode |  atomic
+//! | csr               |rs1/imm|funct3| rd        | opcode |  CSR
+//! | rs3  |fmt | rs2   | rs1   | rm   | rd        | opcode |  fused multiply-add
+//! ```
+//!
+
+use std::fmt::{Display, Formatter};
+
+use crate::riscv::ast::{
+    AOpcode, AtomicOrdering, BImmediate, BOpcode, Csr, CsrImmediate, CsrOpcode, FCompareOpcode,
+    FOpcode, FRegister, FToIntOpcode, FUnaryOpcode, FenceSet, FloatFormat, FusedOpcode, IImmediate,
+    IOpcode, Instruction, IntToFOpcode, JImmediate, JOpcode, LOpcode, LSImmediate, ROpcode,
+    Register, RoundingMode, SOpcode, SystemOpcode, UImmediate, UOpcode,
+};
+use crate::riscv::compressed::{decode_compressed, encode_compressed};
+
+pub const OP_IMM: u32 = 0b001_0011;
+pub const OP: u32 = 0b011_0011;
+pub const LOAD: u32 = 0b000_0011;
+pub const STORE: u32 = 0b010_0011;
+pub const BRANCH: u32 = 0b110_0011;
+pub const JAL: u32 = 0b110_1111;
+pub const LUI: u32 = 0b011_0111;
+pub const AUIPC: u32 = 0b001_0111;
+pub const JALR: u32 = 0b110_0111;
+pub const MISC_MEM: u32 = 0b000_1111;
+pub const SYSTEM: u32 = 0b111_0011;
+pub const AMO: u32 = 0b010_1111;
+pub const LOAD_FP: u32 = 0b000_0111;
+pub const STORE_FP: u32 = 0b010_0111;
+pub const OP_FP: u32 = 0b101_0011;
+pub const MADD: u32 = 0b100_0011;
+pub const MSUB: u32 = 0b100_0111;
+pub const NMSUB: u32 = 0b100_1011;
+pub const NMADD: u32 = 0b100_1111;
+pub const OP_IMM_32: u32 = 0b001_1011;
+pub const OP_32: u32 = 0b011_1011;
+
+/// `funct7` of the M extension, which shares the `OP` opcode with the base instructions.
+const MULDIV: u32 = 0b000_0001;
+
+impl IOpcode {
+    /// `funct3` and, for shifts, the `funct7` held in the upper bits of the immediate.
+    pub fn funct(&self) -> (u32, u32) {
+        match self {
+            IOpcode::Addi => (0b000, 0),
+            IOpcode::Slti => (0b010, 0),
+            IOpcode::Sltiu => (0b011, 0),
+            IOpcode::Xori => (0b100, 0),
+            IOpcode::Ori => (0b110, 0),
+            IOpcode::Andi => (0b111, 0),
+            IOpcode::Slli => (0b001, 0),
+            IOpcode::Srli => (0b101, 0),
+            IOpcode::Srai => (0b101, 0b010_0000),
+            IOpcode::Jalr => (0b000, 0),
+            IOpcode::Addiw => (0b000, 0),
+            IOpcode::Slliw => (0b001, 0),
+            IOpcode::Srliw => (0b101, 0),
+            IOpcode::Sraiw => (0b101, 0b010_0000),
+        }
+    }
+
+    pub fn opcode(&self) -> u32 {
+        match self {
+            IOpcode::Jalr => JALR,
+            _ if self.is_word() => OP_IMM_32,
+            _ => OP_IMM,
+        }
+    }
+}
+
+impl ROpcode {
+    /// `funct3` and `funct7`.
+    pub fn funct(&self) -> (u32, u32) {
+        match self {
+            ROpcode::Add => (0b000, 0),
+            ROpcode::Sub => (0b000, 0b010_0000),
+            ROpcode::Sll => (0b001, 0),
+            ROpcode::Slt => (0b010, 0),
+            ROpcode::Sltu => (0b011, 0),
+            ROpcode::Xor => (0b100, 0),
+            ROpcode::Srl => (0b101, 0),
+            ROpcode::Sra => (0b101, 0b010_0000),
+            ROpcode::Or => (0b110, 0),
+            ROpcode::And => (0b111, 0),
+            ROpcode::Mul => (0b000, MULDIV),
+            ROpcode::Mulh => (0b001, MULDIV),
+            ROpcode::Mulhsu => (0b010, MULDIV),
+            ROpcode::Mulhu => (0b011, MULDIV),
+            ROpcode::Div => (0b100, MULDIV),
+            ROpcode::Divu => (0b101, MULDIV),
+            ROpcode::Rem => (0b110, MULDIV),
+            ROpcode::Remu => (0b111, MULDIV),
+            ROpcode::Addw => (0b000, 0),
+            ROpcode::Subw => (0b000, 0b010_0000),
+            ROpcode::Sllw => (0b001, 0),
+            ROpcode::Srlw => (0b101, 0),
+            ROpcode::Sraw => (0b101, 0b010_0000),
+            ROpcode::Mulw => (0b000, MULDIV),
+            ROpcode::Divw => (0b100, MULDIV),
+            ROpcode::Divuw => (0b101, MULDIV),
+            ROpcode::Remw => (0b110, MULDIV),
+            ROpcode::Remuw => (0b111, MULDIV),
+        }
+    }
+
+    pub fn opcode(&self) -> u32 {
+        if self.is_word() {
+            OP_32
+        } else {
+            OP
+        }
+    }
+}
+
+impl SOpcode {
+    pub fn funct3(&self) -> u32 {
+        match self {
+            SOpcode::Sb => 0b000,
+            SOpcode::Sh => 0b001,
+            SOpcode::Sw => 0b010,
+            SOpcode::Sd => 0b011,
+        }
+    }
+}
+
+impl LOpcode {
+    pub fn funct3(&self) -> u32 {
+        match self {
+            LOpcode::Lb => 0b000,
+            LOpcode::Lh => 0b001,
+            LOpcode::Lw => 0b010,
+            LOpcode::Lbu => 0b100,
+            LOpcode::Lhu => 0b101,
+            LOpcode::Ld => 0b011,
+            LOpcode::Lwu => 0b110,
+        }
+    }
+}
+
+impl BOpcode {
+    pub fn funct3(&self) -> u32 {
+        match self {
+            BOpcode::Beq => 0b000,
+            BOpcode::Bne => 0b001,
+            BOpcode::Blt => 0b100,
+            BOpcode::Bge => 0b101,
+            BOpcode::Bltu => 0b110,
+            BOpcode::Bgeu => 0b111,
+        }
+    }
+}
+
+impl SystemOpcode {
+    /// The whole instruction word, `ecall` and `ebreak` have no operands.
+    pub fn word(&self) -> u32 {
+        match self {
+            SystemOpcode::Ecall => SYSTEM,
+            SystemOpcode::Ebreak => 1 << 20 | SYSTEM,
+        }
+    }
+}
+
+impl AOpcode {
+    pub fn funct5(&self) -> u32 {
+        match self {
+            AOpcode::LrW | AOpcode::LrD => 0b00010,
+            AOpcode::ScW | AOpcode::ScD => 0b00011,
+            AOpcode::AmoswapW | AOpcode::AmoswapD => 0b00001,
+            AOpcode::AmoaddW | AOpcode::AmoaddD => 0b00000,
+            AOpcode::AmoxorW | AOpcode::AmoxorD => 0b00100,
+            AOpcode::AmoandW | AOpcode::AmoandD => 0b01100,
+            AOpcode::AmoorW | AOpcode::AmoorD => 0b01000,
+            AOpcode::AmominW | AOpcode::AmominD => 0b10000,
+            AOpcode::AmomaxW | AOpcode::AmomaxD => 0b10100,
+            AOpcode::AmominuW | AOpcode::AmominuD => 0b11000,
+            AOpcode::AmomaxuW | AOpcode::AmomaxuD => 0b11100,
+        }
+    }
+
+    /// `funct3`, the width of the memory access.
+    pub fn funct3(&self) -> u32 {
+        if self.is_doubleword() {
+            0b011
+        } else {
+            0b010
+        }
+    }
+}
+
+impl CsrOpcode {
+    /// `funct3` of the register form, the immediate form sets bit 2 on top.
+    pub fn funct3(&self) -> u32 {
+        match self {
+            CsrOpcode::Csrrw => 0b001,
+            CsrOpcode::Csrrs => 0b010,
+            CsrOpcode::Csrrc => 0b011,
+        }
+    }
+}
+
+impl FloatFormat {
+    /// The `fmt` field of arithmetic instructions.
+    pub fn fmt(&self) -> u32 {
+        match self {
+            FloatFormat::Single => 0b00,
+            FloatFormat::Double => 0b01,
+        }
+    }
+
+    /// The `funct3` of loads and stores, which is the width of the access.
+    pub fn width(&self) -> u32 {
+        match self {
+            FloatFormat::Single => 0b010,
+            FloatFormat::Double => 0b011,
+        }
+    }
+
+    fn from_fmt(fmt: u32) -> Option<FloatFormat> {
+        match fmt {
+            0b00 => Some(FloatFormat::Single),
+            0b01 => Some(FloatFormat::Double),
+            _ => None,
+        }
+    }
+
+    fn other(&self) -> FloatFormat {
+        match self {
+            FloatFormat::Single => FloatFormat::Double,
+            FloatFormat::Double => FloatFormat::Single,
+        }
+    }
+}
+
+impl RoundingMode {
+    /// The `rm` field, which takes the place of `funct3`.
+    pub fn bits(&self) -> u32 {
+        match self {
+            RoundingMode::Rne => 0b000,
+            RoundingMode::Rtz => 0b001,
+            RoundingMode::Rdn => 0b010,
+            RoundingMode::Rup => 0b011,
+            RoundingMode::Rmm => 0b100,
+            RoundingMode::Dynamic => 0b111,
+        }
+    }
+
+    fn from_bits(bits: u32) -> Option<RoundingMode> {
+        match bits {
+            0b000 => Some(RoundingMode::Rne),
+            0b001 => Some(RoundingMode::Rtz),
+            0b010 => Some(RoundingMode::Rdn),
+            0b011 => Some(RoundingMode::Rup),
+            0b100 => Some(RoundingMode::Rmm),
+            0b111 => Some(RoundingMode::Dynamic),
+            _ => None,
+        }
+    }
+}
+
+impl FOpcode {
+    /// `funct5` and, for the opcodes which do not round, the `funct3` in place of `rm`.
+    pub fn funct(&self) -> (u32, Option<u32>) {
+        match self {
+            FOpcode::Fadd => (0b00000, None),
+            FOpcode::Fsub => (0b00001, None),
+            FOpcode::Fmul => (0b00010, None),
+            FOpcode::Fdiv => (0b00011, None),
+            FOpcode::Fsgnj => (0b00100, Some(0b000)),
+            FOpcode::Fsgnjn => (0b00100, Some(0b001)),
+            FOpcode::Fsgnjx => (0b00100, Some(0b010)),
+            FOpcode::Fmin => (0b00101, Some(0b000)),
+            FOpcode::Fmax => (0b00101, Some(0b001)),
+        }
+    }
+}
+
+impl FCompareOpcode {
+    pub fn funct3(&self) -> u32 {
+        match self {
+            FCompareOpcode::Feq => 0b010,
+            FCompareOpcode::Flt => 0b001,
+            FCompareOpcode::Fle => 0b000,
+        }
+    }
+}
+
+impl FusedOpcode {
+    pub fn opcode(&self) -> u32 {
+        match self {
+            FusedOpcode::Fmadd => MADD,
+            FusedOpcode::Fmsub => MSUB,
+            FusedOpcode::Fnmsub => NMSUB,
+            FusedOpcode::Fnmadd => NMADD,
+        }
+    }
+}
+
+impl UOpcode {
+    pub fn opcode(&self) -> u32 {
+        match self {
+            UOpcode::Lui => LUI,
+            UOpcode::Auipc => AUIPC,
+        }
+    }
+}
+
+impl JOpcode {
+    pub fn opcode(&self) -> u32 {
+        match self {
+            JOpcode::Jal => JAL,
+        }
+    }
+}
+
+// Registers only have five bits, the parser never produces anything larger.
+fn reg(register: Register) -> u32 {
+    debug_assert!(
+        register.0 < 32,
+        "register x{} cannot be encoded",
+        register.0
+    );
+    (register.0 & 0x1F) as u32
+}
+
+// Floating-point registers share the register fields with the integer ones.
+fn freg(register: FRegister) -> Register {
+    Register(register.0)
+}
+
+/// An `OP-FP` instruction, `rs2` selects the variant of conversions and moves.
+fn fp_type(
+    funct5: u32,
+    format: FloatFormat,
+    rs2: u8,
+    rs1: Register,
+    funct3: u32,
+    rd: Register,
+) -> u32 {
+    r_type(
+        funct5 << 2 | format.fmt(),
+        Register(rs2),
+        rs1,
+        funct3,
+        rd,
+        OP_FP,
+    )
+}
+
+fn r_type(
+    funct7: u32,
+    rs2: Register,
+    rs1: Register,
+    funct3: u32,
+    rd: Register,
+    opcode: u32,
+) -> u32 {
+    funct7 << 25 | reg(rs2) << 20 | reg(rs1) << 15 | funct3 << 12 | reg(rd) << 7 | opcode
+}
+
+fn i_type(imm: i32, rs1: Register, funct3: u32, rd: Register, opcode: u32) -> u32 {
+    (imm as u32 & 0xFFF) << 20 | reg(rs1) << 15 | funct3 << 12 | reg(rd) << 7 | opcode
+}
+
+fn s_type(imm: i32, rs2: Register, rs1: Register, funct3: u32, opcode: u32) -> u32 {
+    let imm = imm as u32;
+    (imm >> 5 & 0x7F) << 25
+        | reg(rs2) << 20
+        | reg(rs1) << 15
+        | funct3 << 12
+        | (imm & 0x1F) << 7
+        | opcode
+}
+
+fn b_type(imm: i32, rs2: Register, rs1: Register, funct3: u32, opcode: u32) -> u32 {
+    let imm = imm as u32;
+    (imm >> 12 & 1) << 31
+        | (imm >> 5 & 0x3F) << 25
+        | reg(rs2) << 20
+        | reg(rs1) << 15
+        | funct3 << 12
+        | (imm >> 1 & 0xF) << 8
+        | (imm >> 11 & 1) << 7
+        | opcode
+}
+
+fn u_type(imm: i32, rd: Register, opcode: u32) -> u32 {
+    (imm as u32 & 0xF_FFFF) << 12 | reg(rd) << 7 | opcode
+}
+
+fn j_type(imm: i32, rd: Register, opcode: u32) -> u32 {
+    let imm = imm as u32;
+    (imm >> 20 & 1) << 31
+        | (imm >> 1 & 0x3FF) << 21
+        | (imm >> 11 & 1) << 20
+        | (imm >> 12 & 0xFF) << 12
+        | reg(rd) << 7
+        | opcode
+}
+
+/// The instruction word, compressed instructions only take up the lower half.
+pub fn encode(instruction: &Instruction) -> u32 {
+    match *instruction {
+        Instruction::IType {
+            opcode,
+            rd,
+            rs1,
+            imm,
+        } => {
+            let (funct3, funct7) = opcode.funct();
+            let imm = imm.0 as i32 | (funct7 << 5) as i32;
+            i_type(imm, rs1, funct3, rd, opcode.opcode())
+        }
+        Instruction::UType { opcode, rd, imm } => u_type(imm.0, rd, opcode.opcode()),
+        Instruction::RType {
+            opcode,
+            rd,
+            rs1,
+            rs2,
+        } => {
+            let (funct3, funct7) = opcode.funct();
+            r_type(funct7, rs2, rs1, funct3, rd, opcode.opcode())
+        }
+        Instruction::JType { opcode, rd, imm } => j_type(imm.0, rd, opcode.opcode()),
+        Instruction::BType {
+            opcode,
+            rs1,
+            rs2,
+            imm,
+        } => b_type(imm.0 as i32, rs2, rs1, opcode.funct3(), BRANCH),
+        Instruction::SType {
+            opcode,
+            rs1,
+            rs2,
+            imm,
+        } => s_type(imm.0 as i32, rs2, rs1, opcode.funct3(), STORE),
+        Instruction::LType {
+            opcode,
+            rd,
+            rs1,
+            imm,
+        } => i_type(imm.0 as i32, rs1, opcode.funct3(), rd, LOAD),
+        Instruction::Fence { pred, succ } => {
+            ((pred.0 & 0xF) as u32) << 24 | ((succ.0 & 0xF) as u32) << 20 | MISC_MEM
+        }
+        Instruction::System { opcode } => opcode.word(),
+        Instruction::Atomic {
+            opcode,
+            rd,
+            rs1,
+            rs2,
+            ordering,
+        } => {
+            let funct7 =
+                opcode.funct5() << 2 | (ordering.acquire as u32) << 1 | ordering.release as u32;
+            r_type(funct7, rs2, rs1, opcode.funct3(), rd, AMO)
+        }
+        Instruction::Csr {
+            opcode,
+            rd,
+            rs1,
+            csr,
+        } => i_type(csr.0 as i32, rs1, opcode.funct3(), rd, SYSTEM),
+        Instruction::CsrImmediate {
+            opcode,
+            rd,
+            imm,
+            csr,
+        } => {
+            let uimm = Register(imm.0 & 0x1F);
+            i_type(csr.0 as i32, uimm, 0b100 | opcode.funct3(), rd, SYSTEM)
+        }
+        Instruction::FloatLoad {
+            format,
+            rd,
+            rs1,
+            imm,
+        } => i_type(imm.0 as i32, rs1, format.width(), freg(rd), LOAD_FP),
+        Instruction::FloatStore {
+            format,
+            rs1,
+            rs2,
+            imm,
+        } => s_type(imm.0 as i32, freg(rs2), rs1, format.width(), STORE_FP),
+        Instruction::FloatRType {
+            opcode,
+            format,
+            rd,
+            rs1,
+            rs2,
+            rm,
+        } => {
+            let (funct5, funct3) = opcode.funct();
+            let funct3 = funct3.unwrap_or(rm.bits());
+            fp_type(funct5, format, rs2.0, freg(rs1), funct3, freg(rd))
+        }
+        Instruction::FloatUnary {
+            opcode,
+            format,
+            rd,
+            rs1,
+            rm,
+        } => match opcode {
+            FUnaryOpcode::Sqrt => fp_type(0b01011, format, 0, freg(rs1), rm.bits(), freg(rd)),
+            // `rs2` holds the format converted from.
+            FUnaryOpcode::Convert => {
+                let from = format.other().fmt() as u8;
+                fp_type(0b01000, format, from, freg(rs1), rm.bits(), freg(rd))
+            }
+        },
+        Instruction::FloatToInt {
+            opcode,
+            format,
+            rd,
+            rs1,
+            rm,
+        } => {
+            let (funct5, rs2, funct3) = match opcode {
+                FToIntOpcode::Convert => (0b11000, 0, rm.bits()),
+                FToIntOpcode::ConvertUnsigned => (0b11000, 1, rm.bits()),
+                FToIntOpcode::ConvertLong => (0b11000, 2, rm.bits()),
+                FToIntOpcode::ConvertLongUnsigned => (0b11000, 3, rm.bits()),
+                FToIntOpcode::Move => (0b11100, 0, 0b000),
+                FToIntOpcode::Classify => (0b11100, 0, 0b001),
+            };
+            fp_type(funct5, format, rs2, freg(rs1), funct3, rd)
+        }
+        Instruction::IntToFloat {
+            opcode,
+            format,
+            rd,
+            rs1,
+            rm,
+        } => {
+            let (funct5, rs2, funct3) = match opcode {
+                IntToFOpcode::Convert => (0b11010, 0, rm.bits()),
+                IntToFOpcode::ConvertUnsigned => (0b11010, 1, rm.bits()),
+                IntToFOpcode::ConvertLong => (0b11010, 2, rm.bits()),
+                IntToFOpcode::ConvertLongUnsigned => (0b11010, 3, rm.bits()),
+                IntToFOpcode::Move => (0b11110, 0, 0b000),
+            };
+            fp_type(funct5, format, rs2, rs1, funct3, freg(rd))
+        }
+        Instruction::FloatCompare {
+            opcode,
+            format,
+            rd,
+            rs1,
+            rs2,
+        } => fp_type(0b10100, format, rs2.0, freg(rs1), opcode.funct3(), rd),
+        Instruction::FloatFused {
+            opcode,
+            format,
+            rd,
+            rs1,
+            rs2,
+            rs3,
+            rm,
+        } => {
+            let funct7 = reg(freg(rs3)) << 2 | format.fmt();
+            r_type(
+                funct7,
+                freg(rs2),
+                freg(rs1),
+                rm.bits(),
+                freg(rd),
+                opcode.opcode(),
+            )
+        }
+        Instruction::Compressed { .. } => encode_compressed(instruction) as u32,
+    }
+}
+
+/// Number of bytes `instruction` takes up in memory.
+pub fn encoded_len(instruction: &Instruction) -> usize {
+    match instruction {
+        Instruction::Compressed { .. } => 2,
+        _ => 4,
+    }
+}
+
+/// One word per instruction, see [`encode_bytes`] for programs with compressed instructions.
+pub fn encode_program(instructions: &[Instruction]) -> Vec<u32> {
+    instructions.iter().map(encode).collect()
+}
+
+/// Memory image of `instructions`, each takes up its [`encoded_len`].
+pub fn encode_bytes(instructions: &[Instruction]) -> Vec<u8> {
+    instructions
+        .iter()
+        .flat_map(|instruction| {
+            let bytes = encode(instruction).to_le_bytes();
+            bytes[..encoded_len(instruction)].to_vec()
+        })
+        .collect()
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum DecodeError {
+    InvalidOpcode { word: u32 },
+    InvalidFunction { word: u32 },
+    InvalidCompressed { parcel: u16 },
+    /// The image ends in the middle of an instruction.
+    Truncated,
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::InvalidOpcode { word } => write!(f, "invalid opcode in 0x{:08X}", word),
+            DecodeError::InvalidFunction { word } => {
+                write!(f, "invalid function bits in 0x{:08X}", word)
+            }
+            DecodeError::InvalidCompressed { parcel } => {
+                write!(f, "invalid compressed instruction 0x{:04X}", parcel)
+            }
+            DecodeError::Truncated => write!(f, "the instruction is cut off"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+// Sign extends the lowest `bits` bits of `value`.
+fn sign_extend(value: u32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    ((value << shift) as i32) >> shift
+}
+
+/// Decodes an instruction word, one whose lowest two bits are not `11` holds a compressed
+/// instruction in its lower half and the upper half is ignored. The instructions of RV64I are
+/// decoded as well, [`crate::riscv::isa::Isa::missing_base`] tells whether they belong.
+pub fn decode(word: u32) -> Result<Instruction, DecodeError> {
+    if word & 0b11 != 0b11 {
+        return decode_compressed(word as u16);
+    }
+    let rd = Register(((word >> 7) & 0x1F) as u8);
+    let rs1 = Register(((word >> 15) & 0x1F) as u8);
+    let rs2 = Register(((word >> 20) & 0x1F) as u8);
+    let funct3 = (word >> 12) & 0x7;
+    let funct7 = word >> 25;
+    let invalid_function = DecodeError::InvalidFunction { word };
+    let i_imm = sign_extend(word >> 20, 12);
+    let s_imm = sign_extend((word >> 25) << 5 | (word >> 7) & 0x1F, 12);
+
+    match word & 0x7F {
+        OP_IMM => {
+            // The lowest bit of `funct7` is the sixth bit of the shift amount on RV64.
+            let opcode = match (funct3, funct7 >> 1) {
+                (0b000, _) => IOpcode::Addi,
+                (0b010, _) => IOpcode::Slti,
+                (0b011, _) => IOpcode::Sltiu,
+                (0b100, _) => IOpcode::Xori,
+                (0b110, _) => IOpcode::Ori,
+                (0b111, _) => IOpcode::Andi,
+                (0b001, 0) => IOpcode::Slli,
+                (0b101, 0) => IOpcode::Srli,
+                (0b101, 0b01_0000) => IOpcode::Srai,
+                _ => return Err(invalid_function),
+            };
+            // Shifts keep only the shift amount.
+            let imm = match opcode {
+                IOpcode::Slli | IOpcode::Srli | IOpcode::Srai => i_imm & 0x3F,
+                _ => i_imm,
+            };
+            Ok(Instruction::IType {
+                opcode,
+                rd,
+                rs1,
+                imm: IImmediate(imm as i16),
+            })
+        }
+        OP => {
+            let opcode = match (funct3, funct7) {
+                (0b000, 0) => ROpcode::Add,
+                (0b000, 0b010_0000) => ROpcode::Sub,
+                (0b001, 0) => ROpcode::Sll,
+                (0b010, 0) => ROpcode::Slt,
+                (0b011, 0) => ROpcode::Sltu,
+                (0b100, 0) => ROpcode::Xor,
+                (0b101, 0) => ROpcode::Srl,
+                (0b101, 0b010_0000) => ROpcode::Sra,
+                (0b110, 0) => ROpcode::Or,
+                (0b111, 0) => ROpcode::And,
+                (0b000, MULDIV) => ROpcode::Mul,
+                (0b001, MULDIV) => ROpcode::Mulh,
+                (0b010, MULDIV) => ROpcode::Mulhsu,
+                (0b011, MULDIV) => ROpcode::Mulhu,
+                (0b100, MULDIV) => ROpcode::Div,
+                (0b101, MULDIV) => ROpcode::Divu,
+                (0b110, MULDIV) => ROpcode::Rem,
+                (0b111, MULDIV) => ROpcode::Remu,
+                _ => return Err(invalid_function),
+            };
+            Ok(Instruction::RType {
+                opcode,
+                rd,
+                rs1,
+                rs2,
+            })
+        }
+        OP_IMM_32 => {
+            let opcode = match (funct3, funct7) {
+                (0b000, _) => IOpcode::Addiw,
+                (0b001, 0) => IOpcode::Slliw,
+                (0b101, 0) => IOpcode::Srliw,
+                (0b101, 0b010_0000) => IOpcode::Sraiw,
+                _ => return Err(invalid_function),
+            };
+            let imm = match opcode {
+                IOpcode::Addiw => i_imm,
+                _ => i_imm & 0x1F,
+            };
+            Ok(Instruction::IType {
+                opcode,
+                rd,
+                rs1,
+                imm: IImmediate(imm as i16),
+            })
+        }
+        OP_32 => {
+            let opcode = match (funct3, funct7) {
+                (0b000, 0) => ROpcode::Addw,
+                (0b000, 0b010_0000) => ROpcode::Subw,
+                (0b001, 0) => ROpcode::Sllw,
+                (0b101, 0) => ROpcode::Srlw,
+                (0b101, 0b010_0000) => ROpcode::Sraw,
+                (0b000, MULDIV) => ROpcode::Mulw,
+                (0b100, MULDIV) => ROpcode::Divw,
+                (0b101, MULDIV) => ROpcode::Divuw,
+                (0b110, MULDIV) => ROpcode::Remw,
+                (0b111, MULDIV) => ROpcode::Remuw,
+                _ => return Err(invalid_function),
+            };
+            Ok(Instruction::RType {
+                opcode,
+                rd,
+                rs1,
+                rs2,
+            })
+        }
+        LOAD => {
+            let opcode = match funct3 {
+                0b000 => LOpcode::Lb,
+                0b001 => LOpcode::Lh,
+                0b010 => LOpcode::Lw,
+                0b100 => LOpcode::Lbu,
+                0b101 => LOpcode::Lhu,
+                0b011 => LOpcode::Ld,
+                0b110 => LOpcode::Lwu,
+                _ => return Err(invalid_function),
+            };
+            Ok(Instruction::LType {
+                opcode,
+                rd,
+                rs1,
+                imm: LSImmediate(i_imm as i16),
+            })
+        }
+        STORE => {
+            let opcode = match funct3 {
+                0b000 => SOpcode::Sb,
+                0b001 => SOpcode::Sh,
+                0b010 => SOpcode::Sw,
+                0b011 => SOpcode::Sd,
+                _ => return Err(invalid_function),
+            };
+            Ok(Instruction::SType {
+                opcode,
+                rs1,
+                rs2,
+                imm: LSImmediate(s_imm as i16),
+            })
+        }
+        BRANCH => {
+            let opcode = match funct3 {
+                0b000 => BOpcode::Beq,
+                0b001 => BOpcode::Bne,
+                0b100 => BOpcode::Blt,
+                0b101 => BOpcode::Bge,
+                0b110 => BOpcode::Bltu,
+                0b111 => BOpcode::Bgeu,
+                _ => return Err(invalid_function),
+            };
+            let imm = (word >> 31) << 12
+                | ((word >> 7) & 1) << 11
+                | ((word >> 25) & 0x3F) << 5
+                | ((word >> 8) & 0xF) << 1;
+            Ok(Instruction::BType {
+                opcode,
+                rs1,
+                rs2,
+                imm: BImmediate(sign_extend(imm, 13) as i16),
+            })
+        }
+        LUI | AUIPC => {
+            let opcode = if word & 0x7F == LUI {
+                UOpcode::Lui
+            } else {
+                UOpcode::Auipc
+            };
+            Ok(Instruction::UType {
+                opcode,
+                rd,
+                imm: UImmediate((word as i32) >> 12),
+            })
+        }
+        JAL => {
+            let imm = (word >> 31) << 20
+                | ((word >> 12) & 0xFF) << 12
+                | ((word >> 20) & 1) << 11
+                | ((word >> 21) & 0x3FF) << 1;
+            Ok(Instruction::JType {
+                opcode: JOpcode::Jal,
+                rd,
+                imm: JImmediate(sign_extend(imm, 21)),
+            })
+        }
+        JALR if funct3 == 0 => Ok(Instruction::IType {
+            opcode: IOpcode::Jalr,
+            rd,
+            rs1,
+            imm: IImmediate(i_imm as i16),
+        }),
+        JALR => Err(invalid_function),
+        // Only plain fences, `fence.tso`, empty sets and the reserved fields are rejected.
+        MISC_MEM
+            if word & 0xF00F_FF80 == 0 && word & 0x0F00_0000 != 0 && word & 0x00F0_0000 != 0 =>
+        {
+            Ok(Instruction::Fence {
+                pred: FenceSet(((word >> 24) & 0xF) as u8),
+                succ: FenceSet(((word >> 20) & 0xF) as u8),
+            })
+        }
+        MISC_MEM => Err(invalid_function),
+        SYSTEM if funct3 == 0 => match word {
+            _ if word == SystemOpcode::Ecall.word() => Ok(Instruction::System {
+                opcode: SystemOpcode::Ecall,
+            }),
+            _ if word == SystemOpcode::Ebreak.word() => Ok(Instruction::System {
+                opcode: SystemOpcode::Ebreak,
+            }),
+            _ => Err(invalid_function),
+        },
+        SYSTEM => {
+            let opcode = match funct3 & 0b011 {
+                0b001 => CsrOpcode::Csrrw,
+                0b010 => CsrOpcode::Csrrs,
+                0b011 => CsrOpcode::Csrrc,
+                _ => return Err(invalid_function),
+            };
+            let csr = Csr((word >> 20) as u16);
+            if funct3 & 0b100 == 0 {
+                Ok(Instruction::Csr {
+                    opcode,
+                    rd,
+                    rs1,
+                    csr,
+                })
+            } else {
+                Ok(Instruction::CsrImmediate {
+                    opcode,
+                    rd,
+                    imm: CsrImmediate(rs1.0),
+                    csr,
+                })
+            }
+        }
+        AMO if funct3 == 0b010 || funct3 == 0b011 => {
+            let (word, doubleword) = match funct7 >> 2 {
+                0b00010 if rs2.0 == 0 => (AOpcode::LrW, AOpcode::LrD),
+                0b00011 => (AOpcode::ScW, AOpcode::ScD),
+                0b00001 => (AOpcode::AmoswapW, AOpcode::AmoswapD),
+                0b00000 => (AOpcode::AmoaddW, AOpcode::AmoaddD),
+                0b00100 => (AOpcode::AmoxorW, AOpcode::AmoxorD),
+                0b01100 => (AOpcode::AmoandW, AOpcode::AmoandD),
+                0b01000 => (AOpcode::AmoorW, AOpcode::AmoorD),
+                0b10000 => (AOpcode::AmominW, AOpcode::AmominD),
+                0b10100 => (AOpcode::AmomaxW, AOpcode::AmomaxD),
+                0b11000 => (AOpcode::AmominuW, AOpcode::AmominuD),
+                0b11100 => (AOpcode::AmomaxuW, AOpcode::AmomaxuD),
+                _ => return Err(invalid_function),
+            };
+            let opcode = if funct3 == 0b011 { doubleword } else { word };
+            Ok(Instruction::Atomic {
+                opcode,
+                rd,
+                rs1,
+                rs2,
+                ordering: AtomicOrdering {
+                    acquire: funct7 & 0b10 != 0,
+                    release: funct7 & 0b01 != 0,
+                },
+            })
+        }
+        AMO => Err(invalid_function),
+        LOAD_FP | STORE_FP => {
+            let format = match funct3 {
+                0b010 => FloatFormat::Single,
+                0b011 => FloatFormat::Double,
+                _ => return Err(invalid_function),
+            };
+            if word & 0x7F == LOAD_FP {
+                Ok(Instruction::FloatLoad {
+                    format,
+                    rd: FRegister(rd.0),
+                    rs1,
+                    imm: LSImmediate(i_imm as i16),
+                })
+            } else {
+                Ok(Instruction::FloatStore {
+                    format,
+                    rs1,
+                    rs2: FRegister(rs2.0),
+                    imm: LSImmediate(s_imm as i16),
+                })
+            }
+        }
+        OP_FP => decode_fp(rd, rs1, rs2, funct3, funct7).ok_or(invalid_function),
+        MADD | MSUB | NMSUB | NMADD => {
+            let opcode = match word & 0x7F {
+                MADD => FusedOpcode::Fmadd,
+                MSUB => FusedOpcode::Fmsub,
+                NMSUB => FusedOpcode::Fnmsub,
+                _ => FusedOpcode::Fnmadd,
+            };
+            let format = FloatFormat::from_fmt(funct7 & 0b11).ok_or(invalid_function)?;
+            let rm = RoundingMode::from_bits(funct3).ok_or(invalid_function)?;
+            Ok(Instruction::FloatFused {
+                opcode,
+                format,
+                rd: FRegister(rd.0),
+                rs1: FRegister(rs1.0),
+                rs2: FRegister(rs2.0),
+                rs3: FRegister((funct7 >> 2) as u8),
+                rm,
+            })
+        }
+        _ => Err(DecodeError::InvalidOpcode { word }),
+    }
+}
+
+// The `OP-FP` instructions, `None` if the function bits are invalid.
+fn decode_fp(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    funct3: u32,
+    funct7: u32,
+) -> Option<Instruction> {
+    let format = FloatFormat::from_fmt(funct7 & 0b11)?;
+    let rounding = || RoundingMode::from_bits(funct3);
+    let (frd, frs1, frs2) = (FRegister(rd.0), FRegister(rs1.0), FRegister(rs2.0));
+    let float_r_type = |opcode, rm| Instruction::FloatRType {
+        opcode,
+        format,
+        rd: frd,
+        rs1: frs1,
+        rs2: frs2,
+        rm,
+    };
+    let to_int = |opcode, rm| Instruction::FloatToInt {
+        opcode,
+        format,
+        rd,
+        rs1: frs1,
+        rm,
+    };
+    let from_int = |opcode, rm| Instruction::IntToFloat {
+        opcode,
+        format,
+        rd: frd,
+        rs1,
+        rm,
+    };
+    let unary = |opcode, rm| Instruction::FloatUnary {
+        opcode,
+        format,
+        rd: frd,
+        rs1: frs1,
+        rm,
+    };
+    let compare = |opcode| Instruction::FloatCompare {
+        opcode,
+        format,
+        rd,
+        rs1: frs1,
+        rs2: frs2,
+    };
+    let dynamic = RoundingMode::Dynamic;
+
+    let instruction = match (funct7 >> 2, rs2.0, funct3) {
+        (0b00000, _, _) => float_r_type(FOpcode::Fadd, rounding()?),
+        (0b00001, _, _) => float_r_type(FOpcode::Fsub, rounding()?),
+        (0b00010, _, _) => float_r_type(FOpcode::Fmul, rounding()?),
+        (0b00011, _, _) => float_r_type(FOpcode::Fdiv, rounding()?),
+        (0b00100, _, 0b000) => float_r_type(FOpcode::Fsgnj, dynamic),
+        (0b00100, _, 0b001) => float_r_type(FOpcode::Fsgnjn, dynamic),
+        (0b00100, _, 0b010) => float_r_type(FOpcode::Fsgnjx, dynamic),
+        (0b00101, _, 0b000) => float_r_type(FOpcode::Fmin, dynamic),
+        (0b00101, _, 0b001) => float_r_type(FOpcode::Fmax, dynamic),
+        (0b01011, 0, _) => unary(FUnaryOpcode::Sqrt, rounding()?),
+        (0b01000, from, _) if from as u32 == format.other().fmt() => {
+            unary(FUnaryOpcode::Convert, rounding()?)
+        }
+        (0b10100, _, 0b010) => compare(FCompareOpcode::Feq),
+        (0b10100, _, 0b001) => compare(FCompareOpcode::Flt),
+        (0b10100, _, 0b000) => compare(FCompareOpcode::Fle),
+        (0b11000, 0, _) => to_int(FToIntOpcode::Convert, rounding()?),
+        (0b11000, 1, _) => to_int(FToIntOpcode::ConvertUnsigned, rounding()?),
+        (0b11000, 2, _) => to_int(FToIntOpcode::ConvertLong, rounding()?),
+        (0b11000, 3, _) => to_int(FToIntOpcode::ConvertLongUnsigned, rounding()?),
+        (0b11010, 0, _) => from_int(IntToFOpcode::Convert, rounding()?),
+        (0b11010, 1, _) => from_int(IntToFOpcode::ConvertUnsigned, rounding()?),
+        (0b11010, 2, _) => from_int(IntToFOpcode::ConvertLong, rounding()?),
+        (0b11010, 3, _) => from_int(IntToFOpcode::ConvertLongUnsigned, rounding()?),
+        (0b11100, 0, 0b000) => to_int(FToIntOpcode::Move, dynamic),
+        (0b11100, 0, 0b001) => to_int(FToIntOpcode::Classify, dynamic),
+        (0b11110, 0, 0b000) => from_int(IntToFOpcode::Move, dynamic),
+        _ => return None,
+    };
+    Some(instruction)
+}
+
+/// Length in bytes of the instruction whose first byte is `byte`.
+pub fn instruction_len(byte: u8) -> usize {
+    if byte & 0b11 == 0b11 {
+        4
+    } else {
+        2
+    }
+}
+
+/// Decodes the instruction at the start of `bytes`, returning it with the number of bytes used.
+pub fn decode_bytes(bytes: &[u8]) -> Result<(Instruction, usize), DecodeError> {
+    let len = instruction_len(*bytes.first().ok_or(DecodeError::Truncated)?);
+    let mut word = [0; 4];
+    word[..len].copy_from_slice(bytes.get(..len).ok_or(DecodeError::Truncated)?);
+    Ok((decode(u32::from_le_bytes(word))?, len))
+}
+
+/// Memory image of `words`, RISC-V stores instructions little-endian.
+pub fn to_bytes(words: &[u32]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+/// Words of a little-endian memory image, a trailing partial word is dropped.
+pub fn from_bytes(bytes: &[u8]) -> Vec<u32> {
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::ast::{BImmediate, IImmediate, JImmediate, LSImmediate, Symbol, UImmediate};
+    use crate::riscv::isa::Isa;
+    use crate::riscv::parser::parse_riscv_with;
+    use rstest::rstest;
+
+    fn x(n: u8) -> Register {
+        Register::from(n)
+    }
+
+    // Words as listed by the RISC-V spec and produced by GNU as.
+    #[rstest]
+    #[case("addi x1, x2, 5", 0x0051_0093)]
+    #[case("addi sp, sp, -16", 0xFF01_0113)]
+    #[case("slti a0, a1, 12", 0x00C5_A513)]
+    #[case("sltiu a2, a3, 15", 0x00F6_B613)]
+    #[case("sltiu a0, a1, -1", 0xFFF5_B513)]
+    #[case("xori x5, x6, -1", 0xFFF3_4293)]
+    #[case("ori a6, a7, 21", 0x0158_E813)]
+    #[case("andi s2, s3, 24", 0x0189_F913)]
+    #[case("slli x3, x4, 2", 0x0022_1193)]
+    #[case("srli s4, s5, 27", 0x01BA_DA13)]
+    #[case("srai x1, x2, 3", 0x4031_5093)]
+    #[case("add x5, x1, x2", 0x0020_82B3)]
+    #[case("sub x1, x2, x3", 0x4031_00B3)]
+    #[case("sll x22, x23, x24", 0x018B_9B33)]
+    #[case("slt x31, zero, ra", 0x0010_2FB3)]
+    #[case("sltu sp, gp, tp", 0x0041_B133)]
+    #[case("xor x19, x20, x21", 0x015A_49B3)]
+    #[case("srl x25, x26, x27", 0x01BD_5CB3)]
+    #[case("sra x28, x29, x30", 0x41EE_DE33)]
+    #[case("or x10, x11, x12", 0x00C5_E533)]
+    #[case("and x7, x8, x9", 0x0094_73B3)]
+    #[case("sb s8, 300(s9)", 0x138C_8623)]
+    #[case("sh x3, -20(x4)", 0xFE32_1623)]
+    #[case("sw x1, 100(x2)", 0x0611_2223)]
+    #[case("lb x3, 20(x4)", 0x0142_0183)]
+    #[case("lh x5, 50(x6)", 0x0323_1283)]
+    #[case("lw x1, 100(x2)", 0x0641_2083)]
+    #[case("lw x7, -30(x8)", 0xFE24_2383)]
+    #[case("beq x1, x2, 4", 0x0020_8263)]
+    #[case("bne x3, x4, -8", 0xFE41_9CE3)]
+    #[case("blt x5, x6, -20", 0xFE62_C6E3)]
+    #[case("bge x7, x8, 200", 0x0C83_D463)]
+    #[case("bltu t0, t1, 300", 0x1262_E663)]
+    #[case("bgeu s0, s1, 124", 0x0694_7E63)]
+    #[case("lui x1, 0x12345", 0x1234_50B7)]
+    #[case("lui x2, -8", 0xFFFF_8137)]
+    #[case("auipc x3, 0x1234", 0x0123_4197)]
+    #[case("jal x1, 8", 0x0080_00EF)]
+    #[case("jal zero, -4", 0xFFDF_F06F)]
+    #[case("jalr ra, 0(t0)", 0x0002_80E7)]
+    #[case("jalr x0, x1, 12", 0x00C0_8067)]
+    #[case("lbu x3, 20(x4)", 0x0142_4183)]
+    #[case("lhu x5, 50(x6)", 0x0323_5283)]
+    #[case("fence", 0x0FF0_000F)]
+    #[case("fence rw, w", 0x0310_000F)]
+    #[case("fence i, o", 0x0840_000F)]
+    #[case("ecall", 0x0000_0073)]
+    #[case("ebreak", 0x0010_0073)]
+    #[case("mul a0, a1, a2", 0x02C5_8533)]
+    #[case("mulh t0, t1, t2", 0x0273_12B3)]
+    #[case("mulhsu x1, x2, x3", 0x0231_20B3)]
+    #[case("mulhu x1, x2, x3", 0x0231_30B3)]
+    #[case("div x1, x2, x3", 0x0231_40B3)]
+    #[case("divu x1, x2, x3", 0x0231_50B3)]
+    #[case("rem x1, x2, x3", 0x0231_60B3)]
+    #[case("remu s0, s1, s2", 0x0324_F433)]
+    #[case("lr.w a0, (a1)", 0x1005_A52F)]
+    #[case("sc.w a2, a1, (a0)", 0x18B5_262F)]
+    #[case("amoadd.w.aqrl a0, a1, (a2)", 0x06B6_252F)]
+    #[case("amoswap.w.aq t0, t1, (t2)", 0x0C63_A2AF)]
+    #[case("amomaxu.w.rl a0, a1, (a2)", 0xE2B6_252F)]
+    #[case("csrrw a0, mstatus, a1", 0x3005_9573)]
+    #[case("csrrs a0, mcause, zero", 0x3420_2573)]
+    #[case("csrrc t0, 0x7C0, t1", 0x7C03_32F3)]
+    #[case("csrrwi zero, mstatus, 8", 0x3004_5073)]
+    #[case("csrrsi zero, mstatus, 8", 0x3004_6073)]
+    #[case("flw fa0, 8(sp)", 0x0081_2507)]
+    #[case("fsd fa0, 8(sp)", 0x00A1_3427)]
+    #[case("fadd.s fa0, fa1, fa2", 0x00C5_F553)]
+    #[case("fsub.d ft0, ft1, ft2, rtz", 0x0A20_9053)]
+    #[case("fsqrt.s fa0, fa1", 0x5805_F553)]
+    #[case("fcvt.d.s fa0, fa1", 0x4205_8553)]
+    #[case("fcvt.d.s fa0, fa1, dyn", 0x4205_F553)]
+    #[case("fcvt.d.w fa0, a0", 0xD205_0553)]
+    #[case("fcvt.d.wu fa0, a0", 0xD215_0553)]
+    #[case("fcvt.s.d fa0, fa1", 0x4015_F553)]
+    #[case("fcvt.w.s a0, fa0, rtz", 0xC005_1553)]
+    #[case("fcvt.wu.d a0, fa0", 0xC215_7553)]
+    #[case("fcvt.s.w fa0, a0", 0xD005_7553)]
+    #[case("fmv.x.w a0, fa0", 0xE005_0553)]
+    #[case("fmv.w.x fa0, a0", 0xF005_0553)]
+    #[case("fclass.d a0, fa0", 0xE205_1553)]
+    #[case("feq.s a0, fa0, fa1", 0xA0B5_2553)]
+    #[case("flt.d a0, fa0, fa1", 0xA2B5_1553)]
+    #[case("fsgnjn.s fa0, fa1, fa1", 0x20B5_9553)]
+    #[case("fmax.d fa0, fa1, fa2", 0x2AC5_9553)]
+    #[case("fmadd.s fa0, fa1, fa2, fa3", 0x68C5_F543)]
+    #[case("fnmadd.d fa0, fa1, fa2, fa3, rne", 0x6AC5_854F)]
+    fn test_encode_known(#[case] source: &str, #[case] word: u32) {
+        let program = parse_riscv_with(source, Isa::RV32IMAFDC_ZICSR).unwrap();
+        let Symbol::Instruction(instruction) = &program.symbols[0].node else {
+            panic!("{} is not an instruction", source);
+        };
+        assert_eq!(
+            encode(instruction),
+            word,
+            "{}: 0x{:08X} != 0x{:08X}",
+            source,
+            encode(instruction),
+            word
+        );
+        assert_eq!(decode(word), Ok(*instruction));
+    }
+
+    #[rstest]
+    #[case("ld a0, 8(sp)", 0x0081_3503)]
+    #[case("sd ra, 8(sp)", 0x0011_3423)]
+    #[case("lwu a0, 0(a1)", 0x0005_E503)]
+    #[case("addiw a0, a0, 1", 0x0015_051B)]
+    #[case("slli a0, a0, 63", 0x03F5_1513)]
+    #[case("srai a0, a0, 40", 0x4285_5513)]
+    #[case("slliw t0, t1, 3", 0x0033_129B)]
+    #[case("srliw t0, t1, 3", 0x0033_529B)]
+    #[case("sraiw a0, a0, 31", 0x41F5_551B)]
+    #[case("addw a0, a1, a2", 0x00C5_853B)]
+    #[case("subw a0, a1, a2", 0x40C5_853B)]
+    #[case("sllw a0, a1, a2", 0x00C5_953B)]
+    #[case("srlw a0, a1, a2", 0x00C5_D53B)]
+    #[case("sraw a0, a1, a2", 0x40C5_D53B)]
+    #[case("mulw a0, a1, a2", 0x02C5_853B)]
+    #[case("divw a0, a1, a2", 0x02C5_C53B)]
+    #[case("divuw a0, a1, a2", 0x02C5_D53B)]
+    #[case("remw a0, a1, a2", 0x02C5_E53B)]
+    #[case("remuw a0, a1, a2", 0x02C5_F53B)]
+    #[case("lr.d a0, (a1)", 0x1005_B52F)]
+    #[case("sc.d.rl a0, a2, (a1)", 0x1AC5_B52F)]
+    #[case("amoadd.d a0, a2, (a1)", 0x00C5_B52F)]
+    #[case("amomaxu.d.aqrl a0, a2, (a1)", 0xE6C5_B52F)]
+    #[case("fcvt.l.s a0, fa1", 0xC025_F553)]
+    #[case("fcvt.lu.d a0, fa1, rtz", 0xC235_9553)]
+    #[case("fcvt.d.l fa0, a0", 0xD225_7553)]
+    #[case("fcvt.s.lu fa0, a0", 0xD035_7553)]
+    #[case("fmv.x.d a0, fa1", 0xE205_8553)]
+    #[case("fmv.d.x fa0, a0", 0xF205_0553)]
+    fn test_encode_known_64(#[case] source: &str, #[case] word: u32) {
+        let program = parse_riscv_with(source, Isa::RV64IMAFDC_ZICSR).unwrap();
+        let Symbol::Instruction(instruction) = &program.symbols[0].node else {
+            panic!("{} is not an instruction", source);
+        };
+        assert_eq!(encode(instruction), word, "{}", source);
+        assert_eq!(decode(word), Ok(*instruction));
+    }
+
+    #[rstest]
+    #[case(0x0000_000B)]
+    #[case(0xFFFF_FFFF)]
+    #[case(0x0000_007F)]
+    fn test_decode_invalid_opcode(#[case] word: u32) {
+        assert_eq!(decode(word), Err(DecodeError::InvalidOpcode { word }));
+    }
+
+    #[rstest]
+    #[case(0x0800_80B3)]
+    #[case(0x4000_10B3)]
+    #[case(0x4000_1093)]
+    #[case(0x0000_7003)]
+    #[case(0x0000_4023)]
+    #[case(0x0000_2063)]
+    #[case(0x0000_1067)]
+    #[case(0x0000_100F)]
+    #[case(0x8330_000F)]
+    #[case(0x0030_000F)]
+    #[case(0x0000_0173)]
+    #[case(0x0020_0073)]
+    #[case(0x0000_4073)]
+    #[case(0x1015_A52F)]
+    #[case(0x0000_402F)]
+    #[case(0x2800_202F)]
+    #[case(0x04C5_F553)]
+    #[case(0x00C5_D553)]
+    #[case(0x20B5_B553)]
+    #[case(0xE015_0553)]
+    fn test_decode_invalid_function(#[case] word: u32) {
+        assert_eq!(decode(word), Err(DecodeError::InvalidFunction { word }));
+    }
+
+    #[rstest]
+    #[case(8, 0x0080_00EF)]
+    #[case(-4, 0xFFDF_F0EF)]
+    #[case(2048, 0x0010_00EF)]
+    #[case(-1048576, 0x8000_00EF)]
+    fn test_encode_jal(#[case] offset: i32, #[case] word: u32) {
+        let instruction = Instruction::JType {
+            opcode: JOpcode::Jal,
+            rd: x(1),
+            imm: JImmediate(offset),
+        };
+        assert_eq!(encode(&instruction), word);
+        assert_eq!(decode(word), Ok(instruction));
+    }
+
+    #[test]
+    fn test_branch_immediate_extremes() {
+        let branch = |imm| Instruction::BType {
+            opcode: BOpcode::Beq,
+            rs1: x(0),
+            rs2: x(0),
+            imm: BImmediate(imm),
+        };
+        assert_eq!(encode(&branch(4094)), 0x7E00_0FE3);
+        assert_eq!(encode(&branch(-4096)), 0x8000_0063);
+        assert_eq!(decode(0x7E00_0FE3), Ok(branch(4094)));
+        assert_eq!(decode(0x8000_0063), Ok(branch(-4096)));
+    }
+
+    #[test]
+    fn test_immediates_are_truncated_to_their_field() {
+        let load = Instruction::LType {
+            opcode: LOpcode::Lw,
+            rd: x(1),
+            rs1: x(1),
+            imm: LSImmediate(-1),
+        };
+        assert_eq!(encode(&load) >> 20, 0xFFF);
+        let store = Instruction::SType {
+            opcode: SOpcode::Sw,
+            rs1: x(1),
+            rs2: x(1),
+            imm: LSImmediate(-1),
+        };
+        assert_eq!(encode(&store) & 0xFE00_0F80, 0xFE00_0F80);
+        let upper = Instruction::UType {
+            opcode: UOpcode::Lui,
+            rd: x(1),
+            imm: UImmediate(0xF_FFFF),
+        };
+        assert_eq!(encode(&upper), 0xFFFF_F0B7);
+        let addi = Instruction::IType {
+            opcode: IOpcode::Addi,
+            rd: x(0),
+            rs1: x(0),
+            imm: IImmediate(0),
+        };
+        assert_eq!(encode(&addi), 0x0000_0013);
+    }
+
+    #[test]
+    fn test_decode_bytes() {
+        let bytes = [0x05, 0x05, 0x93, 0x00, 0x51, 0x00, 0x01];
+        assert_eq!(decode_bytes(&bytes).unwrap().1, 2);
+        assert_eq!(decode(0x0051_0093), Ok(decode_bytes(&bytes[2..]).unwrap().0));
+        assert_eq!(decode_bytes(&bytes[6..]), Err(DecodeError::Truncated));
+        assert_eq!(decode_bytes(&bytes[2..4]), Err(DecodeError::Truncated));
+        assert_eq!(decode_bytes(&[]), Err(DecodeError::Truncated));
+        assert_eq!(
+            decode(0),
+            Err(DecodeError::InvalidCompressed { parcel: 0x0000 })
+        );
+    }
+
+    #[test]
+    fn test_to_bytes() {
+        assert_eq!(
+            to_bytes(&[0x0051_0093, 0x0000_0013]),
+            [0x93, 0x00, 0x51, 0x00, 0x13, 0x00, 0x00, 0x00]
+        );
+    }
+}
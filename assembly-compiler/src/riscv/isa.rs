@@ -0,0 +1,567 @@
+//!
+//! The instruction set a program is written for, given as an ISA string such as `rv32im`,
+//! `rv32ima_zicsr` or `rv64imc`.
+//!
+//! The parser accepts every instruction it knows and then rejects those the configured ISA does
+//! not include, so that a program for the wrong ISA is told which extension it is missing.
+//!
+
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::riscv::ast::{
+    AOpcode, COpcode, FToIntOpcode, FUnaryOpcode, FloatFormat, IOpcode, Instruction, IntToFOpcode,
+    LOpcode, ROpcode, SOpcode,
+};
+
+/// Width of the integer registers, XLEN in the specification.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default)]
+pub enum Xlen {
+    #[default]
+    Rv32,
+    Rv64,
+}
+
+impl Xlen {
+    pub fn bits(&self) -> u32 {
+        match self {
+            Xlen::Rv32 => 32,
+            Xlen::Rv64 => 64,
+        }
+    }
+
+    /// Bits of the shift amount of `slli`, `srli` and `srai`, enough to shift out every bit.
+    pub fn shift_bits(&self) -> u32 {
+        self.bits().trailing_zeros()
+    }
+}
+
+/// Prints the base integer instruction set, e.g. `RV64I`.
+impl Display for Xlen {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RV{}I", self.bits())
+    }
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Extension {
+    /// Integer multiplication and division.
+    M,
+    /// Atomic memory operations.
+    A,
+    /// Single-precision floating point.
+    F,
+    /// Double-precision floating point.
+    D,
+    /// 16-bit encodings of common instructions.
+    C,
+    /// Instructions to access the control and status registers.
+    Zicsr,
+}
+
+impl Extension {
+    /// Name in the ISA string, a single letter or a name starting with `z`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Extension::M => "m",
+            Extension::A => "a",
+            Extension::F => "f",
+            Extension::D => "d",
+            Extension::C => "c",
+            Extension::Zicsr => "zicsr",
+        }
+    }
+}
+
+/// Prints the name as the specification writes it, e.g. `M` or `Zicsr`.
+impl Display for Extension {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (first, rest) = self.name().split_at(1);
+        write!(f, "{}{}", first.to_ascii_uppercase(), rest)
+    }
+}
+
+/// The base integer instruction set and the extensions on top of it, `rv32i` by default.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default)]
+pub struct Isa {
+    pub xlen: Xlen,
+    pub m: bool,
+    pub a: bool,
+    pub f: bool,
+    pub d: bool,
+    pub c: bool,
+    pub zicsr: bool,
+}
+
+impl Isa {
+    pub const RV32I: Isa = Isa {
+        xlen: Xlen::Rv32,
+        m: false,
+        a: false,
+        f: false,
+        d: false,
+        c: false,
+        zicsr: false,
+    };
+    pub const RV32IM: Isa = Isa {
+        m: true,
+        ..Isa::RV32I
+    };
+    pub const RV32IC: Isa = Isa {
+        c: true,
+        ..Isa::RV32I
+    };
+    pub const RV32IMC: Isa = Isa {
+        m: true,
+        c: true,
+        ..Isa::RV32I
+    };
+    /// What machine-mode code with interrupts and atomics needs.
+    pub const RV32IMAC_ZICSR: Isa = Isa {
+        m: true,
+        a: true,
+        c: true,
+        zicsr: true,
+        ..Isa::RV32I
+    };
+    /// Everything this assembler knows, what `rv32gc` stands for.
+    pub const RV32IMAFDC_ZICSR: Isa = Isa {
+        xlen: Xlen::Rv32,
+        m: true,
+        a: true,
+        f: true,
+        d: true,
+        c: true,
+        zicsr: true,
+    };
+    pub const RV64I: Isa = Isa {
+        xlen: Xlen::Rv64,
+        ..Isa::RV32I
+    };
+    pub const RV64IM: Isa = Isa {
+        xlen: Xlen::Rv64,
+        ..Isa::RV32IM
+    };
+    pub const RV64IMAFDC_ZICSR: Isa = Isa {
+        xlen: Xlen::Rv64,
+        ..Isa::RV32IMAFDC_ZICSR
+    };
+
+    /// Every extension in the order of the canonical ISA string.
+    const EXTENSIONS: [Extension; 6] = [
+        Extension::M,
+        Extension::A,
+        Extension::F,
+        Extension::D,
+        Extension::C,
+        Extension::Zicsr,
+    ];
+
+    pub fn supports(&self, extension: Extension) -> bool {
+        match extension {
+            Extension::M => self.m,
+            Extension::A => self.a,
+            Extension::F => self.f,
+            Extension::D => self.d,
+            Extension::C => self.c,
+            Extension::Zicsr => self.zicsr,
+        }
+    }
+
+    fn flag(&mut self, extension: Extension) -> &mut bool {
+        match extension {
+            Extension::M => &mut self.m,
+            Extension::A => &mut self.a,
+            Extension::F => &mut self.f,
+            Extension::D => &mut self.d,
+            Extension::C => &mut self.c,
+            Extension::Zicsr => &mut self.zicsr,
+        }
+    }
+
+    /// Alignment of instructions in bytes, compressed instructions allow every other halfword.
+    pub fn instruction_alignment(&self) -> u32 {
+        if self.c {
+            2
+        } else {
+            4
+        }
+    }
+
+    /// The base instruction set `instruction` needs if it is not the one of this ISA, like `ld`
+    /// which only exists in RV64I.
+    pub fn missing_base(&self, instruction: &Instruction) -> Option<Xlen> {
+        instruction.xlen().filter(|&xlen| xlen != self.xlen)
+    }
+
+    /// The extension `instruction` needs which this ISA does not include, if any.
+    pub fn missing_extension(&self, instruction: &Instruction) -> Option<Extension> {
+        instruction
+            .extension()
+            .filter(|&extension| !self.supports(extension))
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum IsaError {
+    /// The string does not start with `rv32i` or `rv64i`.
+    InvalidBase {
+        isa: String,
+    },
+    UnknownExtension {
+        extension: String,
+    },
+    DuplicateExtension {
+        extension: String,
+    },
+}
+
+impl Display for IsaError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IsaError::InvalidBase { isa } => {
+                write!(
+                    f,
+                    "`{}` does not start with a base ISA such as `rv32i` or `rv64i`",
+                    isa
+                )
+            }
+            IsaError::UnknownExtension { extension } => {
+                write!(f, "unknown extension `{}`", extension)
+            }
+            IsaError::DuplicateExtension { extension } => {
+                write!(f, "extension `{}` is given twice", extension)
+            }
+        }
+    }
+}
+
+impl std::error::Error for IsaError {}
+
+///
+/// Parses an ISA string such as `rv32imc`, `rv32ima_zicsr` or `rv64im`, case and the order of
+/// the extensions do not matter.
+///
+/// Single-letter extensions follow the base directly, longer names are separated by `_`. As the
+/// specification requires, `d` brings in `f` and `f` brings in `zicsr`. The base may be `g` in
+/// place of `i`, which stands for `imafd_zicsr_zifencei`.
+///
+/// Zifencei is accepted so that the ISA strings of compilers work, but without `fence.i` it
+/// has nothing to add.
+///
+impl FromStr for Isa {
+    type Err = IsaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lowercase = s.to_ascii_lowercase();
+        let (xlen, rest) = if let Some(rest) = lowercase.strip_prefix("rv32") {
+            (Xlen::Rv32, rest)
+        } else if let Some(rest) = lowercase.strip_prefix("rv64") {
+            (Xlen::Rv64, rest)
+        } else {
+            return Err(IsaError::InvalidBase { isa: s.to_string() });
+        };
+        let (mut isa, general, extensions) = if let Some(extensions) = rest.strip_prefix('i') {
+            (Isa { xlen, ..Isa::RV32I }, false, extensions)
+        } else if let Some(extensions) = rest.strip_prefix('g') {
+            let isa = Isa {
+                xlen,
+                c: false,
+                ..Isa::RV32IMAFDC_ZICSR
+            };
+            (isa, true, extensions)
+        } else {
+            return Err(IsaError::InvalidBase { isa: s.to_string() });
+        };
+        let mut parts = extensions.split('_');
+        let letters = parts.next().unwrap_or_default();
+        let names = letters
+            .char_indices()
+            .map(|(i, _)| &letters[i..i + 1])
+            .chain(parts);
+        for name in names {
+            // Naming again what `g` brings in changes nothing, as with GCC.
+            let implied = general && ["i", "m", "a", "f", "d", "zicsr"].contains(&name);
+            if name == "zifencei" || implied {
+                continue;
+            }
+            let Some(extension) = Isa::EXTENSIONS
+                .into_iter()
+                .find(|extension| extension.name() == name)
+            else {
+                return Err(IsaError::UnknownExtension {
+                    extension: name.to_string(),
+                });
+            };
+            let enabled = isa.flag(extension);
+            if *enabled {
+                return Err(IsaError::DuplicateExtension {
+                    extension: name.to_string(),
+                });
+            }
+            *enabled = true;
+        }
+        isa.f |= isa.d;
+        isa.zicsr |= isa.f;
+        Ok(isa)
+    }
+}
+
+/// Prints the canonical ISA string, e.g. `rv32imac_zicsr`.
+impl Display for Isa {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "rv{}i", self.xlen.bits())?;
+        for extension in Isa::EXTENSIONS {
+            if self.supports(extension) {
+                let separator = if extension.name().len() > 1 { "_" } else { "" };
+                write!(f, "{}{}", separator, extension.name())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Instruction {
+    /// The extension the instruction belongs to, `None` for the base instruction set.
+    pub fn extension(&self) -> Option<Extension> {
+        match self {
+            Instruction::RType { opcode, .. } if opcode.is_muldiv() => Some(Extension::M),
+            Instruction::Atomic { .. } => Some(Extension::A),
+            Instruction::Csr { .. } | Instruction::CsrImmediate { .. } => Some(Extension::Zicsr),
+            // `fcvt.s.d` has the format it converts to.
+            Instruction::FloatUnary {
+                opcode: FUnaryOpcode::Convert,
+                ..
+            } => Some(Extension::D),
+            Instruction::FloatLoad { format, .. }
+            | Instruction::FloatStore { format, .. }
+            | Instruction::FloatRType { format, .. }
+            | Instruction::FloatUnary { format, .. }
+            | Instruction::FloatToInt { format, .. }
+            | Instruction::IntToFloat { format, .. }
+            | Instruction::FloatCompare { format, .. }
+            | Instruction::FloatFused { format, .. } => Some(format.extension()),
+            Instruction::Compressed { .. } => Some(Extension::C),
+            _ => None,
+        }
+    }
+}
+
+impl Instruction {
+    /// The only base instruction set the instruction exists in, `None` for those of both.
+    pub fn xlen(&self) -> Option<Xlen> {
+        match self {
+            Instruction::IType { opcode, .. } if opcode.is_word() => Some(Xlen::Rv64),
+            Instruction::RType { opcode, .. } if opcode.is_word() => Some(Xlen::Rv64),
+            Instruction::LType {
+                opcode: LOpcode::Ld | LOpcode::Lwu,
+                ..
+            }
+            | Instruction::SType {
+                opcode: SOpcode::Sd,
+                ..
+            } => Some(Xlen::Rv64),
+            Instruction::Atomic { opcode, .. } if opcode.is_doubleword() => Some(Xlen::Rv64),
+            // Doublewords only fit into the integer registers of RV64.
+            Instruction::FloatToInt {
+                opcode: FToIntOpcode::ConvertLong | FToIntOpcode::ConvertLongUnsigned,
+                ..
+            }
+            | Instruction::FloatToInt {
+                opcode: FToIntOpcode::Move,
+                format: FloatFormat::Double,
+                ..
+            }
+            | Instruction::IntToFloat {
+                opcode: IntToFOpcode::ConvertLong | IntToFOpcode::ConvertLongUnsigned,
+                ..
+            }
+            | Instruction::IntToFloat {
+                opcode: IntToFOpcode::Move,
+                format: FloatFormat::Double,
+                ..
+            } => Some(Xlen::Rv64),
+            // RV64C has `c.addiw` in its place.
+            Instruction::Compressed {
+                opcode: COpcode::Jal,
+                ..
+            } => Some(Xlen::Rv32),
+            _ => None,
+        }
+    }
+}
+
+impl FloatFormat {
+    pub fn extension(&self) -> Extension {
+        match self {
+            FloatFormat::Single => Extension::F,
+            FloatFormat::Double => Extension::D,
+        }
+    }
+}
+
+impl IOpcode {
+    /// `addiw` and the shifts ending in `w`, which work on the lower 32 bits of a register.
+    pub fn is_word(&self) -> bool {
+        matches!(
+            self,
+            IOpcode::Addiw | IOpcode::Slliw | IOpcode::Srliw | IOpcode::Sraiw
+        )
+    }
+}
+
+impl AOpcode {
+    /// The operations ending in `.d`, which work on doublewords.
+    pub fn is_doubleword(&self) -> bool {
+        matches!(
+            self,
+            AOpcode::LrD
+                | AOpcode::ScD
+                | AOpcode::AmoswapD
+                | AOpcode::AmoaddD
+                | AOpcode::AmoxorD
+                | AOpcode::AmoandD
+                | AOpcode::AmoorD
+                | AOpcode::AmominD
+                | AOpcode::AmomaxD
+                | AOpcode::AmominuD
+                | AOpcode::AmomaxuD
+        )
+    }
+}
+
+impl ROpcode {
+    pub fn is_muldiv(&self) -> bool {
+        matches!(
+            self,
+            ROpcode::Mul
+                | ROpcode::Mulh
+                | ROpcode::Mulhsu
+                | ROpcode::Mulhu
+                | ROpcode::Div
+                | ROpcode::Divu
+                | ROpcode::Rem
+                | ROpcode::Remu
+                | ROpcode::Mulw
+                | ROpcode::Divw
+                | ROpcode::Divuw
+                | ROpcode::Remw
+                | ROpcode::Remuw
+        )
+    }
+
+    /// The operations ending in `w`, which work on the lower 32 bits of the registers.
+    pub fn is_word(&self) -> bool {
+        self.word_operation().is_some()
+    }
+
+    /// The operation an RV64 `w` operation carries out on the lower words before sign extending
+    /// the result, `addw` is an `add` of RV32I.
+    pub fn word_operation(&self) -> Option<ROpcode> {
+        match self {
+            ROpcode::Addw => Some(ROpcode::Add),
+            ROpcode::Subw => Some(ROpcode::Sub),
+            ROpcode::Sllw => Some(ROpcode::Sll),
+            ROpcode::Srlw => Some(ROpcode::Srl),
+            ROpcode::Sraw => Some(ROpcode::Sra),
+            ROpcode::Mulw => Some(ROpcode::Mul),
+            ROpcode::Divw => Some(ROpcode::Div),
+            ROpcode::Divuw => Some(ROpcode::Divu),
+            ROpcode::Remw => Some(ROpcode::Rem),
+            ROpcode::Remuw => Some(ROpcode::Remu),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("rv32i", Isa::RV32I)]
+    #[case("rv32im", Isa::RV32IM)]
+    #[case("RV32IM", Isa::RV32IM)]
+    #[case("rv32ic", Isa::RV32IC)]
+    #[case("rv32imc", Isa::RV32IMC)]
+    #[case("rv32imac_zicsr", Isa::RV32IMAC_ZICSR)]
+    #[case("rv32i_zicsr", Isa { zicsr: true, ..Isa::RV32I })]
+    #[case("rv32ia", Isa { a: true, ..Isa::RV32I })]
+    #[case("rv32imafdc_zicsr", Isa::RV32IMAFDC_ZICSR)]
+    #[case("rv32if_zicsr", Isa { f: true, zicsr: true, ..Isa::RV32I })]
+    #[case("rv64i", Isa::RV64I)]
+    #[case("rv64im", Isa::RV64IM)]
+    #[case("rv64imafdc_zicsr", Isa::RV64IMAFDC_ZICSR)]
+    fn test_parse(#[case] string: &str, #[case] isa: Isa) {
+        assert_eq!(string.parse(), Ok(isa));
+        assert_eq!(isa.to_string(), string.to_ascii_lowercase());
+    }
+
+    #[rstest]
+    #[case(
+        "rv128i",
+        "`rv128i` does not start with a base ISA such as `rv32i` or `rv64i`"
+    )]
+    #[case(
+        "rv64e",
+        "`rv64e` does not start with a base ISA such as `rv32i` or `rv64i`"
+    )]
+    #[case("im", "`im` does not start with a base ISA such as `rv32i` or `rv64i`")]
+    #[case("rv32iq", "unknown extension `q`")]
+    #[case("rv32imm", "extension `m` is given twice")]
+    #[case("rv32icmc", "extension `c` is given twice")]
+    #[case("rv32i_zicsr_zicsr", "extension `zicsr` is given twice")]
+    #[case("rv32izicsr", "unknown extension `z`")]
+    #[case("rv32gcc", "extension `c` is given twice")]
+    #[case("rv32i_zifence", "unknown extension `zifence`")]
+    fn test_parse_fails(#[case] string: &str, #[case] message: &str) {
+        assert_eq!(string.parse::<Isa>().unwrap_err().to_string(), message);
+    }
+
+    #[test]
+    fn test_extension_order() {
+        assert_eq!("rv32icm".parse(), Ok(Isa::RV32IMC));
+        assert_eq!(Isa::RV32IMC.to_string(), "rv32imc");
+        assert_eq!("RV32IMCA_Zicsr".parse(), Ok(Isa::RV32IMAC_ZICSR));
+    }
+
+    #[rstest]
+    #[case("rv32g", Isa { c: false, ..Isa::RV32IMAFDC_ZICSR })]
+    #[case("rv32gc", Isa::RV32IMAFDC_ZICSR)]
+    #[case("RV64GC", Isa::RV64IMAFDC_ZICSR)]
+    #[case("rv32imafdc_zicsr_zifencei", Isa::RV32IMAFDC_ZICSR)]
+    #[case("rv32i_zifencei", Isa::RV32I)]
+    #[case("rv64gc_zifencei", Isa::RV64IMAFDC_ZICSR)]
+    #[case("rv32gc_zicsr", Isa::RV32IMAFDC_ZICSR)]
+    #[case("rv64g_zifencei", Isa { c: false, ..Isa::RV64IMAFDC_ZICSR })]
+    #[case("rv32gmc", Isa::RV32IMAFDC_ZICSR)]
+    fn test_general(#[case] string: &str, #[case] isa: Isa) {
+        assert_eq!(string.parse(), Ok(isa));
+    }
+
+    #[test]
+    fn test_implied_extensions() {
+        assert_eq!("rv32imafdc".parse(), Ok(Isa::RV32IMAFDC_ZICSR));
+        let isa: Isa = "rv32id".parse().unwrap();
+        assert!(isa.f && isa.zicsr);
+        assert_eq!(isa.to_string(), "rv32ifd_zicsr");
+    }
+
+    #[test]
+    fn test_xlen() {
+        assert_eq!(Xlen::Rv32.shift_bits(), 5);
+        assert_eq!(Xlen::Rv64.shift_bits(), 6);
+        assert_eq!(Xlen::Rv64.to_string(), "RV64I");
+        assert_eq!(
+            "RV64IG".parse::<Isa>().unwrap_err().to_string(),
+            "unknown extension `g`"
+        );
+    }
+
+    #[test]
+    fn test_extension_names() {
+        assert_eq!(Extension::M.to_string(), "M");
+        assert_eq!(Extension::Zicsr.to_string(), "Zicsr");
+    }
+}
@@ -0,0 +1,1271 @@
+//!
+//! Linking of several relocatable programs, assembled with
+//! [`AssembleOptions::relocatable`], into one which is placed in memory by a linker script.
+//!
+//! The linker script is a small part of the one of GNU ld:
+//!
+//! ```text
+//! ENTRY(_start)
+//! MEMORY
+//! {
+//!     rom (rx) : ORIGIN = 0x80000000, LENGTH = 64K
+//!     ram (rw) : ORIGIN = 0x80010000, LENGTH = 0x10000
+//! }
+//! SECTIONS
+//! {
+//!     .text : ALIGN(4) > rom
+//!     .rodata : > rom
+//!     .data : > ram
+//!     .bss : > ram
+//! }
+//! ```
+//!
+//! Every output section takes the sections of the same name of every unit, in the order of the
+//! units, and those whose name starts with it and a dot, like `.text.startup`. The output
+//! sections follow each other in their region in the order of the script.
+//!
+//! Only the `.globl` labels and constants of a unit are seen by the others, its other labels
+//! stay its own.
+//!
+//! [`AssembleOptions::relocatable`]: crate::riscv::assembler::AssembleOptions::relocatable
+//!
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter, Write};
+
+use chumsky::prelude::*;
+
+use crate::chumsky_utils::{integer, spanned};
+use crate::riscv::assembler::{offset_bits, relocate, Assembly, Section};
+use crate::riscv::ast::{Instruction, RelocationKind};
+use crate::riscv::encoding::{decode_bytes, encode_bytes};
+use crate::riscv::isa::{Isa, Xlen};
+
+/// A range of memory output sections are placed in.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Region {
+    pub name: String,
+    pub origin: u32,
+    /// Bytes, up to 4 GiB.
+    pub length: u64,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct OutputSection {
+    pub name: String,
+    /// Name of the region the section goes to.
+    pub region: String,
+    /// The least alignment of the section, the one of its input sections may be larger.
+    pub alignment: u32,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct LinkerScript {
+    pub regions: Vec<Region>,
+    pub sections: Vec<OutputSection>,
+    /// Label execution starts at, checked to be defined.
+    pub entry: Option<String>,
+}
+
+/// All of memory as one region holding `.text`, `.rodata`, `.data` and `.bss` in this order.
+impl Default for LinkerScript {
+    fn default() -> Self {
+        let section = |name: &str| OutputSection {
+            name: name.to_string(),
+            region: "memory".to_string(),
+            alignment: 4,
+        };
+        LinkerScript {
+            regions: vec![Region {
+                name: "memory".to_string(),
+                origin: 0,
+                length: 1 << 32,
+            }],
+            sections: vec![
+                section(".text"),
+                section(".rodata"),
+                section(".data"),
+                section(".bss"),
+            ],
+            entry: None,
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum LinkError {
+    DuplicateSymbol {
+        symbol: String,
+        units: (String, String),
+    },
+    /// A symbol no unit defines as `.globl`, with the units referring to it.
+    UndefinedSymbol {
+        symbol: String,
+        units: Vec<String>,
+    },
+    UnknownEntry {
+        symbol: String,
+    },
+    /// A section of a unit which no output section takes.
+    UnplacedSection {
+        unit: String,
+        section: String,
+    },
+    UnknownRegion {
+        section: String,
+        region: String,
+    },
+    RegionOverflow {
+        region: String,
+        section: String,
+        overflow: u64,
+    },
+    /// A branch or jump which does not reach its target once linked. `span` is the one of the
+    /// label in the source of the unit.
+    OutOfRange {
+        unit: String,
+        symbol: String,
+        span: SimpleSpan,
+        offset: i64,
+        bits: u32,
+    },
+    /// A unit for another base instruction set than the first one.
+    MixedXlen {
+        unit: String,
+        xlen: Xlen,
+        expected: Xlen,
+    },
+}
+
+impl Display for LinkError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LinkError::DuplicateSymbol { symbol, units } => write!(
+                f,
+                "symbol `{}` is defined by both `{}` and `{}`",
+                symbol, units.0, units.1
+            ),
+            LinkError::UndefinedSymbol { symbol, units } => {
+                let units: Vec<_> = units.iter().map(|unit| format!("`{}`", unit)).collect();
+                write!(
+                    f,
+                    "symbol `{}` is not defined by any unit as `.globl`, {} refers to it",
+                    symbol,
+                    units.join(", ")
+                )
+            }
+            LinkError::UnknownEntry { symbol } => {
+                write!(f, "entry point `{}` is not defined", symbol)
+            }
+            LinkError::UnplacedSection { unit, section } => write!(
+                f,
+                "section `{}` of `{}` is not placed by the linker script",
+                section, unit
+            ),
+            LinkError::UnknownRegion { section, region } => write!(
+                f,
+                "section `{}` goes to region `{}`, which the linker script does not define",
+                section, region
+            ),
+            LinkError::RegionOverflow {
+                region,
+                section,
+                overflow,
+            } => write!(
+                f,
+                "section `{}` overflows region `{}` by {} bytes",
+                section, region, overflow
+            ),
+            LinkError::OutOfRange {
+                unit,
+                symbol,
+                offset,
+                bits,
+                ..
+            } => write!(
+                f,
+                "symbol `{}` is {} bytes away from its reference in `{}`, out of range of a {} bit offset",
+                symbol, offset, unit, bits
+            ),
+            LinkError::MixedXlen {
+                unit,
+                xlen,
+                expected,
+            } => write!(f, "`{}` is {}, the units before it are {}", unit, xlen, expected),
+        }
+    }
+}
+
+impl std::error::Error for LinkError {}
+
+/// Where a section of a unit ended up.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Placement {
+    pub unit: String,
+    pub section: String,
+    /// Name of the output section it is part of.
+    pub output: String,
+    pub address: u32,
+    pub size: u32,
+    /// Labels inside the section with their address, in ascending order.
+    pub labels: Vec<(String, u32)>,
+}
+
+#[derive(Debug)]
+pub struct Linked {
+    ///
+    /// The linked program, with its output sections in ascending order of address.
+    ///
+    /// Its labels are the globals and those locals whose name no other unit uses, sections
+    /// have no spans as they would refer to the sources of different units.
+    ///
+    pub assembly: Assembly,
+    /// Every input section in ascending order of address.
+    pub placements: Vec<Placement>,
+    /// Regions of the script with the bytes used of each.
+    pub regions: Vec<(Region, u64)>,
+    pub entry: Option<(String, u32)>,
+}
+
+impl Linked {
+    ///
+    /// A map file of the program, which lists the regions, where the sections of every unit
+    /// went and the labels inside them.
+    ///
+    /// ```text
+    /// Memory regions
+    ///
+    /// Name             Origin      Length      Used
+    /// rom              0x80000000  0x00010000  0x00000024
+    ///
+    /// Sections
+    ///
+    /// .text            0x80000000  0x00000024  rom
+    ///  .text           0x80000000  0x00000018  main.s
+    ///                  0x80000000              _start
+    ///  .text           0x80000018  0x0000000c  lib.s
+    ///                  0x80000018              puts
+    ///
+    /// Entry point      0x80000000              _start
+    /// ```
+    ///
+    pub fn map(&self) -> String {
+        let mut map = String::from("Memory regions\n\n");
+        writeln!(map, "{:<16} {:<11} {:<11} Used", "Name", "Origin", "Length").unwrap();
+        for (region, used) in &self.regions {
+            writeln!(
+                map,
+                "{:<16} 0x{:08x}  0x{:08x}  0x{:08x}",
+                region.name, region.origin, region.length, used
+            )
+            .unwrap();
+        }
+        map.push_str("\nSections\n");
+        for section in &self.assembly.sections {
+            let region = self
+                .region(section.address)
+                .map_or("", |region| &region.name);
+            writeln!(
+                map,
+                "\n{:<16} 0x{:08x}  0x{:08x}  {}",
+                section.name,
+                section.address,
+                section.bytes.len(),
+                region
+            )
+            .unwrap();
+            for placement in self
+                .placements
+                .iter()
+                .filter(|placement| placement.output == section.name)
+            {
+                writeln!(
+                    map,
+                    " {:<15} 0x{:08x}  0x{:08x}  {}",
+                    placement.section, placement.address, placement.size, placement.unit
+                )
+                .unwrap();
+                for (label, address) in &placement.labels {
+                    writeln!(map, "{:<16} 0x{:08x}              {}", "", address, label).unwrap();
+                }
+            }
+        }
+        if let Some((label, address)) = &self.entry {
+            writeln!(
+                map,
+                "\n{:<16} 0x{:08x}              {}",
+                "Entry point", address, label
+            )
+            .unwrap();
+        }
+        map
+    }
+
+    fn region(&self, address: u32) -> Option<&Region> {
+        self.regions
+            .iter()
+            .map(|(region, _)| region)
+            .find(|region| {
+                (region.origin as u64..region.origin as u64 + region.length)
+                    .contains(&(address as u64))
+            })
+    }
+}
+
+// Whether an input section goes to the output section, `.text.startup` does to `.text`.
+fn matches(input: &str, output: &str) -> bool {
+    input
+        .strip_prefix(output)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+}
+
+// A unit with where its sections went.
+struct Unit<'a> {
+    name: &'a str,
+    assembly: &'a Assembly,
+    /// Address of every section, `None` for empty sections no output section takes.
+    addresses: Vec<Option<u32>>,
+}
+
+impl Unit<'_> {
+    fn defines(&self, name: &str) -> bool {
+        self.assembly.labels.contains_key(name) || self.assembly.constants.contains_key(name)
+    }
+
+    // The address a label of the unit is linked to, or the value of a constant.
+    fn address(&self, name: &str) -> Option<u32> {
+        if let Some(&value) = self.assembly.constants.get(name) {
+            return Some(value);
+        }
+        let section = self.assembly.label_sections[name];
+        let offset = self.assembly.labels[name] - self.assembly.sections[section].address;
+        Some(self.addresses[section]? + offset)
+    }
+
+    // Where `address` of the unit as it was assembled is linked to.
+    fn relocated(&self, section: usize, address: u32) -> u32 {
+        self.addresses[section].unwrap() + address - self.assembly.sections[section].address
+    }
+}
+
+// The units and the globals they define, which every reference goes through.
+struct Symbols<'a> {
+    units: Vec<Unit<'a>>,
+    globals: BTreeMap<&'a str, usize>,
+}
+
+impl Symbols<'_> {
+    // The unit which defines the symbol `unit` refers to.
+    fn definition(&self, unit: usize, name: &str) -> usize {
+        if self.units[unit].defines(name) {
+            unit
+        } else {
+            self.globals[name]
+        }
+    }
+
+    fn address(&self, unit: usize, name: &str) -> u32 {
+        let unit = self.definition(unit, name);
+        self.units[unit].address(name).unwrap()
+    }
+
+    // The offset from the `auipc` at the label to the target of its `%pcrel_hi`, which a
+    // `%pcrel_lo` of the label left to the linker completes.
+    fn pcrel_offset(&self, unit: usize, name: &str) -> i64 {
+        let unit = self.definition(unit, name);
+        let assembly = self.units[unit].assembly;
+        let auipc = assembly.labels[name];
+        let (section, hi) = assembly
+            .sections
+            .iter()
+            .enumerate()
+            .find_map(|(index, section)| {
+                section
+                    .relocations
+                    .iter()
+                    .find(|(address, relocation)| {
+                        *address == auipc && relocation.kind == RelocationKind::PcrelHi
+                    })
+                    .map(|(_, relocation)| (index, relocation))
+            })
+            .expect("a `%pcrel_lo` left to the linker completes a `%pcrel_hi` left to it");
+        let target = self.address(unit, &hi.label.name);
+        target as i64 - self.units[unit].relocated(section, auipc) as i64
+    }
+}
+
+fn combine(isa: Isa, other: Isa) -> Isa {
+    Isa {
+        xlen: isa.xlen,
+        m: isa.m || other.m,
+        a: isa.a || other.a,
+        f: isa.f || other.f,
+        d: isa.d || other.d,
+        c: isa.c || other.c,
+        zicsr: isa.zicsr || other.zicsr,
+    }
+}
+
+// Checks the units against each other, returning the globals by the unit defining them and the
+// ISA of the linked program.
+fn resolve_globals<'a>(
+    units: &[(&'a str, &'a Assembly)],
+    script: &LinkerScript,
+    errors: &mut Vec<LinkError>,
+) -> (BTreeMap<&'a str, usize>, Isa) {
+    let mut isa = units.first().map_or(Isa::default(), |(_, unit)| unit.isa);
+    let mut globals: BTreeMap<&str, usize> = BTreeMap::new();
+    for (index, (name, assembly)) in units.iter().enumerate() {
+        if assembly.isa.xlen != isa.xlen {
+            errors.push(LinkError::MixedXlen {
+                unit: name.to_string(),
+                xlen: assembly.isa.xlen,
+                expected: isa.xlen,
+            });
+        }
+        isa = combine(isa, assembly.isa);
+        for global in &assembly.globals {
+            if !assembly.labels.contains_key(global) && !assembly.constants.contains_key(global) {
+                continue;
+            }
+            match globals.get(global.as_str()) {
+                Some(&other) => errors.push(LinkError::DuplicateSymbol {
+                    symbol: global.clone(),
+                    units: (units[other].0.to_string(), name.to_string()),
+                }),
+                None => {
+                    globals.insert(global, index);
+                }
+            }
+        }
+    }
+
+    let mut undefined: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for (name, assembly) in units {
+        for symbol in assembly.undefined() {
+            if !globals.contains_key(symbol) {
+                undefined.entry(symbol).or_default().push(name.to_string());
+            }
+        }
+    }
+    errors.extend(
+        undefined
+            .into_iter()
+            .map(|(symbol, units)| LinkError::UndefinedSymbol {
+                symbol: symbol.to_string(),
+                units,
+            }),
+    );
+    if let Some(entry) = &script.entry {
+        if !globals.contains_key(entry.as_str()) {
+            errors.push(LinkError::UnknownEntry {
+                symbol: entry.clone(),
+            });
+        }
+    }
+    (globals, isa)
+}
+
+// Places the sections of every unit, returning the output sections, in the order of the
+// script, and the bytes used of every region.
+fn place(
+    units: &mut [Unit],
+    script: &LinkerScript,
+    errors: &mut Vec<LinkError>,
+) -> (Vec<Section>, Vec<(Region, u64)>) {
+    let mut cursors: Vec<u64> = script
+        .regions
+        .iter()
+        .map(|region| region.origin as u64)
+        .collect();
+    let mut outputs = vec![];
+    for output in &script.sections {
+        let Some(index) = script
+            .regions
+            .iter()
+            .position(|region| region.name == output.region)
+        else {
+            errors.push(LinkError::UnknownRegion {
+                section: output.name.clone(),
+                region: output.region.clone(),
+            });
+            continue;
+        };
+        let mut inputs = vec![];
+        for (unit, placed) in units.iter().enumerate() {
+            for (section, input) in placed.assembly.sections.iter().enumerate() {
+                if placed.addresses[section].is_none() && matches(&input.name, &output.name) {
+                    inputs.push((unit, section));
+                }
+            }
+        }
+        if inputs.is_empty() {
+            continue;
+        }
+
+        let alignment = inputs
+            .iter()
+            .map(|&(unit, section)| units[unit].assembly.sections[section].alignment)
+            .fold(output.alignment.max(1), u32::max);
+        let address = cursors[index].next_multiple_of(alignment as u64);
+        let mut bytes = vec![];
+        for (unit, section) in inputs {
+            let input = &units[unit].assembly.sections[section];
+            let start = (address + bytes.len() as u64).next_multiple_of(input.alignment as u64);
+            bytes.resize((start - address) as usize, 0);
+            bytes.extend(&input.bytes);
+            units[unit].addresses[section] = Some(start as u32);
+        }
+        let end = address + bytes.len() as u64;
+        let region = &script.regions[index];
+        let limit = region.origin as u64 + region.length;
+        if end > limit {
+            errors.push(LinkError::RegionOverflow {
+                region: region.name.clone(),
+                section: output.name.clone(),
+                overflow: end - limit,
+            });
+        }
+        cursors[index] = end;
+        outputs.push(Section {
+            name: output.name.clone(),
+            address: address as u32,
+            alignment,
+            bytes,
+            spans: vec![],
+            relocations: vec![],
+        });
+    }
+
+    for unit in units.iter() {
+        for (section, input) in unit.assembly.sections.iter().enumerate() {
+            if unit.addresses[section].is_none() && !input.bytes.is_empty() {
+                errors.push(LinkError::UnplacedSection {
+                    unit: unit.name.to_string(),
+                    section: input.name.clone(),
+                });
+            }
+        }
+    }
+    let regions = script
+        .regions
+        .iter()
+        .zip(cursors)
+        .map(|(region, cursor)| (region.clone(), cursor - region.origin as u64))
+        .collect();
+    (outputs, regions)
+}
+
+// Fills in the references the units left to the linker.
+fn patch(symbols: &Symbols, outputs: &mut [Section], errors: &mut Vec<LinkError>) {
+    for (index, unit) in symbols.units.iter().enumerate() {
+        for (section, input) in unit.assembly.sections.iter().enumerate() {
+            for (address, relocation) in &input.relocations {
+                let pc = unit.relocated(section, *address);
+                let output = outputs
+                    .iter_mut()
+                    .find(|output| (output.address..output.end()).contains(&pc))
+                    .unwrap();
+                let bytes = &mut output.bytes[(pc - output.address) as usize..];
+                let name = &relocation.label.name;
+                if relocation.kind == RelocationKind::Word {
+                    let value = symbols.address(index, name);
+                    bytes[..4].copy_from_slice(&value.to_le_bytes());
+                    continue;
+                }
+
+                let mut instructions: Vec<Instruction> = vec![];
+                let mut len = 0;
+                let count = match relocation.kind {
+                    RelocationKind::Pcrel | RelocationKind::Absolute => 2,
+                    _ => 1,
+                };
+                for _ in 0..count {
+                    let (instruction, size) = decode_bytes(&bytes[len..])
+                        .expect("references left to the linker are valid instructions");
+                    instructions.push(instruction);
+                    len += size;
+                }
+                let target = symbols.address(index, name);
+                let value = match relocation.kind {
+                    RelocationKind::Hi | RelocationKind::Lo | RelocationKind::Absolute => {
+                        target as i64
+                    }
+                    RelocationKind::PcrelLo => symbols.pcrel_offset(index, name),
+                    _ => target as i64 - pc as i64,
+                };
+                let bits = offset_bits(relocation.kind, instructions[0]);
+                if bits < 32 && !(-(1 << (bits - 1))..1 << (bits - 1)).contains(&value) {
+                    errors.push(LinkError::OutOfRange {
+                        unit: unit.name.to_string(),
+                        symbol: name.clone(),
+                        span: relocation.label.span,
+                        offset: value,
+                        bits,
+                    });
+                    continue;
+                }
+                let resolved = relocate(&instructions, relocation.kind, value as i32);
+                bytes[..len].copy_from_slice(&encode_bytes(&resolved));
+            }
+        }
+    }
+}
+
+///
+/// Links relocatable programs, each with a name for errors and the map file, into one as the
+/// linker script places them.
+///
+/// Every error is reported at once. The linked program can be written as an executable, with
+/// the entry point of the script as its entry.
+///
+pub fn link(units: &[(&str, &Assembly)], script: &LinkerScript) -> Result<Linked, Vec<LinkError>> {
+    let mut errors = vec![];
+    let (globals, isa) = resolve_globals(units, script, &mut errors);
+    let mut placed: Vec<_> = units
+        .iter()
+        .map(|&(name, assembly)| Unit {
+            name,
+            assembly,
+            addresses: vec![None; assembly.sections.len()],
+        })
+        .collect();
+    let (mut outputs, regions) = place(&mut placed, script, &mut errors);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let symbols = Symbols {
+        units: placed,
+        globals,
+    };
+    patch(&symbols, &mut outputs, &mut errors);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    outputs.sort_by_key(|section| section.address);
+
+    // Locals keep their name as long as it is the only symbol of that name.
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for unit in &symbols.units {
+        let names = unit
+            .assembly
+            .labels
+            .keys()
+            .chain(unit.assembly.constants.keys());
+        for name in names {
+            *counts.entry(name.as_str()).or_default() += 1;
+        }
+    }
+    let mut labels = BTreeMap::new();
+    let mut constants = BTreeMap::new();
+    let mut placements = vec![];
+    for (index, unit) in symbols.units.iter().enumerate() {
+        let visible = |name: &str| symbols.globals.get(name) == Some(&index) || counts[name] == 1;
+        for (name, &value) in &unit.assembly.constants {
+            if visible(name) {
+                constants.insert(name.clone(), value);
+            }
+        }
+        for (section, input) in unit.assembly.sections.iter().enumerate() {
+            let Some(address) = unit.addresses[section] else {
+                continue;
+            };
+            let mut inside: Vec<_> = unit
+                .assembly
+                .labels
+                .iter()
+                .filter(|(name, _)| unit.assembly.label_sections[*name] == section)
+                .map(|(name, _)| (name.clone(), unit.address(name).unwrap()))
+                .collect();
+            inside.sort_by_key(|&(_, address)| address);
+            for (name, address) in &inside {
+                if visible(name) {
+                    labels.insert(name.clone(), *address);
+                }
+            }
+            let output = script
+                .sections
+                .iter()
+                .find(|output| matches(&input.name, &output.name))
+                .unwrap();
+            placements.push(Placement {
+                unit: unit.name.to_string(),
+                section: input.name.clone(),
+                output: output.name.clone(),
+                address,
+                size: input.bytes.len() as u32,
+                labels: inside,
+            });
+        }
+    }
+    placements.sort_by_key(|placement| placement.address);
+    let label_sections = labels
+        .iter()
+        .map(|(name, &address)| {
+            let section = outputs
+                .iter()
+                .rposition(|section| section.address <= address)
+                .unwrap_or(0);
+            (name.clone(), section)
+        })
+        .collect();
+    let entry = script.entry.as_ref().map(|name| {
+        (
+            name.clone(),
+            symbols.address(symbols.globals[name.as_str()], name),
+        )
+    });
+
+    let assembly = Assembly {
+        isa,
+        sections: outputs,
+        labels,
+        label_sections,
+        constants,
+        globals: symbols
+            .globals
+            .keys()
+            .map(|name| name.to_string())
+            .collect(),
+    };
+    Ok(Linked {
+        assembly,
+        placements,
+        regions,
+        entry,
+    })
+}
+
+// A statement of a linker script with the spans to report it at.
+enum Statement {
+    Entry(String),
+    Region(Region, SimpleSpan),
+    Output(OutputSection, SimpleSpan),
+}
+
+// Spaces, newlines and `/* */` comments.
+fn blank<'src>() -> impl Parser<'src, &'src str, (), extra::Err<Rich<'src, char>>> + Clone {
+    let comment = just("/*")
+        .then(any().and_is(just("*/").not()).repeated())
+        .then(just("*/"))
+        .ignored();
+    choice((text::whitespace().at_least(1), comment))
+        .repeated()
+        .ignored()
+}
+
+fn token<'src>(
+    token: &'static str,
+) -> impl Parser<'src, &'src str, (), extra::Err<Rich<'src, char>>> + Clone {
+    just(token).ignored().padded_by(blank())
+}
+
+fn name<'src>() -> impl Parser<'src, &'src str, String, extra::Err<Rich<'src, char>>> + Clone {
+    any()
+        .filter(|c: &char| c.is_ascii_alphanumeric() || *c == '_' || *c == '.')
+        .repeated()
+        .at_least(1)
+        .to_slice()
+        .map(str::to_string)
+        .labelled("name")
+}
+
+// A number with an optional `K` or `M` suffix for KiB and MiB.
+fn size<'src>() -> impl Parser<'src, &'src str, u64, extra::Err<Rich<'src, char>>> {
+    integer::<u64>(33.try_into().unwrap(), false)
+        .then(choice((just('K').to(1 << 10), just('M').to(1 << 20))).or_not())
+        .try_map(|(value, unit), span| {
+            let value = value * unit.unwrap_or(1);
+            if value > 1 << 32 {
+                return Err(Rich::custom(span, "number does not fit into 4 GiB"));
+            }
+            Ok(value)
+        })
+        .padded_by(blank())
+}
+
+fn statements<'src>() -> impl Parser<'src, &'src str, Vec<Statement>, extra::Err<Rich<'src, char>>>
+{
+    let entry = token("ENTRY")
+        .ignore_then(
+            name()
+                .padded_by(blank())
+                .delimited_by(token("("), token(")")),
+        )
+        .map(|name| vec![Statement::Entry(name)]);
+
+    let attributes = any()
+        .filter(|c: &char| c.is_ascii_alphabetic() || *c == '!')
+        .repeated()
+        .delimited_by(token("("), token(")"));
+    let origin = choice((token("ORIGIN"), token("org"), token("o")));
+    let length = choice((token("LENGTH"), token("len"), token("l")));
+    let region = spanned(name())
+        .padded_by(blank())
+        .then_ignore(attributes.or_not())
+        .then_ignore(token(":"))
+        .then(origin.ignore_then(token("=")).ignore_then(size()))
+        .then_ignore(token(","))
+        .then(length.ignore_then(token("=")).ignore_then(size()))
+        .try_map(|(((name, span), origin), length), origin_span| {
+            if origin >= 1 << 32 {
+                return Err(Rich::custom(
+                    origin_span,
+                    "origin does not fit into 32 bits",
+                ));
+            }
+            let region = Region {
+                name,
+                origin: origin as u32,
+                length,
+            };
+            Ok(Statement::Region(region, span))
+        });
+    let memory = token("MEMORY").ignore_then(
+        region
+            .repeated()
+            .collect()
+            .delimited_by(token("{"), token("}")),
+    );
+
+    let alignment = token("ALIGN")
+        .ignore_then(size().delimited_by(token("("), token(")")))
+        .try_map(|alignment, span| {
+            if !alignment.is_power_of_two() || alignment >= 1 << 32 {
+                return Err(Rich::custom(span, "alignment is not a power of two"));
+            }
+            Ok(alignment as u32)
+        });
+    let output = name()
+        .then_ignore(token(":"))
+        .then(alignment.or_not())
+        .then_ignore(token(">"))
+        .then(spanned(name()).padded_by(blank()))
+        .map(|((name, alignment), (region, span))| {
+            let section = OutputSection {
+                name,
+                region,
+                alignment: alignment.unwrap_or(1),
+            };
+            Statement::Output(section, span)
+        });
+    let sections = token("SECTIONS").ignore_then(
+        output
+            .repeated()
+            .collect()
+            .delimited_by(token("{"), token("}")),
+    );
+
+    choice((entry, memory, sections))
+        .repeated()
+        .collect::<Vec<Vec<Statement>>>()
+        .map(|statements| statements.into_iter().flatten().collect())
+        .padded_by(blank())
+        .then_ignore(end())
+}
+
+/// Parses a linker script, checking that the regions it puts sections in are defined once.
+pub fn parse_linker_script(source: &str) -> Result<LinkerScript, Vec<Rich<'_, char>>> {
+    let statements = statements().parse(source).into_result()?;
+    let mut errors = vec![];
+    let mut script = LinkerScript {
+        regions: vec![],
+        sections: vec![],
+        entry: None,
+    };
+    let mut outputs = vec![];
+    for statement in statements {
+        match statement {
+            Statement::Entry(name) => script.entry = Some(name),
+            Statement::Region(region, span) => {
+                if script.regions.iter().any(|other| other.name == region.name) {
+                    errors.push(Rich::custom(
+                        span,
+                        format!("region `{}` is already defined", region.name),
+                    ));
+                }
+                script.regions.push(region);
+            }
+            Statement::Output(section, span) => outputs.push((section, span)),
+        }
+    }
+    for (section, span) in outputs {
+        if !script
+            .regions
+            .iter()
+            .any(|region| region.name == section.region)
+        {
+            errors.push(Rich::custom(
+                span,
+                format!("region `{}` is not defined in `MEMORY`", section.region),
+            ));
+        }
+        script.sections.push(section);
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(script)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::assembler::{assemble_riscv_with, AssembleOptions};
+    use crate::riscv::encoding::from_bytes;
+
+    const SCRIPT: &str = "
+        /* Code in flash, data in RAM. */
+        ENTRY(_start)
+        MEMORY
+        {
+            rom (rx) : ORIGIN = 0x80000000, LENGTH = 4K
+            ram (rw!x) : org = 0x80001000, len = 0x100
+        }
+        SECTIONS
+        {
+            .text : ALIGN(16) > rom
+            .data : > ram
+            .bss : > ram
+        }
+    ";
+
+    const MAIN: &str = "
+        .globl _start
+        _start:
+            call puts
+            la a0, message
+        hi: auipc a1, %pcrel_hi(count)
+            lw a1, %pcrel_lo(hi)(a1)
+        loop: j loop
+        .data
+        table: .word puts, loop
+    ";
+
+    const LIB: &str = "
+        .globl puts
+        .globl message
+        .globl count
+        puts:
+        loop: bnez a0, loop
+            ret
+        .data
+        message: .asciz \"hi\"
+        .bss
+        .align 2
+        count: .zero 4
+    ";
+
+    fn assemble(source: &str) -> Assembly {
+        let options = AssembleOptions {
+            relocatable: true,
+            ..Default::default()
+        };
+        assemble_riscv_with(source, &options).unwrap()
+    }
+
+    fn link_sources(sources: &[(&str, &str)], script: &str) -> Result<Linked, Vec<LinkError>> {
+        let assemblies: Vec<_> = sources
+            .iter()
+            .map(|(name, source)| (*name, assemble(source)))
+            .collect();
+        let units: Vec<_> = assemblies
+            .iter()
+            .map(|(name, assembly)| (*name, assembly))
+            .collect();
+        link(&units, &parse_linker_script(script).unwrap())
+    }
+
+    #[test]
+    fn test_parse_linker_script() {
+        let script = parse_linker_script(SCRIPT).unwrap();
+        assert_eq!(script.entry.as_deref(), Some("_start"));
+        assert_eq!(
+            script.regions,
+            [
+                Region {
+                    name: "rom".to_string(),
+                    origin: 0x8000_0000,
+                    length: 4096,
+                },
+                Region {
+                    name: "ram".to_string(),
+                    origin: 0x8000_1000,
+                    length: 0x100,
+                },
+            ]
+        );
+        let sections: Vec<_> = script
+            .sections
+            .iter()
+            .map(|section| {
+                (
+                    section.name.as_str(),
+                    section.region.as_str(),
+                    section.alignment,
+                )
+            })
+            .collect();
+        assert_eq!(
+            sections,
+            [
+                (".text", "rom", 16),
+                (".data", "ram", 1),
+                (".bss", "ram", 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_linker_script_errors() {
+        let source =
+            "MEMORY { rom : o = 0, l = 1K\nrom : o = 1K, l = 1K }\nSECTIONS { .text : > flash }";
+        let errors = parse_linker_script(source).unwrap_err();
+        let messages: Vec<_> = errors
+            .iter()
+            .map(|error| (error.to_string(), &source[error.span().into_range()]))
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                ("region `rom` is already defined".to_string(), "rom"),
+                (
+                    "region `flash` is not defined in `MEMORY`".to_string(),
+                    "flash"
+                ),
+            ]
+        );
+        assert!(parse_linker_script("SECTIONS { .text : ALIGN(3) > rom }").is_err());
+        assert!(parse_linker_script("MEMORY { rom : o = 0, l = 5G }").is_err());
+    }
+
+    #[test]
+    fn test_link() {
+        let linked = link_sources(&[("main.s", MAIN), ("lib.s", LIB)], SCRIPT).unwrap();
+        let assembly = &linked.assembly;
+        let sections: Vec<_> = assembly
+            .sections
+            .iter()
+            .map(|section| (section.name.as_str(), section.address, section.bytes.len()))
+            .collect();
+        // `ALIGN(16)` only aligns the start of `.text`, `lib.s` follows `main.s` word aligned.
+        assert_eq!(
+            sections,
+            [
+                (".text", 0x8000_0000, 36),
+                (".data", 0x8000_1000, 11),
+                (".bss", 0x8000_100C, 4),
+            ]
+        );
+        assert_eq!(assembly.labels["puts"], 0x8000_001C);
+        assert_eq!(assembly.labels["message"], 0x8000_1008);
+        assert_eq!(assembly.labels["count"], 0x8000_100C);
+        // Both units have a `loop`, neither keeps its name.
+        assert!(!assembly.labels.contains_key("loop"));
+        assert_eq!(assembly.labels["table"], 0x8000_1000);
+        assert_eq!(assembly.label_sections["count"], 2);
+        assert_eq!(
+            assembly.globals,
+            ["_start", "count", "message", "puts"]
+                .map(str::to_string)
+                .into()
+        );
+        assert_eq!(linked.entry, Some(("_start".to_string(), 0x8000_0000)));
+
+        let text = from_bytes(&assembly.sections[0].bytes);
+        assert_eq!(
+            text[..7],
+            [
+                // call puts
+                0x0000_0097,
+                0x01C0_80E7,
+                // la a0, message
+                0x0000_1517,
+                0x0005_0513,
+                // auipc a1, %pcrel_hi(count); lw a1, %pcrel_lo(hi)(a1)
+                0x0000_1597,
+                0x0005_A583 | 0xFFC << 20,
+                // j loop
+                0x0000_006F,
+            ]
+        );
+        // .word puts, loop
+        assert_eq!(
+            from_bytes(&assembly.sections[1].bytes[..8]),
+            [0x8000_001C, 0x8000_0018]
+        );
+    }
+
+    #[test]
+    fn test_symbol_errors() {
+        let other = "
+            .globl puts
+            .globl _start
+            puts: ret
+            _start: call exit
+        ";
+        let script = SCRIPT.replace("ENTRY(_start)", "ENTRY(main)");
+        let errors = link_sources(
+            &[("main.s", MAIN), ("lib.s", LIB), ("other.s", other)],
+            &script,
+        )
+        .unwrap_err();
+        let messages: Vec<_> = errors.iter().map(LinkError::to_string).collect();
+        assert_eq!(
+            messages,
+            [
+                "symbol `_start` is defined by both `main.s` and `other.s`",
+                "symbol `puts` is defined by both `lib.s` and `other.s`",
+                "symbol `exit` is not defined by any unit as `.globl`, `other.s` refers to it",
+                "entry point `main` is not defined",
+            ]
+        );
+
+        // A label of another unit which is not `.globl` is not seen.
+        let errors =
+            link_sources(&[("main.s", "j helper"), ("lib.s", "helper: ret")], SCRIPT).unwrap_err();
+        assert_eq!(
+            errors[0],
+            LinkError::UndefinedSymbol {
+                symbol: "helper".to_string(),
+                units: vec!["main.s".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_placement_errors() {
+        let source = "
+            .globl _start
+            _start: nop
+            .data
+            .zero 0x101
+            .section .rodata
+            .word 1
+        ";
+        let errors = link_sources(&[("main.s", source)], SCRIPT).unwrap_err();
+        assert_eq!(
+            errors,
+            [
+                LinkError::RegionOverflow {
+                    region: "ram".to_string(),
+                    section: ".data".to_string(),
+                    overflow: 1,
+                },
+                LinkError::UnplacedSection {
+                    unit: "main.s".to_string(),
+                    section: ".rodata".to_string(),
+                },
+            ]
+        );
+
+        let mut script = LinkerScript::default();
+        script.sections[0].region = "flash".to_string();
+        let assembly = assemble("nop");
+        let errors = link(&[("main.s", &assembly)], &script).unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "section `.text` goes to region `flash`, which the linker script does not define"
+        );
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        let main = "
+            .globl _start
+            _start: beqz a0, far
+        ";
+        let far = "
+            .globl far
+            .zero 4096
+            far: ret
+        ";
+        let assemblies = [assemble(main), assemble(far)];
+        let units = [("main.s", &assemblies[0]), ("far.s", &assemblies[1])];
+        let errors = link(&units, &LinkerScript::default()).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].to_string(),
+            "symbol `far` is 4100 bytes away from its reference in `main.s`, out of range of a \
+             13 bit offset"
+        );
+        let LinkError::OutOfRange { span, .. } = errors[0] else {
+            panic!("expected an out of range error");
+        };
+        assert_eq!(&main[span.into_range()], "far");
+    }
+
+    #[test]
+    fn test_default_script() {
+        let assemblies = [assemble("call f"), assemble(".globl f\nf: ret")];
+        let units = [("a.s", &assemblies[0]), ("b.s", &assemblies[1])];
+        let linked = link(&units, &LinkerScript::default()).unwrap();
+        assert_eq!(linked.assembly.labels["f"], 8);
+        assert_eq!(
+            from_bytes(&linked.assembly.image()),
+            [0x0000_0097, 0x0080_80E7, 0x0000_8067]
+        );
+        assert_eq!(linked.entry, None);
+    }
+
+    // `.data` directly follows `.text`, the `.word` at its start belongs to `.data`.
+    #[test]
+    fn test_relocation_at_section_start() {
+        let main = "
+            .globl _start
+            _start:
+                call f
+                la a0, table
+                lw a1, 0(a0)
+                ret
+            .data
+            table: .word f
+        ";
+        let lib = ".globl f\nf: addi a0, a0, 1\nret";
+        let assemblies = [assemble(main), assemble(lib)];
+        let units = [("a.s", &assemblies[0]), ("b.s", &assemblies[1])];
+        let linked = link(&units, &LinkerScript::default()).unwrap();
+        let assembly = &linked.assembly;
+        let f = assembly.labels["f"];
+        let data = assembly.section(".data").unwrap();
+        assert_eq!(data.address, assembly.section(".text").unwrap().end());
+        assert_eq!(from_bytes(&data.bytes), [f]);
+        assert_eq!(assembly.labels["table"], data.address);
+    }
+
+    #[test]
+    fn test_map() {
+        let linked = link_sources(&[("main.s", MAIN), ("lib.s", LIB)], SCRIPT).unwrap();
+        let expected = "\
+Memory regions
+
+Name             Origin      Length      Used
+rom              0x80000000  0x00001000  0x00000024
+ram              0x80001000  0x00000100  0x00000010
+
+Sections
+
+.text            0x80000000  0x00000024  rom
+ .text           0x80000000  0x0000001c  main.s
+                 0x80000000              _start
+                 0x80000010              hi
+                 0x80000018              loop
+ .text           0x8000001c  0x00000008  lib.s
+                 0x8000001c              loop
+                 0x8000001c              puts
+
+.data            0x80001000  0x0000000b  ram
+ .data           0x80001000  0x00000008  main.s
+                 0x80001000              table
+ .data           0x80001008  0x00000003  lib.s
+                 0x80001008              message
+
+.bss             0x8000100c  0x00000004  ram
+ .bss            0x8000100c  0x00000004  lib.s
+                 0x8000100c              count
+
+Entry point      0x80000000              _start
+";
+        assert_eq!(linked.map(), expected);
+    }
+}
@@ -0,0 +1,228 @@
+//!
+//! Listings of RISC-V programs, see [`crate::listing`].
+//!
+//! A pseudo-instruction is followed by a row for every base instruction it expanded to, and
+//! data by a row for every word of it.
+//!
+
+use std::collections::BTreeMap;
+
+use crate::listing::{Listing, ListingRow};
+use crate::riscv::assembler::Assembly;
+use crate::riscv::ast::{DataValue, Directive, Program, Symbol};
+use crate::riscv::disasm::disassemble_byte_lines;
+
+/// Rows of data before the rest of it is left out.
+const DATA_ROWS: usize = 4;
+
+// Rows of the instructions in `bytes`, the source line takes the first row of a pseudo.
+fn instruction_rows(bytes: &[u8], address: u32, pseudo: bool) -> Vec<ListingRow> {
+    let mut rows = vec![];
+    if pseudo {
+        rows.push(ListingRow {
+            address: Some(address),
+            ..Default::default()
+        });
+    }
+    for line in disassemble_byte_lines(bytes, address) {
+        let text = match line.instruction {
+            Ok(instruction) if pseudo => format!("    {}", instruction),
+            Ok(_) => String::new(),
+            Err(error) => format!("# {}", error),
+        };
+        rows.push(ListingRow {
+            address: Some(line.address),
+            raw: format!("{:0width$X}", line.word, width = 2 * line.len),
+            line: None,
+            text,
+        });
+    }
+    rows
+}
+
+// Rows of four bytes each in the order of memory.
+fn data_rows(bytes: &[u8], address: u32) -> Vec<ListingRow> {
+    let mut rows: Vec<_> = bytes
+        .chunks(4)
+        .take(DATA_ROWS)
+        .enumerate()
+        .map(|(index, chunk)| ListingRow {
+            address: Some(address + 4 * index as u32),
+            raw: chunk.iter().map(|byte| format!("{:02X}", byte)).collect(),
+            ..Default::default()
+        })
+        .collect();
+    let listed = 4 * DATA_ROWS;
+    if bytes.len() > listed {
+        rows.push(ListingRow {
+            address: Some(address + listed as u32),
+            text: format!("... {} more bytes", bytes.len() - listed),
+            ..Default::default()
+        });
+    }
+    rows
+}
+
+/// The listing of a program assembled from `source`, which `program` was parsed from.
+pub fn listing(source: &str, program: &Program, assembly: &Assembly) -> Listing {
+    let symbols: BTreeMap<_, _> = program
+        .symbols
+        .iter()
+        .map(|symbol| ((symbol.span.start, symbol.span.end), &symbol.node))
+        .collect();
+    let mut statements = vec![];
+    for section in &assembly.sections {
+        for (index, &(address, span)) in section.spans.iter().enumerate() {
+            let end = section
+                .spans
+                .get(index + 1)
+                .map_or(section.end(), |&(next, _)| next);
+            let bytes = &section.bytes
+                [(address - section.address) as usize..(end - section.address) as usize];
+            let rows = match symbols.get(&(span.start, span.end)) {
+                Some(symbol) if !symbol.instructions().is_empty() => {
+                    instruction_rows(bytes, address, matches!(symbol, Symbol::Pseudo(_)))
+                }
+                _ => data_rows(bytes, address),
+            };
+            statements.push((span, rows));
+        }
+    }
+
+    let mut definitions = vec![];
+    let mut references = vec![];
+    for symbol in &program.symbols {
+        match &symbol.node {
+            Symbol::Label(label) => definitions.push((
+                label.name.clone(),
+                assembly.labels.get(&label.name).copied(),
+                label.span,
+            )),
+            Symbol::Directive(Directive::Constant(constant)) => definitions.push((
+                constant.label.name.clone(),
+                Some(constant.value),
+                constant.label.span,
+            )),
+            Symbol::Reference(reference) => {
+                let label = &reference.relocation.label;
+                references.push((label.name.clone(), label.span));
+            }
+            Symbol::Pseudo(pseudo) => {
+                if let Some(relocation) = &pseudo.relocation {
+                    references.push((relocation.label.name.clone(), relocation.label.span));
+                }
+            }
+            Symbol::Directive(Directive::Data(data)) => {
+                for value in &data.values {
+                    if let DataValue::Label(label) = value {
+                        references.push((label.name.clone(), label.span));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Listing::new(source, 8, statements, definitions, references)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::listing::CrossReference;
+    use crate::riscv::assembler::{assemble_with, AssembleOptions};
+    use crate::riscv::parser::parse_riscv_with;
+
+    fn list(source: &str, options: &AssembleOptions) -> Listing {
+        let program = parse_riscv_with(source, options.isa).unwrap();
+        let assembly = assemble_with(&program, options).unwrap();
+        listing(source, &program, &assembly)
+    }
+
+    #[test]
+    fn test_listing() {
+        let source = "\
+# Counts down from three.
+.equ COUNT, 3
+_start: li a0, COUNT # times
+loop: addi a0, a0, -1
+    bnez a0, loop
+    call done
+done: ret
+.data
+table: .word loop, 0x12345678
+message: .asciz \"Hello, listing world!\"
+";
+        let expected = "\
+Address   Words      Line  Source
+                        1  # Counts down from three.
+                        2  .equ COUNT, 3
+00000000                3  _start: li a0, COUNT # times
+00000000  00000537             lui a0, 0x0
+00000004  00350513             addi a0, a0, 3
+00000008  FFF50513      4  loop: addi a0, a0, -1
+0000000C                5      bnez a0, loop
+0000000C  FE051EE3             bne a0, zero, -4
+00000010                6      call done
+00000010  00000097             auipc ra, 0x0
+00000014  008080E7             jalr ra, 8(ra)
+00000018                7  done: ret
+00000018  00008067             jalr zero, 0(ra)
+                        8  .data
+0000001C  08000000      9  table: .word loop, 0x12345678
+00000020  78563412
+00000024  48656C6C     10  message: .asciz \"Hello, listing world!\"
+00000028  6F2C206C
+0000002C  69737469
+00000030  6E672077
+00000034                   ... 6 more bytes
+
+Symbols
+
+Name              Value      Line  References
+COUNT             00000003      2  3
+_start            00000000      3
+done              00000018      7  6
+loop              00000008      4  5, 9
+message           00000024     10
+table             0000001C      9
+";
+        assert_eq!(
+            list(source, &AssembleOptions::default()).to_string(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_compressed_and_undefined() {
+        let options = AssembleOptions {
+            isa: "rv32imac".parse().unwrap(),
+            compress: true,
+            relocatable: true,
+            ..Default::default()
+        };
+        let listing = list("call puts\naddi a0, a0, 1", &options);
+        let raw: Vec<_> = listing
+            .rows
+            .iter()
+            .map(|row| (row.address, row.raw.as_str()))
+            .collect();
+        assert_eq!(
+            raw,
+            [
+                (Some(0), ""),
+                (Some(0), "00000097"),
+                (Some(4), "000080E7"),
+                (Some(8), "0505"),
+            ]
+        );
+        assert_eq!(
+            listing.symbols["puts"],
+            CrossReference {
+                value: None,
+                definition: None,
+                references: vec![1],
+            }
+        );
+    }
+}
@@ -1,2 +1,12 @@
+pub mod alu;
+pub mod assembler;
 pub mod ast;
-pub mod parser;
\ No newline at end of file
+pub mod compressed;
+pub mod disasm;
+pub mod elf;
+pub mod encoding;
+pub mod isa;
+pub mod linker;
+pub mod listing;
+pub mod parser;
+pub mod pseudo;
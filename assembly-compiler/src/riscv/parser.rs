@@ -1,10 +1,60 @@
-use crate::chumsky_utils::integer;
+use crate::chumsky_utils::{
+    end_of_line, inline_whitespace, integer, required_whitespace, skip_line, spanned,
+};
+use crate::diagnostics::Vocabulary;
 use crate::riscv::ast::{
-    BImmediate, BOpcode, IImmediate, IOpcode, Instruction, LOpcode, LSImmediate, Program, ROpcode,
-    Register, SOpcode, Symbol, UImmediate, UOpcode,
+    AOpcode, Alignment, AtomicOrdering, BImmediate, BOpcode, CImmediate, COpcode, Constant, Csr,
+    CsrImmediate, CsrOpcode, Data, DataValue, DataWidth, Directive, FCompareOpcode, FOpcode,
+    FRegister, FToIntOpcode, FUnaryOpcode, FenceSet, FloatFormat, FusedOpcode, IImmediate, IOpcode,
+    Instruction, IntToFOpcode, JImmediate, JOpcode, LOpcode, LSImmediate, Label, Program, Pseudo,
+    PseudoOpcode, ROpcode, Reference, Register, Relocation, RelocationKind, RoundingMode, SOpcode,
+    Symbol, SystemOpcode, UImmediate, UOpcode,
 };
+use crate::riscv::compressed;
+use crate::riscv::isa::{Isa, Xlen};
+use crate::riscv::pseudo;
+use crate::span::Spanned;
 use chumsky::prelude::*;
-use chumsky::text::{inline_whitespace, newline, whitespace};
+use chumsky::text::newline;
+
+pub const VOCABULARY: Vocabulary = Vocabulary {
+    mnemonics: &[
+        "addi", "slti", "sltiu", "xori", "ori", "andi", "slli", "srli", "srai", "add", "sub",
+        "sll", "slt", "sltu", "xor", "srl", "sra", "or", "and", "sb", "sh", "sw", "lb", "lh", "lw",
+        "beq", "bne", "blt", "bge", "bltu", "bgeu", "lui", "auipc", "jal", "jalr", "lbu", "lhu",
+        "fence", "ecall", "ebreak", "mul", "mulh", "mulhsu", "mulhu", "div", "divu", "rem", "remu",
+        "nop", "li", "la", "mv", "not", "neg", "seqz", "snez", "sltz", "sgtz", "beqz", "bnez",
+        "blez", "bgez", "bltz", "bgtz", "bgt", "ble", "bgtu", "bleu", "j", "jr", "ret", "call",
+        "tail", ".text", ".data", ".section", ".globl", ".global", ".align", ".p2align", ".word",
+        ".half", ".byte", ".ascii", ".asciz", ".string", ".space", ".zero", ".equ", ".set",
+        "c.addi4spn", "c.lw", "c.sw", "c.nop", "c.addi", "c.jal", "c.li", "c.addi16sp", "c.lui",
+        "c.srli", "c.srai", "c.andi", "c.sub", "c.xor", "c.or", "c.and", "c.j", "c.beqz", "c.bnez",
+        "c.slli", "c.lwsp", "c.jr", "c.mv", "c.ebreak", "c.jalr", "c.add", "c.swsp", "lr.w",
+        "sc.w", "amoswap.w", "amoadd.w", "amoxor.w", "amoand.w", "amoor.w", "amomin.w",
+        "amomax.w", "amominu.w", "amomaxu.w", "csrrw", "csrrs", "csrrc", "csrrwi", "csrrsi",
+        "csrrci", "csrr", "csrw", "csrs", "csrc", "csrwi", "csrsi", "csrci", "flw", "fld", "fsw",
+        "fsd", "fadd.s", "fadd.d", "fsub.s", "fsub.d", "fmul.s", "fmul.d", "fdiv.s", "fdiv.d",
+        "fsqrt.s", "fsqrt.d", "fsgnj.s", "fsgnj.d", "fsgnjn.s", "fsgnjn.d", "fsgnjx.s", "fsgnjx.d",
+        "fmin.s", "fmin.d", "fmax.s", "fmax.d", "fcvt.s.d", "fcvt.d.s", "fcvt.w.s", "fcvt.w.d",
+        "fcvt.wu.s", "fcvt.wu.d", "fcvt.s.w", "fcvt.d.w", "fcvt.s.wu", "fcvt.d.wu", "fmv.x.w",
+        "fmv.w.x", "fclass.s", "fclass.d", "feq.s", "feq.d", "flt.s", "flt.d", "fle.s", "fle.d",
+        "fmadd.s", "fmadd.d", "fmsub.s", "fmsub.d", "fnmsub.s", "fnmsub.d", "fnmadd.s",
+        "fnmadd.d", "fmv.s", "fmv.d", "fneg.s", "fneg.d", "fabs.s", "fabs.d", "frcsr", "fscsr",
+        "frrm", "fsrm", "frflags", "fsflags", "ld", "lwu", "sd", "addiw", "slliw", "srliw", "sraiw",
+        "addw", "subw", "sllw", "srlw", "sraw", "mulw", "divw", "divuw", "remw", "remuw", "sext.w",
+        "negw", ".bss",
+    ],
+    registers: &[
+        "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "fp", "s1", "a0", "a1", "a2", "a3",
+        "a4", "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3",
+        "t4", "t5", "t6", "ft0", "ft1", "ft2", "ft3", "ft4", "ft5", "ft6", "ft7", "fs0", "fs1",
+        "fa0", "fa1", "fa2", "fa3", "fa4", "fa5", "fa6", "fa7", "fs2", "fs3", "fs4", "fs5", "fs6",
+        "fs7", "fs8", "fs9", "fs10", "fs11", "ft8", "ft9", "ft10", "ft11",
+    ],
+};
+
+/// An instruction, the spans of its operands and the relocation of its immediate.
+type Parsed = (Instruction, Vec<SimpleSpan>, Option<Relocation>);
 
 fn register<'src>() -> impl Parser<'src, &'src str, Register, extra::Err<Rich<'src, char>>> {
     choice((
@@ -21,6 +71,9 @@ fn register<'src>() -> impl Parser<'src, &'src str, Register, extra::Err<Rich<'s
             just("t2").to(Register::from(7)),
             just("s0").to(Register::from(8)),
             just("fp").to(Register::from(8)),
+            // `s1` would match the start of these.
+            just("s10").to(Register::from(26)),
+            just("s11").to(Register::from(27)),
             just("s1").to(Register::from(9)),
             just("a0").to(Register::from(10)),
             just("a1").to(Register::from(11)),
@@ -38,8 +91,6 @@ fn register<'src>() -> impl Parser<'src, &'src str, Register, extra::Err<Rich<'s
             just("s7").to(Register::from(23)),
             just("s8").to(Register::from(24)),
             just("s9").to(Register::from(25)),
-            just("s10").to(Register::from(26)),
-            just("s11").to(Register::from(27)),
             just("t3").to(Register::from(28)),
             just("t4").to(Register::from(29)),
             just("t5").to(Register::from(30)),
@@ -49,29 +100,221 @@ fn register<'src>() -> impl Parser<'src, &'src str, Register, extra::Err<Rich<'s
     .labelled("register")
 }
 
-fn i_instruction<'src>() -> impl Parser<'src, &'src str, Instruction, extra::Err<Rich<'src, char>>>
+fn f_register<'src>() -> impl Parser<'src, &'src str, FRegister, extra::Err<Rich<'src, char>>> {
+    choice((
+        just("f").ignore_then(integer::<u8>(5.try_into().unwrap(), false).map(FRegister::from)),
+        choice([
+            // Before `ft1` and `fs1`, which would match their start.
+            just("ft10").to(FRegister::from(30)),
+            just("ft11").to(FRegister::from(31)),
+            just("fs10").to(FRegister::from(26)),
+            just("fs11").to(FRegister::from(27)),
+            just("ft0").to(FRegister::from(0)),
+            just("ft1").to(FRegister::from(1)),
+            just("ft2").to(FRegister::from(2)),
+            just("ft3").to(FRegister::from(3)),
+            just("ft4").to(FRegister::from(4)),
+            just("ft5").to(FRegister::from(5)),
+            just("ft6").to(FRegister::from(6)),
+            just("ft7").to(FRegister::from(7)),
+            just("fs0").to(FRegister::from(8)),
+            just("fs1").to(FRegister::from(9)),
+            just("fa0").to(FRegister::from(10)),
+            just("fa1").to(FRegister::from(11)),
+            just("fa2").to(FRegister::from(12)),
+            just("fa3").to(FRegister::from(13)),
+            just("fa4").to(FRegister::from(14)),
+            just("fa5").to(FRegister::from(15)),
+            just("fa6").to(FRegister::from(16)),
+            just("fa7").to(FRegister::from(17)),
+            just("fs2").to(FRegister::from(18)),
+            just("fs3").to(FRegister::from(19)),
+            just("fs4").to(FRegister::from(20)),
+            just("fs5").to(FRegister::from(21)),
+            just("fs6").to(FRegister::from(22)),
+            just("fs7").to(FRegister::from(23)),
+            just("fs8").to(FRegister::from(24)),
+            just("fs9").to(FRegister::from(25)),
+            just("ft8").to(FRegister::from(28)),
+            just("ft9").to(FRegister::from(29)),
+        ]),
+    ))
+    .labelled("register")
+}
+
+/// Signed offset of `bits` bits, instructions are aligned to two bytes so it cannot be odd.
+fn even_offset<'src>(bits: u32) -> impl Parser<'src, &'src str, i32, extra::Err<Rich<'src, char>>> {
+    integer::<i32>(bits.try_into().unwrap(), true)
+        .labelled("offset")
+        .validate(|int, e, emitter| {
+            if int % 2 != 0 {
+                emitter.emit(Rich::custom(
+                    e.span(),
+                    format!("Invalid offset: {}, cannot be odd in RISC-V!", int),
+                ));
+            }
+            int
+        })
+}
+
+fn is_register_name(name: &str) -> bool {
+    let numeric = |prefix| {
+        name.strip_prefix(prefix)
+            .and_then(|number| number.parse::<u8>().ok())
+            .is_some_and(|number| number < 32)
+    };
+    numeric('x')
+        || numeric('f')
+        || name == "fp"
+        || Register::ABI_NAMES.contains(&name)
+        || FRegister::ABI_NAMES.contains(&name)
+}
+
+/// Name of a label, symbol or section.
+fn identifier<'src>() -> impl Parser<'src, &'src str, &'src str, extra::Err<Rich<'src, char>>> {
+    any()
+        .filter(|c: &char| c.is_ascii_alphabetic() || *c == '_' || *c == '.')
+        .then(
+            any()
+                .filter(|c: &char| c.is_ascii_alphanumeric() || *c == '_' || *c == '.')
+                .repeated(),
+        )
+        .to_slice()
+}
+
+fn label<'src>() -> impl Parser<'src, &'src str, Label, extra::Err<Rich<'src, char>>> {
+    identifier()
+        .try_map(|name: &str, span| {
+            if is_register_name(name) {
+                return Err(Rich::custom(
+                    span,
+                    format!("`{}` is a register and cannot be used as a label", name),
+                ));
+            }
+            Ok(Label {
+                name: name.to_string(),
+                span,
+            })
+        })
+        .labelled("label")
+}
+
+///
+/// A number of `bits` bits which may be given signed or unsigned, as its two's complement.
+///
+/// A number out of range still parses, so that its error names the number and the range
+/// instead of what else could have been written there.
+///
+fn wrapping_integer<'src>(
+    bits: u32,
+) -> impl Parser<'src, &'src str, u64, extra::Err<Rich<'src, char>>> {
+    integer::<i128>(128.try_into().unwrap(), true).validate(move |value, e, emitter| {
+        let (min, max) = (-(1 << (bits - 1)), (1 << bits) - 1);
+        if !(min..=max).contains(&value) {
+            emitter.emit(Rich::custom(
+                e.span(),
+                format!(
+                    "Number out of range for {}-bit integer, {} is not between {} and {}",
+                    bits, value, min, max
+                ),
+            ));
+        }
+        value as u64
+    })
+}
+
+/// `%hi(label)` and `%pcrel_hi(label)` for `lui` and `auipc`.
+fn high_relocation<'src>() -> impl Parser<'src, &'src str, Relocation, extra::Err<Rich<'src, char>>>
 {
+    choice((
+        just("%hi").to(RelocationKind::Hi),
+        just("%pcrel_hi").to(RelocationKind::PcrelHi),
+    ))
+    .then(label().delimited_by(just('('), just(')')))
+    .map(|(kind, label)| Relocation { kind, label })
+}
+
+/// `%lo(label)` and `%pcrel_lo(label)` for 12-bit immediates.
+fn low_relocation<'src>() -> impl Parser<'src, &'src str, Relocation, extra::Err<Rich<'src, char>>>
+{
+    choice((
+        just("%lo").to(RelocationKind::Lo),
+        just("%pcrel_lo").to(RelocationKind::PcrelLo),
+    ))
+    .then(label().delimited_by(just('('), just(')')))
+    .map(|(kind, label)| Relocation { kind, label })
+}
+
+/// An immediate given as a number or through a label the assembler resolves.
+#[derive(Clone, Debug)]
+enum Operand<T> {
+    Value(T),
+    Relocation(Relocation),
+}
+
+impl<T: Default> Operand<T> {
+    /// The immediate to put into the instruction until the relocation is resolved.
+    fn split(self) -> (T, Option<Relocation>) {
+        match self {
+            Operand::Value(value) => (value, None),
+            Operand::Relocation(relocation) => (T::default(), Some(relocation)),
+        }
+    }
+}
+
+/// Offset of a branch or jump, or the label to go to.
+fn offset_operand<'src>(
+    bits: u32,
+    kind: RelocationKind,
+) -> impl Parser<'src, &'src str, Operand<i32>, extra::Err<Rich<'src, char>>> {
+    choice((
+        even_offset(bits).map(Operand::Value),
+        label().map(move |label| Operand::Relocation(Relocation { kind, label })),
+    ))
+}
+
+/// Offset of a load or store, which may also be `%lo(label)`.
+fn memory_offset<'src>() -> impl Parser<'src, &'src str, Operand<i16>, extra::Err<Rich<'src, char>>>
+{
+    choice((
+        integer(12.try_into().unwrap(), true)
+            .labelled("offset")
+            .map(Operand::Value),
+        low_relocation().map(Operand::Relocation),
+    ))
+}
+
+/// The register-immediate instructions, shifts take up to `xlen - 1`.
+fn i_instruction<'src>(
+    xlen: Xlen,
+) -> impl Parser<'src, &'src str, Parsed, extra::Err<Rich<'src, char>>> {
     let instr = |literal: &'static str, opcode: IOpcode| {
+        let immediate = integer::<i16>(
+            opcode.immediate_properties(xlen).0,
+            opcode.immediate_properties(xlen).1,
+        )
+        .labelled("immediate")
+        .map(Operand::Value);
         just(literal)
             .to(opcode)
-            .then_ignore(whitespace().at_least(1))
-            .then(register().labelled("rd"))
-            .padded()
+            .then_ignore(required_whitespace())
+            .then(spanned(register().labelled("rd")))
+            .padded_by(inline_whitespace())
             .then_ignore(just(','))
-            .padded()
-            .then(register().labelled("rs1"))
-            .padded()
+            .padded_by(inline_whitespace())
+            .then(spanned(register().labelled("rs1")))
+            .padded_by(inline_whitespace())
             .then_ignore(just(','))
-            .padded()
-            .then(
-                integer::<i16>(
-                    opcode.immediate_properties().0,
-                    opcode.immediate_properties().1,
-                )
-                .labelled("immediate"),
-            )
+            .padded_by(inline_whitespace())
+            .then(spanned(
+                immediate.or(low_relocation().map(Operand::Relocation)),
+            ))
     };
     choice([
+        instr("addiw", IOpcode::Addiw),
+        instr("slliw", IOpcode::Slliw),
+        instr("srliw", IOpcode::Srliw),
+        instr("sraiw", IOpcode::Sraiw),
         instr("addi", IOpcode::Addi),
         instr("slti", IOpcode::Slti),
         instr("sltiu", IOpcode::Sltiu),
@@ -83,17 +326,51 @@ fn i_instruction<'src>() -> impl Parser<'src, &'src str, Instruction, extra::Err
         instr("srai", IOpcode::Srai),
     ])
     .labelled("instruction")
-    .map(|(((opcode, rd), rs1), imm)| Instruction::IType {
-        opcode,
-        rd,
-        rs1,
-        imm: IImmediate(imm),
+    .validate(|(((opcode, rd), rs1), (imm, imm_span)), _, emitter| {
+        let shift = matches!(
+            opcode,
+            IOpcode::Slli
+                | IOpcode::Srli
+                | IOpcode::Srai
+                | IOpcode::Slliw
+                | IOpcode::Srliw
+                | IOpcode::Sraiw
+        );
+        if shift && matches!(imm, Operand::Relocation(_)) {
+            emitter.emit(Rich::custom(
+                imm_span,
+                "the shift amount cannot be the part of an address",
+            ));
+        }
+        (((opcode, rd), rs1), (imm, imm_span))
     })
+    .map(
+        |(((opcode, (rd, rd_span)), (rs1, rs1_span)), (imm, imm_span))| {
+            let (imm, relocation) = imm.split();
+            let instruction = Instruction::IType {
+                opcode,
+                rd,
+                rs1,
+                imm: IImmediate(imm),
+            };
+            (instruction, vec![rd_span, rs1_span, imm_span], relocation)
+        },
+    )
 }
 
-fn r_instruction<'src>() -> impl Parser<'src, &'src str, Instruction, extra::Err<Rich<'src, char>>>
-{
+fn r_instruction<'src>() -> impl Parser<'src, &'src str, Parsed, extra::Err<Rich<'src, char>>> {
     choice([
+        // Before the instructions which would match their start.
+        just("addw").to(ROpcode::Addw),
+        just("subw").to(ROpcode::Subw),
+        just("sllw").to(ROpcode::Sllw),
+        just("srlw").to(ROpcode::Srlw),
+        just("sraw").to(ROpcode::Sraw),
+        just("mulw").to(ROpcode::Mulw),
+        just("divuw").to(ROpcode::Divuw),
+        just("divw").to(ROpcode::Divw),
+        just("remuw").to(ROpcode::Remuw),
+        just("remw").to(ROpcode::Remw),
         just("add").to(ROpcode::Add),
         just("sub").to(ROpcode::Sub),
         just("sll").to(ROpcode::Sll),
@@ -104,78 +381,113 @@ fn r_instruction<'src>() -> impl Parser<'src, &'src str, Instruction, extra::Err
         just("sra").to(ROpcode::Sra),
         just("or").to(ROpcode::Or),
         just("and").to(ROpcode::And),
+        just("mulhsu").to(ROpcode::Mulhsu),
+        just("mulhu").to(ROpcode::Mulhu),
+        just("mulh").to(ROpcode::Mulh),
+        just("mul").to(ROpcode::Mul),
+        just("divu").to(ROpcode::Divu),
+        just("div").to(ROpcode::Div),
+        just("remu").to(ROpcode::Remu),
+        just("rem").to(ROpcode::Rem),
     ])
     .labelled("instruction")
-    .then_ignore(whitespace().at_least(1))
-    .then(register().labelled("rd"))
-    .padded()
+    .then_ignore(required_whitespace())
+    .then(spanned(register().labelled("rd")))
+    .padded_by(inline_whitespace())
     .then_ignore(just(','))
-    .padded()
-    .then(register().labelled("rs1"))
-    .padded()
+    .padded_by(inline_whitespace())
+    .then(spanned(register().labelled("rs1")))
+    .padded_by(inline_whitespace())
     .then_ignore(just(','))
-    .padded()
-    .then(register().labelled("rs2"))
-    .map(|(((opcode, rd), rs1), rs2)| Instruction::RType {
-        opcode,
-        rd,
-        rs1,
-        rs2,
-    })
+    .padded_by(inline_whitespace())
+    .then(spanned(register().labelled("rs2")))
+    .map(
+        |(((opcode, (rd, rd_span)), (rs1, rs1_span)), (rs2, rs2_span))| {
+            let instruction = Instruction::RType {
+                opcode,
+                rd,
+                rs1,
+                rs2,
+            };
+            (instruction, vec![rd_span, rs1_span, rs2_span], None)
+        },
+    )
 }
 
-fn s_instruction<'src>() -> impl Parser<'src, &'src str, Instruction, extra::Err<Rich<'src, char>>>
-{
+fn s_instruction<'src>() -> impl Parser<'src, &'src str, Parsed, extra::Err<Rich<'src, char>>> {
     choice([
         just("sb").to(SOpcode::Sb),
         just("sh").to(SOpcode::Sh),
         just("sw").to(SOpcode::Sw),
+        just("sd").to(SOpcode::Sd),
     ])
     .labelled("instruction")
-    .then_ignore(whitespace().at_least(1))
-    .then(register().labelled("rs2"))
-    .padded()
+    .then_ignore(required_whitespace())
+    .then(spanned(register().labelled("rs2")))
+    .padded_by(inline_whitespace())
     .then_ignore(just(','))
-    .padded()
-    .then(integer(12.try_into().unwrap(), true).labelled("offset"))
+    .padded_by(inline_whitespace())
+    .then(spanned(memory_offset()))
     .then_ignore(just('('))
-    .then(register().labelled("rs1"))
+    .then(spanned(register().labelled("rs1")))
     .then_ignore(just(')'))
-    .map(|(((opcode, rs2), offset), rs1)| Instruction::SType {
-        opcode,
-        rs1,
-        rs2,
-        imm: LSImmediate(offset),
-    })
+    .map(
+        |(((opcode, (rs2, rs2_span)), (offset, offset_span)), (rs1, rs1_span))| {
+            let (offset, relocation) = offset.split();
+            let instruction = Instruction::SType {
+                opcode,
+                rs1,
+                rs2,
+                imm: LSImmediate(offset),
+            };
+            (
+                instruction,
+                vec![rs2_span, offset_span, rs1_span],
+                relocation,
+            )
+        },
+    )
 }
 
-fn l_instruction<'src>() -> impl Parser<'src, &'src str, Instruction, extra::Err<Rich<'src, char>>>
-{
+fn l_instruction<'src>() -> impl Parser<'src, &'src str, Parsed, extra::Err<Rich<'src, char>>> {
     choice([
+        just("lbu").to(LOpcode::Lbu),
+        just("lhu").to(LOpcode::Lhu),
         just("lb").to(LOpcode::Lb),
         just("lh").to(LOpcode::Lh),
+        just("lwu").to(LOpcode::Lwu),
         just("lw").to(LOpcode::Lw),
+        just("ld").to(LOpcode::Ld),
     ])
     .labelled("instruction")
-    .then_ignore(whitespace().at_least(1))
-    .then(register().labelled("rd"))
-    .padded()
+    .then_ignore(required_whitespace())
+    .then(spanned(register().labelled("rd")))
+    .padded_by(inline_whitespace())
     .then_ignore(just(','))
-    .padded()
-    .then(integer(12.try_into().unwrap(), true).labelled("offset"))
+    .padded_by(inline_whitespace())
+    .then(spanned(memory_offset()))
     .then_ignore(just('('))
-    .then(register().labelled("rs1"))
+    .then(spanned(register().labelled("rs1")))
     .then_ignore(just(')'))
-    .map(|(((opcode, rd), offset), rs1)| Instruction::LType {
-        opcode,
-        rd,
-        rs1,
-        imm: LSImmediate(offset),
-    })
+    .map(
+        |(((opcode, (rd, rd_span)), (offset, offset_span)), (rs1, rs1_span))| {
+            let (offset, relocation) = offset.split();
+            let instruction = Instruction::LType {
+                opcode,
+                rd,
+                rs1,
+                imm: LSImmediate(offset),
+            };
+            (
+                instruction,
+                vec![rd_span, offset_span, rs1_span],
+                relocation,
+            )
+        },
+    )
 }
 
-fn b_instruction<'src>() -> impl Parser<'src, &'src str, Instruction, extra::Err<Rich<'src, char>>>
-{
+fn b_instruction<'src>() -> impl Parser<'src, &'src str, Parsed, extra::Err<Rich<'src, char>>> {
     choice([
         just("beq").to(BOpcode::Beq),
         just("bne").to(BOpcode::Bne),
@@ -185,92 +497,1504 @@ fn b_instruction<'src>() -> impl Parser<'src, &'src str, Instruction, extra::Err
         just("bge").to(BOpcode::Bge),
     ])
     .labelled("instruction")
-    .then_ignore(whitespace().at_least(1))
-    .then(register().labelled("rs1"))
-    .padded()
+    .then_ignore(required_whitespace())
+    .then(spanned(register().labelled("rs1")))
+    .padded_by(inline_whitespace())
     .then_ignore(just(','))
-    .padded()
-    .then(register().labelled("rs2"))
-    .padded()
+    .padded_by(inline_whitespace())
+    .then(spanned(register().labelled("rs2")))
+    .padded_by(inline_whitespace())
     .then_ignore(just(','))
-    .padded()
-    .then(
-        integer(13.try_into().unwrap(), true)
-            .labelled("offset")
-            .validate(|int: i16, e, emitter| {
-                if int % 2 == 1 {
-                    emitter.emit(Rich::custom(
-                        e.span(),
-                        format!("Invalid offset: {}, cannot be odd in RISC-V!", int),
+    .padded_by(inline_whitespace())
+    .then(spanned(offset_operand(13, RelocationKind::Branch)))
+    .map(
+        |(((opcode, (rs1, rs1_span)), (rs2, rs2_span)), (imm, imm_span))| {
+            let (imm, relocation) = imm.split();
+            let instruction = Instruction::BType {
+                opcode,
+                rs1,
+                rs2,
+                imm: BImmediate(imm as i16),
+            };
+            (instruction, vec![rs1_span, rs2_span, imm_span], relocation)
+        },
+    )
+}
+
+fn u_instruction<'src>() -> impl Parser<'src, &'src str, Parsed, extra::Err<Rich<'src, char>>> {
+    choice([
+        just("lui").to(UOpcode::Lui),
+        just("auipc").to(UOpcode::Auipc),
+    ])
+    .labelled("instruction")
+    .then_ignore(required_whitespace())
+    .then(spanned(register().labelled("rd")))
+    .padded_by(inline_whitespace())
+    .then_ignore(just(','))
+    .padded_by(inline_whitespace())
+    .then(spanned(choice((
+        integer::<i32>(20.try_into().unwrap(), true).map(Operand::Value),
+        high_relocation().map(Operand::Relocation),
+    ))))
+    .map(|((opcode, (rd, rd_span)), (imm, imm_span))| {
+        let (imm, relocation) = imm.split();
+        let instruction = Instruction::UType {
+            opcode,
+            rd,
+            imm: UImmediate(imm),
+        };
+        (instruction, vec![rd_span, imm_span], relocation)
+    })
+}
+
+fn j_instruction<'src>() -> impl Parser<'src, &'src str, Parsed, extra::Err<Rich<'src, char>>> {
+    just("jal")
+        .to(JOpcode::Jal)
+        .labelled("instruction")
+        .then_ignore(required_whitespace())
+        .then(spanned(register().labelled("rd")))
+        .padded_by(inline_whitespace())
+        .then_ignore(just(','))
+        .padded_by(inline_whitespace())
+        .then(spanned(offset_operand(21, RelocationKind::Jump)))
+        .map(|((opcode, (rd, rd_span)), (imm, imm_span))| {
+            let (imm, relocation) = imm.split();
+            let instruction = Instruction::JType {
+                opcode,
+                rd,
+                imm: JImmediate(imm),
+            };
+            (instruction, vec![rd_span, imm_span], relocation)
+        })
+}
+
+/// `jalr rd, offset(rs1)` as well as `jalr rd, rs1, offset`.
+fn jalr_instruction<'src>() -> impl Parser<'src, &'src str, Parsed, extra::Err<Rich<'src, char>>> {
+    let offset_rs1 = spanned(memory_offset())
+        .then_ignore(just('('))
+        .then(spanned(register().labelled("rs1")))
+        .then_ignore(just(')'))
+        .map(|((imm, imm_span), (rs1, rs1_span))| (rs1, imm, vec![imm_span, rs1_span]));
+    let rs1_offset = spanned(register().labelled("rs1"))
+        .padded_by(inline_whitespace())
+        .then_ignore(just(','))
+        .padded_by(inline_whitespace())
+        .then(spanned(memory_offset()))
+        .map(|((rs1, rs1_span), (imm, imm_span))| (rs1, imm, vec![rs1_span, imm_span]));
+
+    just("jalr")
+        .labelled("instruction")
+        .ignore_then(required_whitespace())
+        .ignore_then(spanned(register().labelled("rd")))
+        .padded_by(inline_whitespace())
+        .then_ignore(just(','))
+        .padded_by(inline_whitespace())
+        .then(choice((offset_rs1, rs1_offset)))
+        .map(|((rd, rd_span), (rs1, imm, spans))| {
+            let (imm, relocation) = imm.split();
+            let instruction = Instruction::IType {
+                opcode: IOpcode::Jalr,
+                rd,
+                rs1,
+                imm: IImmediate(imm),
+            };
+            (instruction, [vec![rd_span], spans].concat(), relocation)
+        })
+}
+
+/// Any combination of `i`, `o`, `r` and `w`, each at most once.
+fn fence_set<'src>() -> impl Parser<'src, &'src str, FenceSet, extra::Err<Rich<'src, char>>> {
+    one_of("iorw")
+        .repeated()
+        .at_least(1)
+        .to_slice()
+        .try_map(|set: &str, span| {
+            let mut bits = 0;
+            for c in set.chars() {
+                let bit = match c {
+                    'i' => FenceSet::INPUT,
+                    'o' => FenceSet::OUTPUT,
+                    'r' => FenceSet::READ,
+                    _ => FenceSet::WRITE,
+                };
+                if bits & bit != 0 {
+                    return Err(Rich::custom(
+                        span,
+                        format!("`{}` appears twice in {}", c, set),
                     ));
                 }
-                int
-            }),
+                bits |= bit;
+            }
+            Ok(FenceSet(bits))
+        })
+        .labelled("fence set")
+}
+
+/// `fence pred, succ`, a bare `fence` orders everything.
+fn fence_instruction<'src>() -> impl Parser<'src, &'src str, Parsed, extra::Err<Rich<'src, char>>> {
+    let sets = spanned(fence_set().labelled("pred"))
+        .padded_by(inline_whitespace())
+        .then_ignore(just(','))
+        .padded_by(inline_whitespace())
+        .then(spanned(fence_set().labelled("succ")));
+
+    just("fence")
+        .labelled("instruction")
+        .ignore_then(required_whitespace().ignore_then(sets).or_not())
+        .map(|sets| match sets {
+            Some(((pred, pred_span), (succ, succ_span))) => (
+                Instruction::Fence { pred, succ },
+                vec![pred_span, succ_span],
+                None,
+            ),
+            None => {
+                let instruction = Instruction::Fence {
+                    pred: FenceSet::ALL,
+                    succ: FenceSet::ALL,
+                };
+                (instruction, vec![], None)
+            }
+        })
+}
+
+fn system_instruction<'src>() -> impl Parser<'src, &'src str, Parsed, extra::Err<Rich<'src, char>>>
+{
+    choice([
+        just("ecall").to(SystemOpcode::Ecall),
+        just("ebreak").to(SystemOpcode::Ebreak),
+    ])
+    .labelled("instruction")
+    .map(|opcode| (Instruction::System { opcode }, vec![], None))
+}
+
+/// `.aq`, `.rl` or `.aqrl` after the mnemonic of an atomic instruction, none is relaxed.
+fn atomic_ordering<'src>(
+) -> impl Parser<'src, &'src str, AtomicOrdering, extra::Err<Rich<'src, char>>> {
+    let ordering = |acquire, release| AtomicOrdering { acquire, release };
+    choice((
+        just(".aqrl").to(ordering(true, true)),
+        just(".aq").to(ordering(true, false)),
+        just(".rl").to(ordering(false, true)),
+    ))
+    .or_not()
+    .map(Option::unwrap_or_default)
+}
+
+/// `lr.w rd, (rs1)` and `sc.w rd, rs2, (rs1)`, the others are written like `sc.w`.
+fn atomic_instruction<'src>(
+) -> impl Parser<'src, &'src str, Parsed, extra::Err<Rich<'src, char>>> {
+    let comma = || just(',').padded_by(inline_whitespace());
+    // The address may be given with a zero offset, as `0(a0)`.
+    let address = || {
+        just('0')
+            .or_not()
+            .ignore_then(just('('))
+            .ignore_then(spanned(register().labelled("rs1")))
+            .then_ignore(just(')'))
+    };
+
+    let load_reserved = choice([just("lr.w").to(AOpcode::LrW), just("lr.d").to(AOpcode::LrD)])
+        .then(atomic_ordering())
+        .then_ignore(required_whitespace())
+        .then(spanned(register().labelled("rd")))
+        .then_ignore(comma())
+        .then(address())
+        .map(|(((opcode, ordering), (rd, rd_span)), (rs1, rs1_span))| {
+            let instruction = Instruction::Atomic {
+                opcode,
+                rd,
+                rs1,
+                rs2: Register::from(0),
+                ordering,
+            };
+            (instruction, vec![rd_span, rs1_span], None)
+        });
+    let read_modify_write = choice([
+        just("sc.w").to(AOpcode::ScW),
+        just("amoswap.w").to(AOpcode::AmoswapW),
+        just("amoadd.w").to(AOpcode::AmoaddW),
+        just("amoxor.w").to(AOpcode::AmoxorW),
+        just("amoand.w").to(AOpcode::AmoandW),
+        just("amoor.w").to(AOpcode::AmoorW),
+        just("amomin.w").to(AOpcode::AmominW),
+        just("amomax.w").to(AOpcode::AmomaxW),
+        just("amominu.w").to(AOpcode::AmominuW),
+        just("amomaxu.w").to(AOpcode::AmomaxuW),
+        just("sc.d").to(AOpcode::ScD),
+        just("amoswap.d").to(AOpcode::AmoswapD),
+        just("amoadd.d").to(AOpcode::AmoaddD),
+        just("amoxor.d").to(AOpcode::AmoxorD),
+        just("amoand.d").to(AOpcode::AmoandD),
+        just("amoor.d").to(AOpcode::AmoorD),
+        just("amomin.d").to(AOpcode::AmominD),
+        just("amomax.d").to(AOpcode::AmomaxD),
+        just("amominu.d").to(AOpcode::AmominuD),
+        just("amomaxu.d").to(AOpcode::AmomaxuD),
+    ])
+    .then(atomic_ordering())
+    .then_ignore(required_whitespace())
+    .then(spanned(register().labelled("rd")))
+    .then_ignore(comma())
+    .then(spanned(register().labelled("rs2")))
+    .then_ignore(comma())
+    .then(address())
+    .map(
+        |((((opcode, ordering), (rd, rd_span)), (rs2, rs2_span)), (rs1, rs1_span))| {
+            let instruction = Instruction::Atomic {
+                opcode,
+                rd,
+                rs1,
+                rs2,
+                ordering,
+            };
+            (instruction, vec![rd_span, rs2_span, rs1_span], None)
+        },
+    );
+
+    choice((load_reserved, read_modify_write)).labelled("instruction")
+}
+
+/// A CSR given by its name, such as `mstatus`, or by its 12-bit number.
+fn csr<'src>() -> impl Parser<'src, &'src str, Csr, extra::Err<Rich<'src, char>>> {
+    // Labelled before the lookup, so that an unknown name keeps its own error.
+    choice((
+        integer::<u16>(12.try_into().unwrap(), false)
+            .labelled("CSR")
+            .map(Csr),
+        identifier().labelled("CSR").try_map(|name: &str, span| {
+            Csr::from_name(name)
+                .ok_or_else(|| Rich::custom(span, format!("unknown CSR `{}`", name)))
+        }),
+    ))
+}
+
+fn csr_immediate<'src>() -> impl Parser<'src, &'src str, CsrImmediate, extra::Err<Rich<'src, char>>>
+{
+    integer::<u8>(5.try_into().unwrap(), false)
+        .map(CsrImmediate)
+        .labelled("immediate")
+}
+
+/// `csrrw rd, csr, rs1` and `csrrwi rd, csr, uimm`, likewise for setting and clearing bits.
+fn csr_instruction<'src>() -> impl Parser<'src, &'src str, Parsed, extra::Err<Rich<'src, char>>> {
+    let comma = || just(',').padded_by(inline_whitespace());
+
+    let immediate = choice([
+        just("csrrwi").to(CsrOpcode::Csrrw),
+        just("csrrsi").to(CsrOpcode::Csrrs),
+        just("csrrci").to(CsrOpcode::Csrrc),
+    ])
+    .then_ignore(required_whitespace())
+    .then(spanned(register().labelled("rd")))
+    .then_ignore(comma())
+    .then(spanned(csr()))
+    .then_ignore(comma())
+    .then(spanned(csr_immediate()))
+    .map(
+        |(((opcode, (rd, rd_span)), (csr, csr_span)), (imm, imm_span))| {
+            let instruction = Instruction::CsrImmediate {
+                opcode,
+                rd,
+                imm,
+                csr,
+            };
+            (instruction, vec![rd_span, csr_span, imm_span], None)
+        },
+    );
+    let register_source = choice([
+        just("csrrw").to(CsrOpcode::Csrrw),
+        just("csrrs").to(CsrOpcode::Csrrs),
+        just("csrrc").to(CsrOpcode::Csrrc),
+    ])
+    .then_ignore(required_whitespace())
+    .then(spanned(register().labelled("rd")))
+    .then_ignore(comma())
+    .then(spanned(csr()))
+    .then_ignore(comma())
+    .then(spanned(register().labelled("rs1")))
+    .map(
+        |(((opcode, (rd, rd_span)), (csr, csr_span)), (rs1, rs1_span))| {
+            let instruction = Instruction::Csr {
+                opcode,
+                rd,
+                rs1,
+                csr,
+            };
+            (instruction, vec![rd_span, csr_span, rs1_span], None)
+        },
+    );
+
+    choice((immediate, register_source)).labelled("instruction")
+}
+
+/// `, rm` after the operands of an instruction which rounds, the dynamic mode of `frm` if left
+/// out.
+fn rounding_mode<'src>(
+    default: RoundingMode,
+) -> impl Parser<'src, &'src str, (RoundingMode, Vec<SimpleSpan>), extra::Err<Rich<'src, char>>> {
+    let mode = choice([
+        just("rne").to(RoundingMode::Rne),
+        just("rtz").to(RoundingMode::Rtz),
+        just("rdn").to(RoundingMode::Rdn),
+        just("rup").to(RoundingMode::Rup),
+        just("rmm").to(RoundingMode::Rmm),
+        just("dyn").to(RoundingMode::Dynamic),
+    ])
+    .labelled("rounding mode");
+    just(',')
+        .padded_by(inline_whitespace())
+        .ignore_then(spanned(mode))
+        .or_not()
+        .map(move |mode| match mode {
+            Some((mode, span)) => (mode, vec![span]),
+            None => (default, vec![]),
+        })
+}
+
+/// `flw rd, offset(rs1)` and `fsw rs2, offset(rs1)`, likewise `fld` and `fsd`.
+fn float_memory_instruction<'src>(
+) -> impl Parser<'src, &'src str, Parsed, extra::Err<Rich<'src, char>>> {
+    choice([
+        just("flw").to((true, FloatFormat::Single)),
+        just("fld").to((true, FloatFormat::Double)),
+        just("fsw").to((false, FloatFormat::Single)),
+        just("fsd").to((false, FloatFormat::Double)),
+    ])
+    .then_ignore(required_whitespace())
+    .then(spanned(f_register()))
+    .then_ignore(just(',').padded_by(inline_whitespace()))
+    .then(spanned(memory_offset()))
+    .then_ignore(just('('))
+    .then(spanned(register().labelled("rs1")))
+    .then_ignore(just(')'))
+    .map(
+        |(
+            (((load, format), (register, register_span)), (offset, offset_span)),
+            (rs1, rs1_span),
+        )| {
+            let (offset, relocation) = offset.split();
+            let imm = LSImmediate(offset);
+            let instruction = if load {
+                Instruction::FloatLoad {
+                    format,
+                    rd: register,
+                    rs1,
+                    imm,
+                }
+            } else {
+                Instruction::FloatStore {
+                    format,
+                    rs1,
+                    rs2: register,
+                    imm,
+                }
+            };
+            let operands = vec![register_span, offset_span, rs1_span];
+            (instruction, operands, relocation)
+        },
     )
-    .map(|(((opcode, rs1), rs2), imm)| Instruction::BType {
+    .labelled("instruction")
+}
+
+/// The arithmetic, conversion, comparison and fused multiply-add instructions of the F and D
+/// extensions, those which round take an optional rounding mode after their operands.
+fn float_instruction<'src>() -> impl Parser<'src, &'src str, Parsed, extra::Err<Rich<'src, char>>> {
+    use FloatFormat::{Double, Single};
+    let comma = || just(',').padded_by(inline_whitespace());
+    let f_operand = |name| spanned(f_register().labelled(name));
+    let x_operand = |name| spanned(register().labelled(name));
+    let exact = || empty().to((RoundingMode::Dynamic, vec![])).boxed();
+
+    let r_type = |mnemonics, rounding| {
+        choice(mnemonics)
+            .then_ignore(required_whitespace())
+            .then(f_operand("rd"))
+            .then_ignore(comma())
+            .then(f_operand("rs1"))
+            .then_ignore(comma())
+            .then(f_operand("rs2"))
+            .then(rounding)
+            .map(
+                |(
+                    ((((opcode, format), (rd, rd_span)), (rs1, rs1_span)), (rs2, rs2_span)),
+                    (rm, rm_span),
+                )| {
+                    let instruction = Instruction::FloatRType {
+                        opcode,
+                        format,
+                        rd,
+                        rs1,
+                        rs2,
+                        rm,
+                    };
+                    let operands = [vec![rd_span, rs1_span, rs2_span], rm_span].concat();
+                    (instruction, operands, None)
+                },
+            )
+    };
+    let rounded = r_type(
+        vec![
+            just("fadd.s").to((FOpcode::Fadd, Single)),
+            just("fadd.d").to((FOpcode::Fadd, Double)),
+            just("fsub.s").to((FOpcode::Fsub, Single)),
+            just("fsub.d").to((FOpcode::Fsub, Double)),
+            just("fmul.s").to((FOpcode::Fmul, Single)),
+            just("fmul.d").to((FOpcode::Fmul, Double)),
+            just("fdiv.s").to((FOpcode::Fdiv, Single)),
+            just("fdiv.d").to((FOpcode::Fdiv, Double)),
+        ],
+        rounding_mode(RoundingMode::Dynamic).boxed(),
+    );
+    let unrounded = r_type(
+        vec![
+            just("fsgnjn.s").to((FOpcode::Fsgnjn, Single)),
+            just("fsgnjn.d").to((FOpcode::Fsgnjn, Double)),
+            just("fsgnjx.s").to((FOpcode::Fsgnjx, Single)),
+            just("fsgnjx.d").to((FOpcode::Fsgnjx, Double)),
+            just("fsgnj.s").to((FOpcode::Fsgnj, Single)),
+            just("fsgnj.d").to((FOpcode::Fsgnj, Double)),
+            just("fmin.s").to((FOpcode::Fmin, Single)),
+            just("fmin.d").to((FOpcode::Fmin, Double)),
+            just("fmax.s").to((FOpcode::Fmax, Single)),
+            just("fmax.d").to((FOpcode::Fmax, Double)),
+        ],
+        exact(),
+    );
+    let unary = |mnemonics, rounding| {
+        choice(mnemonics)
+            .then_ignore(required_whitespace())
+            .then(f_operand("rd"))
+            .then_ignore(comma())
+            .then(f_operand("rs1"))
+            .then(rounding)
+            .map(
+                |((((opcode, format), (rd, rd_span)), (rs1, rs1_span)), (rm, rm_span))| {
+                    let instruction = Instruction::FloatUnary {
+                        opcode,
+                        format,
+                        rd,
+                        rs1,
+                        rm,
+                    };
+                    (
+                        instruction,
+                        [vec![rd_span, rs1_span], rm_span].concat(),
+                        None,
+                    )
+                },
+            )
+    };
+    let rounded_unary = unary(
+        vec![
+            just("fsqrt.s").to((FUnaryOpcode::Sqrt, Single)),
+            just("fsqrt.d").to((FUnaryOpcode::Sqrt, Double)),
+            just("fcvt.s.d").to((FUnaryOpcode::Convert, Single)),
+        ],
+        rounding_mode(RoundingMode::Dynamic).boxed(),
+    );
+    // Exact conversions never round, GNU as gives them `rne` unless told otherwise.
+    let widened = unary(
+        vec![just("fcvt.d.s").to((FUnaryOpcode::Convert, Double))],
+        rounding_mode(RoundingMode::Rne).boxed(),
+    );
+    let to_integer = |mnemonics, rounding| {
+        choice(mnemonics)
+            .then_ignore(required_whitespace())
+            .then(x_operand("rd"))
+            .then_ignore(comma())
+            .then(f_operand("rs1"))
+            .then(rounding)
+            .map(
+                |((((opcode, format), (rd, rd_span)), (rs1, rs1_span)), (rm, rm_span))| {
+                    let instruction = Instruction::FloatToInt {
+                        opcode,
+                        format,
+                        rd,
+                        rs1,
+                        rm,
+                    };
+                    (
+                        instruction,
+                        [vec![rd_span, rs1_span], rm_span].concat(),
+                        None,
+                    )
+                },
+            )
+    };
+    let converted_to_integer = to_integer(
+        vec![
+            // Before `fcvt.w.s`, which would match their start.
+            just("fcvt.wu.s").to((FToIntOpcode::ConvertUnsigned, Single)),
+            just("fcvt.wu.d").to((FToIntOpcode::ConvertUnsigned, Double)),
+            just("fcvt.w.s").to((FToIntOpcode::Convert, Single)),
+            just("fcvt.w.d").to((FToIntOpcode::Convert, Double)),
+            just("fcvt.lu.s").to((FToIntOpcode::ConvertLongUnsigned, Single)),
+            just("fcvt.lu.d").to((FToIntOpcode::ConvertLongUnsigned, Double)),
+            just("fcvt.l.s").to((FToIntOpcode::ConvertLong, Single)),
+            just("fcvt.l.d").to((FToIntOpcode::ConvertLong, Double)),
+        ],
+        rounding_mode(RoundingMode::Dynamic).boxed(),
+    );
+    let moved_to_integer = to_integer(
+        vec![
+            just("fmv.x.w").to((FToIntOpcode::Move, Single)),
+            just("fmv.x.d").to((FToIntOpcode::Move, Double)),
+            just("fclass.s").to((FToIntOpcode::Classify, Single)),
+            just("fclass.d").to((FToIntOpcode::Classify, Double)),
+        ],
+        exact(),
+    );
+    let from_integer = |mnemonics, rounding| {
+        choice(mnemonics)
+            .then_ignore(required_whitespace())
+            .then(f_operand("rd"))
+            .then_ignore(comma())
+            .then(x_operand("rs1"))
+            .then(rounding)
+            .map(
+                |((((opcode, format), (rd, rd_span)), (rs1, rs1_span)), (rm, rm_span))| {
+                    let instruction = Instruction::IntToFloat {
+                        opcode,
+                        format,
+                        rd,
+                        rs1,
+                        rm,
+                    };
+                    (
+                        instruction,
+                        [vec![rd_span, rs1_span], rm_span].concat(),
+                        None,
+                    )
+                },
+            )
+    };
+    let converted_from_integer = from_integer(
+        vec![
+            just("fcvt.s.wu").to((IntToFOpcode::ConvertUnsigned, Single)),
+            just("fcvt.s.w").to((IntToFOpcode::Convert, Single)),
+            just("fcvt.s.lu").to((IntToFOpcode::ConvertLongUnsigned, Single)),
+            just("fcvt.s.l").to((IntToFOpcode::ConvertLong, Single)),
+            // Unlike words, doublewords do not always fit into a double.
+            just("fcvt.d.lu").to((IntToFOpcode::ConvertLongUnsigned, Double)),
+            just("fcvt.d.l").to((IntToFOpcode::ConvertLong, Double)),
+        ],
+        rounding_mode(RoundingMode::Dynamic).boxed(),
+    );
+    let widened_from_integer = from_integer(
+        vec![
+            just("fcvt.d.wu").to((IntToFOpcode::ConvertUnsigned, Double)),
+            just("fcvt.d.w").to((IntToFOpcode::Convert, Double)),
+        ],
+        rounding_mode(RoundingMode::Rne).boxed(),
+    );
+    let moved_from_integer = from_integer(
+        vec![
+            just("fmv.w.x").to((IntToFOpcode::Move, Single)),
+            just("fmv.d.x").to((IntToFOpcode::Move, Double)),
+        ],
+        exact(),
+    );
+    let compare = choice([
+        just("feq.s").to((FCompareOpcode::Feq, Single)),
+        just("feq.d").to((FCompareOpcode::Feq, Double)),
+        just("flt.s").to((FCompareOpcode::Flt, Single)),
+        just("flt.d").to((FCompareOpcode::Flt, Double)),
+        just("fle.s").to((FCompareOpcode::Fle, Single)),
+        just("fle.d").to((FCompareOpcode::Fle, Double)),
+    ])
+    .then_ignore(required_whitespace())
+    .then(x_operand("rd"))
+    .then_ignore(comma())
+    .then(f_operand("rs1"))
+    .then_ignore(comma())
+    .then(f_operand("rs2"))
+    .map(
+        |((((opcode, format), (rd, rd_span)), (rs1, rs1_span)), (rs2, rs2_span))| {
+            let instruction = Instruction::FloatCompare {
+                opcode,
+                format,
+                rd,
+                rs1,
+                rs2,
+            };
+            (instruction, vec![rd_span, rs1_span, rs2_span], None)
+        },
+    );
+    let fused = choice([
+        just("fmadd.s").to((FusedOpcode::Fmadd, Single)),
+        just("fmadd.d").to((FusedOpcode::Fmadd, Double)),
+        just("fmsub.s").to((FusedOpcode::Fmsub, Single)),
+        just("fmsub.d").to((FusedOpcode::Fmsub, Double)),
+        just("fnmsub.s").to((FusedOpcode::Fnmsub, Single)),
+        just("fnmsub.d").to((FusedOpcode::Fnmsub, Double)),
+        just("fnmadd.s").to((FusedOpcode::Fnmadd, Single)),
+        just("fnmadd.d").to((FusedOpcode::Fnmadd, Double)),
+    ])
+    .then_ignore(required_whitespace())
+    .then(f_operand("rd"))
+    .then_ignore(comma())
+    .then(f_operand("rs1"))
+    .then_ignore(comma())
+    .then(f_operand("rs2"))
+    .then_ignore(comma())
+    .then(f_operand("rs3"))
+    .then(rounding_mode(RoundingMode::Dynamic))
+    .map(
+        |(
+            (
+                ((((opcode, format), (rd, rd_span)), (rs1, rs1_span)), (rs2, rs2_span)),
+                (rs3, rs3_span),
+            ),
+            (rm, rm_span),
+        )| {
+            let instruction = Instruction::FloatFused {
+                opcode,
+                format,
+                rd,
+                rs1,
+                rs2,
+                rs3,
+                rm,
+            };
+            let operands = [vec![rd_span, rs1_span, rs2_span, rs3_span], rm_span].concat();
+            (instruction, operands, None)
+        },
+    );
+
+    choice((
+        rounded,
+        unrounded,
+        rounded_unary,
+        widened,
+        converted_to_integer,
+        moved_to_integer,
+        converted_from_integer,
+        widened_from_integer,
+        moved_from_integer,
+        compare,
+        fused,
+    ))
+    .labelled("instruction")
+}
+
+/// The `c.` instructions of the C extension, written like their base instructions with the
+/// registers and immediates the opcode implies left out.
+fn compressed_instruction<'src>(
+) -> impl Parser<'src, &'src str, Parsed, extra::Err<Rich<'src, char>>> {
+    let compressed = |opcode, rd, rs1, rs2, imm| Instruction::Compressed {
         opcode,
+        rd,
         rs1,
         rs2,
-        imm: BImmediate(imm),
+        imm: CImmediate(imm),
+    };
+    let zero = Register::from(0);
+    let comma = || just(',').padded_by(inline_whitespace());
+    let immediate = || {
+        integer::<i32>(13.try_into().unwrap(), true)
+            .labelled("immediate")
+    };
+
+    let no_operands = choice([
+        just("c.nop").to(COpcode::Nop),
+        just("c.ebreak").to(COpcode::Ebreak),
+    ])
+    .map(move |opcode| (compressed(opcode, zero, zero, zero, 0), vec![], None));
+    let jump_register = choice([
+        just("c.jalr").to(COpcode::Jalr),
+        just("c.jr").to(COpcode::Jr),
+    ])
+    .then_ignore(required_whitespace())
+    .then(spanned(register().labelled("rs1")))
+    .map(move |(opcode, (rs1, rs1_span))| {
+        (compressed(opcode, zero, rs1, zero, 0), vec![rs1_span], None)
+    });
+    let jump = choice([
+        just("c.jal").to(COpcode::Jal),
+        just("c.j").to(COpcode::J),
+    ])
+    .then_ignore(required_whitespace())
+    .then(spanned(offset_operand(12, RelocationKind::Jump)))
+    .map(move |(opcode, (offset, offset_span))| {
+        let (offset, relocation) = offset.split();
+        let instruction = compressed(opcode, zero, zero, zero, offset);
+        (instruction, vec![offset_span], relocation)
+    });
+    let branch = choice([
+        just("c.beqz").to(COpcode::Beqz),
+        just("c.bnez").to(COpcode::Bnez),
+    ])
+    .then_ignore(required_whitespace())
+    .then(spanned(register().labelled("rs1")))
+    .then_ignore(comma())
+    .then(spanned(offset_operand(9, RelocationKind::Branch)))
+    .map(move |((opcode, (rs1, rs1_span)), (offset, offset_span))| {
+        let (offset, relocation) = offset.split();
+        let instruction = compressed(opcode, zero, rs1, zero, offset);
+        (instruction, vec![rs1_span, offset_span], relocation)
+    });
+    // `c.lw rd, offset(rs1)` and `c.sw rs2, offset(rs1)`.
+    let memory = choice([
+        just("c.lwsp").to(COpcode::Lwsp),
+        just("c.lw").to(COpcode::Lw),
+        just("c.swsp").to(COpcode::Swsp),
+        just("c.sw").to(COpcode::Sw),
+    ])
+    .then_ignore(required_whitespace())
+    .then(spanned(register().labelled("register")))
+    .then_ignore(comma())
+    .then(spanned(immediate().labelled("offset")))
+    .then_ignore(just('('))
+    .then(spanned(register().labelled("rs1")))
+    .then_ignore(just(')'))
+    .map(
+        move |(((opcode, (register, register_span)), (offset, offset_span)), (rs1, rs1_span))| {
+            let instruction = match opcode {
+                COpcode::Lw | COpcode::Lwsp => compressed(opcode, register, rs1, zero, offset),
+                _ => compressed(opcode, zero, rs1, register, offset),
+            };
+            (instruction, vec![register_span, offset_span, rs1_span], None)
+        },
+    );
+    let add_stack_pointer = just("c.addi4spn")
+        .to(COpcode::Addi4spn)
+        .then_ignore(required_whitespace())
+        .then(spanned(register().labelled("rd")))
+        .then_ignore(comma())
+        .then(spanned(register().labelled("rs1")))
+        .then_ignore(comma())
+        .then(spanned(immediate()))
+        .map(
+            move |(((opcode, (rd, rd_span)), (rs1, rs1_span)), (imm, imm_span))| {
+                let instruction = compressed(opcode, rd, rs1, zero, imm);
+                (instruction, vec![rd_span, rs1_span, imm_span], None)
+            },
+        );
+    let register_pair = choice([
+        just("c.sub").to(COpcode::Sub),
+        just("c.xor").to(COpcode::Xor),
+        just("c.or").to(COpcode::Or),
+        just("c.and").to(COpcode::And),
+        just("c.mv").to(COpcode::Mv),
+        just("c.add").to(COpcode::Add),
+    ])
+    .then_ignore(required_whitespace())
+    .then(spanned(register().labelled("rd")))
+    .then_ignore(comma())
+    .then(spanned(register().labelled("rs2")))
+    .map(move |((opcode, (rd, rd_span)), (rs2, rs2_span))| {
+        (compressed(opcode, rd, zero, rs2, 0), vec![rd_span, rs2_span], None)
+    });
+    let register_immediate = choice([
+        just("c.addi16sp").to(COpcode::Addi16sp),
+        just("c.addi").to(COpcode::Addi),
+        just("c.li").to(COpcode::Li),
+        just("c.lui").to(COpcode::Lui),
+        just("c.srli").to(COpcode::Srli),
+        just("c.srai").to(COpcode::Srai),
+        just("c.andi").to(COpcode::Andi),
+        just("c.slli").to(COpcode::Slli),
+    ])
+    .then_ignore(required_whitespace())
+    .then(spanned(register().labelled("rd")))
+    .then_ignore(comma())
+    .then(spanned(immediate()))
+    .map(move |((opcode, (rd, rd_span)), (imm, imm_span))| {
+        (compressed(opcode, rd, zero, zero, imm), vec![rd_span, imm_span], None)
+    });
+
+    choice((
+        no_operands,
+        jump_register,
+        jump,
+        branch,
+        memory,
+        add_stack_pointer,
+        register_immediate,
+        register_pair,
+    ))
+    .labelled("instruction")
+    .validate(|parsed: Parsed, e, emitter| {
+        if let Err(message) = compressed::check(&parsed.0) {
+            emitter.emit(Rich::custom(e.span(), message));
+        }
+        parsed
     })
 }
 
-fn u_instruction<'src>() -> impl Parser<'src, &'src str, Instruction, extra::Err<Rich<'src, char>>>
-{
+/// `li rd, imm` and `la rd, address`, the value may take all `xlen` bits, signed or unsigned.
+/// With a label `li` loads its value and `la` its address relative to the `auipc` it expands to.
+fn pseudo_load<'src>(
+    xlen: Xlen,
+) -> impl Parser<'src, &'src str, (Pseudo, Vec<SimpleSpan>), extra::Err<Rich<'src, char>>> {
+    let instr = |literal: &'static str, opcode: PseudoOpcode, kind: RelocationKind| {
+        just(literal)
+            .to(opcode)
+            .then_ignore(required_whitespace())
+            .then(spanned(register().labelled("rd")))
+            .padded_by(inline_whitespace())
+            .then_ignore(just(','))
+            .padded_by(inline_whitespace())
+            .then(spanned(choice((
+                wrapping_integer(xlen.bits())
+                    .labelled("immediate")
+                    .map(|value| Operand::Value(value as i64)),
+                label().map(move |label| Operand::Relocation(Relocation { kind, label })),
+            ))))
+    };
+
     choice([
-        just("lui").to(UOpcode::Lui),
-        just("auipc").to(UOpcode::Auipc),
+        instr("li", PseudoOpcode::Li, RelocationKind::Absolute),
+        instr("la", PseudoOpcode::La, RelocationKind::Pcrel),
     ])
     .labelled("instruction")
-    .then_ignore(whitespace().at_least(1))
-    .then(register().labelled("rd"))
-    .padded()
+    .map(move |((opcode, (rd, rd_span)), (value, value_span))| {
+        let (expansion, relocation) = match value {
+            Operand::Value(value) => match xlen {
+                Xlen::Rv32 => (pseudo::load_immediate(rd, value as i32), None),
+                Xlen::Rv64 => (pseudo::load_immediate_64(rd, value), None),
+            },
+            Operand::Relocation(relocation) => {
+                let opcode = match relocation.kind {
+                    RelocationKind::Pcrel => UOpcode::Auipc,
+                    _ => UOpcode::Lui,
+                };
+                (pseudo::load_address(opcode, rd, 0), Some(relocation))
+            }
+        };
+        let pseudo = Pseudo {
+            opcode,
+            expansion,
+            relocation,
+        };
+        (pseudo, vec![rd_span, value_span])
+    })
+}
+
+fn pseudo_register_pair<'src>(
+) -> impl Parser<'src, &'src str, (Pseudo, Vec<SimpleSpan>), extra::Err<Rich<'src, char>>> {
+    choice([
+        just("mv").to(PseudoOpcode::Mv),
+        just("not").to(PseudoOpcode::Not),
+        just("negw").to(PseudoOpcode::Negw),
+        just("neg").to(PseudoOpcode::Neg),
+        just("seqz").to(PseudoOpcode::Seqz),
+        just("snez").to(PseudoOpcode::Snez),
+        just("sltz").to(PseudoOpcode::Sltz),
+        just("sgtz").to(PseudoOpcode::Sgtz),
+        just("sext.w").to(PseudoOpcode::SextW),
+    ])
+    .labelled("instruction")
+    .then_ignore(required_whitespace())
+    .then(spanned(register().labelled("rd")))
+    .padded_by(inline_whitespace())
     .then_ignore(just(','))
-    .padded()
-    .then(integer::<i32>(20.try_into().unwrap(), true))
-    .map(|((opcode, rd), imm)| Instruction::UType {
-        opcode,
-        rd,
-        imm: UImmediate(imm),
+    .padded_by(inline_whitespace())
+    .then(spanned(register().labelled("rs")))
+    .map(|((opcode, (rd, rd_span)), (rs, rs_span))| {
+        let pseudo = Pseudo {
+            opcode,
+            expansion: pseudo::register_pair(opcode, rd, rs),
+            relocation: None,
+        };
+        (pseudo, vec![rd_span, rs_span])
     })
 }
 
-fn instruction_parser<'src>() -> impl Parser<'src, &'src str, Symbol, extra::Err<Rich<'src, char>>>
-{
-    choice((
-        i_instruction(),
+fn pseudo_branch_zero<'src>(
+) -> impl Parser<'src, &'src str, (Pseudo, Vec<SimpleSpan>), extra::Err<Rich<'src, char>>> {
+    choice([
+        just("beqz").to(PseudoOpcode::Beqz),
+        just("bnez").to(PseudoOpcode::Bnez),
+        just("blez").to(PseudoOpcode::Blez),
+        just("bgez").to(PseudoOpcode::Bgez),
+        just("bltz").to(PseudoOpcode::Bltz),
+        just("bgtz").to(PseudoOpcode::Bgtz),
+    ])
+    .labelled("instruction")
+    .then_ignore(required_whitespace())
+    .then(spanned(register().labelled("rs")))
+    .padded_by(inline_whitespace())
+    .then_ignore(just(','))
+    .padded_by(inline_whitespace())
+    .then(spanned(offset_operand(13, RelocationKind::Branch)))
+    .map(|((opcode, (rs, rs_span)), (offset, offset_span))| {
+        let (offset, relocation) = offset.split();
+        let pseudo = Pseudo {
+            opcode,
+            expansion: pseudo::branch_zero(opcode, rs, offset as i16),
+            relocation,
+        };
+        (pseudo, vec![rs_span, offset_span])
+    })
+}
+
+fn pseudo_branch_swapped<'src>(
+) -> impl Parser<'src, &'src str, (Pseudo, Vec<SimpleSpan>), extra::Err<Rich<'src, char>>> {
+    choice([
+        just("bgtu").to(PseudoOpcode::Bgtu),
+        just("bleu").to(PseudoOpcode::Bleu),
+        just("bgt").to(PseudoOpcode::Bgt),
+        just("ble").to(PseudoOpcode::Ble),
+    ])
+    .labelled("instruction")
+    .then_ignore(required_whitespace())
+    .then(spanned(register().labelled("rs")))
+    .padded_by(inline_whitespace())
+    .then_ignore(just(','))
+    .padded_by(inline_whitespace())
+    .then(spanned(register().labelled("rt")))
+    .padded_by(inline_whitespace())
+    .then_ignore(just(','))
+    .padded_by(inline_whitespace())
+    .then(spanned(offset_operand(13, RelocationKind::Branch)))
+    .map(
+        |(((opcode, (rs, rs_span)), (rt, rt_span)), (offset, offset_span))| {
+            let (offset, relocation) = offset.split();
+            let pseudo = Pseudo {
+                opcode,
+                expansion: pseudo::branch_swapped(opcode, rs, rt, offset as i16),
+                relocation,
+            };
+            (pseudo, vec![rs_span, rt_span, offset_span])
+        },
+    )
+}
+
+/// `j` and `jal` reach 1 MiB in each direction, `call` and `tail` the whole address space.
+fn pseudo_jump<'src>(
+) -> impl Parser<'src, &'src str, (Pseudo, Vec<SimpleSpan>), extra::Err<Rich<'src, char>>> {
+    let near = choice([
+        just("jal").to(PseudoOpcode::Jal),
+        just("j").to(PseudoOpcode::J),
+    ])
+    .then_ignore(required_whitespace())
+    .then(spanned(offset_operand(21, RelocationKind::Jump)));
+    let far = choice([
+        just("call").to(PseudoOpcode::Call),
+        just("tail").to(PseudoOpcode::Tail),
+    ])
+    .then_ignore(required_whitespace())
+    .then(spanned(offset_operand(32, RelocationKind::Pcrel)));
+
+    choice((near, far))
+        .labelled("instruction")
+        .map(|(opcode, (offset, offset_span))| {
+            let (offset, relocation) = offset.split();
+            let pseudo = Pseudo {
+                opcode,
+                expansion: pseudo::jump(opcode, offset),
+                relocation,
+            };
+            (pseudo, vec![offset_span])
+        })
+}
+
+fn pseudo_jump_register<'src>(
+) -> impl Parser<'src, &'src str, (Pseudo, Vec<SimpleSpan>), extra::Err<Rich<'src, char>>> {
+    choice([
+        just("jalr").to(PseudoOpcode::Jalr),
+        just("jr").to(PseudoOpcode::Jr),
+    ])
+    .labelled("instruction")
+    .then_ignore(required_whitespace())
+    .then(spanned(register().labelled("rs")))
+    .map(|(opcode, (rs, rs_span))| {
+        let pseudo = Pseudo {
+            opcode,
+            expansion: pseudo::jump_register(opcode, rs),
+            relocation: None,
+        };
+        (pseudo, vec![rs_span])
+    })
+}
+
+fn pseudo_no_operands<'src>(
+) -> impl Parser<'src, &'src str, (Pseudo, Vec<SimpleSpan>), extra::Err<Rich<'src, char>>> {
+    choice([
+        just("nop").to(PseudoOpcode::Nop),
+        just("ret").to(PseudoOpcode::Ret),
+    ])
+    .labelled("instruction")
+    .map(|opcode| {
+        let expansion = match opcode {
+            PseudoOpcode::Ret => pseudo::jump_register(opcode, Register::from(1)),
+            _ => pseudo::nop(),
+        };
+        let pseudo = Pseudo {
+            opcode,
+            expansion,
+            relocation: None,
+        };
+        (pseudo, vec![])
+    })
+}
+
+/// `csrr rd, csr` reads a CSR, `csrw csr, rs` and `csrwi csr, uimm` write, set or clear its bits.
+fn pseudo_csr<'src>(
+) -> impl Parser<'src, &'src str, (Pseudo, Vec<SimpleSpan>), extra::Err<Rich<'src, char>>> {
+    let comma = || just(',').padded_by(inline_whitespace());
+    let pseudo = |opcode, expansion| Pseudo {
+        opcode,
+        expansion,
+        relocation: None,
+    };
+
+    let read = just("csrr")
+        .to(PseudoOpcode::Csrr)
+        .then_ignore(required_whitespace())
+        .then(spanned(register().labelled("rd")))
+        .then_ignore(comma())
+        .then(spanned(csr()))
+        .map(move |((opcode, (rd, rd_span)), (csr, csr_span))| {
+            let expansion = pseudo::csr_read(rd, csr);
+            (pseudo(opcode, expansion), vec![rd_span, csr_span])
+        });
+    let write_immediate = choice([
+        just("csrwi").to(PseudoOpcode::Csrwi),
+        just("csrsi").to(PseudoOpcode::Csrsi),
+        just("csrci").to(PseudoOpcode::Csrci),
+    ])
+    .then_ignore(required_whitespace())
+    .then(spanned(csr()))
+    .then_ignore(comma())
+    .then(spanned(csr_immediate()))
+    .map(move |((opcode, (csr, csr_span)), (imm, imm_span))| {
+        let expansion = pseudo::csr_write_immediate(opcode, csr, imm);
+        (pseudo(opcode, expansion), vec![csr_span, imm_span])
+    });
+    let write = choice([
+        just("csrw").to(PseudoOpcode::Csrw),
+        just("csrs").to(PseudoOpcode::Csrs),
+        just("csrc").to(PseudoOpcode::Csrc),
+    ])
+    .then_ignore(required_whitespace())
+    .then(spanned(csr()))
+    .then_ignore(comma())
+    .then(spanned(register().labelled("rs")))
+    .map(move |((opcode, (csr, csr_span)), (rs, rs_span))| {
+        let expansion = pseudo::csr_write(opcode, csr, rs);
+        (pseudo(opcode, expansion), vec![csr_span, rs_span])
+    });
+
+    choice((read, write_immediate, write)).labelled("instruction")
+}
+
+/// `fmv.s rd, rs`, `fneg.s` and `fabs.s` and their doubles, `frcsr rd` reads `fcsr` and
+/// `fscsr rd, rs` swaps it, where `rd` may be left out. Likewise for `frm` and `fflags`.
+fn pseudo_float<'src>(
+) -> impl Parser<'src, &'src str, (Pseudo, Vec<SimpleSpan>), extra::Err<Rich<'src, char>>> {
+    let comma = || just(',').padded_by(inline_whitespace());
+    let pseudo = |opcode, expansion| Pseudo {
+        opcode,
+        expansion,
+        relocation: None,
+    };
+
+    let sign = choice([
+        just("fmv.s").to(PseudoOpcode::FmvS),
+        just("fmv.d").to(PseudoOpcode::FmvD),
+        just("fneg.s").to(PseudoOpcode::FnegS),
+        just("fneg.d").to(PseudoOpcode::FnegD),
+        just("fabs.s").to(PseudoOpcode::FabsS),
+        just("fabs.d").to(PseudoOpcode::FabsD),
+    ])
+    .then_ignore(required_whitespace())
+    .then(spanned(f_register().labelled("rd")))
+    .then_ignore(comma())
+    .then(spanned(f_register().labelled("rs")))
+    .map(move |((opcode, (rd, rd_span)), (rs, rs_span))| {
+        let expansion = pseudo::float_sign(opcode, rd, rs);
+        (pseudo(opcode, expansion), vec![rd_span, rs_span])
+    });
+    let read = choice([
+        just("frcsr").to(PseudoOpcode::Frcsr),
+        just("frrm").to(PseudoOpcode::Frrm),
+        just("frflags").to(PseudoOpcode::Frflags),
+    ])
+    .then_ignore(required_whitespace())
+    .then(spanned(register().labelled("rd")))
+    .map(move |(opcode, (rd, rd_span))| {
+        let expansion = pseudo::float_csr(opcode, rd, Register::from(0));
+        (pseudo(opcode, expansion), vec![rd_span])
+    });
+    let write = choice([
+        just("fscsr").to(PseudoOpcode::Fscsr),
+        just("fsrm").to(PseudoOpcode::Fsrm),
+        just("fsflags").to(PseudoOpcode::Fsflags),
+    ])
+    .then_ignore(required_whitespace())
+    .then(
+        spanned(register().labelled("rd"))
+            .then_ignore(comma())
+            .or_not(),
+    )
+    .then(spanned(register().labelled("rs")))
+    .map(move |((opcode, rd), (rs, rs_span))| {
+        let (rd, operands) = match rd {
+            Some((rd, rd_span)) => (rd, vec![rd_span, rs_span]),
+            None => (Register::from(0), vec![rs_span]),
+        };
+        let expansion = pseudo::float_csr(opcode, rd, rs);
+        (pseudo(opcode, expansion), operands)
+    });
+
+    choice((sign, read, write)).labelled("instruction")
+}
+
+fn instruction_parser<'src>(
+    isa: Isa,
+) -> impl Parser<'src, &'src str, Spanned<Symbol>, extra::Err<Rich<'src, char>>> {
+    let instruction = choice((
+        i_instruction(isa.xlen),
         r_instruction(),
         s_instruction(),
         l_instruction(),
         b_instruction(),
         u_instruction(),
+        jalr_instruction(),
+        j_instruction(),
+        fence_instruction(),
+        system_instruction(),
+        atomic_instruction(),
+        csr_instruction(),
+        float_memory_instruction(),
+        float_instruction().boxed(),
+        compressed_instruction(),
     ))
-    .map(Symbol::from)
+    .map(|(instruction, operands, relocation)| match relocation {
+        Some(relocation) => {
+            let reference = Reference {
+                instruction,
+                relocation,
+            };
+            (Symbol::from(reference), operands)
+        }
+        None => (Symbol::from(instruction), operands),
+    })
+    // Boxed, with every alternative inlined a failing line overflows the stack of a thread.
+    .boxed();
+    // Tried last, `jal` and `jalr` take fewer operands as pseudo-instructions.
+    let pseudo = choice((
+        pseudo_load(isa.xlen),
+        pseudo_register_pair(),
+        pseudo_branch_zero(),
+        pseudo_branch_swapped(),
+        pseudo_jump(),
+        pseudo_jump_register(),
+        pseudo_no_operands(),
+        pseudo_csr(),
+        pseudo_float(),
+    ))
+    .map(|(pseudo, operands)| (Symbol::from(pseudo), operands))
+    .boxed();
+
+    choice((instruction, pseudo))
+        .map_with(|(node, operands), e| Spanned {
+            node,
+            span: e.span(),
+            operands,
+        })
+        .validate(move |symbol, _, emitter| {
+            let base = symbol.node.instructions().iter().find_map(|instruction| {
+                let xlen = isa.missing_base(instruction)?;
+                Some((instruction.mnemonic(), xlen))
+            });
+            if let Some((mnemonic, xlen)) = base {
+                emitter.emit(Rich::custom(
+                    symbol.span,
+                    format!("`{}` only exists in {}, {} is not", mnemonic, xlen, isa),
+                ));
+                return symbol;
+            }
+            let missing = symbol.node.instructions().iter().find_map(|instruction| {
+                let extension = isa.missing_extension(instruction)?;
+                Some((instruction.mnemonic(), extension))
+            });
+            if let Some((mnemonic, extension)) = missing {
+                emitter.emit(Rich::custom(
+                    symbol.span,
+                    format!(
+                        "`{}` needs the {} extension, which {} does not include",
+                        mnemonic, extension, isa
+                    ),
+                ));
+            }
+            symbol
+        })
 }
 
-pub fn parse_riscv<'src>(assembly: &'src str) -> Result<Program, Vec<Rich<'src, char>>> {
-    let parser = choice([instruction_parser()])
+/// A string in double quotes with the escapes of C, as UTF-8 bytes.
+fn string<'src>() -> impl Parser<'src, &'src str, Vec<u8>, extra::Err<Rich<'src, char>>> {
+    let escape = just('\\').ignore_then(choice((
+        just('n').to(b'\n'),
+        just('t').to(b'\t'),
+        just('r').to(b'\r'),
+        just('0').to(b'\0'),
+        just('\\').to(b'\\'),
+        just('"').to(b'"'),
+        just('\'').to(b'\''),
+        just('x').ignore_then(
+            text::digits(16)
+                .exactly(2)
+                .to_slice()
+                .map(|hex: &str| u8::from_str_radix(hex, 16).unwrap()),
+        ),
+    )));
+    let character = none_of("\\\"\r\n").map(|c: char| c.to_string().into_bytes());
+    choice((escape.map(|byte| vec![byte]), character))
+        .repeated()
+        .collect::<Vec<Vec<u8>>>()
+        .map(|bytes| bytes.concat())
+        .delimited_by(just('"'), just('"'))
+        .labelled("string")
+}
+
+/// Comma separated operands of a directive, at least one.
+fn operand_list<'src, O>(
+    operand: impl Parser<'src, &'src str, O, extra::Err<Rich<'src, char>>>,
+) -> impl Parser<'src, &'src str, Vec<(O, SimpleSpan)>, extra::Err<Rich<'src, char>>> {
+    spanned(operand)
+        .separated_by(just(',').padded_by(inline_whitespace()))
+        .at_least(1)
+        .collect()
+}
+
+fn section_directive<'src>(
+) -> impl Parser<'src, &'src str, (Directive, Vec<SimpleSpan>), extra::Err<Rich<'src, char>>> {
+    let named = just(".section")
+        .ignore_then(required_whitespace())
+        .ignore_then(spanned(identifier().labelled("section name")))
+        // The flags and type compilers emit after the name make no difference here.
+        .then_ignore(
+            inline_whitespace()
+                .then(just(','))
+                .then(any().and_is(newline().not()).repeated())
+                .or_not(),
+        )
+        .map(|(name, span)| (name, vec![span]));
+    choice((
+        just(".text").map(|name| (name, vec![])),
+        just(".data").map(|name| (name, vec![])),
+        just(".bss").map(|name| (name, vec![])),
+        named,
+    ))
+    .map(|(name, operands)| (Directive::Section(name.to_string()), operands))
+}
+
+fn data_directive<'src>(
+) -> impl Parser<'src, &'src str, (Directive, Vec<SimpleSpan>), extra::Err<Rich<'src, char>>> {
+    let numbers = |literal: &'static str, width: DataWidth| {
+        just(literal)
+            .to(width)
+            .then_ignore(required_whitespace())
+            .then(operand_list(
+                wrapping_integer(8 * width.bytes() as u32)
+                    .labelled("value")
+                    .map(|value| DataValue::Number(value as u32)),
+            ))
+    };
+    let words = just(".word")
+        .to(DataWidth::Word)
+        .then_ignore(required_whitespace())
+        .then(operand_list(choice((
+            wrapping_integer(32)
+                .labelled("value")
+                .map(|value| DataValue::Number(value as u32)),
+            label().map(DataValue::Label),
+        ))));
+
+    choice((
+        words,
+        numbers(".half", DataWidth::Half),
+        numbers(".byte", DataWidth::Byte),
+    ))
+    .map(|(width, values)| {
+        let (values, operands) = values.into_iter().unzip();
+        (Directive::Data(Data { width, values }), operands)
+    })
+}
+
+fn bytes_directive<'src>(
+) -> impl Parser<'src, &'src str, (Directive, Vec<SimpleSpan>), extra::Err<Rich<'src, char>>> {
+    let strings = choice((
+        just(".asciz").to(true),
+        just(".ascii").to(false),
+        just(".string").to(true),
+    ))
+    .then_ignore(required_whitespace())
+    .then(operand_list(string()))
+    .map(|(terminated, strings)| {
+        let mut bytes = vec![];
+        let mut operands = vec![];
+        for (string, span) in strings {
+            bytes.extend(string);
+            if terminated {
+                bytes.push(0);
+            }
+            operands.push(span);
+        }
+        (bytes, operands)
+    });
+    // `.space size, fill`, the fill byte defaults to zero.
+    let space = choice((just(".space"), just(".zero")))
+        .ignore_then(required_whitespace())
+        .ignore_then(spanned(
+            integer::<u32>(24.try_into().unwrap(), false).labelled("size"),
+        ))
+        .then(
+            just(',')
+                .padded_by(inline_whitespace())
+                .ignore_then(spanned(wrapping_integer(8).labelled("fill")))
+                .or_not(),
+        )
+        .map(|((size, size_span), fill)| match fill {
+            Some((fill, fill_span)) => {
+                (vec![fill as u8; size as usize], vec![size_span, fill_span])
+            }
+            None => (vec![0; size as usize], vec![size_span]),
+        });
+
+    choice((strings, space)).map(|(bytes, operands)| (Directive::Bytes(bytes), operands))
+}
+
+fn symbol_directive<'src>(
+) -> impl Parser<'src, &'src str, (Directive, Vec<SimpleSpan>), extra::Err<Rich<'src, char>>> {
+    let align = choice((just(".p2align"), just(".align")))
+        .ignore_then(required_whitespace())
+        .ignore_then(spanned(
+            integer::<u8>(4.try_into().unwrap(), false).labelled("alignment"),
+        ))
+        .map(|(power, span)| {
+            let alignment = Alignment {
+                alignment: 1 << power,
+            };
+            (Directive::Alignment(alignment), vec![span])
+        });
+    let global = choice((just(".globl"), just(".global")))
+        .ignore_then(required_whitespace())
+        .ignore_then(label())
+        .map(|label| {
+            let span = label.span;
+            (Directive::Global(label), vec![span])
+        });
+    let constant = choice((just(".equ"), just(".set")))
+        .ignore_then(required_whitespace())
+        .ignore_then(label())
+        .then_ignore(just(',').padded_by(inline_whitespace()))
+        .then(spanned(wrapping_integer(32).labelled("value")))
+        .map(|(label, (value, value_span))| {
+            let value = value as u32;
+            let operands = vec![label.span, value_span];
+            (Directive::Constant(Constant { label, value }), operands)
+        });
+
+    choice((align, global, constant))
+}
+
+fn directive_parser<'src>(
+) -> impl Parser<'src, &'src str, Spanned<Symbol>, extra::Err<Rich<'src, char>>> {
+    choice((
+        section_directive(),
+        data_directive(),
+        bytes_directive(),
+        symbol_directive(),
+    ))
+    .labelled("directive")
+    .map_with(|(directive, operands), e| Spanned {
+        node: Symbol::Directive(directive),
+        span: e.span(),
+        operands,
+    })
+}
+
+/// `# comment` and `// comment`, up to the end of the line.
+fn comment<'src>() -> impl Parser<'src, &'src str, Spanned<Symbol>, extra::Err<Rich<'src, char>>> {
+    choice((just("#"), just("//")))
+        .ignore_then(any().and_is(newline().not()).repeated().to_slice())
+        .map_with(|text: &str, e| Spanned::new(Symbol::Comment(text.to_string()), e.span()))
+}
+
+fn label_definition<'src>(
+) -> impl Parser<'src, &'src str, Spanned<Symbol>, extra::Err<Rich<'src, char>>> {
+    // A missing colon fails at the start of the word, so that a misspelled mnemonic is not
+    // reported as a label without its colon.
+    label()
+        .then(just(':').or_not())
+        .try_map(|(label, colon), span| match colon {
+            Some(_) => Ok(Spanned::new(Symbol::Label(label), span)),
+            None => Err(Rich::custom(span, "expected `:` after label")),
+        })
+}
+
+/// Parses as much of `assembly` as possible, a line which does not parse is left out of the
+/// program and its error is reported alongside the errors of all other lines.
+pub fn parse_riscv_partial<'src>(assembly: &'src str) -> (Program, Vec<Rich<'src, char>>) {
+    parse_riscv_partial_with(assembly, Isa::default())
+}
+
+/// Like `parse_riscv_partial`, an instruction of an extension `isa` does not include is an error.
+pub fn parse_riscv_partial_with<'src>(
+    assembly: &'src str,
+    isa: Isa,
+) -> (Program, Vec<Rich<'src, char>>) {
+    // An empty statement is one of the alternatives instead of using `.or_not()`, which would
+    // swallow the error of a statement failing halfway through.
+    let statement = choice((
+        instruction_parser(isa).map(Some),
+        directive_parser().map(Some),
+        comment().map(Some),
+        end_of_line().map(|()| None),
+    ));
+    let line = label_definition()
+        .then_ignore(inline_whitespace())
         .or_not()
+        .then(statement)
+        .then_ignore(inline_whitespace())
+        .then(comment().or_not())
         .padded_by(inline_whitespace())
+        .then_ignore(end_of_line())
+        .map(|((label, symbol), comment)| {
+            label
+                .into_iter()
+                .chain(symbol)
+                .chain(comment)
+                .collect::<Vec<Spanned<Symbol>>>()
+        })
+        .recover_with(via_parser(skip_line().map(|()| vec![])));
+
+    let parser = line
         .separated_by(newline())
         .allow_leading()
         .allow_trailing()
         // TODO: Once .flatten() is implemented for ItemParser, use that.
-        .collect::<Vec<Option<Symbol>>>();
+        .collect::<Vec<Vec<Spanned<Symbol>>>>();
 
-    let symbols = parser
-        .parse(assembly)
-        .into_result()?
-        .into_iter()
-        .flatten()
-        .collect();
+    let (lines, errors) = parser.parse(assembly).into_output_errors();
+    let symbols = lines.into_iter().flatten().flatten().collect();
+    (Program { symbols }, errors)
+}
 
-    Ok(Program { symbols })
+/// Parses `assembly` for the base instruction set `rv32i`.
+pub fn parse_riscv<'src>(assembly: &'src str) -> Result<Program, Vec<Rich<'src, char>>> {
+    parse_riscv_with(assembly, Isa::default())
+}
+
+pub fn parse_riscv_with<'src>(
+    assembly: &'src str,
+    isa: Isa,
+) -> Result<Program, Vec<Rich<'src, char>>> {
+    match parse_riscv_partial_with(assembly, isa) {
+        (program, errors) if errors.is_empty() => Ok(program),
+        (_, errors) => Err(errors),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::riscv::encoding::encode;
+    use rstest::rstest;
 
     #[test]
     fn test_parse_single_instruction() {
@@ -284,7 +2008,7 @@ mod tests {
             rd,
             rs1,
             imm,
-        }) = &program.symbols[0]
+        }) = &program.symbols[0].node
         {
             assert!(matches!(opcode, IOpcode::Addi));
             assert_eq!(*rd, Register::from(1));
@@ -295,20 +2019,51 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_recovers_after_bad_lines() {
+        let input = "addi x1, x2, 5\nlui x1\n  add x1, x2, q3\nsub x1, x2, x3\nbeq x1, x2, 3";
+        let (program, errors) = parse_riscv_partial(input);
+        assert_eq!(errors.len(), 3);
+        assert_eq!(errors[0].span().start, input.find("\n  add").unwrap());
+        assert_eq!(&input[errors[1].span().into_range()], "q");
+        assert_eq!(&input[errors[2].span().into_range()], "3");
+        // The odd branch offset is reported while the branch itself still parses.
+        assert_eq!(program.symbols.len(), 3);
+    }
+
+    #[test]
+    fn test_parse_spans() {
+        let input = "addi x1, x2, 5\n  sw ra, -4(sp)";
+        let program = parse_riscv(input).unwrap();
+        let text = |span: SimpleSpan| &input[span.into_range()];
+        assert_eq!(text(program.symbols[0].span), "addi x1, x2, 5");
+        assert_eq!(text(program.symbols[1].span), "sw ra, -4(sp)");
+        let operands: Vec<_> = program.symbols[1]
+            .operands
+            .iter()
+            .map(|&s| text(s))
+            .collect();
+        assert_eq!(operands, ["ra", "-4", "sp"]);
+    }
+
     #[test]
     fn test_i_instruction_addi() {
         let input = "addi x1, x2, 5";
-        let result = i_instruction().parse(input);
+        let result = i_instruction(Xlen::Rv32).parse(input);
         for error in result.errors() {
             println!("{}", error);
         }
         assert!(!result.has_errors());
-        let Instruction::IType {
-            opcode,
-            rd,
-            rs1,
-            imm,
-        } = result.output().unwrap()
+        let (
+            Instruction::IType {
+                opcode,
+                rd,
+                rs1,
+                imm,
+            },
+            _,
+            _,
+        ) = result.output().unwrap()
         else {
             panic!("Unexpected instruction.")
         };
@@ -321,17 +2076,21 @@ mod tests {
     #[test]
     fn test_i_instruction_slli() {
         let input = "slli x3, x4, 2";
-        let result = i_instruction().parse(input);
+        let result = i_instruction(Xlen::Rv32).parse(input);
         for error in result.errors() {
             println!("{}", error);
         }
         assert!(!result.has_errors());
-        let Instruction::IType {
-            opcode,
-            rd,
-            rs1,
-            imm,
-        } = result.output().unwrap()
+        let (
+            Instruction::IType {
+                opcode,
+                rd,
+                rs1,
+                imm,
+            },
+            _,
+            _,
+        ) = result.output().unwrap()
         else {
             panic!("Unexpected instruction.")
         };
@@ -344,17 +2103,21 @@ mod tests {
     #[test]
     fn test_i_instruction_negative_immediate() {
         let input = "xori x5, x6, -1";
-        let result = i_instruction().parse(input);
+        let result = i_instruction(Xlen::Rv32).parse(input);
         for error in result.errors() {
             println!("{}", error);
         }
         assert!(!result.has_errors());
-        let Instruction::IType {
-            opcode,
-            rd,
-            rs1,
-            imm,
-        } = result.output().unwrap()
+        let (
+            Instruction::IType {
+                opcode,
+                rd,
+                rs1,
+                imm,
+            },
+            _,
+            _,
+        ) = result.output().unwrap()
         else {
             panic!("Unexpected instruction.")
         };
@@ -368,7 +2131,7 @@ mod tests {
     #[test]
     fn test_i_instruction_invalid_format() {
         let input = "addi x1, x2";
-        let result = i_instruction().parse(input);
+        let result = i_instruction(Xlen::Rv32).parse(input);
         assert!(result.has_errors());
     }
 
@@ -380,12 +2143,16 @@ mod tests {
             println!("{}", error);
         }
         assert!(!result.has_errors());
-        let Instruction::RType {
-            opcode,
-            rd,
-            rs1,
-            rs2,
-        } = result.output().unwrap()
+        let (
+            Instruction::RType {
+                opcode,
+                rd,
+                rs1,
+                rs2,
+            },
+            _,
+            _,
+        ) = result.output().unwrap()
         else {
             panic!("Unexpected instruction.")
         };
@@ -403,12 +2170,16 @@ mod tests {
             println!("{}", error);
         }
         assert!(!result.has_errors());
-        let Instruction::RType {
-            opcode,
-            rd,
-            rs1,
-            rs2,
-        } = result.output().unwrap()
+        let (
+            Instruction::RType {
+                opcode,
+                rd,
+                rs1,
+                rs2,
+            },
+            _,
+            _,
+        ) = result.output().unwrap()
         else {
             panic!("Unexpected instruction.")
         };
@@ -433,12 +2204,16 @@ mod tests {
             println!("{}", error);
         }
         assert!(!result.has_errors());
-        let Instruction::SType {
-            opcode,
-            rs1,
-            rs2,
-            imm,
-        } = result.output().unwrap()
+        let (
+            Instruction::SType {
+                opcode,
+                rs1,
+                rs2,
+                imm,
+            },
+            _,
+            _,
+        ) = result.output().unwrap()
         else {
             panic!("Unexpected instruction.")
         };
@@ -456,12 +2231,16 @@ mod tests {
             println!("{}", error);
         }
         assert!(!result.has_errors());
-        let Instruction::SType {
-            opcode,
-            rs1,
-            rs2,
-            imm,
-        } = result.output().unwrap()
+        let (
+            Instruction::SType {
+                opcode,
+                rs1,
+                rs2,
+                imm,
+            },
+            _,
+            _,
+        ) = result.output().unwrap()
         else {
             panic!("Unexpected instruction.")
         };
@@ -486,7 +2265,7 @@ mod tests {
             println!("{}", error);
         }
         assert!(!result.has_errors());
-        let Instruction::UType { opcode, rd, imm } = result.output().unwrap() else {
+        let (Instruction::UType { opcode, rd, imm }, _, _) = result.output().unwrap() else {
             panic!("Unexpected instruction.")
         };
         assert!(matches!(opcode, UOpcode::Lui));
@@ -502,7 +2281,7 @@ mod tests {
             println!("{}", error);
         }
         assert!(!result.has_errors());
-        let Instruction::UType { opcode, rd, imm } = result.output().unwrap() else {
+        let (Instruction::UType { opcode, rd, imm }, _, _) = result.output().unwrap() else {
             panic!("Unexpected instruction.")
         };
         assert!(matches!(opcode, UOpcode::Auipc));
@@ -532,7 +2311,7 @@ mod tests {
             println!("{}", error);
         }
         assert!(!result.has_errors());
-        let Instruction::UType { opcode, rd, imm } = result.output().unwrap() else {
+        let (Instruction::UType { opcode, rd, imm }, _, _) = result.output().unwrap() else {
             panic!("Unexpected instruction.")
         };
         assert!(matches!(opcode, UOpcode::Lui));
@@ -548,12 +2327,16 @@ mod tests {
             println!("{}", error);
         }
         assert!(!result.has_errors());
-        let Instruction::LType {
-            opcode,
-            rd,
-            rs1,
-            imm,
-        } = result.output().unwrap()
+        let (
+            Instruction::LType {
+                opcode,
+                rd,
+                rs1,
+                imm,
+            },
+            _,
+            _,
+        ) = result.output().unwrap()
         else {
             panic!("Unexpected instruction.")
         };
@@ -571,12 +2354,16 @@ mod tests {
             println!("{}", error);
         }
         assert!(!result.has_errors());
-        let Instruction::LType {
-            opcode,
-            rd,
-            rs1,
-            imm,
-        } = result.output().unwrap()
+        let (
+            Instruction::LType {
+                opcode,
+                rd,
+                rs1,
+                imm,
+            },
+            _,
+            _,
+        ) = result.output().unwrap()
         else {
             panic!("Unexpected instruction.")
         };
@@ -594,12 +2381,16 @@ mod tests {
             println!("{}", error);
         }
         assert!(!result.has_errors());
-        let Instruction::LType {
-            opcode,
-            rd,
-            rs1,
-            imm,
-        } = result.output().unwrap()
+        let (
+            Instruction::LType {
+                opcode,
+                rd,
+                rs1,
+                imm,
+            },
+            _,
+            _,
+        ) = result.output().unwrap()
         else {
             panic!("Unexpected instruction.")
         };
@@ -617,12 +2408,16 @@ mod tests {
             println!("{}", error);
         }
         assert!(!result.has_errors());
-        let Instruction::LType {
-            opcode,
-            rd,
-            rs1,
-            imm,
-        } = result.output().unwrap()
+        let (
+            Instruction::LType {
+                opcode,
+                rd,
+                rs1,
+                imm,
+            },
+            _,
+            _,
+        ) = result.output().unwrap()
         else {
             panic!("Unexpected instruction.")
         };
@@ -647,12 +2442,16 @@ mod tests {
             println!("{}", error);
         }
         assert!(!result.has_errors());
-        let Instruction::BType {
-            opcode,
-            rs1,
-            rs2,
-            imm,
-        } = result.output().unwrap()
+        let (
+            Instruction::BType {
+                opcode,
+                rs1,
+                rs2,
+                imm,
+            },
+            _,
+            _,
+        ) = result.output().unwrap()
         else {
             panic!("Unexpected instruction.")
         };
@@ -670,12 +2469,16 @@ mod tests {
             println!("{}", error);
         }
         assert!(!result.has_errors());
-        let Instruction::BType {
-            opcode,
-            rs1,
-            rs2,
-            imm,
-        } = result.output().unwrap()
+        let (
+            Instruction::BType {
+                opcode,
+                rs1,
+                rs2,
+                imm,
+            },
+            _,
+            _,
+        ) = result.output().unwrap()
         else {
             panic!("Unexpected instruction.")
         };
@@ -685,6 +2488,413 @@ mod tests {
         assert_eq!(*imm, BImmediate(-8));
     }
 
+    #[test]
+    fn test_j_instruction_jal() {
+        let result = j_instruction().parse("jal ra, -2048");
+        assert!(!result.has_errors());
+        let (Instruction::JType { opcode, rd, imm }, _, _) = result.output().unwrap() else {
+            panic!("Unexpected instruction.")
+        };
+        assert!(matches!(opcode, JOpcode::Jal));
+        assert_eq!(*rd, Register::from(1));
+        assert_eq!(*imm, JImmediate(-2048));
+    }
+
+    #[test]
+    fn test_j_instruction_out_of_range() {
+        assert!(j_instruction().parse("jal ra, 1048576").has_errors());
+        assert!(j_instruction().parse("jal ra, -1048578").has_errors());
+        assert!(j_instruction().parse("jal ra, -7").has_errors());
+    }
+
+    #[test]
+    fn test_jalr_instruction_forms() {
+        let expected = Instruction::IType {
+            opcode: IOpcode::Jalr,
+            rd: Register::from(1),
+            rs1: Register::from(5),
+            imm: IImmediate(-4),
+        };
+        let result = jalr_instruction().parse("jalr ra, -4(t0)").into_result();
+        let (instruction, operands, _) = result.unwrap();
+        assert_eq!(instruction, expected);
+        assert_eq!(operands, [(5..7).into(), (9..11).into(), (12..14).into()]);
+        let result = jalr_instruction().parse("jalr ra, t0, -4").into_result();
+        let (instruction, operands, _) = result.unwrap();
+        assert_eq!(instruction, expected);
+        assert_eq!(operands, [(5..7).into(), (9..11).into(), (13..15).into()]);
+    }
+
+    #[test]
+    fn test_fence_instruction() {
+        let (instruction, _, _) = fence_instruction().parse("fence").into_result().unwrap();
+        assert_eq!(
+            instruction,
+            Instruction::Fence {
+                pred: FenceSet::ALL,
+                succ: FenceSet::ALL
+            }
+        );
+        let (instruction, _, _) = fence_instruction()
+            .parse("fence ow, ir")
+            .into_result()
+            .unwrap();
+        assert_eq!(
+            instruction,
+            Instruction::Fence {
+                pred: FenceSet(FenceSet::OUTPUT | FenceSet::WRITE),
+                succ: FenceSet(FenceSet::INPUT | FenceSet::READ)
+            }
+        );
+    }
+
+    fn expansion(input: &str) -> (PseudoOpcode, Vec<u32>) {
+        let program = parse_riscv(input).unwrap();
+        let Symbol::Pseudo(pseudo) = &program.symbols[0].node else {
+            panic!("{} is not a pseudo-instruction", input);
+        };
+        let words = program.symbols[0]
+            .node
+            .instructions()
+            .iter()
+            .map(encode)
+            .collect();
+        (pseudo.opcode, words)
+    }
+
+    #[rstest]
+    #[case("nop", PseudoOpcode::Nop, &[0x0000_0013])]
+    #[case("li a0, -5", PseudoOpcode::Li, &[0xFFB0_0513])]
+    #[case("li a0, 0x12345FFF", PseudoOpcode::Li, &[0x1234_6537, 0xFFF5_0513])]
+    #[case("li a0, 0xFFFFF800", PseudoOpcode::Li, &[0x8000_0513])]
+    #[case("la a0, 0x2000", PseudoOpcode::La, &[0x0000_2537])]
+    #[case("mv a0, a1", PseudoOpcode::Mv, &[0x0005_8513])]
+    #[case("not a0, a1", PseudoOpcode::Not, &[0xFFF5_C513])]
+    #[case("neg a0, a1", PseudoOpcode::Neg, &[0x40B0_0533])]
+    #[case("seqz a0, a1", PseudoOpcode::Seqz, &[0x0015_B513])]
+    #[case("snez a0, a1", PseudoOpcode::Snez, &[0x00B0_3533])]
+    #[case("sltz a0, a1", PseudoOpcode::Sltz, &[0x0005_A533])]
+    #[case("sgtz a0, a1", PseudoOpcode::Sgtz, &[0x00B0_2533])]
+    #[case("beqz a0, 8", PseudoOpcode::Beqz, &[0x0005_0463])]
+    #[case("bnez a0, -8", PseudoOpcode::Bnez, &[0xFE05_1CE3])]
+    #[case("blez a0, 8", PseudoOpcode::Blez, &[0x00A0_5463])]
+    #[case("bgtz a0, 8", PseudoOpcode::Bgtz, &[0x00A0_4463])]
+    #[case("bgt a0, a1, 8", PseudoOpcode::Bgt, &[0x00A5_C463])]
+    #[case("bleu a0, a1, 8", PseudoOpcode::Bleu, &[0x00A5_F463])]
+    #[case("j -4", PseudoOpcode::J, &[0xFFDF_F06F])]
+    #[case("jal 8", PseudoOpcode::Jal, &[0x0080_00EF])]
+    #[case("jr t0", PseudoOpcode::Jr, &[0x0002_8067])]
+    #[case("jalr t0", PseudoOpcode::Jalr, &[0x0002_80E7])]
+    #[case("ret", PseudoOpcode::Ret, &[0x0000_8067])]
+    #[case("call 0x1800", PseudoOpcode::Call, &[0x0000_2097, 0x8000_80E7])]
+    #[case("tail 0x10", PseudoOpcode::Tail, &[0x0000_0317, 0x0103_0067])]
+    fn test_pseudo_expansion(
+        #[case] input: &str,
+        #[case] opcode: PseudoOpcode,
+        #[case] words: &[u32],
+    ) {
+        assert_eq!(expansion(input), (opcode, words.to_vec()));
+    }
+
+    #[test]
+    fn test_pseudo_operand_spans() {
+        let input = "bgt a0, a1, -8\nret";
+        let program = parse_riscv(input).unwrap();
+        let text = |span: SimpleSpan| &input[span.into_range()];
+        let operands: Vec<_> = program.symbols[0]
+            .operands
+            .iter()
+            .map(|&s| text(s))
+            .collect();
+        assert_eq!(operands, ["a0", "a1", "-8"]);
+        assert_eq!(text(program.symbols[1].span), "ret");
+        assert!(program.symbols[1].operands.is_empty());
+    }
+
+    #[test]
+    fn test_label_definition() {
+        let input = "start:\n  loop: addi a0, a0, -1\nbnez a0, loop";
+        let program = parse_riscv(input).unwrap();
+        assert_eq!(program.symbols.len(), 4);
+        let Symbol::Label(label) = &program.symbols[1].node else {
+            panic!("expected a label, got {:?}", program.symbols[1].node);
+        };
+        assert_eq!(label.name, "loop");
+        assert_eq!(&input[label.span.into_range()], "loop");
+        assert_eq!(&input[program.symbols[1].span.into_range()], "loop:");
+        assert!(matches!(program.symbols[2].node, Symbol::Instruction(_)));
+        let Symbol::Pseudo(pseudo) = &program.symbols[3].node else {
+            panic!("expected a pseudo-instruction");
+        };
+        let relocation = pseudo.relocation.as_ref().unwrap();
+        assert_eq!(relocation.kind, RelocationKind::Branch);
+        assert_eq!(&input[relocation.label.span.into_range()], "loop");
+    }
+
+    #[rstest]
+    #[case("beq a0, a1, loop", RelocationKind::Branch, "loop")]
+    #[case("jal ra, .L1", RelocationKind::Jump, ".L1")]
+    #[case("lui a0, %hi(data)", RelocationKind::Hi, "data")]
+    #[case("lw a1, %lo(data)(a0)", RelocationKind::Lo, "data")]
+    #[case("auipc a0, %pcrel_hi(_x1)", RelocationKind::PcrelHi, "_x1")]
+    #[case("addi a0, a0, %pcrel_lo(here)", RelocationKind::PcrelLo, "here")]
+    #[case("ori a0, a0, %lo(data)", RelocationKind::Lo, "data")]
+    #[case("xori a0, a0, %lo(data)", RelocationKind::Lo, "data")]
+    #[case("andi a0, a0, %lo(data)", RelocationKind::Lo, "data")]
+    #[case("slti a0, a0, %lo(data)", RelocationKind::Lo, "data")]
+    fn test_relocation_operand(
+        #[case] input: &str,
+        #[case] kind: RelocationKind,
+        #[case] name: &str,
+    ) {
+        let program = parse_riscv(input).unwrap();
+        let Symbol::Reference(reference) = &program.symbols[0].node else {
+            panic!("expected a reference, got {:?}", program.symbols[0].node);
+        };
+        assert_eq!(reference.relocation.kind, kind);
+        assert_eq!(reference.relocation.label.name, name);
+        assert_eq!(&input[reference.relocation.label.span.into_range()], name);
+    }
+
+    #[rstest]
+    #[case("slli a0, a0, %lo(data)", Isa::RV32I)]
+    #[case("srai a0, a0, %lo(data)", Isa::RV32I)]
+    #[case("srliw a0, a0, %lo(data)", Isa::RV64I)]
+    fn test_shift_by_relocation(#[case] input: &str, #[case] isa: Isa) {
+        let errors = parse_riscv_with(input, isa).unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "the shift amount cannot be the part of an address"
+        );
+    }
+
+    #[test]
+    fn test_register_is_not_a_label() {
+        assert!(parse_riscv("sp: nop").is_err());
+        assert!(parse_riscv("j x5").is_err());
+        assert!(parse_riscv("x32: nop").is_ok());
+        assert!(parse_riscv("spx: nop").is_ok());
+    }
+
+    #[test]
+    fn test_extension_needs_isa() {
+        let errors = parse_riscv("add a0, a0, a1\nmul a0, a0, a1").unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "`mul` needs the M extension, which rv32i does not include"
+        );
+        assert_eq!(errors[0].span().into_range(), 15..29);
+        assert!(parse_riscv_with("mul a0, a0, a1", Isa::RV32IM).is_ok());
+    }
+
+    #[rstest]
+    #[case("csrr a0, mcause", PseudoOpcode::Csrr, 0x3420_2573)]
+    #[case("csrw mtvec, t0", PseudoOpcode::Csrw, 0x3052_9073)]
+    #[case("csrs mie, a1", PseudoOpcode::Csrs, 0x3045_A073)]
+    #[case("csrc mstatus, a1", PseudoOpcode::Csrc, 0x3005_B073)]
+    #[case("csrwi mscratch, 0", PseudoOpcode::Csrwi, 0x3400_5073)]
+    #[case("csrsi mstatus, 8", PseudoOpcode::Csrsi, 0x3004_6073)]
+    #[case("csrci 0x300, 8", PseudoOpcode::Csrci, 0x3004_7073)]
+    fn test_pseudo_csr(#[case] input: &str, #[case] opcode: PseudoOpcode, #[case] word: u32) {
+        let program = parse_riscv_with(input, Isa::RV32IMAC_ZICSR).unwrap();
+        let Symbol::Pseudo(pseudo) = &program.symbols[0].node else {
+            panic!("{} is not a pseudo-instruction", input);
+        };
+        assert_eq!(pseudo.opcode, opcode);
+        assert_eq!(
+            pseudo.expansion.iter().map(encode).collect::<Vec<_>>(),
+            [word]
+        );
+    }
+
+    #[test]
+    fn test_csr_names() {
+        let errors = parse_riscv_with("csrr a0, mstatuss", Isa::RV32IMAC_ZICSR).unwrap_err();
+        assert_eq!(errors[0].to_string(), "unknown CSR `mstatuss`");
+        assert!(parse_riscv_with("csrr a0, 0x1000", Isa::RV32IMAC_ZICSR).is_err());
+        assert!(parse_riscv_with("csrrwi a0, mepc, 32", Isa::RV32IMAC_ZICSR).is_err());
+        let errors = parse_riscv("csrr a0, mepc\namoadd.w a0, a1, (a2)").unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "`csrrs` needs the Zicsr extension, which rv32i does not include"
+        );
+        assert_eq!(
+            errors[1].to_string(),
+            "`amoadd.w` needs the A extension, which rv32i does not include"
+        );
+    }
+
+    #[rstest]
+    #[case("fmv.s fa0, fa1", PseudoOpcode::FmvS, 0x20B5_8553)]
+    #[case("fneg.d ft0, ft1", PseudoOpcode::FnegD, 0x2210_9053)]
+    #[case("fabs.s fa0, fa0", PseudoOpcode::FabsS, 0x20A5_2553)]
+    #[case("frcsr a0", PseudoOpcode::Frcsr, 0x0030_2573)]
+    #[case("fscsr a1", PseudoOpcode::Fscsr, 0x0035_9073)]
+    #[case("fscsr a0, a1", PseudoOpcode::Fscsr, 0x0035_9573)]
+    #[case("frrm t0", PseudoOpcode::Frrm, 0x0020_22F3)]
+    #[case("fsflags a0", PseudoOpcode::Fsflags, 0x0015_1073)]
+    fn test_pseudo_float(#[case] input: &str, #[case] opcode: PseudoOpcode, #[case] word: u32) {
+        let program = parse_riscv_with(input, Isa::RV32IMAFDC_ZICSR).unwrap();
+        let Symbol::Pseudo(pseudo) = &program.symbols[0].node else {
+            panic!("{} is not a pseudo-instruction", input);
+        };
+        assert_eq!(pseudo.opcode, opcode);
+        assert_eq!(
+            pseudo.expansion.iter().map(encode).collect::<Vec<_>>(),
+            [word]
+        );
+    }
+
+    #[test]
+    fn test_float_registers() {
+        let isa = Isa::RV32IMAFDC_ZICSR;
+        assert!(parse_riscv_with("fadd.s f31, ft11, fs11", isa).is_ok());
+        assert!(parse_riscv_with("fadd.s f32, fa0, fa0", isa).is_err());
+        assert!(parse_riscv_with("fadd.s a0, fa0, fa0", isa).is_err());
+        assert!(parse_riscv_with("fsqrt.d fa0, fa0, rxx", isa).is_err());
+        assert!(parse_riscv_with("fsgnj.s fa0, fa0, fa0, rne", isa).is_err());
+        assert!(parse_riscv_with("fa0: nop", isa).is_err());
+        assert!(parse_riscv_with("f31: nop", isa).is_err());
+        let errors = parse_riscv_with("fld fa0, 0(sp)", Isa::RV32IMAC_ZICSR).unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "`fld` needs the D extension, which rv32imac_zicsr does not include"
+        );
+    }
+
+    #[rstest]
+    #[case("li a0, 0x123456789", vec![0x0009_2537, 0xA2B5_051B, 0x00D5_1513, 0x7895_0513])]
+    #[case("li a0, -1", vec![0xFFF0_0513])]
+    #[case("li a0, 0xFFFFFFFFFFFFFFFF", vec![0xFFF0_0513])]
+    #[case("sext.w a0, a1", vec![0x0005_851B])]
+    #[case("negw a0, a1", vec![0x40B0_053B])]
+    fn test_pseudo_64(#[case] input: &str, #[case] words: Vec<u32>) {
+        let program = parse_riscv_with(input, Isa::RV64I).unwrap();
+        let Symbol::Pseudo(pseudo) = &program.symbols[0].node else {
+            panic!("{} is not a pseudo-instruction", input);
+        };
+        assert_eq!(
+            pseudo.expansion.iter().map(encode).collect::<Vec<_>>(),
+            words
+        );
+    }
+
+    #[rstest]
+    #[case(
+        "li a0, 4294967296",
+        "Number out of range for 32-bit integer, 4294967296 is not between -2147483648 and 4294967295"
+    )]
+    #[case(
+        "li a0, -0x80000001",
+        "Number out of range for 32-bit integer, -2147483649 is not between -2147483648 and 4294967295"
+    )]
+    #[case(
+        ".byte 256",
+        "Number out of range for 8-bit integer, 256 is not between -128 and 255"
+    )]
+    fn test_value_out_of_range(#[case] input: &str, #[case] message: &str) {
+        let errors = parse_riscv(input).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), message);
+        assert_eq!(
+            &input[errors[0].span().into_range()],
+            &input[input.rfind(' ').unwrap() + 1..]
+        );
+    }
+
+    #[test]
+    fn test_xlen_needs_isa() {
+        assert!(parse_riscv_with("slli a0, a0, 63", Isa::RV64I).is_ok());
+        assert!(parse_riscv("slli a0, a0, 32").is_err());
+        assert!(parse_riscv_with("slliw a0, a0, 32", Isa::RV64I).is_err());
+        assert!(parse_riscv("li a0, 0x100000000").is_err());
+        let errors = parse_riscv("ld a0, 0(sp)\naddw a0, a0, a1").unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "`ld` only exists in RV64I, rv32i is not"
+        );
+        assert_eq!(
+            errors[1].to_string(),
+            "`addw` only exists in RV64I, rv32i is not"
+        );
+        let errors = parse_riscv_with("mulw a0, a0, a1", Isa::RV64I).unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "`mulw` needs the M extension, which rv64i does not include"
+        );
+        let source = "lr.d a0, (a1)\nfcvt.l.s a0, fa0\nfmv.d.x fa0, a0";
+        assert!(parse_riscv_with(source, Isa::RV64IMAFDC_ZICSR).is_ok());
+        let errors = parse_riscv_with(source, Isa::RV32IMAFDC_ZICSR).unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "`lr.d` only exists in RV64I, rv32imafdc_zicsr is not"
+        );
+        assert_eq!(errors.len(), 3);
+        let errors = parse_riscv_with("c.jal 8", Isa::RV64IMAFDC_ZICSR).unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "`c.jal` only exists in RV32I, rv64imafdc_zicsr is not"
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        let input = "# header\nloop: addi a0, a0, -1 // count down\n  bnez a0, loop#again";
+        let program = parse_riscv(input).unwrap();
+        let text = |span: SimpleSpan| &input[span.into_range()];
+        let comments: Vec<_> = program
+            .symbols
+            .iter()
+            .filter(|symbol| matches!(symbol.node, Symbol::Comment(_)))
+            .map(|symbol| text(symbol.span))
+            .collect();
+        assert_eq!(comments, ["# header", "// count down", "#again"]);
+        let texts: Vec<_> = program
+            .symbols
+            .iter()
+            .filter_map(|symbol| match &symbol.node {
+                Symbol::Comment(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(texts, [" header", " count down", "again"]);
+        assert_eq!(text(program.symbols[2].span), "addi a0, a0, -1");
+        assert_eq!(program.symbols.len(), 6);
+    }
+
+    #[test]
+    fn test_directives() {
+        let input = ".section .bss\n.word 1, end\n.asciz \"a\\n\", \"\"\n.zero 2, -1\n.p2align 3";
+        let program = parse_riscv(input).unwrap();
+        let directives: Vec<_> = program
+            .symbols
+            .iter()
+            .map(|symbol| match &symbol.node {
+                Symbol::Directive(directive) => directive.clone(),
+                other => panic!("expected a directive, got {:?}", other),
+            })
+            .collect();
+        assert!(matches!(&directives[0], Directive::Section(name) if name == ".bss"));
+        let Directive::Data(data) = &directives[1] else {
+            panic!("expected data, got {:?}", directives[1]);
+        };
+        assert_eq!(data.width, DataWidth::Word);
+        assert_eq!(data.values[0], DataValue::Number(1));
+        assert!(matches!(&data.values[1], DataValue::Label(label) if label.name == "end"));
+        assert!(matches!(&directives[2], Directive::Bytes(bytes) if bytes == b"a\n\0\0"));
+        assert!(matches!(&directives[3], Directive::Bytes(bytes) if bytes == &[0xFF, 0xFF]));
+        assert!(matches!(
+            &directives[4],
+            Directive::Alignment(Alignment { alignment: 8 })
+        ));
+        let operands: Vec<_> = program.symbols[1]
+            .operands
+            .iter()
+            .map(|&span| &input[span.into_range()])
+            .collect();
+        assert_eq!(operands, ["1", "end"]);
+    }
+
     #[test]
     fn test_b_instruction_odd_offset() {
         let input = "beq x5, x6, 3";
@@ -0,0 +1,420 @@
+//!
+//! Expansion of the pseudo-instructions from the RISC-V assembly manual into base instructions.
+//!
+
+use crate::riscv::ast::{
+    BImmediate, BOpcode, Csr, CsrImmediate, CsrOpcode, FOpcode, FRegister, FloatFormat, IImmediate,
+    IOpcode, Instruction, JImmediate, JOpcode, PseudoOpcode, ROpcode, Register, RoundingMode,
+    UImmediate, UOpcode,
+};
+
+const ZERO: Register = Register(0);
+const RA: Register = Register(1);
+const T1: Register = Register(6);
+
+///
+/// Splits `value` into the upper 20 bits for `lui`/`auipc` and the lower 12 bits for the
+/// instruction after it.
+///
+/// The lower part is sign extended by the hardware, so the upper part is rounded up whenever
+/// bit 11 is set. Both parts add up to `value` again in wrapping arithmetic.
+///
+pub fn split_immediate(value: i32) -> (i32, i16) {
+    let lo = ((value << 20) >> 20) as i16;
+    let hi = value.wrapping_sub(lo as i32) >> 12;
+    (hi, lo)
+}
+
+fn addi(rd: Register, rs1: Register, imm: i16) -> Instruction {
+    Instruction::IType {
+        opcode: IOpcode::Addi,
+        rd,
+        rs1,
+        imm: IImmediate(imm),
+    }
+}
+
+/// `li` and `la` with a constant address, a single `addi` if the value fits into 12 bits.
+pub fn load_immediate(rd: Register, value: i32) -> Vec<Instruction> {
+    let (hi, lo) = split_immediate(value);
+    let lui = Instruction::UType {
+        opcode: UOpcode::Lui,
+        rd,
+        imm: UImmediate(hi),
+    };
+    match (hi, lo) {
+        (0, lo) => vec![addi(rd, ZERO, lo)],
+        (_, 0) => vec![lui],
+        (_, lo) => vec![lui, addi(rd, rd, lo)],
+    }
+}
+
+///
+/// `li` and `la` with a constant on RV64, where `lui` sign extends its value.
+///
+/// A value of 32 bits is loaded like on RV32 but with `addiw`, which cannot carry into the
+/// upper word. A wider one loads its upper bits without the trailing zeros, shifts them into
+/// place and adds the lower 12 bits, like GNU as does.
+///
+pub fn load_immediate_64(rd: Register, value: i64) -> Vec<Instruction> {
+    if let Ok(value) = i32::try_from(value) {
+        let mut instructions = load_immediate(rd, value);
+        if let [_, Instruction::IType { opcode, .. }] = &mut instructions[..] {
+            *opcode = IOpcode::Addiw;
+        }
+        return instructions;
+    }
+    let lo = ((value << 52) >> 52) as i16;
+    let hi = value.wrapping_sub(lo as i64);
+    let shift = hi.trailing_zeros();
+    let mut instructions = load_immediate_64(rd, hi >> shift);
+    instructions.push(Instruction::IType {
+        opcode: IOpcode::Slli,
+        rd,
+        rs1: rd,
+        imm: IImmediate(shift as i16),
+    });
+    if lo != 0 {
+        instructions.push(addi(rd, rd, lo));
+    }
+    instructions
+}
+
+/// `la` and `li` with a label, always an `auipc` or `lui` and an `addi` since the value is only
+/// known once the program is assembled.
+pub fn load_address(opcode: UOpcode, rd: Register, value: i32) -> Vec<Instruction> {
+    let (hi, lo) = split_immediate(value);
+    vec![
+        Instruction::UType {
+            opcode,
+            rd,
+            imm: UImmediate(hi),
+        },
+        addi(rd, rd, lo),
+    ]
+}
+
+/// Pseudo-instructions with a destination and a source register.
+pub fn register_pair(opcode: PseudoOpcode, rd: Register, rs: Register) -> Vec<Instruction> {
+    let (i_opcode, imm) = match opcode {
+        PseudoOpcode::Mv => (IOpcode::Addi, 0),
+        PseudoOpcode::Not => (IOpcode::Xori, -1),
+        PseudoOpcode::Seqz => (IOpcode::Sltiu, 1),
+        PseudoOpcode::SextW => (IOpcode::Addiw, 0),
+        _ => {
+            let (r_opcode, rs1, rs2) = match opcode {
+                PseudoOpcode::Neg => (ROpcode::Sub, ZERO, rs),
+                PseudoOpcode::Negw => (ROpcode::Subw, ZERO, rs),
+                PseudoOpcode::Snez => (ROpcode::Sltu, ZERO, rs),
+                PseudoOpcode::Sltz => (ROpcode::Slt, rs, ZERO),
+                PseudoOpcode::Sgtz => (ROpcode::Slt, ZERO, rs),
+                _ => unreachable!("{:?} does not take two registers", opcode),
+            };
+            return vec![Instruction::RType {
+                opcode: r_opcode,
+                rd,
+                rs1,
+                rs2,
+            }];
+        }
+    };
+    vec![Instruction::IType {
+        opcode: i_opcode,
+        rd,
+        rs1: rs,
+        imm: IImmediate(imm),
+    }]
+}
+
+/// Branches comparing `rs` against zero.
+pub fn branch_zero(opcode: PseudoOpcode, rs: Register, offset: i16) -> Vec<Instruction> {
+    let (b_opcode, rs1, rs2) = match opcode {
+        PseudoOpcode::Beqz => (BOpcode::Beq, rs, ZERO),
+        PseudoOpcode::Bnez => (BOpcode::Bne, rs, ZERO),
+        PseudoOpcode::Blez => (BOpcode::Bge, ZERO, rs),
+        PseudoOpcode::Bgez => (BOpcode::Bge, rs, ZERO),
+        PseudoOpcode::Bltz => (BOpcode::Blt, rs, ZERO),
+        PseudoOpcode::Bgtz => (BOpcode::Blt, ZERO, rs),
+        _ => unreachable!("{:?} is not a branch against zero", opcode),
+    };
+    vec![Instruction::BType {
+        opcode: b_opcode,
+        rs1,
+        rs2,
+        imm: BImmediate(offset),
+    }]
+}
+
+/// Branches which only exist the other way around, the operands are swapped.
+pub fn branch_swapped(
+    opcode: PseudoOpcode,
+    rs: Register,
+    rt: Register,
+    offset: i16,
+) -> Vec<Instruction> {
+    let b_opcode = match opcode {
+        PseudoOpcode::Bgt => BOpcode::Blt,
+        PseudoOpcode::Ble => BOpcode::Bge,
+        PseudoOpcode::Bgtu => BOpcode::Bltu,
+        PseudoOpcode::Bleu => BOpcode::Bgeu,
+        _ => unreachable!("{:?} is not a swapped branch", opcode),
+    };
+    vec![Instruction::BType {
+        opcode: b_opcode,
+        rs1: rt,
+        rs2: rs,
+        imm: BImmediate(offset),
+    }]
+}
+
+/// `j` and `jal` with an offset, `call` and `tail` reach the whole address space with an
+/// `auipc` in front of the jump, their offset is relative to that `auipc`.
+pub fn jump(opcode: PseudoOpcode, offset: i32) -> Vec<Instruction> {
+    let jal = |rd| Instruction::JType {
+        opcode: JOpcode::Jal,
+        rd,
+        imm: JImmediate(offset),
+    };
+    let far = |link, scratch| {
+        let (hi, lo) = split_immediate(offset);
+        vec![
+            Instruction::UType {
+                opcode: UOpcode::Auipc,
+                rd: scratch,
+                imm: UImmediate(hi),
+            },
+            Instruction::IType {
+                opcode: IOpcode::Jalr,
+                rd: link,
+                rs1: scratch,
+                imm: IImmediate(lo),
+            },
+        ]
+    };
+    match opcode {
+        PseudoOpcode::J => vec![jal(ZERO)],
+        PseudoOpcode::Jal => vec![jal(RA)],
+        PseudoOpcode::Call => far(RA, RA),
+        PseudoOpcode::Tail => far(ZERO, T1),
+        _ => unreachable!("{:?} is not a jump to an offset", opcode),
+    }
+}
+
+/// `jr`, `jalr` with a single register and `ret`.
+pub fn jump_register(opcode: PseudoOpcode, rs: Register) -> Vec<Instruction> {
+    let rd = match opcode {
+        PseudoOpcode::Jr | PseudoOpcode::Ret => ZERO,
+        PseudoOpcode::Jalr => RA,
+        _ => unreachable!("{:?} is not a jump to a register", opcode),
+    };
+    vec![Instruction::IType {
+        opcode: IOpcode::Jalr,
+        rd,
+        rs1: rs,
+        imm: IImmediate(0),
+    }]
+}
+
+pub fn nop() -> Vec<Instruction> {
+    vec![addi(ZERO, ZERO, 0)]
+}
+
+/// `csrr rd, csr`, sets no bits so the CSR is only read.
+pub fn csr_read(rd: Register, csr: Csr) -> Vec<Instruction> {
+    vec![Instruction::Csr {
+        opcode: CsrOpcode::Csrrs,
+        rd,
+        rs1: ZERO,
+        csr,
+    }]
+}
+
+fn csr_opcode(opcode: PseudoOpcode) -> CsrOpcode {
+    match opcode {
+        PseudoOpcode::Csrw | PseudoOpcode::Csrwi => CsrOpcode::Csrrw,
+        PseudoOpcode::Csrs | PseudoOpcode::Csrsi => CsrOpcode::Csrrs,
+        PseudoOpcode::Csrc | PseudoOpcode::Csrci => CsrOpcode::Csrrc,
+        _ => unreachable!("{:?} does not write a CSR", opcode),
+    }
+}
+
+/// `csrw`, `csrs` and `csrc`, the old value of the CSR goes to `x0`.
+pub fn csr_write(opcode: PseudoOpcode, csr: Csr, rs: Register) -> Vec<Instruction> {
+    vec![Instruction::Csr {
+        opcode: csr_opcode(opcode),
+        rd: ZERO,
+        rs1: rs,
+        csr,
+    }]
+}
+
+/// `csrwi`, `csrsi` and `csrci`.
+pub fn csr_write_immediate(opcode: PseudoOpcode, csr: Csr, imm: CsrImmediate) -> Vec<Instruction> {
+    vec![Instruction::CsrImmediate {
+        opcode: csr_opcode(opcode),
+        rd: ZERO,
+        imm,
+        csr,
+    }]
+}
+
+/// `fmv`, `fneg` and `fabs`, sign injections of a register with itself.
+pub fn float_sign(opcode: PseudoOpcode, rd: FRegister, rs: FRegister) -> Vec<Instruction> {
+    let (opcode, format) = match opcode {
+        PseudoOpcode::FmvS => (FOpcode::Fsgnj, FloatFormat::Single),
+        PseudoOpcode::FmvD => (FOpcode::Fsgnj, FloatFormat::Double),
+        PseudoOpcode::FnegS => (FOpcode::Fsgnjn, FloatFormat::Single),
+        PseudoOpcode::FnegD => (FOpcode::Fsgnjn, FloatFormat::Double),
+        PseudoOpcode::FabsS => (FOpcode::Fsgnjx, FloatFormat::Single),
+        PseudoOpcode::FabsD => (FOpcode::Fsgnjx, FloatFormat::Double),
+        _ => unreachable!("{:?} is not a sign injection", opcode),
+    };
+    vec![Instruction::FloatRType {
+        opcode,
+        format,
+        rd,
+        rs1: rs,
+        rs2: rs,
+        rm: RoundingMode::Dynamic,
+    }]
+}
+
+/// `frcsr rd` and `fscsr rd, rs`, likewise for `frm` and `fflags`. Writing swaps the old value
+/// into `rd`.
+pub fn float_csr(opcode: PseudoOpcode, rd: Register, rs: Register) -> Vec<Instruction> {
+    let (opcode, name) = match opcode {
+        PseudoOpcode::Frcsr => (CsrOpcode::Csrrs, "fcsr"),
+        PseudoOpcode::Fscsr => (CsrOpcode::Csrrw, "fcsr"),
+        PseudoOpcode::Frrm => (CsrOpcode::Csrrs, "frm"),
+        PseudoOpcode::Fsrm => (CsrOpcode::Csrrw, "frm"),
+        PseudoOpcode::Frflags => (CsrOpcode::Csrrs, "fflags"),
+        PseudoOpcode::Fsflags => (CsrOpcode::Csrrw, "fflags"),
+        _ => unreachable!("{:?} does not access a floating-point CSR", opcode),
+    };
+    vec![Instruction::Csr {
+        opcode,
+        rd,
+        rs1: rs,
+        csr: Csr::from_name(name).unwrap(),
+    }]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::encoding::encode;
+    use rstest::rstest;
+
+    // What the expansion computes, the value as seen by the hardware.
+    fn evaluate(instructions: &[Instruction]) -> i32 {
+        let mut value = 0i32;
+        for instruction in instructions {
+            match *instruction {
+                Instruction::UType { imm, .. } => value = imm.0 << 12,
+                Instruction::IType { rs1, imm, .. } if rs1 == ZERO => value = imm.0 as i32,
+                Instruction::IType { imm, .. } => value = value.wrapping_add(imm.0 as i32),
+                _ => panic!("unexpected {:?}", instruction),
+            }
+        }
+        value
+    }
+
+    // Like `evaluate` with the 64-bit registers of RV64.
+    fn evaluate_64(instructions: &[Instruction]) -> i64 {
+        let mut value = 0i64;
+        for instruction in instructions {
+            match *instruction {
+                Instruction::UType { imm, .. } => value = (imm.0 << 12) as i64,
+                Instruction::IType {
+                    opcode: IOpcode::Addiw,
+                    imm,
+                    ..
+                } => value = (value as i32).wrapping_add(imm.0 as i32) as i64,
+                Instruction::IType {
+                    opcode: IOpcode::Slli,
+                    imm,
+                    ..
+                } => value <<= imm.0,
+                Instruction::IType { rs1, imm, .. } if rs1 == ZERO => value = imm.0 as i64,
+                Instruction::IType { imm, .. } => value = value.wrapping_add(imm.0 as i64),
+                _ => panic!("unexpected {:?}", instruction),
+            }
+        }
+        value
+    }
+
+    #[rstest]
+    #[case(0, 0, 0)]
+    #[case(0x7FF, 0, 0x7FF)]
+    #[case(0x800, 1, -0x800)]
+    #[case(-1, 0, -1)]
+    #[case(0x1234_5678, 0x12345, 0x678)]
+    #[case(0x1234_5FFF, 0x12346, -1)]
+    #[case(0x7FFF_FFFF, -0x8_0000, -1)]
+    #[case(i32::MIN, -0x8_0000, 0)]
+    fn test_split_immediate(#[case] value: i32, #[case] hi: i32, #[case] lo: i16) {
+        assert_eq!(split_immediate(value), (hi, lo));
+        assert_eq!((hi << 12).wrapping_add(lo as i32), value);
+    }
+
+    #[rstest]
+    #[case(5, &[0x0050_0513])]
+    #[case(-2048, &[0x8000_0513])]
+    #[case(0x1000, &[0x0000_1537])]
+    #[case(0x800, &[0x0000_1537, 0x8005_0513])]
+    #[case(0x1234_5678, &[0x1234_5537, 0x6785_0513])]
+    #[case(-0x1234_5678, &[0xEDCB_B537, 0x9885_0513])]
+    #[case(0xDEAD_BEEFu32 as i32, &[0xDEAD_C537, 0xEEF5_0513])]
+    fn test_load_immediate(#[case] value: i32, #[case] words: &[u32]) {
+        let instructions = load_immediate(Register(10), value);
+        assert_eq!(evaluate(&instructions), value);
+        assert_eq!(instructions.iter().map(encode).collect::<Vec<_>>(), words);
+    }
+
+    #[rstest]
+    #[case(5, &[0x0050_0513])]
+    #[case(-1, &[0xFFF0_0513])]
+    #[case(0x1000, &[0x0000_1537])]
+    #[case(0x7FFF_FFFF, &[0x8000_0537, 0xFFF5_051B])]
+    #[case(0x8000_0000, &[0x0010_0513, 0x01F5_1513])]
+    #[case(0x1_0000_0000, &[0x0010_0513, 0x0205_1513])]
+    #[case(i64::MIN, &[0xFFF0_0513, 0x03F5_1513])]
+    #[case(0xFFFF_FFFF, &[0x0010_0513, 0x0205_1513, 0xFFF5_0513])]
+    fn test_load_immediate_64(#[case] value: i64, #[case] words: &[u32]) {
+        let instructions = load_immediate_64(Register(10), value);
+        assert_eq!(evaluate_64(&instructions), value);
+        assert_eq!(instructions.iter().map(encode).collect::<Vec<_>>(), words);
+    }
+
+    #[rstest]
+    #[case(0x1234_5678_9ABC_DEF0)]
+    #[case(-0x1234_5678_9ABC_DEF0)]
+    #[case(0x7FFF_FFFF_FFFF_FFFF)]
+    #[case(0x0000_8000_0000_0800)]
+    #[case(-0x8000_0001)]
+    fn test_load_immediate_64_values(#[case] value: i64) {
+        let instructions = load_immediate_64(Register(10), value);
+        assert_eq!(evaluate_64(&instructions), value);
+        assert!(instructions.len() <= 8);
+    }
+
+    #[test]
+    fn test_call_reaches_far() {
+        let instructions = jump(PseudoOpcode::Call, -0x1234_5800);
+        assert_eq!(evaluate(&instructions[..1]), -0x1234_5000);
+        assert_eq!(
+            instructions[1],
+            Instruction::IType {
+                opcode: IOpcode::Jalr,
+                rd: RA,
+                rs1: RA,
+                imm: IImmediate(-0x800),
+            }
+        );
+        let instructions = jump(PseudoOpcode::Tail, 0x10);
+        assert_eq!(
+            instructions.iter().map(encode).collect::<Vec<_>>(),
+            [0x0000_0317, 0x0103_0067]
+        );
+    }
+}
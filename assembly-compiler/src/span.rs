@@ -0,0 +1,96 @@
+use chumsky::span::SimpleSpan;
+
+/// A parsed node together with the part of the source it came from.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: SimpleSpan,
+    /// Spans of the operands, in the order they are written in the source.
+    pub operands: Vec<SimpleSpan>,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(node: T, span: SimpleSpan) -> Self {
+        Spanned {
+            node,
+            span,
+            operands: vec![],
+        }
+    }
+}
+
+/// Position in the source, both line and column start at 1 and columns count characters.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug)]
+pub struct LineCol {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Turns the byte offsets of spans into lines and columns.
+#[derive(Clone, Debug)]
+pub struct LineIndex<'src> {
+    source: &'src str,
+    line_starts: Vec<usize>,
+}
+
+impl<'src> LineIndex<'src> {
+    pub fn new(source: &'src str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        LineIndex {
+            source,
+            line_starts,
+        }
+    }
+
+    pub fn line_col(&self, offset: usize) -> LineCol {
+        let offset = offset.min(self.source.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let start = self.line_starts[line];
+        LineCol {
+            line: line + 1,
+            column: self.source[start..offset].chars().count() + 1,
+        }
+    }
+
+    pub fn range(&self, span: SimpleSpan) -> (LineCol, LineCol) {
+        (self.line_col(span.start), self.line_col(span.end))
+    }
+
+    /// Text of the 1-based `line` without its line break.
+    pub fn line(&self, line: usize) -> Option<&'src str> {
+        let start = *self.line_starts.get(line.checked_sub(1)?)?;
+        let end = self
+            .line_starts
+            .get(line)
+            .map_or(self.source.len(), |&next| next - 1);
+        Some(self.source[start..end].trim_end_matches('\r'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_col() {
+        let index = LineIndex::new("nop\n  lc 5\n\nänd b0 1");
+        assert_eq!(index.line_col(0), LineCol { line: 1, column: 1 });
+        assert_eq!(index.line_col(3), LineCol { line: 1, column: 4 });
+        assert_eq!(index.line_col(6), LineCol { line: 2, column: 3 });
+        assert_eq!(index.line_col(11), LineCol { line: 3, column: 1 });
+        assert_eq!(index.line_col(15), LineCol { line: 4, column: 3 });
+        assert_eq!(index.line_col(100), LineCol { line: 4, column: 9 });
+    }
+
+    #[test]
+    fn test_line() {
+        let index = LineIndex::new("nop\r\nlc 5\n");
+        assert_eq!(index.line(1), Some("nop"));
+        assert_eq!(index.line(2), Some("lc 5"));
+        assert_eq!(index.line(3), Some(""));
+        assert_eq!(index.line(0), None);
+        assert_eq!(index.line(4), None);
+    }
+}
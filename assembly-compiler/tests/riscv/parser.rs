@@ -1,4 +1,5 @@
-use assembly_compiler::parser;
+use assembly_compiler::riscv::isa::Isa;
+use assembly_compiler::riscv::parser;
 use rstest::rstest;
 
 #[rstest]
@@ -35,6 +36,52 @@ use rstest::rstest;
 #[case::lui("lui x1, 5")]
 #[case::lui_2("lui x2, -8")]
 #[case::auipc("auipc x3, 0x1234")]
+#[case::jal("jal ra, 2048")]
+#[case::jal_2("jal x1, 0x3456")]
+#[case::jal_back("jal zero, -0x10")]
+#[case::jal_label("jal ra, label")]
+#[case::beq_label("beq a0, a1, .loop")]
+#[case::lui_hi("lui a0, %hi(data)")]
+#[case::addi_lo("addi a0, a0, %lo(data)")]
+#[case::lw_lo("lw a0, %lo(data)(a0)")]
+#[case::sw_lo("sw a0, %lo(data)(a1)")]
+#[case::auipc_pcrel_hi("auipc a0, %pcrel_hi(data)")]
+#[case::addi_pcrel_lo("addi a0, a0, %pcrel_lo(here)")]
+#[case::jalr_lo("jalr ra, %lo(function)(t0)")]
+#[case::label_only("loop:")]
+#[case::label_instruction("loop: addi x1, x1, -1")]
+#[case::comment("# only a comment")]
+#[case::comment_slashes("// only a comment")]
+#[case::trailing_comment("addi x1, x1, -1 # decrement")]
+#[case::label_comment("loop: // top of the loop")]
+#[case::text(".text")]
+#[case::data(".data")]
+#[case::bss(".bss")]
+#[case::section(".section .rodata")]
+#[case::section_flags(".section .rodata.str1.1,\"aMS\",@progbits,1")]
+#[case::globl(".globl _start")]
+#[case::global(".global main")]
+#[case::align(".align 2")]
+#[case::p2align(".p2align 4")]
+#[case::word(".word 1, -1, 0xFFFFFFFF, label")]
+#[case::half(".half 0xFFFF, -32768")]
+#[case::byte("table: .byte 1,2 , 3")]
+#[case::ascii(".ascii \"hello\", \"world\"")]
+#[case::asciz(".asciz \"tab\\t quote\\\" \\x7F # not a comment\"")]
+#[case::string(".string \"\"")]
+#[case::space(".space 16")]
+#[case::zero(".zero 4, 0xFF")]
+#[case::equ(".equ SIZE, 64")]
+#[case::set(".set MASK, -1")]
+#[case::jalr("jalr ra, 0(t0)")]
+#[case::jalr_2("jalr x0, x1, -4")]
+#[case::lbu("lbu a0, 7(sp)")]
+#[case::lhu("lhu a1, -2(gp)")]
+#[case::fence("fence")]
+#[case::fence_2("fence rw, rw")]
+#[case::fence_3("fence iorw,o")]
+#[case::ecall("ecall")]
+#[case::ebreak(" ebreak ")]
 fn test_instruction(#[case] instruction: &str) {
     let parsed = parser::parse_riscv(instruction);
     if let Err(err) = parsed {
@@ -46,20 +93,252 @@ fn test_instruction(#[case] instruction: &str) {
 }
 
 #[rstest]
-#[case::jal("jal ra, label")]
-#[case::jal_2("jal x1, 0x3456")]
-fn test_pseudo_instruction(#[case] _instruction: &str) {
-    // TODO
+#[case::mul("mul x1, x2, x3")]
+#[case::mulh("mulh a0, a1,a2")]
+#[case::mulhsu("mulhsu t0, t1, t2")]
+#[case::mulhu(" mulhu s0, s1, s2")]
+#[case::div("div x4, x5, x6")]
+#[case::divu("divu x7 , x8, x9")]
+#[case::rem("rem a3, a4, a5")]
+#[case::remu("remu a6, a7, zero ")]
+fn test_muldiv_instruction(#[case] instruction: &str) {
+    let parsed = parser::parse_riscv_with(instruction, Isa::RV32IM);
+    assert!(parsed.is_ok(), "{:?}", parsed);
+}
+
+#[rstest]
+#[case::c_nop("c.nop")]
+#[case::c_addi("c.addi a0, -1")]
+#[case::c_li("c.li a5, 31")]
+#[case::c_lui("c.lui t0, 0x1F")]
+#[case::c_addi16sp("c.addi16sp sp, -512")]
+#[case::c_addi4spn("c.addi4spn a0 , sp, 4")]
+#[case::c_lw("c.lw a0, 124(a5)")]
+#[case::c_swsp("c.swsp t6, 0(sp)")]
+#[case::c_mv("c.mv x31, x1")]
+#[case::c_sub("c.sub s0,s1")]
+#[case::c_jalr("c.jalr t0")]
+#[case::c_beqz_label("c.beqz a0, loop")]
+#[case::c_j_label("c.j _start")]
+fn test_compressed_instruction(#[case] instruction: &str) {
+    let parsed = parser::parse_riscv_with(instruction, Isa::RV32IC);
+    assert!(parsed.is_ok(), "{:?}", parsed);
+}
+
+#[rstest]
+#[case::lr_w("lr.w a0, (a1)")]
+#[case::lr_w_offset("lr.w.aq t0, 0(sp)")]
+#[case::sc_w("sc.w.rl a2, a1, (a0)")]
+#[case::amoswap_w("amoswap.w.aqrl a0, a1, (a2)")]
+#[case::amoadd_w("amoadd.w zero, t0,(t1)")]
+#[case::amominu_w("amominu.w a0, a1, (a2)")]
+#[case::csrrw("csrrw a0, mstatus, a1")]
+#[case::csrrs("csrrs t0, mcause, zero")]
+#[case::csrrc("csrrc x0, 0x344, x5")]
+#[case::csrrwi("csrrwi a0, mtvec, 31")]
+#[case::csrr("csrr a0, mepc")]
+#[case::csrw("csrw mtvec, t0")]
+#[case::csrsi("csrsi mstatus, 8")]
+#[case::csrc("csrc mie, a0")]
+fn test_machine_mode_instruction(#[case] instruction: &str) {
+    let parsed = parser::parse_riscv_with(instruction, Isa::RV32IMAC_ZICSR);
+    assert!(parsed.is_ok(), "{:?}", parsed);
+}
+
+#[rstest]
+#[case::nop("nop")]
+#[case::li("li a0, 5")]
+#[case::li_large("li t0, 0x12345678")]
+#[case::li_unsigned("li t0, 0xFFFFFFFF")]
+#[case::li_negative("li t0, -2147483648")]
+#[case::la("la a0, 0x80000000")]
+#[case::la_label("la a0, data")]
+#[case::li_label("li a0, SIZE")]
+#[case::mv("mv a0, a1")]
+#[case::not("not t0, t1")]
+#[case::neg("neg s0, s1")]
+#[case::seqz("seqz a0, a0")]
+#[case::snez("snez a0, a0")]
+#[case::sltz("sltz a0, a1")]
+#[case::sgtz("sgtz a0, a1")]
+#[case::beqz("beqz a0, -8")]
+#[case::bnez("bnez a0, 16")]
+#[case::blez("blez a0, 4")]
+#[case::bgez("bgez a0, 4")]
+#[case::bltz("bltz a0, 4")]
+#[case::bgtz("bgtz a0, 4")]
+#[case::bgt("bgt a0, a1, 4")]
+#[case::ble("ble a0, a1, 4")]
+#[case::bgtu("bgtu a0, a1, 4")]
+#[case::bleu("bleu a0, a1, 4")]
+#[case::j("j -16")]
+#[case::jal("jal 0x3456")]
+#[case::jr("jr t0")]
+#[case::jalr("jalr t0")]
+#[case::ret("ret")]
+#[case::call("call 0x12345678")]
+#[case::tail("tail -4")]
+#[case::j_label("j _start")]
+#[case::call_label("call printf")]
+#[case::bnez_label("bnez t0, loop")]
+fn test_pseudo_instruction(#[case] instruction: &str) {
+    let parsed = parser::parse_riscv(instruction);
+    if let Err(err) = parsed {
+        for e in err.iter() {
+            println!("{}", e);
+        }
+        panic!("{:?}", err);
+    }
 }
 
 #[rstest]
 #[case::add("addx1, x2, x3")]
 #[case::swapped_save("sb x0, x1[100]")]
+#[case::jal_odd("jal ra, 3")]
+#[case::jal_missing_offset("jal ra")]
+#[case::li_too_large("li a0, 0x100000000")]
+#[case::mv_immediate("mv a0, 5")]
+#[case::ret_operand("ret ra")]
+#[case::bgt_missing_rt("bgt a0, 8")]
+#[case::jalr_missing_paren("jalr ra, 4(t0")]
+#[case::fence_twice("fence rr, w")]
+#[case::fence_one_set("fence rw")]
+#[case::ecall_operand("ecall x1")]
+#[case::label_missing_colon("loop addi x1, x1, -1")]
+#[case::register_label("a0: nop")]
+#[case::register_reference("j t0")]
+#[case::unknown_directive(".long 5")]
+#[case::byte_too_large(".byte 256")]
+#[case::half_label(".half label")]
+#[case::word_empty(".word")]
+#[case::unterminated_string(".ascii \"abc")]
+#[case::bad_escape(".ascii \"\\q\"")]
+#[case::align_too_large(".align 16")]
+#[case::equ_missing_value(".equ SIZE")]
+#[case::section_missing_name(".section")]
+#[case::lui_lo("lui a0, %lo(data)")]
+#[case::addi_hi("addi a0, a0, %hi(data)")]
+#[case::slli_lo("slli a0, a0, %lo(data)")]
+#[case::mul_without_m("mul a0, a1, a2")]
+#[case::remu_without_m("remu t0,t1,t2")]
+#[case::c_nop_without_c("c.nop")]
+#[case::c_add_without_c("c.add a0, a1")]
+#[case::lr_w_without_a("lr.w a0, (a1)")]
+#[case::csrr_without_zicsr("csrr a0, mepc")]
+#[case::flw_without_f("flw fa0, 0(sp)")]
+#[case::frcsr_without_zicsr("frcsr a0")]
 fn test_instruction_parse_fails(#[case] instruction: &str) {
     let parsed = parser::parse_riscv(instruction);
     assert!(parsed.is_err());
 }
 
+#[rstest]
+#[case::c_lw_register("c.lw t0, 0(a0)")]
+#[case::c_lw_unaligned("c.lw a0, 2(a1)")]
+#[case::c_addi_zero("c.addi a0, 0")]
+#[case::c_li_too_large("c.li a0, 32")]
+#[case::c_lui_sp("c.lui sp, 1")]
+#[case::c_lwsp_not_sp("c.lwsp a0, 4(a1)")]
+#[case::c_jr_zero("c.jr zero")]
+#[case::c_j_odd("c.j 3")]
+#[case::c_beqz_too_far("c.beqz a0, 256")]
+fn test_compressed_parse_fails(#[case] instruction: &str) {
+    let parsed = parser::parse_riscv_with(instruction, Isa::RV32IC);
+    assert!(parsed.is_err());
+}
+
+#[rstest]
+#[case::lr_w_offset("lr.w a0, 4(a1)")]
+#[case::lr_w_rs2("lr.w a0, a2, (a1)")]
+#[case::sc_w_no_parens("sc.w a0, a1, a2")]
+#[case::amoadd_d("amoadd.d a0, a1, (a2)")]
+#[case::ordering_twice("amoor.w.aq.rl a0, a1, (a2)")]
+#[case::unknown_csr("csrr a0, mstatu")]
+#[case::csr_too_large("csrw 0x1000, a0")]
+#[case::csr_immediate_too_large("csrwi mstatus, 32")]
+#[case::csr_register_for_immediate("csrrsi a0, mstatus, a1")]
+fn test_machine_mode_parse_fails(#[case] instruction: &str) {
+    let parsed = parser::parse_riscv_with(instruction, Isa::RV32IMAC_ZICSR);
+    assert!(parsed.is_err());
+}
+
+#[rstest]
+#[case::flw("flw fa0, 8(sp)")]
+#[case::fsd_low("fsd fs0, %lo(value)(a0)")]
+#[case::fadd_s("fadd.s fa0, fa1, fa2")]
+#[case::fadd_d_rounding("fadd.d ft0, ft1, ft2, rne")]
+#[case::fsgnjx_numeric("fsgnjx.d f0, f1, f31")]
+#[case::fsqrt_s("fsqrt.s fa0, fa0, rdn")]
+#[case::fcvt_d_s("fcvt.d.s fa0, fa1")]
+#[case::fcvt_w_d("fcvt.w.d a0, fa0, rtz")]
+#[case::fcvt_s_wu("fcvt.s.wu fa0, a0")]
+#[case::fmv_x_w("fmv.x.w a0, fa0")]
+#[case::fclass_s("fclass.s a0, fa0")]
+#[case::feq_d("feq.d a0, fa0, fa1")]
+#[case::fnmsub_s("fnmsub.s fa0, fa1, fa2, fa3, dyn")]
+#[case::fneg_d("fneg.d fa0, fa1")]
+#[case::frrm("frrm a0")]
+#[case::fsflags("fsflags t0, t1")]
+fn test_float_instruction(#[case] instruction: &str) {
+    let parsed = parser::parse_riscv_with(instruction, Isa::RV32IMAFDC_ZICSR);
+    assert!(parsed.is_ok(), "{:?}", parsed);
+}
+
+#[rstest]
+#[case::integer_destination("fadd.s a0, fa1, fa2")]
+#[case::float_base("flw fa0, 8(fa1)")]
+#[case::float_compare_destination("feq.s fa0, fa1, fa2")]
+#[case::rounding_mode_unknown("fmul.s fa0, fa1, fa2, rnd")]
+#[case::rounding_mode_not_rounded("fmin.d fa0, fa1, fa2, rtz")]
+#[case::fmv_x_d("fmv.x.d a0, fa0")]
+#[case::fused_missing_operand("fmadd.s fa0, fa1, fa2")]
+fn test_float_parse_fails(#[case] instruction: &str) {
+    let parsed = parser::parse_riscv_with(instruction, Isa::RV32IMAFDC_ZICSR);
+    assert!(parsed.is_err());
+}
+
+#[rstest]
+#[case::ld("ld a0, 8(sp)")]
+#[case::sd_low("sd a1, %lo(value)(a0)")]
+#[case::lwu("lwu t0, -4(s0)")]
+#[case::addiw("addiw a0, a0, -1")]
+#[case::slli_wide("slli a0, a0, 63")]
+#[case::sraiw("sraiw a0, a1, 31")]
+#[case::addw("addw a0, a1, a2")]
+#[case::sraw("sraw a0, a1, a2")]
+#[case::divuw("divuw a0, a1, a2")]
+#[case::remw("remw a0, a1, a2")]
+#[case::li_wide("li a0, 0x123456789abcdef0")]
+#[case::sext_w("sext.w a0, a1")]
+#[case::negw("negw a0, a1")]
+#[case::lw("lw a0, 0(sp)")]
+fn test_rv64_instruction(#[case] instruction: &str) {
+    let parsed = parser::parse_riscv_with(instruction, Isa::RV64IM);
+    assert!(parsed.is_ok(), "{:?}", parsed);
+}
+
+#[rstest]
+#[case::slli_too_wide("slli a0, a0, 64")]
+#[case::slliw_too_wide("slliw a0, a0, 32")]
+#[case::li_too_wide("li a0, 0x10000000000000000")]
+#[case::ld_float("ld a0, 8(fa0)")]
+#[case::addiw_too_wide("addiw a0, a0, 2048")]
+fn test_rv64_parse_fails(#[case] instruction: &str) {
+    let parsed = parser::parse_riscv_with(instruction, Isa::RV64IM);
+    assert!(parsed.is_err());
+}
+
+#[rstest]
+#[case::ld("ld a0, 8(sp)")]
+#[case::sd("sd a0, 8(sp)")]
+#[case::addw("addw a0, a1, a2")]
+#[case::slli_wide("slli a0, a0, 32")]
+#[case::li_wide("li a0, 0x100000000")]
+fn test_rv64_only_parse_fails_on_rv32(#[case] instruction: &str) {
+    let parsed = parser::parse_riscv_with(instruction, Isa::RV32IM);
+    assert!(parsed.is_err());
+}
+
 #[test]
 fn test_parse_multiple_instructions() {
     let input = " addi x3, x4, 6\n  sub x5, x6, x7\n  ";
@@ -67,10 +346,10 @@ fn test_parse_multiple_instructions() {
     match result {
         Ok(program) => assert_eq!(program.symbols.len(), 2),
         Err(err) => {
-            for e in err {
+            for e in err.iter() {
                 println!("{}", e);
-                assert!(false);
             }
+            panic!("{:?}", err);
         }
     }
 }
@@ -0,0 +1,56 @@
+use std::fmt::{Display, Formatter};
+
+use assembly_compiler::belt::ast::Instruction;
+use assembly_compiler::belt::encoding::DecodeError;
+
+use crate::{BELT_LENGTH, Frame};
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum FaultKind {
+    DivideByZero,
+    JumpOutOfBounds { target: u16 },
+    CallStackUnderflow,
+    CallStackOverflow,
+    InvalidInstruction(DecodeError),
+}
+
+/// Registers of the machine at the moment of a fault, memory is left out as it is not
+/// touched by a faulting instruction.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Snapshot {
+    pub belt: [u16; BELT_LENGTH],
+    pub pc: u16,
+    pub call_stack: Vec<Frame>,
+}
+
+/// Raised instead of executing an instruction, the machine is left as it was before it.
+/// `instruction` is missing when the faulting word could not be decoded.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Fault {
+    pub kind: FaultKind,
+    pub pc: u16,
+    pub instruction: Option<Instruction>,
+    pub snapshot: Snapshot,
+}
+
+impl Display for FaultKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FaultKind::DivideByZero => write!(f, "division by zero"),
+            FaultKind::JumpOutOfBounds { target } => {
+                write!(f, "jump to 0x{:04X} outside of program memory", target)
+            }
+            FaultKind::CallStackUnderflow => write!(f, "return without a matching call"),
+            FaultKind::CallStackOverflow => write!(f, "call stack overflow"),
+            FaultKind::InvalidInstruction(error) => write!(f, "invalid instruction, {}", error),
+        }
+    }
+}
+
+impl Display for Fault {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at 0x{:04X}", self.kind, self.pc)
+    }
+}
+
+impl std::error::Error for Fault {}
@@ -1,4 +1,749 @@
+pub mod fault;
+
+use std::collections::BTreeMap;
+
+use assembly_compiler::belt::ast::{
+    BeltPos, ConstantOp, ImmediateOp, Instruction, RegOp, UnaryOp, ZeroOp,
+};
+use assembly_compiler::belt::encoding::{decode, encode_program};
+use assembly_compiler::belt::image::Image;
+
+use crate::fault::{Fault, FaultKind, Snapshot};
+
+pub const BELT_LENGTH: usize = 16;
+pub const MEMORY_SIZE: usize = 65536;
+pub const CALL_STACK_LIMIT: usize = 256;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub return_pc: u16,
+    pub belt: [u16; BELT_LENGTH],
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum StepOutcome {
+    Continue,
+    Break,
+    Halted,
+    Fault(Fault),
+}
+
+///
+/// Belt machine executing `belt::ast` instructions.
+///
+/// Every instruction producing a value pushes it to the front of the belt (`b0`),
+/// moving the older values one position back; whatever was in `b15` falls off.
+/// Programs live in `memory` in their binary encoding and are decoded as they execute,
+/// running past the end of the loaded program halts the machine.
+///
+#[derive(Clone)]
 pub struct BeltMachine {
-    pub belt: [u16; 16],
-    pub memory: [u16; 65536],
-}
\ No newline at end of file
+    pub belt: [u16; BELT_LENGTH],
+    pub memory: [u16; MEMORY_SIZE],
+    pub pc: u16,
+    /// Address execution starts at after a reset.
+    pub entry: u16,
+    pub call_stack: Vec<Frame>,
+    pub program_end: usize,
+    /// Names of the labels of the loaded program by their address.
+    pub symbols: BTreeMap<u16, String>,
+    halted: bool,
+    fault: Option<Fault>,
+}
+
+impl Default for BeltMachine {
+    fn default() -> Self {
+        BeltMachine {
+            belt: [0; BELT_LENGTH],
+            memory: [0; MEMORY_SIZE],
+            pc: 0,
+            entry: 0,
+            call_stack: Vec::new(),
+            program_end: 0,
+            symbols: BTreeMap::new(),
+            halted: false,
+            fault: None,
+        }
+    }
+}
+
+impl BeltMachine {
+    pub fn new() -> BeltMachine {
+        BeltMachine::default()
+    }
+
+    pub fn with_program(program: &[Instruction]) -> BeltMachine {
+        let mut machine = BeltMachine::new();
+        machine.load(program);
+        machine
+    }
+
+    pub fn load(&mut self, program: &[Instruction]) {
+        self.load_image(&encode_program(program));
+    }
+
+    /// Copies the program words to the start of memory and resets the execution state,
+    /// the rest of memory is kept.
+    pub fn load_image(&mut self, words: &[u16]) {
+        assert!(
+            words.len() <= MEMORY_SIZE,
+            "program does not fit into memory"
+        );
+        self.memory[..words.len()].copy_from_slice(words);
+        self.entry = 0;
+        self.program_end = words.len();
+        self.symbols.clear();
+        self.reset();
+    }
+
+    /// Copies the sections of the image to their addresses and resets the machine to start at
+    /// its entry point, the rest of memory is kept. Running past the end of the section holding
+    /// the entry point halts the machine.
+    pub fn load_sections(&mut self, image: &Image) {
+        let mut program_end = 0;
+        for section in &image.sections {
+            let start = section.address as usize;
+            let end = start + section.words.len();
+            assert!(end <= MEMORY_SIZE, "section does not fit into memory");
+            self.memory[start..end].copy_from_slice(&section.words);
+            if (start..end).contains(&(image.entry as usize)) {
+                program_end = end;
+            }
+        }
+        self.entry = image.entry;
+        self.program_end = program_end;
+        // The first name in order of several at the same address.
+        self.symbols.clear();
+        for (name, &address) in &image.symbols {
+            self.symbols.entry(address).or_insert_with(|| name.clone());
+        }
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.belt = [0; BELT_LENGTH];
+        self.pc = self.entry;
+        self.call_stack.clear();
+        self.halted = false;
+        self.fault = None;
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// The fault which stopped the machine, it stays set until the next reset.
+    pub fn fault(&self) -> Option<&Fault> {
+        self.fault.as_ref()
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            belt: self.belt,
+            pc: self.pc,
+            call_stack: self.call_stack.clone(),
+        }
+    }
+
+    /// The symbol at or before `address` and how far the address is past it, for a debugger to
+    /// show it as `loop+2`.
+    pub fn symbol_at(&self, address: u16) -> Option<(&str, u16)> {
+        self.symbols
+            .range(..=address)
+            .next_back()
+            .map(|(&start, name)| (name.as_str(), address - start))
+    }
+
+    pub fn current_instruction(&self) -> Option<Instruction> {
+        decode(&self.memory[self.pc as usize..])
+            .ok()
+            .map(|(instruction, _)| instruction)
+    }
+
+    /// Executes at most `max_steps` instructions, stopping early on anything but `Continue`.
+    pub fn run(&mut self, max_steps: usize) -> StepOutcome {
+        for _ in 0..max_steps {
+            let outcome = self.step();
+            if outcome != StepOutcome::Continue {
+                return outcome;
+            }
+        }
+        StepOutcome::Continue
+    }
+
+    pub fn step(&mut self) -> StepOutcome {
+        if let Some(fault) = &self.fault {
+            return StepOutcome::Fault(fault.clone());
+        }
+        if self.halted {
+            return StepOutcome::Halted;
+        }
+        if self.pc as usize >= self.program_end {
+            self.halted = true;
+            return StepOutcome::Halted;
+        }
+        let (instruction, len) = match decode(&self.memory[self.pc as usize..]) {
+            Ok(decoded) => decoded,
+            Err(error) => return self.raise(FaultKind::InvalidInstruction(error), None),
+        };
+        match self.execute(instruction, len) {
+            Ok(outcome) => outcome,
+            Err(kind) => self.raise(kind, Some(instruction)),
+        }
+    }
+
+    fn raise(&mut self, kind: FaultKind, instruction: Option<Instruction>) -> StepOutcome {
+        let fault = Fault {
+            kind,
+            pc: self.pc,
+            instruction,
+            snapshot: self.snapshot(),
+        };
+        self.fault = Some(fault.clone());
+        StepOutcome::Fault(fault)
+    }
+
+    fn execute(&mut self, instruction: Instruction, len: usize) -> Result<StepOutcome, FaultKind> {
+        let next_pc = self.pc.wrapping_add(len as u16);
+
+        match instruction {
+            Instruction::Constant { op, pos, constant } => {
+                let value = self.get(pos);
+                match op {
+                    ConstantOp::And => self.push(value & constant),
+                    ConstantOp::Or => self.push(value | constant),
+                    ConstantOp::Xor => self.push(value ^ constant),
+                    ConstantOp::Jump if value != 0 => return self.jump(constant),
+                    ConstantOp::Jump => {}
+                }
+            }
+            Instruction::LoadConstant { constant } => self.push(constant),
+            Instruction::Immediate { op, pos, imm } => match op {
+                ImmediateOp::Left => self.push(shift_left(self.get(pos), imm as u32)),
+                ImmediateOp::Right => self.push(shift_right(self.get(pos), imm as u32)),
+                ImmediateOp::Call => return self.call(pos, imm, next_pc),
+                ImmediateOp::Ret => return self.ret(pos, imm),
+            },
+            Instruction::Register { op, pos1, pos2 } => {
+                let (lhs, rhs) = (self.get(pos1), self.get(pos2));
+                match op {
+                    RegOp::Add => self.push(lhs.wrapping_add(rhs)),
+                    RegOp::Sub => self.push(lhs.wrapping_sub(rhs)),
+                    RegOp::And => self.push(lhs & rhs),
+                    RegOp::Or => self.push(lhs | rhs),
+                    RegOp::Xor => self.push(lhs ^ rhs),
+                    RegOp::Mul => self.push(lhs.wrapping_mul(rhs)),
+                    RegOp::Div => match lhs.checked_div(rhs) {
+                        Some(quotient) => self.push(quotient),
+                        None => return Err(FaultKind::DivideByZero),
+                    },
+                    RegOp::Save => self.memory[lhs as usize] = rhs,
+                    RegOp::ShiftLeft => self.push(shift_left(lhs, rhs as u32)),
+                    RegOp::ShiftRight => self.push(shift_right(lhs, rhs as u32)),
+                    // Branches test the first operand as a signed value and take
+                    // their target from the second one.
+                    RegOp::BranchLower if (lhs as i16) < 0 => return self.jump(rhs),
+                    RegOp::BranchLowerEq if (lhs as i16) <= 0 => return self.jump(rhs),
+                    RegOp::BranchEq if lhs == 0 => return self.jump(rhs),
+                    RegOp::BranchLower | RegOp::BranchLowerEq | RegOp::BranchEq => {}
+                }
+            }
+            Instruction::Unary { op, pos } => {
+                let value = self.get(pos);
+                match op {
+                    UnaryOp::Load => self.push(self.memory[value as usize]),
+                    UnaryOp::Jump => return self.jump(value),
+                    UnaryOp::Push => self.push(value),
+                }
+            }
+            Instruction::Zero { op } => match op {
+                ZeroOp::Nop => {}
+                ZeroOp::Pop => self.pop(),
+                ZeroOp::Break => {
+                    self.pc = next_pc;
+                    return Ok(StepOutcome::Break);
+                }
+            },
+        }
+
+        self.pc = next_pc;
+        Ok(StepOutcome::Continue)
+    }
+
+    fn get(&self, pos: BeltPos) -> u16 {
+        self.belt.get(pos.0 as usize).copied().unwrap_or(0)
+    }
+
+    fn push(&mut self, value: u16) {
+        self.belt.rotate_right(1);
+        self.belt[0] = value;
+    }
+
+    fn pop(&mut self) {
+        self.belt.rotate_left(1);
+        self.belt[BELT_LENGTH - 1] = 0;
+    }
+
+    // Jumping right behind the last instruction is allowed and halts the machine.
+    fn jump(&mut self, target: u16) -> Result<StepOutcome, FaultKind> {
+        self.check_target(target)?;
+        self.pc = target;
+        Ok(StepOutcome::Continue)
+    }
+
+    fn check_target(&self, target: u16) -> Result<(), FaultKind> {
+        if target as usize > self.program_end {
+            return Err(FaultKind::JumpOutOfBounds { target });
+        }
+        Ok(())
+    }
+
+    // The callee starts with a fresh belt holding the `count` values behind the target.
+    fn call(&mut self, pos: BeltPos, count: u8, return_pc: u16) -> Result<StepOutcome, FaultKind> {
+        if self.call_stack.len() >= CALL_STACK_LIMIT {
+            return Err(FaultKind::CallStackOverflow);
+        }
+        let target = self.get(pos);
+        self.check_target(target)?;
+        let mut belt = [0; BELT_LENGTH];
+        for (i, value) in belt.iter_mut().enumerate().take(count as usize) {
+            *value = self.get(BeltPos(pos.0.saturating_add(1 + i as u8)));
+        }
+        self.call_stack.push(Frame {
+            return_pc,
+            belt: self.belt,
+        });
+        self.belt = belt;
+        self.pc = target;
+        Ok(StepOutcome::Continue)
+    }
+
+    // Returns `count` values starting at `pos`, they end up in the same order at the
+    // front of the caller's belt.
+    fn ret(&mut self, pos: BeltPos, count: u8) -> Result<StepOutcome, FaultKind> {
+        let results: Vec<u16> = (0..count)
+            .map(|i| self.get(BeltPos(pos.0.saturating_add(i))))
+            .collect();
+        let frame = self.call_stack.pop().ok_or(FaultKind::CallStackUnderflow)?;
+        self.belt = frame.belt;
+        for &value in results.iter().rev() {
+            self.push(value);
+        }
+        self.pc = frame.return_pc;
+        Ok(StepOutcome::Continue)
+    }
+}
+
+fn shift_left(value: u16, amount: u32) -> u16 {
+    value.checked_shl(amount).unwrap_or(0)
+}
+
+fn shift_right(value: u16, amount: u32) -> u16 {
+    value.checked_shr(amount).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assembly_compiler::belt::assembler::assemble_belt;
+    use assembly_compiler::belt::encoding::DecodeError;
+    use assembly_compiler::belt::image::ImageSection;
+    use rstest::rstest;
+
+    fn run_program(program: Vec<Instruction>) -> (BeltMachine, StepOutcome) {
+        let mut machine = BeltMachine::with_program(&program);
+        let outcome = machine.run(1000);
+        (machine, outcome)
+    }
+
+    fn lc(constant: u16) -> Instruction {
+        Instruction::LoadConstant { constant }
+    }
+
+    fn reg(op: RegOp, pos1: u8, pos2: u8) -> Instruction {
+        Instruction::Register {
+            op,
+            pos1: BeltPos(pos1),
+            pos2: BeltPos(pos2),
+        }
+    }
+
+    #[test]
+    fn test_push_rotates_belt() {
+        let (machine, outcome) = run_program(vec![lc(1), lc(2), lc(3)]);
+        assert_eq!(outcome, StepOutcome::Halted);
+        assert_eq!(machine.belt[..4], [3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn test_oldest_value_falls_off() {
+        let (machine, _) = run_program((1..=17).map(lc).collect());
+        assert_eq!(machine.belt[0], 17);
+        assert_eq!(machine.belt[15], 2);
+    }
+
+    #[test]
+    fn test_pop() {
+        let program = vec![lc(1), lc(2), Instruction::Zero { op: ZeroOp::Pop }];
+        let (machine, _) = run_program(program);
+        assert_eq!(machine.belt[..2], [1, 0]);
+    }
+
+    #[rstest]
+    #[case(RegOp::Add, 7, 5, 12)]
+    #[case(RegOp::Sub, 7, 5, 2)]
+    #[case(RegOp::Sub, 5, 7, 0xFFFE)]
+    #[case(RegOp::And, 0b1100, 0b1010, 0b1000)]
+    #[case(RegOp::Or, 0b1100, 0b1010, 0b1110)]
+    #[case(RegOp::Xor, 0b1100, 0b1010, 0b0110)]
+    #[case(RegOp::Mul, 300, 300, 0x5F90)]
+    #[case(RegOp::Div, 7, 2, 3)]
+    #[case(RegOp::ShiftLeft, 1, 4, 16)]
+    #[case(RegOp::ShiftLeft, 1, 16, 0)]
+    #[case(RegOp::ShiftRight, 0x8000, 15, 1)]
+    fn test_register_op(
+        #[case] op: RegOp,
+        #[case] lhs: u16,
+        #[case] rhs: u16,
+        #[case] result: u16,
+    ) {
+        let (machine, outcome) = run_program(vec![lc(rhs), lc(lhs), reg(op, 0, 1)]);
+        assert_eq!(outcome, StepOutcome::Halted);
+        assert_eq!(machine.belt[..3], [result, lhs, rhs]);
+    }
+
+    #[rstest]
+    #[case(ConstantOp::And, 0x00FF, 0x0F0F, 0x000F)]
+    #[case(ConstantOp::Or, 0x00FF, 0x0F0F, 0x0FFF)]
+    #[case(ConstantOp::Xor, 0x00FF, 0x0F0F, 0x0FF0)]
+    fn test_constant_op(
+        #[case] op: ConstantOp,
+        #[case] value: u16,
+        #[case] constant: u16,
+        #[case] result: u16,
+    ) {
+        let program = vec![
+            lc(value),
+            Instruction::Constant {
+                op,
+                pos: BeltPos(0),
+                constant,
+            },
+        ];
+        let (machine, _) = run_program(program);
+        assert_eq!(machine.belt[0], result);
+    }
+
+    #[rstest]
+    #[case(ImmediateOp::Left, 0x0001, 4, 0x0010)]
+    #[case(ImmediateOp::Right, 0x0100, 8, 0x0001)]
+    fn test_immediate_shift(
+        #[case] op: ImmediateOp,
+        #[case] value: u16,
+        #[case] imm: u8,
+        #[case] result: u16,
+    ) {
+        let program = vec![
+            lc(value),
+            Instruction::Immediate {
+                op,
+                pos: BeltPos(0),
+                imm,
+            },
+        ];
+        let (machine, _) = run_program(program);
+        assert_eq!(machine.belt[0], result);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let program = vec![
+            lc(0xBEEF),
+            lc(0x0100),
+            reg(RegOp::Save, 0, 1),
+            Instruction::Unary {
+                op: UnaryOp::Load,
+                pos: BeltPos(0),
+            },
+        ];
+        let (machine, _) = run_program(program);
+        assert_eq!(machine.memory[0x0100], 0xBEEF);
+        assert_eq!(machine.belt[0], 0xBEEF);
+    }
+
+    #[test]
+    fn test_push_duplicates_value() {
+        let program = vec![
+            lc(1),
+            lc(2),
+            Instruction::Unary {
+                op: UnaryOp::Push,
+                pos: BeltPos(1),
+            },
+        ];
+        let (machine, _) = run_program(program);
+        assert_eq!(machine.belt[..3], [1, 2, 1]);
+    }
+
+    #[test]
+    fn test_countdown_loop() {
+        // Counts b0 down from 5, jump loops while the counter is non-zero.
+        let program = vec![
+            lc(5),
+            lc(0xFFFF),
+            reg(RegOp::Add, 1, 0),
+            Instruction::Constant {
+                op: ConstantOp::Jump,
+                pos: BeltPos(0),
+                constant: 2,
+            },
+        ];
+        let mut machine = BeltMachine::with_program(&program);
+        let outcome = machine.run(1000);
+        assert_eq!(outcome, StepOutcome::Halted);
+        assert_eq!(machine.belt[0], 0);
+    }
+
+    #[test]
+    fn test_assembled_countdown_loop() {
+        let source = "
+            lc 5
+            loop:
+                lc 0xFFFF
+                add b0 b1
+                jump b0 loop
+            lc done
+            done:
+        ";
+        let assembly = assemble_belt(source).unwrap();
+        let mut machine = BeltMachine::with_program(&assembly.instructions);
+        assert_eq!(machine.run(1000), StepOutcome::Halted);
+        assert_eq!(machine.belt[..2], [assembly.labels["done"], 0]);
+    }
+
+    #[rstest]
+    #[case(RegOp::BranchLower, 0xFFFF, true)]
+    #[case(RegOp::BranchLower, 0, false)]
+    #[case(RegOp::BranchLowerEq, 0, true)]
+    #[case(RegOp::BranchLowerEq, 1, false)]
+    #[case(RegOp::BranchEq, 0, true)]
+    #[case(RegOp::BranchEq, 0x8000, false)]
+    fn test_branch(#[case] op: RegOp, #[case] value: u16, #[case] taken: bool) {
+        let program = vec![lc(7), lc(value), reg(op, 0, 1), lc(0xAAAA)];
+        let (machine, _) = run_program(program);
+        assert_eq!(machine.belt[0] != 0xAAAA, taken);
+    }
+
+    #[test]
+    fn test_call_and_ret() {
+        let program = vec![
+            lc(20),
+            lc(22),
+            lc(9),
+            Instruction::Immediate {
+                op: ImmediateOp::Call,
+                pos: BeltPos(0),
+                imm: 2,
+            },
+            Instruction::Constant {
+                op: ConstantOp::Jump,
+                pos: BeltPos(0),
+                constant: 11,
+            },
+            reg(RegOp::Add, 0, 1),
+            Instruction::Immediate {
+                op: ImmediateOp::Ret,
+                pos: BeltPos(0),
+                imm: 1,
+            },
+        ];
+        let mut machine = BeltMachine::with_program(&program);
+        assert_eq!(machine.run(4), StepOutcome::Continue);
+        assert_eq!(machine.pc, 9);
+        assert_eq!(machine.call_stack.len(), 1);
+        assert_eq!(machine.belt[..3], [22, 20, 0]);
+
+        assert_eq!(machine.run(1000), StepOutcome::Halted);
+        assert!(machine.call_stack.is_empty());
+        assert_eq!(machine.belt[..4], [42, 9, 22, 20]);
+    }
+
+    #[test]
+    fn test_break_resumes() {
+        let program = vec![lc(1), Instruction::Zero { op: ZeroOp::Break }, lc(2)];
+        let mut machine = BeltMachine::with_program(&program);
+        assert_eq!(machine.run(1000), StepOutcome::Break);
+        assert_eq!(machine.pc, 3);
+        assert_eq!(machine.run(1000), StepOutcome::Halted);
+        assert_eq!(machine.belt[..2], [2, 1]);
+        assert_eq!(machine.step(), StepOutcome::Halted);
+    }
+
+    #[test]
+    fn test_run_stops_after_max_steps() {
+        let program = vec![Instruction::Unary {
+            op: UnaryOp::Jump,
+            pos: BeltPos(0),
+        }];
+        let mut machine = BeltMachine::with_program(&program);
+        assert_eq!(machine.run(100), StepOutcome::Continue);
+        assert_eq!(machine.pc, 0);
+    }
+
+    fn expect_fault(outcome: StepOutcome) -> Fault {
+        match outcome {
+            StepOutcome::Fault(fault) => fault,
+            _ => panic!("Expected a fault, got {:?}", outcome),
+        }
+    }
+
+    #[test]
+    fn test_divide_by_zero_faults() {
+        let (machine, outcome) = run_program(vec![lc(0), lc(1), reg(RegOp::Div, 0, 1)]);
+        let fault = expect_fault(outcome);
+        assert_eq!(fault.kind, FaultKind::DivideByZero);
+        assert_eq!(fault.pc, 4);
+        assert_eq!(fault.instruction, Some(reg(RegOp::Div, 0, 1)));
+        assert_eq!(fault.snapshot.belt[..2], [1, 0]);
+        assert_eq!(machine.fault(), Some(&fault));
+        assert_eq!(machine.pc, 4);
+    }
+
+    #[test]
+    fn test_fault_is_sticky_until_reset() {
+        let mut machine = BeltMachine::with_program(&[lc(0), reg(RegOp::Div, 0, 0)]);
+        let fault = expect_fault(machine.run(1000));
+        assert_eq!(machine.step(), StepOutcome::Fault(fault));
+        machine.reset();
+        assert_eq!(machine.fault(), None);
+        assert_eq!(machine.step(), StepOutcome::Continue);
+    }
+
+    #[test]
+    fn test_ret_without_call_faults() {
+        let program = vec![Instruction::Immediate {
+            op: ImmediateOp::Ret,
+            pos: BeltPos(0),
+            imm: 0,
+        }];
+        let (_, outcome) = run_program(program);
+        let fault = expect_fault(outcome);
+        assert_eq!(fault.kind, FaultKind::CallStackUnderflow);
+        assert_eq!(
+            fault.to_string(),
+            "return without a matching call at 0x0000"
+        );
+    }
+
+    #[test]
+    fn test_unbounded_recursion_faults() {
+        let program = vec![
+            lc(1),
+            Instruction::Immediate {
+                op: ImmediateOp::Call,
+                pos: BeltPos(0),
+                imm: 0,
+            },
+        ];
+        let (machine, outcome) = run_program(program);
+        let fault = expect_fault(outcome);
+        assert_eq!(fault.kind, FaultKind::CallStackOverflow);
+        assert_eq!(fault.snapshot.call_stack.len(), CALL_STACK_LIMIT);
+        assert_eq!(machine.call_stack.len(), CALL_STACK_LIMIT);
+    }
+
+    #[test]
+    fn test_jump_outside_program_faults() {
+        let program = vec![
+            lc(100),
+            Instruction::Unary {
+                op: UnaryOp::Jump,
+                pos: BeltPos(0),
+            },
+        ];
+        let (machine, outcome) = run_program(program);
+        let fault = expect_fault(outcome);
+        assert_eq!(fault.kind, FaultKind::JumpOutOfBounds { target: 100 });
+        assert_eq!(fault.pc, 2);
+        assert_eq!(machine.pc, 2);
+    }
+
+    #[test]
+    fn test_undecodable_word_faults() {
+        let mut machine = BeltMachine::new();
+        machine.load_image(&[0x0000, 0xFFFF]);
+        let fault = expect_fault(machine.run(1000));
+        assert_eq!(
+            fault.kind,
+            FaultKind::InvalidInstruction(DecodeError::InvalidOpcode { word: 0xFFFF })
+        );
+        assert_eq!(fault.pc, 1);
+        assert_eq!(fault.instruction, None);
+    }
+
+    #[test]
+    fn test_missing_constant_faults() {
+        let mut machine = BeltMachine::new();
+        machine.load_image(&[0xC000]);
+        machine.memory[1] = 0x1234;
+        machine.pc = (MEMORY_SIZE - 1) as u16;
+        machine.memory[MEMORY_SIZE - 1] = 0xC000;
+        machine.program_end = MEMORY_SIZE;
+        let fault = expect_fault(machine.step());
+        assert_eq!(
+            fault.kind,
+            FaultKind::InvalidInstruction(DecodeError::MissingConstant { word: 0xC000 })
+        );
+    }
+
+    #[test]
+    fn test_self_modifying_code() {
+        // Overwrites the trailing nop with a break.
+        let program = vec![
+            lc(0x0002),
+            lc(5),
+            reg(RegOp::Save, 0, 1),
+            Instruction::Zero { op: ZeroOp::Nop },
+        ];
+        let (machine, outcome) = run_program(program);
+        assert_eq!(outcome, StepOutcome::Break);
+        assert_eq!(machine.memory[5], 0x0002);
+        assert_eq!(machine.pc, 6);
+    }
+
+    #[test]
+    fn test_load_sections() {
+        let source = "
+            helper: nop
+            main:
+                lc 0x100
+                load b0
+            done:
+        ";
+        let assembly = assemble_belt(source).unwrap();
+        let mut image = Image::from_assembly(&assembly);
+        image.entry = assembly.labels["main"];
+        image.sections.push(ImageSection {
+            name: ".data".to_string(),
+            address: 0x100,
+            words: vec![0xBEEF],
+        });
+        let image = Image::from_bytes(&image.to_bytes()).unwrap();
+
+        let mut machine = BeltMachine::new();
+        machine.load_sections(&image);
+        assert_eq!(machine.pc, 1);
+        assert_eq!(machine.run(1000), StepOutcome::Halted);
+        assert_eq!(machine.belt[0], 0xBEEF);
+        assert_eq!(machine.pc, assembly.labels["done"]);
+        assert_eq!(machine.symbol_at(2), Some(("main", 1)));
+        assert_eq!(machine.symbol_at(0), Some(("helper", 0)));
+
+        machine.reset();
+        assert_eq!(machine.pc, 1);
+        // A plain program starts at 0 again and has no symbols.
+        machine.load_image(&assembly.image());
+        assert_eq!(machine.pc, 0);
+        assert_eq!(machine.symbol_at(2), None);
+    }
+}
@@ -15,7 +15,7 @@ pub fn App() -> impl IntoView {
                 <thaw::Flex>
                     <thaw::Flex vertical=true style="width: 30%;">
                         <EditorPanel execution_state=execution_state code=code />
-                        <StatusBar execution_mode=execution_state.read_only() />
+                        <StatusBar execution_mode=execution_state.read_only() machine=app_state.machine.read_only() />
                     </thaw::Flex>
                     <StateVisualization state=app_state.clone() />
                 </thaw::Flex>
@@ -14,7 +14,7 @@ pub fn StateVisualization(state: AppState) -> impl IntoView {
                     <CardHeader>
                         "Program Counter"
                     </CardHeader>
-                    <CardFooter>{move || format!("0x{:04X}", 0x1234/*state.machine.program_counter*/)}</CardFooter>
+                    <CardFooter>{move || format!("0x{:04X}", state.machine.read().pc)}</CardFooter>
                 </thaw::Card>
                 <thaw::Card>
                     <thaw::CardHeader>"Stack Pointer"</thaw::CardHeader>
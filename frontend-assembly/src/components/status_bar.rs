@@ -1,9 +1,13 @@
 use crate::state::{AppState, ExecutionMode};
+use belt_interpreter::BeltMachine;
 use leptos::prelude::*;
 use thaw::Flex;
 
 #[component]
-pub fn StatusBar(execution_mode: ReadSignal<ExecutionMode>) -> impl IntoView {
+pub fn StatusBar(
+    execution_mode: ReadSignal<ExecutionMode>,
+    machine: ReadSignal<BeltMachine>,
+) -> impl IntoView {
     view! {
         <Flex
             style="padding: 8px 16px; background-color: var(--color-bg-2)"
@@ -18,7 +22,7 @@ pub fn StatusBar(execution_mode: ReadSignal<ExecutionMode>) -> impl IntoView {
                     }}
                 </span>
                 <span style="font-size: 14px">
-                    "Errors: None" 
+                    "Errors: " {move || machine.read().fault().map_or("None".to_string(), |fault| fault.to_string())}
                 </span>
             </Flex>
             <span style="font-size: 14px; color: var(--color-text-secondary)">
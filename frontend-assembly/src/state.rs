@@ -17,10 +17,7 @@ pub struct AppState {
 impl AppState {
     pub fn new() -> AppState {
         AppState {
-            machine: RwSignal::new(BeltMachine {
-                belt: [0; 16],
-                memory: [0; 65536],
-            }),
+            machine: RwSignal::new(BeltMachine::new()),
         }
     }
 }
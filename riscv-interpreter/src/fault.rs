@@ -0,0 +1,78 @@
+use std::fmt::{Display, Formatter};
+
+use assembly_compiler::riscv::ast::Instruction;
+use assembly_compiler::riscv::encoding::DecodeError;
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum FaultKind {
+    /// A jump or branch to an address which is not aligned to an instruction.
+    MisalignedJump {
+        target: u32,
+    },
+    MisalignedLoad {
+        address: u32,
+    },
+    MisalignedStore {
+        address: u32,
+    },
+    /// An access of memory which the machine does not have.
+    AccessFault {
+        address: u32,
+    },
+    InvalidInstruction(DecodeError),
+    /// A valid instruction which the ISA of the machine does not include.
+    IllegalInstruction,
+}
+
+/// Registers of the machine at the moment of a fault, memory is left out as it is not
+/// touched by a faulting instruction.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Snapshot {
+    pub registers: [u32; 32],
+    pub pc: u32,
+}
+
+/// Raised instead of executing an instruction, the machine is left as it was before it.
+/// `instruction` is missing when the faulting bytes could not be decoded.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Fault {
+    pub kind: FaultKind,
+    pub pc: u32,
+    pub instruction: Option<Instruction>,
+    pub snapshot: Snapshot,
+}
+
+impl Display for FaultKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FaultKind::MisalignedJump { target } => {
+                write!(f, "jump to misaligned address 0x{:08X}", target)
+            }
+            FaultKind::MisalignedLoad { address } => {
+                write!(f, "misaligned load from 0x{:08X}", address)
+            }
+            FaultKind::MisalignedStore { address } => {
+                write!(f, "misaligned store to 0x{:08X}", address)
+            }
+            FaultKind::AccessFault { address } => {
+                write!(f, "access of 0x{:08X} outside of memory", address)
+            }
+            FaultKind::InvalidInstruction(error) => write!(f, "invalid instruction, {}", error),
+            FaultKind::IllegalInstruction => write!(f, "illegal instruction"),
+        }
+    }
+}
+
+impl Display for Fault {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match (&self.kind, self.instruction) {
+            (FaultKind::IllegalInstruction, Some(instruction)) => {
+                write!(f, "illegal instruction `{}`", instruction.mnemonic())?;
+            }
+            (kind, _) => write!(f, "{}", kind)?,
+        }
+        write!(f, " at 0x{:08X}", self.pc)
+    }
+}
+
+impl std::error::Error for Fault {}
@@ -0,0 +1,929 @@
+pub mod fault;
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use assembly_compiler::riscv::ast::{
+    BOpcode, IOpcode, Instruction, LOpcode, ROpcode, Register, SOpcode, SystemOpcode, UOpcode,
+};
+use assembly_compiler::riscv::elf::{ElfError, read_executable};
+use assembly_compiler::riscv::encoding::{decode_bytes, encode_bytes};
+use assembly_compiler::riscv::isa::{Isa, Xlen};
+
+use crate::fault::{Fault, FaultKind, Snapshot};
+
+pub const MEMORY_SIZE: usize = 65536;
+/// The most memory an executable may span, its heap and stack included.
+pub const MAX_MEMORY_SIZE: usize = 1 << 24;
+
+const PAGE_SIZE: u32 = 0x1000;
+
+const SP: Register = Register(2);
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum StepOutcome {
+    Continue,
+    /// `ebreak`, the machine stops behind it.
+    Break,
+    /// `ecall`, the machine stops behind it for the caller to service the call from `a7` and
+    /// the argument registers before resuming.
+    Ecall,
+    Halted,
+    Fault(Fault),
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum LoadError {
+    Elf(ElfError),
+    /// The segments of the executable are too far apart for the memory of the machine.
+    TooLarge {
+        size: u64,
+    },
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Elf(error) => write!(f, "{}", error),
+            LoadError::TooLarge { size } => write!(
+                f,
+                "executable needs {} bytes of memory, at most {} fit",
+                size, MAX_MEMORY_SIZE
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+///
+/// A single RV32I hart executing `riscv::ast` instructions.
+///
+/// `x0` reads as zero whatever is written to it. Memory is byte-addressable and
+/// little-endian, programs live in it in their binary encoding and are decoded as they
+/// execute, reaching the end of the loaded program halts the machine. The M and C extensions
+/// are executed as well when `isa` includes them, any other instruction is illegal.
+///
+/// `memory[0]` is at address `memory_base`, which is 0 but for executables linked elsewhere.
+///
+#[derive(Clone)]
+pub struct RiscvMachine {
+    pub registers: [u32; 32],
+    pub memory: Vec<u8>,
+    pub memory_base: u32,
+    pub pc: u32,
+    /// Address execution starts at after a reset.
+    pub entry: u32,
+    pub program_end: u32,
+    pub isa: Isa,
+    /// Names of the functions and data of the loaded program by their address.
+    pub symbols: BTreeMap<u32, String>,
+    halted: bool,
+    fault: Option<Fault>,
+}
+
+impl Default for RiscvMachine {
+    fn default() -> Self {
+        let mut machine = RiscvMachine {
+            registers: [0; 32],
+            memory: vec![0; MEMORY_SIZE],
+            memory_base: 0,
+            pc: 0,
+            entry: 0,
+            program_end: 0,
+            isa: Isa::RV32I,
+            symbols: BTreeMap::new(),
+            halted: false,
+            fault: None,
+        };
+        machine.reset();
+        machine
+    }
+}
+
+impl RiscvMachine {
+    pub fn new() -> RiscvMachine {
+        RiscvMachine::default()
+    }
+
+    pub fn with_isa(isa: Isa) -> RiscvMachine {
+        assert_eq!(isa.xlen, Xlen::Rv32, "the machine only implements RV32");
+        RiscvMachine {
+            isa,
+            ..RiscvMachine::default()
+        }
+    }
+
+    pub fn with_program(program: &[Instruction]) -> RiscvMachine {
+        let mut machine = RiscvMachine::new();
+        machine.load(program);
+        machine
+    }
+
+    pub fn load(&mut self, program: &[Instruction]) {
+        self.load_image(&encode_bytes(program));
+    }
+
+    /// Copies the program bytes to the start of memory, where it starts, and resets the
+    /// execution state, the rest of memory is kept.
+    pub fn load_image(&mut self, bytes: &[u8]) {
+        assert!(
+            bytes.len() <= self.memory.len(),
+            "program does not fit into memory"
+        );
+        self.memory[..bytes.len()].copy_from_slice(bytes);
+        self.entry = self.memory_base;
+        self.program_end = self.memory_base + bytes.len() as u32;
+        self.symbols.clear();
+        self.reset();
+    }
+
+    ///
+    /// Loads the segments of an RV32 executable, like one GCC compiled, and resets the machine
+    /// to start at its entry point.
+    ///
+    /// Memory is replaced by one starting at the page of the lowest segment and reaching
+    /// `MEMORY_SIZE` bytes past the page of the highest for the heap and the stack. Reaching
+    /// the end of the segment holding the entry point halts the machine.
+    ///
+    pub fn load_elf(&mut self, bytes: &[u8]) -> Result<(), LoadError> {
+        let executable = read_executable(bytes).map_err(LoadError::Elf)?;
+        let segments = &executable.segments;
+        let base = segments
+            .iter()
+            .map(|segment| segment.address)
+            .min()
+            .unwrap_or(0)
+            & !(PAGE_SIZE - 1);
+        let end = segments
+            .iter()
+            .map(|segment| segment.address as u64 + segment.memory_size as u64)
+            .max()
+            .unwrap_or(0);
+        let size = (end - base as u64).next_multiple_of(PAGE_SIZE as u64) + MEMORY_SIZE as u64;
+        if size > MAX_MEMORY_SIZE as u64 || base as u64 + size > 1 << 32 {
+            return Err(LoadError::TooLarge { size });
+        }
+
+        self.memory = vec![0; size as usize];
+        self.memory_base = base;
+        for segment in segments {
+            let start = (segment.address - base) as usize;
+            self.memory[start..start + segment.bytes.len()].copy_from_slice(&segment.bytes);
+        }
+        self.entry = executable.entry;
+        self.program_end = segments
+            .iter()
+            .find(|segment| {
+                let start = segment.address as u64;
+                (start..start + segment.memory_size as u64).contains(&(executable.entry as u64))
+            })
+            .map_or(end as u32, |segment| {
+                segment.address + segment.bytes.len() as u32
+            });
+        self.symbols = executable.symbols;
+        self.reset();
+        Ok(())
+    }
+
+    /// Clears the registers but for `sp`, which points to the end of memory for the stack to
+    /// grow down from.
+    pub fn reset(&mut self) {
+        self.registers = [0; 32];
+        self.registers[SP.0 as usize] = self.memory_base.wrapping_add(self.memory.len() as u32);
+        self.pc = self.entry;
+        self.halted = false;
+        self.fault = None;
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// The fault which stopped the machine, it stays set until the next reset.
+    pub fn fault(&self) -> Option<&Fault> {
+        self.fault.as_ref()
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            registers: self.registers,
+            pc: self.pc,
+        }
+    }
+
+    /// The symbol at or before `address` and how far the address is past it, for a debugger to
+    /// show it as `main+0x10`.
+    pub fn symbol_at(&self, address: u32) -> Option<(&str, u32)> {
+        self.symbols
+            .range(..=address)
+            .next_back()
+            .map(|(&start, name)| (name.as_str(), address - start))
+    }
+
+    pub fn current_instruction(&self) -> Option<Instruction> {
+        decode_bytes(self.memory.get(self.offset(self.pc)..)?)
+            .ok()
+            .map(|(instruction, _)| instruction)
+    }
+
+    /// Executes at most `max_steps` instructions, stopping early on anything but `Continue`.
+    pub fn run(&mut self, max_steps: usize) -> StepOutcome {
+        for _ in 0..max_steps {
+            let outcome = self.step();
+            if outcome != StepOutcome::Continue {
+                return outcome;
+            }
+        }
+        StepOutcome::Continue
+    }
+
+    pub fn step(&mut self) -> StepOutcome {
+        if let Some(fault) = &self.fault {
+            return StepOutcome::Fault(fault.clone());
+        }
+        if self.halted {
+            return StepOutcome::Halted;
+        }
+        if self.pc == self.program_end {
+            self.halted = true;
+            return StepOutcome::Halted;
+        }
+        if !self.pc.is_multiple_of(self.isa.instruction_alignment()) {
+            let target = self.pc;
+            return self.raise(FaultKind::MisalignedJump { target }, None);
+        }
+        let Some(bytes) = self.memory.get(self.offset(self.pc)..) else {
+            let address = self.pc;
+            return self.raise(FaultKind::AccessFault { address }, None);
+        };
+        let (instruction, len) = match decode_bytes(bytes) {
+            Ok(decoded) => decoded,
+            Err(error) => return self.raise(FaultKind::InvalidInstruction(error), None),
+        };
+        match self.execute(instruction, len as u32) {
+            Ok(outcome) => outcome,
+            Err(kind) => self.raise(kind, Some(instruction)),
+        }
+    }
+
+    fn raise(&mut self, kind: FaultKind, instruction: Option<Instruction>) -> StepOutcome {
+        let fault = Fault {
+            kind,
+            pc: self.pc,
+            instruction,
+            snapshot: self.snapshot(),
+        };
+        self.fault = Some(fault.clone());
+        StepOutcome::Fault(fault)
+    }
+
+    fn execute(&mut self, instruction: Instruction, len: u32) -> Result<StepOutcome, FaultKind> {
+        if self.isa.missing_base(&instruction).is_some()
+            || self.isa.missing_extension(&instruction).is_some()
+        {
+            return Err(FaultKind::IllegalInstruction);
+        }
+        let next_pc = self.pc.wrapping_add(len);
+
+        match instruction.expand() {
+            Instruction::IType {
+                opcode: IOpcode::Jalr,
+                rd,
+                rs1,
+                imm,
+            } => {
+                let target = self.get(rs1).wrapping_add(imm.0 as u32) & !1;
+                self.jump(target)?;
+                self.set(rd, next_pc);
+                return Ok(StepOutcome::Continue);
+            }
+            Instruction::IType {
+                opcode,
+                rd,
+                rs1,
+                imm,
+            } => {
+                let operation = immediate_operation(opcode).ok_or(FaultKind::IllegalInstruction)?;
+                // The 12 bit immediate is sign-extended, even where `sltiu` compares unsigned.
+                let imm = (imm.0 as i32) << 20 >> 20;
+                self.set(rd, operation.evaluate(self.get(rs1), imm as u32));
+            }
+            Instruction::UType { opcode, rd, imm } => {
+                let upper = (imm.0 as u32) << 12;
+                match opcode {
+                    UOpcode::Lui => self.set(rd, upper),
+                    UOpcode::Auipc => self.set(rd, self.pc.wrapping_add(upper)),
+                }
+            }
+            Instruction::RType {
+                opcode,
+                rd,
+                rs1,
+                rs2,
+            } => self.set(rd, opcode.evaluate(self.get(rs1), self.get(rs2))),
+            Instruction::JType { rd, imm, .. } => {
+                self.jump(self.pc.wrapping_add(imm.0 as u32))?;
+                self.set(rd, next_pc);
+                return Ok(StepOutcome::Continue);
+            }
+            Instruction::BType {
+                opcode,
+                rs1,
+                rs2,
+                imm,
+            } => {
+                let (lhs, rhs) = (self.get(rs1), self.get(rs2));
+                let taken = match opcode {
+                    BOpcode::Beq => lhs == rhs,
+                    BOpcode::Bne => lhs != rhs,
+                    BOpcode::Blt => (lhs as i32) < (rhs as i32),
+                    BOpcode::Bge => (lhs as i32) >= (rhs as i32),
+                    BOpcode::Bltu => lhs < rhs,
+                    BOpcode::Bgeu => lhs >= rhs,
+                };
+                if taken {
+                    return self.jump(self.pc.wrapping_add(imm.0 as u32));
+                }
+            }
+            Instruction::LType {
+                opcode,
+                rd,
+                rs1,
+                imm,
+            } => {
+                let address = self.get(rs1).wrapping_add(imm.0 as u32);
+                let value = match opcode {
+                    LOpcode::Lb => self.read(address, 1)? as i8 as u32,
+                    LOpcode::Lh => self.read(address, 2)? as i16 as u32,
+                    LOpcode::Lw => self.read(address, 4)?,
+                    LOpcode::Lbu => self.read(address, 1)?,
+                    LOpcode::Lhu => self.read(address, 2)?,
+                    LOpcode::Ld | LOpcode::Lwu => return Err(FaultKind::IllegalInstruction),
+                };
+                self.set(rd, value);
+            }
+            Instruction::SType {
+                opcode,
+                rs1,
+                rs2,
+                imm,
+            } => {
+                let address = self.get(rs1).wrapping_add(imm.0 as u32);
+                let width = match opcode {
+                    SOpcode::Sb => 1,
+                    SOpcode::Sh => 2,
+                    SOpcode::Sw => 4,
+                    SOpcode::Sd => return Err(FaultKind::IllegalInstruction),
+                };
+                self.write(address, width, self.get(rs2))?;
+            }
+            // There is a single hart and no cache, memory is always in order.
+            Instruction::Fence { .. } => {}
+            Instruction::System { opcode } => {
+                self.pc = next_pc;
+                return Ok(match opcode {
+                    SystemOpcode::Ecall => StepOutcome::Ecall,
+                    SystemOpcode::Ebreak => StepOutcome::Break,
+                });
+            }
+            _ => return Err(FaultKind::IllegalInstruction),
+        }
+
+        self.pc = next_pc;
+        Ok(StepOutcome::Continue)
+    }
+
+    pub fn get(&self, register: Register) -> u32 {
+        match register.0 {
+            0 => 0,
+            index => self.registers[index as usize],
+        }
+    }
+
+    pub fn set(&mut self, register: Register, value: u32) {
+        if register.0 != 0 {
+            self.registers[register.0 as usize] = value;
+        }
+    }
+
+    fn jump(&mut self, target: u32) -> Result<StepOutcome, FaultKind> {
+        if !target.is_multiple_of(self.isa.instruction_alignment()) {
+            return Err(FaultKind::MisalignedJump { target });
+        }
+        self.pc = target;
+        Ok(StepOutcome::Continue)
+    }
+
+    fn offset(&self, address: u32) -> usize {
+        address.wrapping_sub(self.memory_base) as usize
+    }
+
+    // Where the `width` bytes at `address` are in `memory`.
+    fn bytes(&self, address: u32, width: u32) -> Result<std::ops::Range<usize>, FaultKind> {
+        let start = self.offset(address);
+        let end = start + width as usize;
+        if end > self.memory.len() {
+            return Err(FaultKind::AccessFault { address });
+        }
+        Ok(start..end)
+    }
+
+    fn read(&self, address: u32, width: u32) -> Result<u32, FaultKind> {
+        if !address.is_multiple_of(width) {
+            return Err(FaultKind::MisalignedLoad { address });
+        }
+        let mut value = [0; 4];
+        value[..width as usize].copy_from_slice(&self.memory[self.bytes(address, width)?]);
+        Ok(u32::from_le_bytes(value))
+    }
+
+    fn write(&mut self, address: u32, width: u32, value: u32) -> Result<(), FaultKind> {
+        if !address.is_multiple_of(width) {
+            return Err(FaultKind::MisalignedStore { address });
+        }
+        let range = self.bytes(address, width)?;
+        self.memory[range].copy_from_slice(&value.to_le_bytes()[..width as usize]);
+        Ok(())
+    }
+}
+
+// The register-register operation an immediate one applies to its immediate.
+fn immediate_operation(opcode: IOpcode) -> Option<ROpcode> {
+    let operation = match opcode {
+        IOpcode::Addi => ROpcode::Add,
+        IOpcode::Slti => ROpcode::Slt,
+        IOpcode::Sltiu => ROpcode::Sltu,
+        IOpcode::Xori => ROpcode::Xor,
+        IOpcode::Ori => ROpcode::Or,
+        IOpcode::Andi => ROpcode::And,
+        IOpcode::Slli => ROpcode::Sll,
+        IOpcode::Srli => ROpcode::Srl,
+        IOpcode::Srai => ROpcode::Sra,
+        _ => return None,
+    };
+    Some(operation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assembly_compiler::riscv::assembler::{AssembleOptions, assemble_riscv_with};
+    use assembly_compiler::riscv::elf::{ExecutableOptions, write_executable};
+    use assembly_compiler::riscv::encoding::DecodeError;
+    use assembly_compiler::riscv::linker::{link, parse_linker_script};
+    use rstest::rstest;
+
+    const A0: Register = Register(10);
+    const A1: Register = Register(11);
+
+    fn assemble(source: &str, isa: Isa) -> RiscvMachine {
+        let options = AssembleOptions {
+            isa,
+            ..Default::default()
+        };
+        let assembly = assemble_riscv_with(source, &options).unwrap();
+        let mut machine = RiscvMachine::with_isa(isa);
+        machine.load_image(&assembly.image());
+        machine
+    }
+
+    fn run_source(source: &str) -> (RiscvMachine, StepOutcome) {
+        let mut machine = assemble(source, Isa::RV32I);
+        let outcome = machine.run(1000);
+        (machine, outcome)
+    }
+
+    fn expect_fault(outcome: StepOutcome) -> Fault {
+        match outcome {
+            StepOutcome::Fault(fault) => fault,
+            _ => panic!("Expected a fault, got {:?}", outcome),
+        }
+    }
+
+    #[test]
+    fn test_x0_is_hardwired() {
+        let (machine, outcome) = run_source("addi zero, zero, 5\naddi a0, zero, 7");
+        assert_eq!(outcome, StepOutcome::Halted);
+        assert_eq!(machine.registers[0], 0);
+        assert_eq!(machine.get(A0), 7);
+    }
+
+    #[rstest]
+    #[case("add", 7, 5, 12)]
+    #[case("sub", 5, 7, 0xFFFF_FFFE)]
+    #[case("sll", 1, 35, 8)]
+    #[case("slt", -1, 0, 1)]
+    #[case("sltu", -1, 0, 0)]
+    #[case("xor", 0b1100, 0b1010, 0b0110)]
+    #[case("srl", -16, 2, 0x3FFF_FFFC)]
+    #[case("sra", -16, 2, 0xFFFF_FFFC)]
+    #[case("or", 0b1100, 0b1010, 0b1110)]
+    #[case("and", 0b1100, 0b1010, 0b1000)]
+    fn test_register_op(
+        #[case] mnemonic: &str,
+        #[case] lhs: i32,
+        #[case] rhs: i32,
+        #[case] result: u32,
+    ) {
+        let source = format!("li a1, {}\nli a2, {}\n{} a0, a1, a2", lhs, rhs, mnemonic);
+        let (machine, outcome) = run_source(&source);
+        assert_eq!(outcome, StepOutcome::Halted);
+        assert_eq!(machine.get(A0), result);
+    }
+
+    #[rstest]
+    #[case("addi a0, a1, -1", 0xFFFF_FFFF)]
+    #[case("slti a0, a1, 1", 1)]
+    #[case("sltiu a0, a1, 1", 1)]
+    #[case("xori a0, a1, -1", 0xFFFF_FFFF)]
+    #[case("ori a0, a1, 0x7FF", 0x7FF)]
+    #[case("andi a0, sp, -16", 0x1_0000)]
+    #[case("slli a0, sp, 4", 0x10_0000)]
+    #[case("srli a0, sp, 16", 1)]
+    #[case("lui a0, -1", 0xFFFF_F000)]
+    #[case("auipc a0, 1", 0x1000)]
+    fn test_immediate_op(#[case] source: &str, #[case] result: u32) {
+        let (machine, _) = run_source(source);
+        assert_eq!(machine.get(A0), result);
+    }
+
+    // The immediate is sign-extended before the unsigned comparison.
+    #[rstest]
+    #[case("sltiu a0, a1, -2048", 1)]
+    #[case("sltiu a0, a1, -1", 1)]
+    #[case("sltiu a0, zero, -1", 1)]
+    #[case("sltiu a0, a1, 2047", 0)]
+    fn test_sltiu_negative_immediate(#[case] instruction: &str, #[case] result: u32) {
+        let (machine, outcome) = run_source(&format!("li a1, 0x10000\n{}", instruction));
+        assert_eq!(outcome, StepOutcome::Halted);
+        assert_eq!(machine.get(A0), result);
+    }
+
+    #[test]
+    fn test_srai_keeps_sign() {
+        let (machine, _) = run_source("li a1, -64\nsrai a0, a1, 3");
+        assert_eq!(machine.get(A0), -8i32 as u32);
+    }
+
+    #[test]
+    fn test_memory_is_little_endian() {
+        let source = "
+            li a0, 0x12345680
+            sw a0, -4(sp)
+            lbu a1, -4(sp)
+            lb a2, -4(sp)
+            lhu a3, -2(sp)
+            lh a4, -4(sp)
+            sh a0, -8(sp)
+            sb a0, -5(sp)
+        ";
+        let (machine, outcome) = run_source(source);
+        assert_eq!(outcome, StepOutcome::Halted);
+        assert_eq!(machine.get(A1), 0x80);
+        assert_eq!(machine.get(Register(12)), 0xFFFF_FF80);
+        assert_eq!(machine.get(Register(13)), 0x1234);
+        assert_eq!(machine.get(Register(14)), 0x5680);
+        assert_eq!(
+            machine.memory[MEMORY_SIZE - 8..],
+            [0x80, 0x56, 0, 0x80, 0x80, 0x56, 0x34, 0x12]
+        );
+    }
+
+    #[rstest]
+    #[case("beq", 1, 1, true)]
+    #[case("beq", 1, 2, false)]
+    #[case("bne", 1, 2, true)]
+    #[case("blt", -1, 0, true)]
+    #[case("blt", 0, 0, false)]
+    #[case("bge", 0, -1, true)]
+    #[case("bltu", -1, 0, false)]
+    #[case("bgeu", -1, 0, true)]
+    fn test_branch(
+        #[case] mnemonic: &str,
+        #[case] lhs: i32,
+        #[case] rhs: i32,
+        #[case] taken: bool,
+    ) {
+        let source = format!(
+            "li a1, {}\nli a2, {}\n{} a1, a2, skip\nli a0, 1\nskip:",
+            lhs, rhs, mnemonic
+        );
+        let (machine, outcome) = run_source(&source);
+        assert_eq!(outcome, StepOutcome::Halted);
+        assert_eq!(machine.get(A0) == 0, taken);
+    }
+
+    #[test]
+    fn test_countdown_loop() {
+        let source = "
+            li a0, 5
+            loop:
+                addi a1, a1, 2
+                addi a0, a0, -1
+                bnez a0, loop
+        ";
+        let (machine, outcome) = run_source(source);
+        assert_eq!(outcome, StepOutcome::Halted);
+        assert_eq!(machine.get(A0), 0);
+        assert_eq!(machine.get(A1), 10);
+    }
+
+    #[test]
+    fn test_call_and_ret() {
+        let source = "
+            li a0, 20
+            call double
+            j end
+            double:
+                add a0, a0, a0
+                ret
+            end:
+        ";
+        let mut machine = assemble(source, Isa::RV32I);
+        assert_eq!(machine.run(3), StepOutcome::Continue);
+        assert_eq!(machine.pc, 16);
+        assert_eq!(machine.get(Register(1)), 12);
+        assert_eq!(machine.run(1000), StepOutcome::Halted);
+        assert_eq!(machine.get(A0), 40);
+    }
+
+    #[test]
+    fn test_jalr_clears_lowest_bit() {
+        let (machine, outcome) = run_source("li a1, 13\njalr a1, 0(a1)\nnop\nnop");
+        assert_eq!(outcome, StepOutcome::Halted);
+        assert_eq!(machine.get(A1), 8);
+    }
+
+    #[test]
+    fn test_ecall_resumes() {
+        let source = "li a7, 93\necall\naddi a0, a0, 1";
+        let mut machine = assemble(source, Isa::RV32I);
+        assert_eq!(machine.run(1000), StepOutcome::Ecall);
+        assert_eq!(machine.pc, 8);
+        assert_eq!(machine.get(Register(17)), 93);
+        machine.set(A0, 41);
+        assert_eq!(machine.run(1000), StepOutcome::Halted);
+        assert_eq!(machine.get(A0), 42);
+    }
+
+    #[test]
+    fn test_break_resumes() {
+        let mut machine = assemble("li a0, 1\nebreak\nli a0, 2", Isa::RV32I);
+        assert_eq!(machine.run(1000), StepOutcome::Break);
+        assert_eq!(machine.pc, 8);
+        assert_eq!(machine.get(A0), 1);
+        assert_eq!(machine.run(1000), StepOutcome::Halted);
+        assert_eq!(machine.get(A0), 2);
+        assert_eq!(machine.step(), StepOutcome::Halted);
+    }
+
+    #[test]
+    fn test_run_stops_after_max_steps() {
+        let mut machine = assemble("loop: j loop", Isa::RV32I);
+        assert_eq!(machine.run(100), StepOutcome::Continue);
+        assert_eq!(machine.pc, 0);
+    }
+
+    #[rstest]
+    #[case("lw a0, 2(zero)", FaultKind::MisalignedLoad { address: 2 })]
+    #[case("lh a0, -1(sp)", FaultKind::MisalignedLoad { address: 0xFFFF })]
+    #[case("sw a0, 1(zero)", FaultKind::MisalignedStore { address: 1 })]
+    #[case("lw a0, 0(sp)", FaultKind::AccessFault { address: 0x1_0000 })]
+    #[case("sb a0, -1(zero)", FaultKind::AccessFault { address: 0xFFFF_FFFF })]
+    #[case("jal 6", FaultKind::MisalignedJump { target: 22 })]
+    #[case("beq zero, zero, 2", FaultKind::MisalignedJump { target: 18 })]
+    fn test_memory_faults(#[case] source: &str, #[case] kind: FaultKind) {
+        let source = format!("nop\nnop\nnop\nnop\n{}", source);
+        let (machine, outcome) = run_source(&source);
+        let fault = expect_fault(outcome);
+        assert_eq!(fault.kind, kind);
+        assert_eq!(fault.pc, 16);
+        assert_eq!(machine.pc, 16);
+    }
+
+    #[test]
+    fn test_fault_keeps_registers() {
+        let (machine, outcome) = run_source("li a0, 3\nlw a1, 0(a0)");
+        let fault = expect_fault(outcome);
+        assert_eq!(
+            fault.to_string(),
+            "misaligned load from 0x00000003 at 0x00000004"
+        );
+        assert_eq!(fault.snapshot.registers[10], 3);
+        assert_eq!(fault.snapshot.pc, 4);
+        assert_eq!(machine.fault(), Some(&fault));
+    }
+
+    #[test]
+    fn test_fault_is_sticky_until_reset() {
+        let (mut machine, outcome) = run_source("lw a0, 1(zero)");
+        let fault = expect_fault(outcome);
+        assert_eq!(machine.step(), StepOutcome::Fault(fault));
+        machine.reset();
+        assert_eq!(machine.fault(), None);
+        assert_eq!(machine.registers[2], MEMORY_SIZE as u32);
+        assert!(matches!(machine.step(), StepOutcome::Fault(_)));
+    }
+
+    #[test]
+    fn test_illegal_instruction_faults() {
+        let assembly = assemble("mul a0, a0, a1", Isa::RV32IM);
+        let mut machine = RiscvMachine::new();
+        machine.load_image(&assembly.memory[..assembly.program_end as usize]);
+        let fault = expect_fault(machine.run(1000));
+        assert_eq!(fault.kind, FaultKind::IllegalInstruction);
+        assert_eq!(fault.to_string(), "illegal instruction `mul` at 0x00000000");
+
+        let mut machine = assemble("csrr a0, mcause", Isa::RV32IMAC_ZICSR);
+        let fault = expect_fault(machine.run(1000));
+        assert_eq!(fault.kind, FaultKind::IllegalInstruction);
+    }
+
+    #[test]
+    fn test_undecodable_word_faults() {
+        let mut machine = RiscvMachine::new();
+        machine.load_image(&[0x13, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF]);
+        let fault = expect_fault(machine.run(1000));
+        assert_eq!(
+            fault.kind,
+            FaultKind::InvalidInstruction(DecodeError::InvalidOpcode { word: 0xFFFF_FFFF })
+        );
+        assert_eq!(fault.pc, 4);
+        assert_eq!(fault.instruction, None);
+    }
+
+    #[test]
+    fn test_muldiv_extension() {
+        let source = "li a1, -7\nli a2, 2\nmul a0, a1, a2\ndiv a1, a1, a2\ndivu a3, a2, zero";
+        let mut machine = assemble(source, Isa::RV32IM);
+        assert_eq!(machine.run(1000), StepOutcome::Halted);
+        assert_eq!(machine.get(A0), -14i32 as u32);
+        assert_eq!(machine.get(A1), -3i32 as u32);
+        assert_eq!(machine.get(Register(13)), u32::MAX);
+    }
+
+    #[test]
+    fn test_compressed_extension() {
+        let source = "
+            c.li a0, 5
+            loop:
+                c.addi a1, 3
+                c.addi a0, -1
+                c.bnez a0, loop
+            c.jal 2
+        ";
+        let mut machine = assemble(source, Isa::RV32IC);
+        assert_eq!(machine.run(1000), StepOutcome::Halted);
+        assert_eq!(machine.get(A1), 15);
+        assert_eq!(machine.get(Register(1)), 10);
+        assert_eq!(machine.pc, 10);
+    }
+
+    #[test]
+    fn test_self_modifying_code() {
+        // Overwrites the trailing nop with an ebreak.
+        let source = "li a0, 0x00100073\nsw a0, 12(zero)\nnop\nnop";
+        let (machine, outcome) = run_source(source);
+        assert_eq!(outcome, StepOutcome::Break);
+        assert_eq!(machine.pc, 16);
+    }
+
+    fn executable(source: &str) -> Vec<u8> {
+        let options = AssembleOptions {
+            origin: 0x8000_0000,
+            ..Default::default()
+        };
+        let assembly = assemble_riscv_with(source, &options).unwrap();
+        write_executable(&assembly, &ExecutableOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn test_load_elf() {
+        let source = "
+            .globl _start
+            helper: ret
+            _start:
+                la a1, value
+                lw a0, 0(a1)
+                la a1, result
+                sw a0, 0(a1)
+                call helper
+                ecall
+            .data
+            value: .word 42
+            .bss
+            result: .zero 4
+        ";
+        let mut machine = RiscvMachine::new();
+        machine.memory[0] = 0xFF;
+        machine.load_elf(&executable(source)).unwrap();
+        assert_eq!(machine.memory_base, 0x8000_0000);
+        assert_eq!(machine.memory.len(), 0x1000 + MEMORY_SIZE);
+        assert_eq!(machine.pc, 0x8000_0004);
+        assert_eq!(machine.get(SP), 0x8001_1000);
+        assert_eq!(machine.run(1000), StepOutcome::Ecall);
+        assert_eq!(machine.get(A0), 42);
+        assert_eq!(machine.read(machine.get(A1), 4), Ok(42));
+        assert_eq!(machine.symbol_at(0x8000_0004), Some(("_start", 0)));
+        assert_eq!(machine.symbol_at(0x8000_0010), Some(("_start", 12)));
+        assert_eq!(machine.symbol_at(0x7FFF_FFFC), None);
+        // Memory past the loaded program starts out zero.
+        assert_eq!(machine.read(0x8000_1000, 4), Ok(0));
+        assert_eq!(
+            machine.read(0x1000, 4),
+            Err(FaultKind::AccessFault { address: 0x1000 })
+        );
+
+        // Reset starts the program over.
+        machine.reset();
+        assert_eq!(machine.pc, 0x8000_0004);
+    }
+
+    #[test]
+    fn test_load_linked_elf() {
+        let main = "
+            .globl _start
+            _start:
+                la a1, values
+                lw a0, 4(a1)
+                lw a0, 0(a0)
+                call double
+                ecall
+            .data
+            values: .word 1, answer
+        ";
+        let lib = "
+            .globl double
+            .globl answer
+            double:
+                add a0, a0, a0
+                ret
+            .section .rodata
+            answer: .word 21
+        ";
+        let script = parse_linker_script(
+            "MEMORY { ram : ORIGIN = 0x80000000, LENGTH = 64K }
+             SECTIONS { .text : > ram .rodata : > ram .data : ALIGN(256) > ram }",
+        )
+        .unwrap();
+        let options = AssembleOptions {
+            relocatable: true,
+            ..Default::default()
+        };
+        let units = [main, lib].map(|source| assemble_riscv_with(source, &options).unwrap());
+        let linked = link(&[("main.s", &units[0]), ("lib.s", &units[1])], &script).unwrap();
+        let elf = write_executable(&linked.assembly, &ExecutableOptions::default()).unwrap();
+
+        let mut machine = RiscvMachine::new();
+        machine.load_elf(&elf).unwrap();
+        assert_eq!(machine.run(1000), StepOutcome::Ecall);
+        assert_eq!(machine.get(A0), 42);
+        let answer = linked.assembly.labels["answer"];
+        assert_eq!(machine.symbol_at(answer), Some(("answer", 0)));
+        assert_eq!(machine.get(A1), 0x8000_0100);
+    }
+
+    #[test]
+    fn test_load_elf_errors() {
+        let mut machine = RiscvMachine::new();
+        assert_eq!(
+            machine.load_elf(b"#!/bin/sh"),
+            Err(LoadError::Elf(ElfError::NotElf))
+        );
+        // A segment reaching far past the loaded bytes.
+        let mut bytes = executable("nop");
+        bytes[52 + 20..52 + 24].copy_from_slice(&0x1000_0000u32.to_le_bytes());
+        let error = machine.load_elf(&bytes).unwrap_err();
+        assert_eq!(
+            error,
+            LoadError::TooLarge {
+                size: 0x1000_0000 + MEMORY_SIZE as u64
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "executable needs 268500992 bytes of memory, at most 16777216 fit"
+        );
+        // Segments which do not fit into their memory or wrap around the address space.
+        let mut bytes = executable("nop");
+        bytes[52 + 16..52 + 20].copy_from_slice(&0x2_0000u32.to_le_bytes());
+        bytes.resize(0x2_0000 + 0x1000, 0);
+        assert_eq!(
+            machine.load_elf(&bytes),
+            Err(LoadError::Elf(ElfError::InvalidSegment {
+                address: 0x8000_0000
+            }))
+        );
+        let mut bytes = executable("nop");
+        bytes[52 + 8..52 + 12].copy_from_slice(&0xFFFF_FFFEu32.to_le_bytes());
+        assert_eq!(
+            machine.load_elf(&bytes),
+            Err(LoadError::Elf(ElfError::InvalidSegment {
+                address: 0xFFFF_FFFE
+            }))
+        );
+        // The machine is left as it was.
+        assert_eq!(machine.memory_base, 0);
+    }
+}